@@ -0,0 +1,43 @@
+//! Benchmarks for the work done on every frame. Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use diesel::{backend, editor::Editor, lines::{LineIndex, Lines}};
+
+use std::hint::black_box;
+
+/// Some source-code-like text with the given number of lines.
+fn document(lines: usize) -> String {
+    (0..lines).map(|i| format!("    let value_{} = compute(\"line {}\", {});\n", i, i, i * 7)).collect()
+}
+
+fn lines(c: &mut Criterion) {
+    for &n in &[1_000, 100_000] {
+        let text = document(n);
+        c.bench_function(&format!("Lines::new, {} lines", n), |b| b.iter(|| Lines::new(black_box(&text)).len()));
+        let mut index = LineIndex::new(&text);
+        c.bench_function(&format!("LineIndex::lines, {} lines", n), |b| b.iter(|| index.lines(black_box(&text)).len()));
+    }
+}
+
+fn render(c: &mut Criterion) {
+    let size = (120, 40);
+    backend::start_headless(size);
+    for &n in &[1_000, 100_000] {
+        let mut editor = Editor::new();
+        let mut buf = scribe::Buffer::new();
+        buf.insert(document(n));
+        editor.open_buffer(buf);
+        let mut screen = backend::Grid::new(size);
+        c.bench_function(&format!("render, {} lines", n), |b| b.iter(|| editor.render(&mut screen)));
+        // An edit moves the line index along rather than finding it again
+        let key = Event::Key(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::empty()));
+        c.bench_function(&format!("type and render, {} lines", n), |b| b.iter(|| {
+            editor.handle_event(&mut screen, key.clone());
+            editor.render(&mut screen);
+        }));
+    }
+}
+
+criterion_group!(benches, lines, render);
+criterion_main!(benches);
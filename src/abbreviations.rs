@@ -0,0 +1,40 @@
+//! Abbreviations from the config file, like `teh` for `the` or `sop` for `System.out.println`,
+//! which expand as soon as the word is typed out and followed by a space, punctuation or a new
+//! line. `abbreviation.<word> = text` sets one for every file, and
+//! `abbreviation.<extension>.<word> = text` one for files with that extension, which wins over a
+//! general one for the same word.
+//!
+//! Typing the character after the word with Alt held down leaves the word as it is, and
+//! Backspace straight after an expansion turns it back into the word.
+
+use std::path::Path;
+
+/// A word and what it expands to, in files with a given extension or in all of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Abbreviation {
+    pub extension: Option<String>,
+    pub word: String,
+    pub expansion: String,
+}
+
+/// Whether a character is part of a word, rather than ending one.
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// What a word expands to in a file, if it is an abbreviation there.
+pub fn expansion(abbreviations: &[Abbreviation], word: &str, path: Option<&Path>) -> Option<String> {
+    let extension = path.and_then(|p| p.extension()).map(|e| e.to_string_lossy().to_lowercase());
+    let for_file = abbreviations.iter().rev()
+        .find(|a| a.word == word && a.extension.is_some() && a.extension == extension);
+    for_file.or_else(|| abbreviations.iter().rev().find(|a| a.word == word && a.extension.is_none()))
+        .map(|a| a.expansion.clone())
+}
+
+/// Where the word that ends at a byte offset of some text starts, if one does.
+pub fn word_before(text: &str, offset: usize) -> Option<usize> {
+    text[..offset].char_indices().rev()
+        .take_while(|&(_, c)| is_word_char(c))
+        .last()
+        .map(|(i, _)| i)
+}
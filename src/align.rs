@@ -0,0 +1,35 @@
+//! Lining up a character across lines, like the `=` of a run of assignments or the `|` of a
+//! markdown table.
+
+/// Pad the lines of some text so that each occurrence of a character lines up with the same
+/// occurrence on the other lines: the first `=` of every line in one column, the second in the
+/// next, and so on. Whitespace before the character is replaced by the padding, keeping a space
+/// if there was one. Lines without the character are left alone.
+pub fn align_on(text: &str, c: char) -> String {
+    let lines: Vec<Vec<&str>> = text.split('\n').map(|l| l.split(c).collect()).collect();
+    let columns = lines.iter().map(|cells| cells.len() - 1).max().unwrap_or(0);
+
+    // How wide each cell gets, and whether a space goes between it and the character
+    let mut widths = vec![0; columns];
+    let mut gaps = vec![false; columns];
+    for cells in &lines {
+        for (i, cell) in cells.iter().take(cells.len() - 1).enumerate() {
+            widths[i] = widths[i].max(cell.trim_end().chars().count());
+            gaps[i] |= cell.ends_with(char::is_whitespace);
+        }
+    }
+
+    lines.iter().map(|cells| {
+        let mut line = String::new();
+        for (i, cell) in cells.iter().enumerate() {
+            if i + 1 == cells.len() {
+                line.push_str(cell);
+            } else {
+                let cell = cell.trim_end();
+                let width = widths[i] + gaps[i] as usize;
+                line.push_str(&format!("{:<width$}{}", cell, c, width = width));
+            }
+        }
+        line
+    }).collect::<Vec<_>>().join("\n")
+}
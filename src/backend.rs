@@ -0,0 +1,285 @@
+//! The seam between the editor and the terminal it runs in.
+//!
+//! Input and the terminal size come from crossterm, unless a headless session was started on
+//! the current thread, in which case they come from a queue of synthetic events and a fixed
+//! size. Output always goes through `std::io::Write`, so running headlessly only needs a `Grid`
+//! to render into: it understands the escape sequences we emit, and keeps the resulting screen.
+
+use crossterm::{*, event::{Event, KeyEventKind}, style::Color};
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::Duration;
+
+struct Headless {
+    size: (u16, u16),
+    events: VecDeque<Event>,
+}
+
+thread_local! {
+    static HEADLESS: RefCell<Option<Headless>> = const { RefCell::new(None) };
+}
+
+/// From now on, this thread gets its input from `push_events` instead of the terminal, and the
+/// terminal is assumed to be of the given size.
+pub fn start_headless(size: (u16, u16)) {
+    HEADLESS.with(|h| *h.borrow_mut() = Some(Headless { size, events: VecDeque::new() }));
+}
+
+/// Queue up synthetic input for the headless session.
+pub fn push_events<I: IntoIterator<Item = Event>>(events: I) {
+    HEADLESS.with(|h| h.borrow_mut().as_mut().expect("Not in a headless session").events.extend(events));
+}
+
+/// Take the next queued synthetic event, if any are left.
+pub fn next_queued_event() -> Option<Event> {
+    HEADLESS.with(|h| h.borrow_mut().as_mut().and_then(|h| h.events.pop_front()))
+}
+
+fn is_headless() -> bool {
+    HEADLESS.with(|h| h.borrow().is_some())
+}
+
+/// The size of the terminal, in columns and rows.
+pub fn size() -> (u16, u16) {
+    HEADLESS.with(|h| match &*h.borrow() {
+        Some(h) => h.size,
+        None => terminal::size().unwrap(),
+    })
+}
+
+/// Returns true if an event is available to `read` within the timeout.
+pub fn poll(timeout: Duration) -> bool {
+    HEADLESS.with(|h| match &*h.borrow() {
+        Some(h) => !h.events.is_empty(),
+        None => event::poll(timeout).unwrap(),
+    })
+}
+
+/// Blocks until the next event arrives. Keys are only reported as they are pressed, the way
+/// most terminals report them, even where their release is reported as well.
+pub fn read() -> Event {
+    if is_headless() {
+        return next_queued_event().expect("Ran out of synthetic events while waiting for input");
+    }
+    loop {
+        match event::read().unwrap() {
+            Event::Key(key) if key.kind == KeyEventKind::Release => continue,
+            event => return event,
+        }
+    }
+}
+
+/// A single character cell of a `Grid`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: Color,
+    pub bg: Color,
+}
+
+impl Default for Cell {
+    fn default() -> Cell {
+        Cell { ch: ' ', fg: Color::Reset, bg: Color::Reset }
+    }
+}
+
+/// An in-memory screen, to render into instead of a terminal.
+pub struct Grid {
+    size: (u16, u16),
+    cells: Vec<Cell>,
+    cursor: (u16, u16),
+    saved_cursor: (u16, u16),
+    cursor_visible: bool,
+    fg: Color,
+    bg: Color,
+    title: String,
+    // Bytes of an escape sequence or character that has not been completely written yet.
+    pending: Vec<u8>,
+}
+
+impl Grid {
+    pub fn new(size: (u16, u16)) -> Grid {
+        Grid {
+            size,
+            cells: vec![Cell::default(); size.0 as usize * size.1 as usize],
+            cursor: (0, 0),
+            saved_cursor: (0, 0),
+            cursor_visible: true,
+            fg: Color::Reset,
+            bg: Color::Reset,
+            title: String::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn cell(&self, x: u16, y: u16) -> Cell {
+        self.cells[y as usize * self.size.0 as usize + x as usize]
+    }
+
+    /// The text of a single row, without trailing whitespace.
+    pub fn row(&self, y: u16) -> String {
+        (0..self.size.0).map(|x| self.cell(x, y).ch).collect::<String>().trim_end().to_owned()
+    }
+
+    /// The text of the whole screen, one line per row.
+    pub fn text(&self) -> String {
+        (0..self.size.1).map(|y| self.row(y)).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Where the cursor is, if it is shown.
+    pub fn cursor(&self) -> Option<(u16, u16)> {
+        if self.cursor_visible { Some(self.cursor) } else { None }
+    }
+
+    /// The terminal's window title.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn print(&mut self, ch: char) {
+        let (x, y) = self.cursor;
+        if x < self.size.0 && y < self.size.1 {
+            let (fg, bg) = (self.fg, self.bg);
+            self.cells[y as usize * self.size.0 as usize + x as usize] = Cell { ch, fg, bg };
+        }
+        self.cursor.0 = x.saturating_add(1);
+    }
+
+    /// Interpret the "select graphic rendition" parameters of a CSI ... m sequence.
+    fn sgr(&mut self, params: &[u16]) {
+        let mut params = params.iter().copied();
+        while let Some(p) = params.next() {
+            let color = |params: &mut dyn Iterator<Item = u16>| match params.next() {
+                Some(5) => params.next().map(|n| ansi_color(n as u8)),
+                Some(2) => match (params.next(), params.next(), params.next()) {
+                    (Some(r), Some(g), Some(b)) => Some(Color::Rgb { r: r as u8, g: g as u8, b: b as u8 }),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match p {
+                0 => { self.fg = Color::Reset; self.bg = Color::Reset; }
+                38 => if let Some(c) = color(&mut params) { self.fg = c },
+                48 => if let Some(c) = color(&mut params) { self.bg = c },
+                39 => self.fg = Color::Reset,
+                49 => self.bg = Color::Reset,
+                _ => {} // Attributes such as bold are not kept
+            }
+        }
+    }
+
+    fn csi(&mut self, params: &str, action: char) {
+        let numbers: Vec<u16> = params.trim_start_matches('?').split(';').map(|n| n.parse().unwrap_or(0)).collect();
+        let n = |i: usize| numbers.get(i).copied().filter(|&n| n > 0).unwrap_or(1);
+        match action {
+            'H' => self.cursor = (n(1) - 1, n(0) - 1),
+            'A' => self.cursor.1 = self.cursor.1.saturating_sub(n(0)),
+            'B' => self.cursor.1 += n(0),
+            'C' => self.cursor.0 += n(0),
+            'D' => self.cursor.0 = self.cursor.0.saturating_sub(n(0)),
+            'G' => self.cursor.0 = n(0) - 1,
+            'm' => self.sgr(&numbers),
+            'J' if params == "2" => self.cells.iter_mut().for_each(|c| *c = Cell::default()),
+            'h' if params == "?25" => self.cursor_visible = true,
+            'l' if params == "?25" => self.cursor_visible = false,
+            _ => {}
+        }
+    }
+
+    /// Interpret as much of the pending output as is complete.
+    fn process(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        let mut i = 0;
+        while i < pending.len() {
+            let rest = &pending[i..];
+            if rest[0] == 0x1b {
+                let consumed = match rest.get(1) {
+                    None => 0,
+                    Some(b'[') => match rest.iter().skip(2).position(|&b| (0x40..=0x7e).contains(&b)) {
+                        Some(end) => {
+                            let params = String::from_utf8_lossy(&rest[2..2 + end]).into_owned();
+                            self.csi(&params, rest[2 + end] as char);
+                            3 + end
+                        }
+                        None => 0,
+                    },
+                    // Operating system commands, device control strings and application program
+                    // commands end with BEL or ST
+                    Some(b']') | Some(b'P') | Some(b'_') => match rest.windows(2).skip(2).position(|w| w[0] == 0x07 || w == b"\x1b\\") {
+                        Some(end) => {
+                            let end = end + 2;
+                            if rest[1] == b']' && rest[2..].starts_with(b"0;") {
+                                self.title = String::from_utf8_lossy(&rest[4..end]).into_owned();
+                            }
+                            if rest[end] == 0x07 { end + 1 } else { end + 2 }
+                        }
+                        None if rest.last() == Some(&0x07) => rest.len(),
+                        None => 0,
+                    },
+                    Some(b'7') => { self.saved_cursor = self.cursor; 2 }
+                    Some(b'8') => { self.cursor = self.saved_cursor; 2 }
+                    Some(_) => 2,
+                };
+                if consumed == 0 {
+                    break; // Incomplete, wait for the rest
+                }
+                i += consumed;
+            } else {
+                let len = match rest[0] {
+                    0x00..=0x7f => 1,
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    _ => 4,
+                };
+                if rest.len() < len {
+                    break; // Incomplete, wait for the rest
+                }
+                match std::str::from_utf8(&rest[..len]).ok().and_then(|s| s.chars().next()) {
+                    Some('\r') => self.cursor.0 = 0,
+                    Some('\n') => self.cursor.1 += 1,
+                    Some(ch) if ch >= ' ' => self.print(ch),
+                    _ => {}
+                }
+                i += len;
+            }
+        }
+        self.pending = pending[i..].to_vec();
+    }
+}
+
+impl Write for Grid {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        self.process();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The named color for one of the first 16 ANSI colors, the way crossterm encodes them.
+fn ansi_color(n: u8) -> Color {
+    match n {
+        0 => Color::Black,
+        1 => Color::DarkRed,
+        2 => Color::DarkGreen,
+        3 => Color::DarkYellow,
+        4 => Color::DarkBlue,
+        5 => Color::DarkMagenta,
+        6 => Color::DarkCyan,
+        7 => Color::Grey,
+        8 => Color::DarkGrey,
+        9 => Color::Red,
+        10 => Color::Green,
+        11 => Color::Yellow,
+        12 => Color::Blue,
+        13 => Color::Magenta,
+        14 => Color::Cyan,
+        15 => Color::White,
+        n => Color::AnsiValue(n),
+    }
+}
@@ -0,0 +1,60 @@
+//! Coloring brackets by how deeply they are nested, so that the pairs in deeply nested code are
+//! easy to match up by eye. Brackets in strings and comments are counted like any other.
+
+use crossterm::style::Color;
+
+use crate::lines::Lines;
+
+/// The colors brackets take in turn, from the outermost in.
+static COLORS: &[Color] = &[Color::Yellow, Color::Magenta, Color::Cyan];
+
+/// The color of a bracket at the given depth, where the outermost pair is at depth zero.
+pub fn color(depth: usize) -> Color {
+    COLORS[depth % COLORS.len()]
+}
+
+/// How a bracket changes the depth: opening ones go one deeper, closing ones come back out.
+pub fn step(depth: usize, c: char) -> usize {
+    match c {
+        '(' | '[' | '{' => depth + 1,
+        ')' | ']' | '}' => depth.saturating_sub(1),
+        _ => depth,
+    }
+}
+
+/// The closing bracket of an opening one.
+pub fn closing(c: char) -> Option<char> {
+    match c {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '{' => Some('}'),
+        _ => None,
+    }
+}
+
+/// The depth at the start of each line of a buffer. Only the lines up to the last one drawn are
+/// worked out, and they are remembered until the buffer changes above them.
+#[derive(Default)]
+pub struct BracketDepths {
+    line_starts: Vec<usize>,
+}
+
+impl BracketDepths {
+    /// Forget the depths of the lines after the given one, which has changed.
+    pub fn invalidate(&mut self, line: usize) {
+        self.line_starts.truncate(line + 1);
+    }
+
+    /// The depth at the start of a line, given all the lines of the buffer.
+    pub fn at_line(&mut self, lines: &Lines, line: usize) -> usize {
+        if self.line_starts.is_empty() {
+            self.line_starts.push(0);
+        }
+        while self.line_starts.len() <= line {
+            let previous = self.line_starts.len() - 1;
+            let depth = lines.get(previous).map_or(0, |l| l.chars().fold(self.line_starts[previous], step));
+            self.line_starts.push(depth);
+        }
+        self.line_starts[line]
+    }
+}
@@ -0,0 +1,120 @@
+//! The bar over a buffer showing where the cursor is, from the folders of the file down to the
+//! scopes around it, like `src › viewport.rs › impl Viewport › fn render`. Clicking a segment, or
+//! picking it with Go → Breadcrumbs, lists what is beside it to go to instead: the other files in
+//! a folder, or the other scopes in a scope.
+
+use crossterm::{cursor, queue, style::{self, Color}};
+
+use crate::lines::Lines;
+
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// What goes between the segments.
+static SEPARATOR: &str = " › ";
+
+/// A part of the bar, and what it stands for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub name: String,
+    pub kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    /// A folder the file is in.
+    Folder(PathBuf),
+    /// The file itself, which has no path until it is saved.
+    File(Option<PathBuf>),
+    /// A scope the cursor is in, by the line starting it.
+    Scope(usize),
+}
+
+/// The segments for a cursor on a line of a file: the folders from the working one down, or from
+/// the root for a file outside it, the file and the scopes around the line, outermost first.
+pub fn segments(path: Option<&Path>, title: &str, lines: &Lines, line: usize) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    match path {
+        Some(path) => {
+            let cwd = std::env::current_dir().unwrap_or_default();
+            let path = cwd.join(path);
+            let (mut folder, shown) = match path.strip_prefix(&cwd) {
+                Ok(shown) => (cwd.clone(), shown),
+                Err(_) => (PathBuf::new(), path.as_path()),
+            };
+            for component in shown.parent().into_iter().flat_map(Path::components) {
+                folder.push(component);
+                segments.push(Segment { name: component.as_os_str().to_string_lossy().into_owned(), kind: Kind::Folder(folder.clone()) });
+            }
+            let name = path.file_name().map_or_else(|| title.to_owned(), |name| name.to_string_lossy().into_owned());
+            segments.push(Segment { name, kind: Kind::File(Some(path.clone())) });
+        }
+        None => segments.push(Segment { name: title.to_owned(), kind: Kind::File(None) }),
+    }
+    for scope in crate::scopes::enclosing(lines, line) {
+        segments.push(Segment { name: crate::scopes::name(&lines[scope]), kind: Kind::Scope(scope) });
+    }
+    segments
+}
+
+/// Draw the bar on a row so many columns wide from a cell, leaving out segments from the start
+/// while they do not fit. Returns the columns each segment was drawn on, which are none for the
+/// ones left out.
+pub fn render<S: Write>(s: &mut S, origin: (u16, u16), width: usize, segments: &[Segment], focused: bool) -> Vec<Range<u16>> {
+    let names: Vec<String> = segments.iter().map(|segment| crate::util::sanitize(&segment.name)).collect();
+    let separator = SEPARATOR.chars().count();
+    let mut skipped = 0;
+    let length = |skipped: usize| 1 + names[skipped..].iter().map(|name| name.chars().count() + separator).sum::<usize>() - separator
+        + if skipped > 0 { 1 + separator } else { 0 };
+    while skipped + 1 < names.len() && length(skipped) > width {
+        skipped += 1;
+    }
+
+    queue!(s,
+        cursor::MoveTo(origin.0, origin.1), style::SetBackgroundColor(Color::Blue), style::SetForegroundColor(Color::DarkGrey),
+        style::Print(" ".repeat(width)), cursor::MoveTo(origin.0 + 1, origin.1),
+    );
+    let mut column = 1;
+    if skipped > 0 {
+        queue!(s, style::Print('…'), style::Print(SEPARATOR));
+        column += 1 + separator;
+    }
+    let mut drawn = vec![origin.0..origin.0; skipped];
+    for (i, name) in names.iter().enumerate().skip(skipped) {
+        if i > skipped {
+            queue!(s, style::SetForegroundColor(Color::DarkGrey), style::Print(SEPARATOR));
+            column += separator;
+        }
+        let name: String = name.chars().take(width.saturating_sub(column)).collect();
+        let length = name.chars().count();
+        let color = match (focused, i + 1 == names.len()) {
+            (true, true) => Color::White,
+            _ => Color::Grey,
+        };
+        queue!(s, style::SetForegroundColor(color), style::Print(name));
+        drawn.push(origin.0 + column as u16..origin.0 + (column + length) as u16);
+        column += length;
+    }
+    queue!(s, style::SetForegroundColor(Color::Grey));
+    drawn
+}
+
+/// The entries of a folder to pick from, each with what to show for it: the folder above it
+/// first as `..`, then the folders in it ending in `/`, then its files.
+pub fn entries(folder: &Path) -> Vec<(String, PathBuf)> {
+    let mut entries: Vec<(String, PathBuf, bool)> = match std::fs::read_dir(folder) {
+        Ok(entries) => entries.filter_map(|e| e.ok())
+            .map(|e| (e.file_name().to_string_lossy().into_owned(), e.path(), e.path().is_dir()))
+            .filter(|(name, _, _)| name != ".git")
+            .collect(),
+        Err(e) => {
+            log::warn!("Could not list the files in {:?}: {}", folder, e);
+            Vec::new()
+        }
+    };
+    entries.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.to_lowercase().cmp(&b.0.to_lowercase())));
+    folder.parent().map(|parent| ("..".to_owned(), parent.to_owned())).into_iter()
+        .chain(entries.into_iter().map(|(name, path, is_dir)| (if is_dir { format!("{}/", name) } else { name }, path)))
+        .collect()
+}
@@ -0,0 +1,395 @@
+//! Working out arithmetic, like `0x1F00 + 16 * 3` or `sqrt(2) / 2`, to put the result in the
+//! text. Whole numbers are worked with exactly, and the rest as floating point.
+//!
+//! The operators are those of most programming languages, tightest binding first: `**` (power),
+//! unary `-` and `~`, `*` `/` `%`, `+` `-`, `<<` `>>`, `&`, `^` (exclusive or) and `|`. Numbers may
+//! be written in hex (`0xFF`), binary (`0b1010`) or octal (`0o17`), with `_` between digits.
+
+use std::fmt;
+
+/// A number worked out so far: whole numbers stay whole until something makes them otherwise.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Number {
+    Whole(i128),
+    Real(f64),
+}
+use Number::*;
+
+impl Number {
+    fn real(self) -> f64 {
+        match self {
+            Whole(n) => n as f64,
+            Real(x) => x,
+        }
+    }
+
+    fn whole(self, operator: &str) -> Result<i128, String> {
+        match self {
+            Whole(n) => Ok(n),
+            Real(x) if x.fract() == 0.0 && x.abs() < 1e38 => Ok(x as i128),
+            Real(_) => Err(format!("{} only works on whole numbers", operator)),
+        }
+    }
+}
+
+/// How the result is written: in hex, binary or octal if every number in the expression was.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Base {
+    Decimal,
+    Hex,
+    Binary,
+    Octal,
+}
+
+/// The result of an expression, written the way its numbers were.
+pub struct Answer {
+    pub value: Number,
+    pub base: Base,
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (sign, magnitude) = match self.value {
+            Whole(n) if n < 0 => ("-", n.unsigned_abs()),
+            Whole(n) => ("", n as u128),
+            // Rounded to 12 significant digits, so that 0.1 + 0.2 comes out as 0.3, and with an
+            // exponent when very large or small
+            Real(x) => {
+                let rounded: f64 = format!("{:.11e}", x).parse().unwrap_or(x);
+                return match rounded != 0.0 && !(1e-6..1e16).contains(&rounded.abs()) {
+                    true => write!(f, "{:e}", rounded),
+                    false => write!(f, "{}", rounded),
+                };
+            }
+        };
+        match self.base {
+            Base::Decimal => write!(f, "{}{}", sign, magnitude),
+            Base::Hex => write!(f, "{}0x{:X}", sign, magnitude),
+            Base::Binary => write!(f, "{}0b{:b}", sign, magnitude),
+            Base::Octal => write!(f, "{}0o{:o}", sign, magnitude),
+        }
+    }
+}
+
+/// Work out an expression, or say what is wrong with it.
+pub fn evaluate(expression: &str) -> Result<Answer, String> {
+    let mut parser = Parser { text: expression, pos: 0, bases: Vec::new() };
+    let value = parser.bitwise_or()?;
+    parser.skip_spaces();
+    if let Some(c) = parser.peek() {
+        return Err(format!("Did not expect '{}' at column {}", c, parser.pos + 1));
+    }
+    if let Real(x) = value {
+        if !x.is_finite() {
+            return Err("The result is not a finite number".to_owned());
+        }
+    }
+    let base = match parser.bases.first() {
+        Some(&first) if parser.bases.iter().all(|&b| b == first) => first,
+        _ => Base::Decimal,
+    };
+    Ok(Answer { value, base })
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    // How each number in the expression was written.
+    bases: Vec<Base>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn skip_spaces(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    // Take an operator if it comes next, but not one that only starts with it, like `*` of `**`.
+    fn take(&mut self, operator: &str, unless: &str) -> bool {
+        self.skip_spaces();
+        let rest = &self.text[self.pos..];
+        if rest.starts_with(operator) && (unless.is_empty() || !rest.starts_with(unless)) {
+            self.pos += operator.len();
+            return true;
+        }
+        false
+    }
+
+    fn bitwise_or(&mut self) -> Result<Number, String> {
+        let mut left = self.bitwise_xor()?;
+        while self.take("|", "") {
+            left = Whole(left.whole("|")? | self.bitwise_xor()?.whole("|")?);
+        }
+        Ok(left)
+    }
+
+    fn bitwise_xor(&mut self) -> Result<Number, String> {
+        let mut left = self.bitwise_and()?;
+        while self.take("^", "") {
+            left = Whole(left.whole("^")? ^ self.bitwise_and()?.whole("^")?);
+        }
+        Ok(left)
+    }
+
+    fn bitwise_and(&mut self) -> Result<Number, String> {
+        let mut left = self.shift()?;
+        while self.take("&", "") {
+            left = Whole(left.whole("&")? & self.shift()?.whole("&")?);
+        }
+        Ok(left)
+    }
+
+    fn shift(&mut self) -> Result<Number, String> {
+        let mut left = self.sum()?;
+        loop {
+            let operator = if self.take("<<", "") { "<<" } else if self.take(">>", "") { ">>" } else { return Ok(left) };
+            let (n, by) = (left.whole(operator)?, self.sum()?.whole(operator)?);
+            if !(0..128).contains(&by) {
+                return Err(format!("Cannot shift by {}", by));
+            }
+            left = Whole(if operator == "<<" { n.checked_shl(by as u32).unwrap_or(0) } else { n >> by });
+        }
+    }
+
+    fn sum(&mut self) -> Result<Number, String> {
+        let mut left = self.product()?;
+        loop {
+            left = if self.take("+", "") {
+                let right = self.product()?;
+                match (left, right) {
+                    (Whole(a), Whole(b)) if a.checked_add(b).is_some() => Whole(a + b),
+                    _ => Real(left.real() + right.real()),
+                }
+            } else if self.take("-", "") {
+                let right = self.product()?;
+                match (left, right) {
+                    (Whole(a), Whole(b)) if a.checked_sub(b).is_some() => Whole(a - b),
+                    _ => Real(left.real() - right.real()),
+                }
+            } else {
+                return Ok(left);
+            };
+        }
+    }
+
+    fn product(&mut self) -> Result<Number, String> {
+        let mut left = self.unary()?;
+        loop {
+            left = if self.take("*", "**") {
+                let right = self.unary()?;
+                match (left, right) {
+                    (Whole(a), Whole(b)) if a.checked_mul(b).is_some() => Whole(a * b),
+                    _ => Real(left.real() * right.real()),
+                }
+            } else if self.take("/", "") {
+                let right = self.unary()?;
+                match (left, right) {
+                    (_, Whole(0)) => return Err("Division by zero".to_owned()),
+                    (Whole(a), Whole(b)) if a.checked_rem(b) == Some(0) => Whole(a / b),
+                    _ => Real(left.real() / right.real()),
+                }
+            } else if self.take("%", "") {
+                let right = self.unary()?;
+                match (left, right) {
+                    (_, Whole(0)) => return Err("Division by zero".to_owned()),
+                    (Whole(a), Whole(b)) => a.checked_rem(b).map_or(Real(0.0), Whole),
+                    _ => Real(left.real() % right.real()),
+                }
+            } else {
+                return Ok(left);
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<Number, String> {
+        if self.take("-", "") {
+            return Ok(match self.unary()? {
+                Whole(n) => n.checked_neg().map_or(Real(-(n as f64)), Whole),
+                Real(x) => Real(-x),
+            });
+        }
+        if self.take("+", "") {
+            return self.unary();
+        }
+        if self.take("~", "") {
+            return Ok(Whole(!self.unary()?.whole("~")?));
+        }
+        self.power()
+    }
+
+    // Powers bind from the right, so 2 ** 3 ** 2 is 2 ** 9.
+    fn power(&mut self) -> Result<Number, String> {
+        let base = self.primary()?;
+        if !self.take("**", "") {
+            return Ok(base);
+        }
+        let exponent = self.unary()?;
+        Ok(match (base, exponent) {
+            (Whole(a), Whole(b)) if (0..=u32::MAX as i128).contains(&b) && a.checked_pow(b as u32).is_some() => Whole(a.pow(b as u32)),
+            _ => Real(base.real().powf(exponent.real())),
+        })
+    }
+
+    fn primary(&mut self) -> Result<Number, String> {
+        self.skip_spaces();
+        let start = self.pos;
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let value = self.bitwise_or()?;
+                match self.take(")", "") {
+                    true => Ok(value),
+                    false => Err(format!("Missing ')' for the '(' at column {}", start + 1)),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name_len = self.text[start..].find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(self.text.len() - start);
+                let name = &self.text[start..start + name_len];
+                self.pos += name_len;
+                if !self.take("(", "") {
+                    return constant(name).map(Real).ok_or_else(|| format!("There is no constant called '{}'", name));
+                }
+                let mut arguments = Vec::new();
+                if !self.take(")", "") {
+                    loop {
+                        arguments.push(self.bitwise_or()?);
+                        if self.take(")", "") {
+                            break;
+                        }
+                        if !self.take(",", "") {
+                            return Err(format!("Missing ')' for the call of {} at column {}", name, start + 1));
+                        }
+                    }
+                }
+                call(name, &arguments)
+            }
+            Some(c) => Err(format!("Did not expect '{}' at column {}", c, start + 1)),
+            None => Err("The expression ends too soon".to_owned()),
+        }
+    }
+
+    fn number(&mut self) -> Result<Number, String> {
+        let start = self.pos;
+        let rest = &self.text[start..];
+        let radix = match rest.get(..2).map(str::to_ascii_lowercase).as_deref() {
+            Some("0x") => Some((16, Base::Hex)),
+            Some("0b") => Some((2, Base::Binary)),
+            Some("0o") => Some((8, Base::Octal)),
+            _ => None,
+        };
+        if let Some((radix, base)) = radix {
+            let digits_len = rest[2..].find(|c: char| !(c.is_digit(radix) || c == '_')).unwrap_or(rest.len() - 2);
+            let digits = rest[2..2 + digits_len].replace('_', "");
+            self.pos += 2 + digits_len;
+            self.bases.push(base);
+            return i128::from_str_radix(&digits, radix).map(Whole).map_err(|_| format!("'{}' is not a number", &rest[..2 + digits_len]));
+        }
+
+        // Digits, maybe with a fraction and an exponent, like 1.5e-3
+        let mut len = rest.find(|c: char| !(c.is_ascii_digit() || c == '_' || c == '.')).unwrap_or(rest.len());
+        let after = &rest[len..];
+        if after.starts_with(['e', 'E']) {
+            let sign = if after[1..].starts_with(['+', '-']) { 1 } else { 0 };
+            let exponent_digits = after[1 + sign..].chars().take_while(char::is_ascii_digit).count();
+            if exponent_digits > 0 {
+                len += 1 + sign + exponent_digits;
+            }
+        }
+        let written = rest[..len].replace('_', "");
+        self.pos += len;
+        self.bases.push(Base::Decimal);
+        match written.parse::<i128>() {
+            Ok(n) => Ok(Whole(n)),
+            Err(_) => written.parse::<f64>().map(Real).map_err(|_| format!("'{}' is not a number", &rest[..len])),
+        }
+    }
+}
+
+fn constant(name: &str) -> Option<f64> {
+    match name {
+        "pi" => Some(std::f64::consts::PI),
+        "e" => Some(std::f64::consts::E),
+        "tau" => Some(std::f64::consts::TAU),
+        _ => None,
+    }
+}
+
+fn call(name: &str, arguments: &[Number]) -> Result<Number, String> {
+    let real = |f: fn(f64) -> f64| match arguments {
+        [x] => Ok(Real(f(x.real()))),
+        _ => Err(format!("{} takes one number", name)),
+    };
+    let rounded = |f: fn(f64) -> f64| match arguments {
+        [Whole(n)] => Ok(Whole(*n)),
+        [Real(x)] if f(*x).abs() < 1e38 => Ok(Whole(f(*x) as i128)),
+        [x] => Ok(Real(f(x.real()))),
+        _ => Err(format!("{} takes one number", name)),
+    };
+    match name {
+        "abs" => match arguments {
+            [Whole(n)] => Ok(n.checked_abs().map_or(Real((*n as f64).abs()), Whole)),
+            _ => real(f64::abs),
+        },
+        "sqrt" => real(f64::sqrt),
+        "cbrt" => real(f64::cbrt),
+        "exp" => real(f64::exp),
+        "ln" => real(f64::ln),
+        "log" | "log10" => real(f64::log10),
+        "log2" => real(f64::log2),
+        "sin" => real(f64::sin),
+        "cos" => real(f64::cos),
+        "tan" => real(f64::tan),
+        "asin" => real(f64::asin),
+        "acos" => real(f64::acos),
+        "atan" => real(f64::atan),
+        "round" => rounded(f64::round),
+        "floor" => rounded(f64::floor),
+        "ceil" => rounded(f64::ceil),
+        "trunc" => rounded(f64::trunc),
+        "min" | "max" if !arguments.is_empty() => {
+            let pick = |a: Number, b: Number| match name {
+                "min" if b.real() < a.real() => b,
+                "max" if b.real() > a.real() => b,
+                _ => a,
+            };
+            Ok(arguments.iter().copied().reduce(pick).unwrap())
+        }
+        "min" | "max" => Err(format!("{} takes one or more numbers", name)),
+        "pow" => match arguments {
+            [a, b] => Ok(Real(a.real().powf(b.real()))),
+            _ => Err("pow takes two numbers".to_owned()),
+        },
+        "hypot" => match arguments {
+            [a, b] => Ok(Real(a.real().hypot(b.real()))),
+            _ => Err("hypot takes two numbers".to_owned()),
+        },
+        _ => Err(format!("There is no function called '{}'", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_spaces_of_more_than_one_byte() {
+        assert_eq!(evaluate("1\u{a0}+ 2").unwrap().to_string(), "3");
+        assert_eq!(evaluate("\u{3000}2 *\u{3000}3\u{3000}").unwrap().to_string(), "6");
+        assert!(evaluate("_rgb(\u{3000}a").is_err());
+    }
+
+    #[test]
+    fn abs_of_the_smallest_whole_number_is_real() {
+        for expression in ["abs(-170141183460469231731687303715884105728)", "abs(-170141183460469231731687303715884105727 - 1)", "abs(~0x7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF)"] {
+            let answer = evaluate(expression).unwrap();
+            assert!(matches!(answer.value, Real(x) if x == 2f64.powi(127)), "{}", expression);
+        }
+        assert_eq!(evaluate("abs(-5)").unwrap().to_string(), "5");
+    }
+}
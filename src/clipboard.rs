@@ -0,0 +1,119 @@
+//! The editor's clipboard. Copied text is always kept internally for pasting, and is also
+//! handed to the system clipboard: through a local clipboard program when one is available,
+//! or through an OSC 52 escape sequence, which asks the terminal emulator itself to set the
+//! clipboard. The latter is what makes copying work over SSH, where a clipboard program would
+//! only ever reach the remote machine.
+//!
+//! The last few copied snippets are kept too, so an earlier one can be pasted again.
+
+use crate::config::{self, Osc52};
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Programs that can place text on the system clipboard, tried in order.
+static CLIPBOARD_PROGRAMS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("pbcopy", &[]),
+    ("clip.exe", &[]),
+];
+
+pub struct Clipboard {
+    contents: String,
+    /// What was copied before, the most recent first, not counting what is on the clipboard now.
+    history: VecDeque<String>,
+}
+
+impl Clipboard {
+    pub fn new() -> Clipboard {
+        Clipboard { contents: String::new(), history: VecDeque::new() }
+    }
+
+    /// The text that was last copied.
+    pub fn contents(&self) -> &str {
+        &self.contents
+    }
+
+    /// Everything that can be pasted, the most recent first: what is on the clipboard, then what
+    /// was copied before it.
+    pub fn history(&self) -> Vec<&str> {
+        std::iter::once(self.contents.as_str()).filter(|c| !c.is_empty())
+            .chain(self.history.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// Put the text on the clipboard, remembering what it replaces.
+    fn remember(&mut self, text: String) {
+        let previous = std::mem::replace(&mut self.contents, text);
+        let contents = &self.contents;
+        self.history.retain(|entry| entry != contents);
+        if !previous.is_empty() && previous != self.contents {
+            self.history.push_front(previous);
+        }
+        self.history.truncate(config::get().clipboard_history.saturating_sub(1));
+    }
+
+    /// Copy the text to the clipboard. The text is always available to paste from within the
+    /// editor, but an error message is returned if it could not reach the system clipboard.
+    pub fn copy<S: Write>(&mut self, s: &mut S, text: String) -> Result<(), String> {
+        let remote = is_remote_session();
+        let copied_locally = !remote && copy_with_program(&text);
+        self.remember(text);
+
+        let config = config::get();
+        let use_osc52 = match config.osc52 {
+            Osc52::Always => true,
+            Osc52::Never => false,
+            Osc52::Auto => !copied_locally,
+        };
+
+        if use_osc52 {
+            let payload = crate::util::base64_encode(self.contents.as_bytes());
+            if payload.len() > config.osc52_max_bytes {
+                return Err(format!(
+                    "The copied text is too large to send to the terminal's clipboard ({} bytes encoded, the limit is {}). It can still be pasted within the editor.",
+                    payload.len(), config.osc52_max_bytes,
+                ));
+            }
+            s.write_all(osc52_sequence(&payload).as_bytes()).and_then(|_| s.flush()).map_err(|e| e.to_string())
+        } else if !copied_locally {
+            Err("No clipboard program was found, so the text can only be pasted within the editor.".to_owned())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Returns true if we are running on a machine the user is logged into over SSH.
+fn is_remote_session() -> bool {
+    ["SSH_TTY", "SSH_CONNECTION", "SSH_CLIENT"].iter().any(|var| std::env::var_os(var).is_some())
+}
+
+/// Pipe the text into the first clipboard program that accepts it. Returns false if none did.
+fn copy_with_program(text: &str) -> bool {
+    for (program, args) in CLIPBOARD_PROGRAMS {
+        let child = Command::new(program).args(*args)
+            .stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null())
+            .spawn();
+        if let Ok(mut child) = child {
+            let written = child.stdin.take().is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+            if child.wait().is_ok_and(|status| status.success()) && written {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Wraps an already base64-encoded payload in an OSC 52 "set clipboard" sequence. Inside tmux
+/// the sequence has to be passed through to the outer terminal explicitly.
+fn osc52_sequence(payload: &str) -> String {
+    if std::env::var_os("TMUX").is_some() {
+        format!("\x1bPtmux;\x1b\x1b]52;c;{}\x07\x1b\\", payload)
+    } else {
+        format!("\x1b]52;c;{}\x07", payload)
+    }
+}
@@ -0,0 +1,119 @@
+//! The date and time of day, for the log and for inserting into buffers. The standard library only
+//! knows about durations since the epoch, so the calendar arithmetic is done here.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static MONTHS: &[&str] = &["January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December"];
+static WEEKDAYS: &[&str] = &["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    /// 1 to 12.
+    pub month: u32,
+    /// 1 to 31.
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub millisecond: u32,
+    /// 0 is Sunday.
+    pub weekday: u32,
+}
+
+impl DateTime {
+    /// The current time in UTC.
+    pub fn now_utc() -> DateTime {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = now.as_secs();
+        let (time, days) = (secs % 86400, (secs / 86400) as i64);
+
+        // Convert days since the epoch to a civil date (after Howard Hinnant's `civil_from_days`)
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let day_of_era = z - era * 146_097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year,
+            month: month as u32,
+            day: day as u32,
+            hour: (time / 3600) as u32,
+            minute: (time / 60 % 60) as u32,
+            second: (time % 60) as u32,
+            millisecond: now.subsec_millis(),
+            weekday: ((days + 4) % 7) as u32, // The epoch was a Thursday
+        }
+    }
+
+    /// The current time in the local time zone.
+    #[cfg(unix)]
+    pub fn now_local() -> DateTime {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = now.as_secs() as libc::time_t;
+        // SAFETY: `localtime_r` only writes to the `tm` we give it, which is plain data.
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+            return DateTime::now_utc();
+        }
+        DateTime {
+            year: tm.tm_year as i64 + 1900,
+            month: tm.tm_mon as u32 + 1,
+            day: tm.tm_mday as u32,
+            hour: tm.tm_hour as u32,
+            minute: tm.tm_min as u32,
+            second: tm.tm_sec as u32,
+            millisecond: now.subsec_millis(),
+            weekday: tm.tm_wday as u32,
+        }
+    }
+
+    /// The current time, in UTC where we cannot tell the local time zone.
+    #[cfg(not(unix))]
+    pub fn now_local() -> DateTime {
+        DateTime::now_utc()
+    }
+
+    /// Format the time like `strftime` does, understanding `%Y %y %m %d %e %H %I %M %S %p %B %b %A
+    /// %a %F %T` and `%%`. Anything else is left as it is.
+    pub fn format(&self, format: &str) -> String {
+        let mut out = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => out += &self.year.to_string(),
+                Some('y') => out += &format!("{:02}", self.year.rem_euclid(100)),
+                Some('m') => out += &format!("{:02}", self.month),
+                Some('d') => out += &format!("{:02}", self.day),
+                Some('e') => out += &format!("{:>2}", self.day),
+                Some('H') => out += &format!("{:02}", self.hour),
+                Some('I') => out += &format!("{:02}", (self.hour + 11) % 12 + 1),
+                Some('M') => out += &format!("{:02}", self.minute),
+                Some('S') => out += &format!("{:02}", self.second),
+                Some('p') => out += if self.hour < 12 { "AM" } else { "PM" },
+                Some('B') => out += MONTHS[self.month as usize - 1],
+                Some('b') => out += &MONTHS[self.month as usize - 1][..3],
+                Some('A') => out += WEEKDAYS[self.weekday as usize],
+                Some('a') => out += &WEEKDAYS[self.weekday as usize][..3],
+                Some('F') => out += &self.format("%Y-%m-%d"),
+                Some('T') => out += &self.format("%H:%M:%S"),
+                Some('%') => out.push('%'),
+                Some(other) => {
+                    out.push('%');
+                    out.push(other);
+                }
+                None => out.push('%'),
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,275 @@
+//! What colors the terminal can show, and bringing the colors we draw with down to them.
+//!
+//! Everything is drawn with crossterm colors, which it writes as 256 color or 24-bit escape
+//! sequences whatever the terminal. `Degrade` sits between the editor and the terminal and
+//! rewrites those sequences into ones the terminal understands, down to none at all. It also
+//! repaints them for the light theme, see `theme`.
+
+use lazy_static::*;
+
+use crate::theme::{self, Theme};
+
+use std::io::{self, Write};
+
+/// How many colors a terminal can show.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorSupport {
+    /// Any 24-bit color.
+    TrueColor,
+    /// The 256 color palette.
+    Ansi256,
+    /// Only the 16 basic colors, which the user's theme may have changed.
+    Ansi16,
+    /// No colors: light backgrounds are drawn in reverse video instead.
+    Monochrome,
+}
+
+impl ColorSupport {
+    /// Go by what the environment says about the terminal: `NO_COLOR`, `COLORTERM` and `TERM`.
+    pub fn detect() -> ColorSupport {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        let term = var("TERM");
+        if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) || term == "dumb" {
+            ColorSupport::Monochrome
+        } else if matches!(var("COLORTERM").as_str(), "truecolor" | "24bit") || term.ends_with("-direct") {
+            ColorSupport::TrueColor
+        } else if term.contains("256color") {
+            ColorSupport::Ansi256
+        } else {
+            ColorSupport::Ansi16
+        }
+    }
+}
+
+impl std::str::FromStr for ColorSupport {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ColorSupport, ()> {
+        match s {
+            "truecolor" | "24bit" => Ok(ColorSupport::TrueColor),
+            "256" => Ok(ColorSupport::Ansi256),
+            "16" => Ok(ColorSupport::Ansi16),
+            "none" | "monochrome" => Ok(ColorSupport::Monochrome),
+            _ => Err(()),
+        }
+    }
+}
+
+lazy_static! {
+    static ref SUPPORT: ColorSupport = crate::config::get().colors.unwrap_or_else(ColorSupport::detect);
+}
+
+/// The colors of the terminal we run in, as configured or else detected.
+pub fn support() -> ColorSupport {
+    *SUPPORT
+}
+
+/// The red, green and blue of the 16 basic colors, as xterm shows them by default.
+static BASIC: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0), (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0), (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+/// The levels of red, green and blue in the 6x6x6 color cube of the 256 color palette.
+static CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The red, green and blue of a color of the 256 color palette.
+fn palette_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => BASIC[n as usize],
+        16..=231 => {
+            let n = n - 16;
+            (CUBE[n as usize / 36], CUBE[n as usize / 6 % 6], CUBE[n as usize % 6])
+        }
+        _ => {
+            let level = 8 + 10 * (n - 232);
+            (level, level, level)
+        }
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// The closest of the palette colors in the range.
+fn nearest(rgb: (u8, u8, u8), range: std::ops::RangeInclusive<u8>) -> u8 {
+    range.min_by_key(|&n| distance(rgb, palette_rgb(n))).unwrap()
+}
+
+/// Whether dark text reads better on the color than light text.
+pub fn is_light(rgb: (u8, u8, u8)) -> bool {
+    299 * rgb.0 as u32 + 587 * rgb.1 as u32 + 114 * rgb.2 as u32 > 128_000
+}
+
+/// A color as it was asked for, either from the palette or in 24 bits.
+#[derive(Copy, Clone)]
+enum Wanted {
+    Palette(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Wanted {
+    fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            Wanted::Palette(n) => palette_rgb(n),
+            Wanted::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+}
+
+/// The SGR parameters drawing with a color as the foreground (30) or background (40), as near as
+/// the terminal can show it.
+fn degrade(color: Wanted, base: u16, support: ColorSupport) -> String {
+    match (support, color) {
+        (ColorSupport::TrueColor, Wanted::Rgb(r, g, b)) => format!("{};2;{};{};{}", base + 8, r, g, b),
+        (ColorSupport::TrueColor, Wanted::Palette(n)) | (ColorSupport::Ansi256, Wanted::Palette(n)) => format!("{};5;{}", base + 8, n),
+        (ColorSupport::Ansi256, rgb) => format!("{};5;{}", base + 8, nearest(rgb.rgb(), 16..=255)),
+        (ColorSupport::Ansi16, color) => {
+            let n = match color {
+                Wanted::Palette(n) if n < 16 => n as u16,
+                _ => nearest(color.rgb(), 0..=15) as u16,
+            };
+            match n < 8 {
+                true => (base + n).to_string(),
+                false => (base + 60 + n - 8).to_string(),
+            }
+        }
+        // Only the background tells anything apart, by reversing light ones
+        (ColorSupport::Monochrome, _) if base == 30 => "39".to_owned(),
+        (ColorSupport::Monochrome, color) => match is_light(color.rgb()) {
+            true => "49;7".to_owned(),
+            false => "49;27".to_owned(),
+        },
+    }
+}
+
+/// The colors of the light theme for a background and foreground of the dark one. The blue of
+/// the text areas turns white, and the light colors of their text dark. Menus, dialogs and the
+/// status bar are drawn light already, and stay as they are.
+fn light(bg: Option<Wanted>, fg: Option<Wanted>) -> (Option<Wanted>, Option<Wanted>) {
+    let paper = match bg {
+        Some(Wanted::Palette(12)) => 231,
+        Some(Wanted::Palette(4)) => 254,
+        _ => return (bg, fg),
+    };
+    let ink = match fg {
+        Some(Wanted::Palette(n)) => Some(Wanted::Palette(match n {
+            7 => 240,
+            8 => 245,
+            9 => 160,
+            10 => 28,
+            11 => 136,
+            12 => 26,
+            13 => 127,
+            6 | 14 => 30,
+            15 => 16,
+            n => n,
+        })),
+        fg => fg,
+    };
+    (Some(Wanted::Palette(paper)), ink)
+}
+
+/// Rewrite the parameters of an SGR sequence for the terminal. `drawn` is the background and
+/// foreground asked for so far, which the light theme repaints together.
+fn rewrite_sgr(params: &str, support: ColorSupport, drawn: &mut (Option<Wanted>, Option<Wanted>)) -> String {
+    // Without colors there is nothing to repaint, and white would only come out in reverse video
+    let repaint = theme::current() == Theme::Light && support != ColorSupport::Monochrome;
+    let numbers: Vec<u16> = params.split(';').map(|n| n.parse().unwrap_or(0)).collect();
+    let mut out = Vec::new();
+    let mut changed = false;
+    let mut i = 0;
+    while i < numbers.len() {
+        let wanted = match (numbers[i], numbers.get(i + 1)) {
+            (38 | 48, Some(5)) => numbers.get(i + 2).map(|&n| (Wanted::Palette(n as u8), 3)),
+            (38 | 48, Some(2)) if i + 4 < numbers.len() => Some((Wanted::Rgb(numbers[i + 2] as u8, numbers[i + 3] as u8, numbers[i + 4] as u8), 5)),
+            _ => None,
+        };
+        match wanted {
+            Some((color, len)) => {
+                match numbers[i] {
+                    38 => drawn.1 = Some(color),
+                    _ => drawn.0 = Some(color),
+                }
+                if !repaint {
+                    out.push(degrade(color, if numbers[i] == 38 { 30 } else { 40 }, support));
+                }
+                changed = true;
+                i += len;
+            }
+            None => {
+                match numbers[i] {
+                    0 => *drawn = (None, None),
+                    39 => drawn.1 = None,
+                    49 => drawn.0 = None,
+                    _ => {}
+                }
+                out.push(numbers[i].to_string());
+                i += 1;
+            }
+        }
+    }
+    // The theme's colors go after everything else, which may have reset them
+    if repaint && changed {
+        let (bg, fg) = light(drawn.0, drawn.1);
+        out.push(fg.map_or("39".to_owned(), |fg| degrade(fg, 30, support)));
+        out.push(bg.map_or("49".to_owned(), |bg| degrade(bg, 40, support)));
+    }
+    out.join(";")
+}
+
+/// Passes output on to the terminal with its colors brought down to what the terminal can show.
+/// Output is held back until it is flushed, so that no escape sequence is cut in two.
+pub struct Degrade<W: Write> {
+    inner: W,
+    support: ColorSupport,
+    pending: Vec<u8>,
+    drawn: (Option<Wanted>, Option<Wanted>),
+}
+
+impl<W: Write> Degrade<W> {
+    pub fn new(inner: W, support: ColorSupport) -> Degrade<W> {
+        Degrade { inner, support, pending: Vec::new(), drawn: (None, None) }
+    }
+}
+
+impl<W: Write> Write for Degrade<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+        if self.support == ColorSupport::TrueColor && theme::current() == Theme::Dark {
+            self.inner.write_all(&pending)?;
+            return self.inner.flush();
+        }
+
+        let mut out = Vec::with_capacity(pending.len());
+        let mut i = 0;
+        while i < pending.len() {
+            if pending[i..].starts_with(b"\x1b[") {
+                if let Some(end) = pending[i + 2..].iter().position(|&b| (0x40..=0x7e).contains(&b)) {
+                    let end = i + 2 + end;
+                    match pending[end] {
+                        b'm' => {
+                            let params = String::from_utf8_lossy(&pending[i + 2..end]);
+                            out.extend_from_slice(format!("\x1b[{}m", rewrite_sgr(&params, self.support, &mut self.drawn)).as_bytes());
+                        }
+                        _ => out.extend_from_slice(&pending[i..=end]),
+                    }
+                    i = end + 1;
+                    continue;
+                }
+            }
+            out.push(pending[i]);
+            i += 1;
+        }
+        self.inner.write_all(&out)?;
+        self.inner.flush()
+    }
+}
+
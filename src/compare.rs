@@ -0,0 +1,245 @@
+//! Comparing two folders, or a git working folder with its last commit: the files added,
+//! removed and changed are listed in a panel down the left side, and each one's differences
+//! open in a viewport of their own.
+
+use crossterm::{*, event::KeyCode, style::Color};
+
+use crate::jobs::Progress;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directories that are not compared, besides hidden ones.
+static IGNORED_DIRECTORIES: &[&str] = &["target", "node_modules"];
+
+/// What the files of a folder are compared with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Base {
+    /// The files of another folder.
+    Folder(PathBuf),
+    /// The last commit of the git repository the folder is in.
+    Head,
+}
+
+/// How a file differs between the two sides.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Status {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// A file that differs, by its path under the folders compared.
+pub struct FileChange {
+    pub path: PathBuf,
+    pub status: Status,
+}
+
+/// What a folder is compared with, and the files that differ between them.
+pub struct Comparison {
+    pub base: Base,
+    /// The folder whose files are compared, which is the top of the repository when comparing
+    /// with the last commit.
+    pub folder: PathBuf,
+    pub changes: Vec<FileChange>,
+}
+
+/// Compare the files of a folder with another folder or the last commit, sorted by path.
+pub fn compare(base: Base, folder: &Path, progress: &Progress) -> io::Result<Comparison> {
+    match base {
+        Base::Folder(old) => {
+            let (before, after) = (files(&old, progress)?, files(folder, progress)?);
+            let mut changes: Vec<FileChange> = before.iter()
+                .filter(|path| !after.contains(path))
+                .map(|path| FileChange { path: path.clone(), status: Status::Removed })
+                .collect();
+            for path in after {
+                let status = match before.contains(&path) {
+                    true if same_contents(&old.join(&path), &folder.join(&path)) => continue,
+                    true => Status::Changed,
+                    false => Status::Added,
+                };
+                changes.push(FileChange { path, status });
+            }
+            changes.sort_by(|a, b| a.path.cmp(&b.path));
+            Ok(Comparison { base: Base::Folder(old), folder: folder.to_path_buf(), changes })
+        }
+        Base::Head => {
+            let root = PathBuf::from(git(folder, &["rev-parse", "--show-toplevel"])?.trim_end());
+            progress.report(format!("Comparing {} with its last commit", root.display()));
+            let mut changes = Vec::new();
+            let listed = git(&root, &["diff", "--name-status", "--no-renames", "-z", "HEAD"])?;
+            let mut fields = listed.split('\0');
+            while let (Some(status), Some(path)) = (fields.next(), fields.next()) {
+                let status = match status {
+                    "A" => Status::Added,
+                    "D" => Status::Removed,
+                    _ => Status::Changed,
+                };
+                changes.push(FileChange { path: PathBuf::from(path), status });
+            }
+            // Files git does not know about yet are new as well
+            let untracked = git(&root, &["ls-files", "--others", "--exclude-standard", "-z"])?;
+            changes.extend(untracked.split('\0').filter(|path| !path.is_empty()).map(|path| FileChange { path: PathBuf::from(path), status: Status::Added }));
+            changes.sort_by(|a, b| a.path.cmp(&b.path));
+            Ok(Comparison { base: Base::Head, folder: root, changes })
+        }
+    }
+}
+
+impl Comparison {
+    /// A title for the differences in one of the files, and the differences themselves in the
+    /// unified format. Files that are not text are compared as empty.
+    pub fn diff(&self, change: &FileChange) -> (String, String) {
+        let name = change.path.display().to_string();
+        let old = match (&self.base, change.status) {
+            (_, Status::Added) => String::new(),
+            (Base::Folder(old), _) => fs::read_to_string(old.join(&change.path)).unwrap_or_default(),
+            (Base::Head, _) => git(&self.folder, &["show", &format!("HEAD:{}", name.replace('\\', "/"))]).unwrap_or_default(),
+        };
+        let new = match change.status {
+            Status::Removed => String::new(),
+            _ => fs::read_to_string(self.folder.join(&change.path)).unwrap_or_default(),
+        };
+        let old_name = match &self.base {
+            Base::Folder(old) => old.join(&change.path).display().to_string(),
+            Base::Head => format!("{} (HEAD)", name),
+        };
+        let text = crate::diff::unified(&old, &new, &old_name, &self.folder.join(&change.path).display().to_string());
+        (format!("Changes to {}", name), text)
+    }
+}
+
+// Every file under a folder, as paths under it, skipping hidden directories and build output.
+fn files(root: &Path, progress: &Progress) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(e) if directory == root => return Err(e),
+            Err(_) => continue, // Something we may not read, deeper in
+        };
+        progress.report(format!("Comparing {}", directory.display()));
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            match entry.metadata() {
+                Ok(m) if m.is_dir() && !name.starts_with('.') && !IGNORED_DIRECTORIES.contains(&name.as_ref()) => directories.push(entry.path()),
+                Ok(m) if m.is_file() => files.push(entry.path().strip_prefix(root).unwrap().to_path_buf()),
+                _ => {}
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn same_contents(a: &Path, b: &Path) -> bool {
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(ma), Ok(mb)) if ma.len() != mb.len() => false,
+        _ => matches!((fs::read(a), fs::read(b)), (Ok(a), Ok(b)) if a == b),
+    }
+}
+
+// Run git in a folder, returning what it wrote, or what it complained of as the error.
+fn git(dir: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_owned())),
+    }
+}
+
+/// The files that differ between the two sides of a comparison, listed to open their
+/// differences from.
+pub struct ComparePanel {
+    pub comparison: Comparison,
+    selected: usize,
+    // The first file in view, and how many fit, as of the last render.
+    scroll: usize,
+    height: usize,
+}
+
+impl ComparePanel {
+    pub fn new(comparison: Comparison) -> ComparePanel {
+        ComparePanel { comparison, selected: 0, scroll: 0, height: 1 }
+    }
+
+    /// Handle a key while the panel has focus. Returns the title and differences of the file
+    /// picked with Enter, if one was.
+    pub fn handle_key(&mut self, key: KeyCode) -> Option<(String, String)> {
+        let last = self.comparison.changes.len().saturating_sub(1);
+        match key {
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(last),
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(self.height),
+            KeyCode::PageDown => self.selected = (self.selected + self.height).min(last),
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = last,
+            KeyCode::Enter => return self.comparison.changes.get(self.selected).map(|change| self.comparison.diff(change)),
+            _ => {}
+        }
+        None
+    }
+
+    /// Select the file on a row of the panel, counting from its top edge, and return its title
+    /// and differences.
+    pub fn click(&mut self, row: usize) -> Option<(String, String)> {
+        let index = self.scroll + row.checked_sub(1)?;
+        self.selected = index.min(self.comparison.changes.len().checked_sub(1)?);
+        Some(self.comparison.diff(&self.comparison.changes[self.selected]))
+    }
+
+    /// Draw the list, with what was compared on top and a line down its right edge. Each file is
+    /// marked with whether it was added, removed or changed.
+    pub fn render<S: Write>(&mut self, s: &mut S, origin: (u16, u16), size: (usize, usize), focused: bool) {
+        crate::util::draw_rectangle(s, &Color::DarkBlue, origin, size);
+        let width = size.0.saturating_sub(1);
+        self.height = size.1.saturating_sub(1).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + self.height {
+            self.scroll = self.selected + 1 - self.height;
+        }
+
+        let against = match &self.comparison.base {
+            Base::Folder(old) => old.file_name().map_or_else(|| old.display().to_string(), |name| name.to_string_lossy().into_owned()),
+            Base::Head => "HEAD".to_owned(),
+        };
+        let title = format!(" {} ↔ {} ", against, self.comparison.folder.file_name().unwrap_or_default().to_string_lossy());
+        let title: String = crate::util::sanitize(&title).chars().take(width).collect();
+        queue!(s, cursor::MoveTo(origin.0, origin.1), style::SetBackgroundColor(Color::Grey), style::SetForegroundColor(Color::Black), style::Print(format!("{:<1$}", title, width)));
+
+        if self.comparison.changes.is_empty() {
+            let note: String = crate::locale::tr("No differences").chars().take(width).collect();
+            queue!(s, cursor::MoveTo(origin.0, origin.1 + 1), style::SetBackgroundColor(Color::DarkBlue), style::SetForegroundColor(Color::Grey), style::Print(note));
+        }
+        for (row, change) in self.comparison.changes.iter().enumerate().skip(self.scroll).take(self.height) {
+            let (mark, color) = match change.status {
+                Status::Added => ('+', Color::Green),
+                Status::Removed => ('-', Color::Red),
+                Status::Changed => ('~', Color::Yellow),
+            };
+            let bg = match row == self.selected {
+                true if focused => Color::Grey,
+                true => Color::Blue,
+                false => Color::DarkBlue,
+            };
+            let name: String = crate::util::sanitize(&change.path.display().to_string()).chars().take(width.saturating_sub(2)).collect();
+            let fg = if row == self.selected && focused { Color::Black } else { Color::Grey };
+            queue!(s,
+                cursor::MoveTo(origin.0, origin.1 + 1 + (row - self.scroll) as u16), style::SetBackgroundColor(bg),
+                style::SetForegroundColor(if bg == Color::Grey { Color::Black } else { color }), style::Print(format!("{} ", mark)),
+                style::SetForegroundColor(fg), style::Print(format!("{:<1$}", name, width.saturating_sub(2))),
+            );
+        }
+
+        let edge = if crate::config::get().screen_reader { ' ' } else { '│' };
+        queue!(s, style::SetBackgroundColor(Color::DarkBlue), style::SetForegroundColor(Color::Grey));
+        for y in 0..size.1 {
+            queue!(s, cursor::MoveTo(origin.0 + width as u16, origin.1 + y as u16), style::Print(edge));
+        }
+    }
+}
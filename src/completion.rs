@@ -0,0 +1,105 @@
+//! Completing paths typed in a buffer. Text before the cursor that starts with `./`, `../`, `~/`
+//! or `/` and a name is taken for a path, and the files and folders whose names start with the
+//! last part of it are offered in a popup under the cursor. Relative paths start from the folder
+//! of the buffer's file, or from the working folder for a buffer with none.
+
+use crossterm::{*, style::Color};
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// At most this many names are offered, the first in order.
+static MAX_NAMES: usize = 500;
+/// The popup shows this many names at once, scrolling through the rest.
+static ROWS: usize = 8;
+
+/// The names offered to finish a path with.
+pub struct Completion {
+    /// Where the last part of the path starts in the text, as a byte offset, and what of it has
+    /// been typed.
+    pub start: usize,
+    pub typed: String,
+    /// The names starting with what was typed, in order, with a `/` after those of folders.
+    pub names: Vec<String>,
+    pub selected: usize,
+}
+
+/// Offer the names that can finish the path ending at a byte offset of the text, if there is a
+/// path there and anything can. `file` is the buffer's file, whose folder relative paths start from.
+pub fn complete(text: &str, end: usize, file: Option<&Path>) -> Option<Completion> {
+    let line_start = text[..end].rfind('\n').map_or(0, |i| i + 1);
+    // The path goes back to the space, quote or bracket before it
+    let path_start = text[line_start..end].char_indices().rev()
+        .find(|&(_, c)| c.is_whitespace() || "\"'`()[]{}<>=,;:".contains(c))
+        .map_or(line_start, |(i, c)| line_start + i + c.len_utf8());
+    let path = &text[path_start..end];
+    // A lone `/` is more often division, and `//` a comment, than the start of a path
+    let absolute = path.starts_with('/') && path.len() > 1 && !path.starts_with("//");
+    if !(absolute || path.starts_with("./") || path.starts_with("../") || path.starts_with("~/")) {
+        return None;
+    }
+    let slash = path.rfind('/')?;
+    let (folder, typed) = (&path[..=slash], &path[slash + 1..]);
+
+    let mut names: Vec<String> = std::fs::read_dir(resolve(folder, file)?).ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden files only come up once a dot is typed
+            if !name.starts_with(typed) || (name.starts_with('.') && !typed.starts_with('.')) {
+                return None;
+            }
+            Some(if entry.path().is_dir() { name + "/" } else { name })
+        })
+        .collect();
+    names.sort();
+    names.truncate(MAX_NAMES);
+    // There is nothing to offer once the only name there is has been typed out
+    if names.is_empty() || names == [typed] {
+        return None;
+    }
+    Some(Completion { start: end - typed.len(), typed: typed.to_owned(), names, selected: 0 })
+}
+
+/// The folder a path's folder part is, taken from the folder of `file` if it is relative.
+fn resolve(folder: &str, file: Option<&Path>) -> Option<PathBuf> {
+    match folder.strip_prefix("~/") {
+        Some(rest) => Some(PathBuf::from(std::env::var_os("HOME")?).join(rest)),
+        None if folder.starts_with('/') => Some(PathBuf::from(folder)),
+        None => Some(file.and_then(Path::parent).map_or_else(|| PathBuf::from("."), Path::to_owned).join(folder)),
+    }
+}
+
+impl Completion {
+    /// Select the next name, or the one before, going round at the ends.
+    pub fn select(&mut self, next: bool) {
+        let count = self.names.len();
+        self.selected = match next {
+            true => (self.selected + 1) % count,
+            false => (self.selected + count - 1) % count,
+        };
+    }
+
+    /// Draw the names under the cell of the cursor, lined up with the name being typed, or over
+    /// it if there is no room below.
+    pub fn render<S: Write>(&self, s: &mut S, cursor: (u16, u16)) {
+        let (w, h) = crate::backend::size();
+        let rows = self.names.len().min(ROWS);
+        let width = (self.names.iter().map(|name| name.chars().count()).max().unwrap_or(0) + 2).min(w as usize).max(3);
+        let x = cursor.0.saturating_sub(self.typed.chars().count() as u16 + 1).min(w.saturating_sub(width as u16));
+        let y = match cursor.1 as usize + 1 + rows <= h as usize {
+            true => cursor.1 + 1,
+            false => cursor.1.saturating_sub(rows as u16),
+        };
+        let top = self.selected.saturating_sub(rows - 1);
+        for (row, (i, name)) in self.names.iter().enumerate().skip(top).take(rows).enumerate() {
+            let (bg, fg) = if i == self.selected { (Color::Black, Color::Grey) } else { (Color::Grey, Color::Black) };
+            let name: String = crate::util::sanitize(name).chars().take(width - 2).collect();
+            queue!(s,
+                cursor::MoveTo(x, y + row as u16),
+                style::SetBackgroundColor(bg), style::SetForegroundColor(fg),
+                style::Print(format!(" {:<1$} ", name, width - 2)),
+            );
+        }
+    }
+}
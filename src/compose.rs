@@ -0,0 +1,27 @@
+//! Dead keys: typing an accent and then a letter to get the letter with the accent on it, for
+//! keyboards and terminals that do not compose the two themselves.
+
+/// The accents that wait for a letter, each with the letters it goes on, in pairs of the plain
+/// letter and the accented one.
+static ACCENTS: &[(char, &str)] = &[
+    ('`', "aàeèiìoòuùAÀEÈIÌOÒUÙ"),
+    ('\'', "aáeéiíoóuúyýcćnńsśzźAÁEÉIÍOÓUÚYÝCĆNŃSŚZŹ"),
+    ('´', "aáeéiíoóuúyýcćnńsśzźAÁEÉIÍOÓUÚYÝCĆNŃSŚZŹ"),
+    ('^', "aâeêiîoôuûAÂEÊIÎOÔUÛ"),
+    ('~', "aãoõnñAÃOÕNÑ"),
+    ('"', "aäeëiïoöuüyÿAÄEËIÏOÖUÜ"),
+    ('¨', "aäeëiïoöuüyÿAÄEËIÏOÖUÜ"),
+    ('°', "aåuůAÅUŮ"),
+];
+
+/// Whether a character is an accent that waits for the letter it goes on.
+pub fn is_dead_key(c: char) -> bool {
+    ACCENTS.iter().any(|&(accent, _)| accent == c)
+}
+
+/// The letter with the accent on it, if there is such a character.
+pub fn compose(accent: char, letter: char) -> Option<char> {
+    let (_, pairs) = ACCENTS.iter().find(|&&(a, _)| a == accent)?;
+    let pairs: Vec<char> = pairs.chars().collect();
+    pairs.chunks(2).find(|pair| pair[0] == letter).map(|pair| pair[1])
+}
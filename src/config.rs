@@ -0,0 +1,466 @@
+//! User preferences, read once at startup from `$XDG_CONFIG_HOME/qedit/config` (or
+//! `~/.config/qedit/config`). The file is a plain list of `key = value` lines, where a line
+//! starting with `#` is a comment. A `#` anywhere else is part of the value. Unknown keys and malformed values are ignored, keeping the default.
+//!
+//! A folder opened as a project can have a `.qedit` file of its own in the same form, whose
+//! settings go over the user's for as long as the editor runs.
+
+use crossterm::style::Color;
+use lazy_static::*;
+
+use crate::abbreviations::Abbreviation;
+use crate::colors::ColorSupport;
+use crate::cursorshape::Shape;
+use crate::image::Protocol;
+use crate::indent::Indentation;
+use crate::theme::Theme;
+
+use std::path::{Path, PathBuf};
+use std::sync::{RwLock, RwLockReadGuard};
+
+lazy_static! {
+    static ref CONFIG: RwLock<Config> = RwLock::new(Config::load());
+}
+
+/// Returns the editor's configuration.
+pub fn get() -> RwLockReadGuard<'static, Config> {
+    CONFIG.read().unwrap()
+}
+
+/// The name of a project's config file, in the folder opened as the project.
+pub static PROJECT_FILE: &str = ".qedit";
+
+/// The keys a project's config file is not let set, as they run commands: any folder that was
+/// downloaded could otherwise run what it liked on being opened.
+static NOT_FOR_PROJECTS: &[&str] = &["check_command"];
+
+/// Read the config file of a project folder over the user's settings. Returns whether it has one.
+pub fn load_project(root: &Path) -> bool {
+    match std::fs::read_to_string(root.join(PROJECT_FILE)) {
+        Ok(contents) => {
+            CONFIG.write().unwrap().read(&contents, NOT_FOR_PROJECTS);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// When to hand copied text to the terminal emulator with an OSC 52 escape sequence.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Osc52 {
+    /// Only when no local clipboard program took the text, like when running over SSH.
+    Auto,
+    Always,
+    Never,
+}
+
+/// How far the rows a wrapped line goes on over are indented.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WrapIndent {
+    /// Not at all: they start at the left edge.
+    None,
+    /// As far as the line itself is indented.
+    Same,
+    /// By so many columns.
+    Hanging(usize),
+}
+
+pub struct Config {
+    pub osc52: Osc52,
+    /// The largest base64-encoded payload, in bytes, we will send in a single OSC 52 sequence.
+    /// Many terminals (and tmux) silently drop anything bigger than about 100 kB.
+    pub osc52_max_bytes: usize,
+    /// How many copied and cut snippets Edit → Paste from history offers.
+    pub clipboard_history: usize,
+    /// Whether the mouse can be used to place the cursor and select text. The terminal's own
+    /// selection then needs Shift held down, in most terminals.
+    pub mouse: bool,
+    /// How many lines are kept in view above and below the cursor as it moves.
+    pub scrolloff: usize,
+    /// How long, in milliseconds, menu mode waits for a key before listing the keys it takes.
+    /// `0` never lists them.
+    pub key_hint_delay: u64,
+    /// How long, in milliseconds, the mouse pointer rests on a diagnostic, a gutter mark or a title
+    /// cut short before a tooltip shows the whole of it. `0` shows no tooltips.
+    pub tooltip_delay: u64,
+    /// The most frames drawn in a second. Changes coming in faster are drawn together in the
+    /// next frame. `0` draws after every change as soon as there are no events waiting.
+    pub max_fps: u64,
+    /// Whether to draw for screen readers: no decorative frames or shading, changes of mode and
+    /// opened dialogs announced as plain lines at the bottom, and the terminal cursor kept where
+    /// typing would go, even in menu mode.
+    pub screen_reader: bool,
+    /// The colors the terminal can show: `truecolor`, `256`, `16` or `none`. Detected from the
+    /// environment unless set.
+    pub colors: Option<ColorSupport>,
+    /// Whether the text is drawn `dark` or `light`. Unless set, the terminal is asked for its
+    /// background color and the theme suiting it is picked. View → Light theme switches it.
+    pub theme: Option<Theme>,
+    /// The cursor's shape while editing text: `block`, `underline` or `bar`. The terminal's own
+    /// is kept unless set.
+    pub cursor_shape: Option<Shape>,
+    /// The cursor's shape in menus and dialogs, the same as while editing unless set.
+    pub menu_cursor_shape: Option<Shape>,
+    /// Whether the cursor blinks, once it has been given a shape.
+    pub cursor_blink: bool,
+    /// How images opened as files are drawn: with the `kitty` graphics protocol, as `sixel`s, or
+    /// `none` at all, describing them instead. Detected from the environment unless set.
+    pub images: Option<Protocol>,
+    /// The language of the menus and dialogs, like `de`. Taken from `LANG` unless set.
+    pub language: Option<String>,
+    /// The least severe messages written to the log file.
+    pub log_level: log::LevelFilter,
+    /// Whether to keep the undo history of files between sessions.
+    pub persistent_undo: bool,
+    /// The largest undo history file, in bytes, kept for a single file. The oldest changes are
+    /// forgotten to stay within it.
+    pub persistent_undo_max_bytes: usize,
+    /// Whether to go back to where the cursor was left in a file when it is opened again.
+    pub remember_positions: bool,
+    /// How many files' cursor positions are remembered. The files closed longest ago are forgotten
+    /// first.
+    pub remember_positions_max: usize,
+    /// Whether to open the workspaces and files that were open when the editor last quit, when it
+    /// is started without a file.
+    pub restore_session: bool,
+    /// Whether to keep untitled buffers when the editor quits, and offer to open them again next
+    /// time.
+    pub keep_untitled: bool,
+    /// Whether to save the modified buffers that have a file when the terminal loses focus, as
+    /// when switching to another window. Files changed on disk since are left alone.
+    pub autosave_on_focus_loss: bool,
+    /// Whether to offer the files and folders that finish a path as it is typed in a buffer.
+    pub complete_paths: bool,
+    /// How new buffers are indented, and opened files that give no clue of their own.
+    pub indentation: Indentation,
+    /// Whether to guess the indentation of opened files from their contents.
+    pub detect_indentation: bool,
+    /// Whether lines of Python and YAML files indented against the buffer's indentation are
+    /// marked in the gutter, with what is wrong after them.
+    pub indentation_warnings: bool,
+    /// Whether viewports start out showing line numbers. View → Line numbers toggles them.
+    pub line_numbers: bool,
+    /// Whether viewports start out wrapping long lines, rather than scrolling sideways.
+    pub word_wrap: bool,
+    /// How wrapped lines go on over the rows after their first: `none`, `same` as the line's own
+    /// indentation, or a number of columns.
+    pub wrap_indent: WrapIndent,
+    /// Whether viewports start out showing spaces as `·` and tabs as `→`.
+    pub show_whitespace: bool,
+    /// Whether viewports start out with a minimap of the buffer down their right side.
+    pub minimap: bool,
+    /// Whether the first lines of the functions and blocks the top of a view is in stay pinned
+    /// over it, as far as indentation tells them.
+    pub sticky_scope: bool,
+    /// Whether tiled viewports start out with a bar over them showing the folders, file and scopes
+    /// the cursor is in.
+    pub breadcrumbs: bool,
+    /// Whether typing an opening bracket puts in the closing one after the cursor too. Edit →
+    /// Auto-close brackets toggles it.
+    pub auto_close_brackets: bool,
+    /// Whether pasting several lines re-indents them to the indentation of the line they go into.
+    pub paste_reindent: bool,
+    /// Whether Enter indents the new line like the one before it, a level further after a line
+    /// opening a block, and a level less before a line closing one.
+    pub auto_indent: bool,
+    /// Patterns overriding the language's own of the lines after which the next is indented a
+    /// level further, set with `indent_increase.<extension> = pattern`. An empty pattern turns it
+    /// off for that extension.
+    pub indent_increase: Vec<(String, String)>,
+    /// Patterns overriding the language's own of the lines indented a level less than those
+    /// before them, as they are typed, set with `indent_decrease.<extension> = pattern`.
+    pub indent_decrease: Vec<(String, String)>,
+    /// Whether typed and pasted text is put in its composed form (NFC), so that an accent typed
+    /// as a combining mark joins the letter before it as one character.
+    pub normalize_input: bool,
+    /// Whether accents typed on their own wait for the letter they go on, for keyboards whose
+    /// dead keys the terminal does not compose. Edit → Dead keys for accents toggles it.
+    pub dead_keys: bool,
+    /// Whether to color brackets by how deeply they are nested.
+    pub bracket_colors: bool,
+    /// Whether comments and strings are drawn in colors of their own, in the languages told by
+    /// file extension.
+    pub syntax_colors: bool,
+    /// The background of TODO, FIXME, HACK and NOTE markers in comments.
+    pub marker_color: Color,
+    /// Whether to draw color literals like `#ff8800` on the color they describe.
+    pub color_swatches: bool,
+    /// Whether the columns of delimited values, like those of `.csv` files, are each drawn in a
+    /// color of their own.
+    pub rainbow_columns: bool,
+    /// The lines of followed files matching this pattern are highlighted, like `(?i)error|warn`.
+    pub tail_highlight: Option<String>,
+    /// The command checking the project for errors after each save, like `cargo check
+    /// --message-format=short`, whose `path:line:column: message` lines are shown on those lines.
+    pub check_command: Option<String>,
+    /// Whether the first diagnostic on a line is shown dimmed after its text.
+    pub inline_diagnostics: bool,
+    /// The column past which the characters of a line are drawn in red, as in a style guide's
+    /// limit, set with `line_length = 100`, or with `line_length.<extension> = 80` for files with
+    /// that extension alone. 0 sets no limit.
+    pub line_lengths: Vec<(Option<String>, usize)>,
+    /// Whether the status bar counts the lines going past the line length limit.
+    pub overlong_line_count: bool,
+    /// How Insert → Date and time writes the time, in the style of `strftime`.
+    pub date_format: String,
+    /// The name templates fill in for `{author}`, instead of the login name.
+    pub author: Option<String>,
+    /// The terminal window's title. `{filename}` is the focused file's name, `{path}` its full
+    /// path, and `{modified}` becomes ` •` while it has unsaved changes.
+    pub title_format: String,
+    /// The templates offered by Insert → Template, as their names and text. The config file sets
+    /// them with `template.<name> = text`, where `\n` starts a new line.
+    pub templates: Vec<(String, String)>,
+    /// Words expanded as they are typed, set with `abbreviation.<word> = text`, or with
+    /// `abbreviation.<extension>.<word> = text` for files with that extension alone.
+    pub abbreviations: Vec<Abbreviation>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            osc52: Osc52::Auto,
+            osc52_max_bytes: 100_000,
+            clipboard_history: 20,
+            mouse: true,
+            scrolloff: 3,
+            key_hint_delay: 600,
+            tooltip_delay: 600,
+            max_fps: 60,
+            screen_reader: false,
+            colors: None,
+            theme: None,
+            cursor_shape: None,
+            menu_cursor_shape: None,
+            cursor_blink: true,
+            images: None,
+            language: None,
+            log_level: log::LevelFilter::Info,
+            persistent_undo: true,
+            persistent_undo_max_bytes: 1_000_000,
+            remember_positions: true,
+            remember_positions_max: 1000,
+            restore_session: false,
+            keep_untitled: true,
+            autosave_on_focus_loss: false,
+            complete_paths: true,
+            indentation: Indentation::Tabs,
+            detect_indentation: true,
+            indentation_warnings: true,
+            line_numbers: true,
+            word_wrap: false,
+            wrap_indent: WrapIndent::None,
+            show_whitespace: false,
+            minimap: false,
+            sticky_scope: true,
+            breadcrumbs: false,
+            auto_close_brackets: true,
+            paste_reindent: true,
+            auto_indent: true,
+            indent_increase: Vec::new(),
+            indent_decrease: Vec::new(),
+            normalize_input: true,
+            dead_keys: false,
+            bracket_colors: true,
+            syntax_colors: true,
+            marker_color: Color::Yellow,
+            color_swatches: true,
+            rainbow_columns: true,
+            tail_highlight: None,
+            check_command: None,
+            inline_diagnostics: true,
+            line_lengths: Vec::new(),
+            overlong_line_count: false,
+            date_format: "%Y-%m-%d %H:%M".to_owned(),
+            title_format: "{filename}{modified} — QEdit".to_owned(),
+            author: None,
+            templates: vec![
+                ("File header".to_owned(), "// {filename}\n//\n// Created by {author} on {date}.\n// Copyright (c) {year} {author}\n\n".to_owned()),
+                ("Signature".to_owned(), "-- {author}, {date}".to_owned()),
+            ],
+            abbreviations: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// The line length limit for a file, going by its extension, if there is one.
+    pub fn line_length(&self, path: Option<&Path>) -> Option<usize> {
+        let extension = path.and_then(|p| p.extension()).map(|e| e.to_string_lossy().to_lowercase());
+        let for_file = self.line_lengths.iter().find(|(e, _)| e.is_some() && *e == extension);
+        for_file.or_else(|| self.line_lengths.iter().find(|(e, _)| e.is_none())).map(|&(_, n)| n).filter(|&n| n > 0)
+    }
+
+    /// Reads the configuration file, falling back to the defaults for anything it does not set.
+    pub fn load() -> Config {
+        let mut config = Config::default();
+        if let Some(contents) = config_dir().and_then(|dir| std::fs::read_to_string(dir.join("config")).ok()) {
+            config.read(&contents, &[]);
+        }
+        config
+    }
+
+    /// Set what the lines of a config file say, but for the keys left out.
+    fn read(&mut self, contents: &str, left_out: &[&str]) {
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+                match left_out.contains(&key.trim()) {
+                    true => log::warn!("{} cannot be set by a project's config file", key.trim()),
+                    false => self.set(key.trim(), value.trim()),
+                }
+            }
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "osc52" => match value {
+                "auto" => self.osc52 = Osc52::Auto,
+                "always" => self.osc52 = Osc52::Always,
+                "never" => self.osc52 = Osc52::Never,
+                _ => {}
+            },
+            "osc52_max_bytes" => if let Ok(n) = value.parse() { self.osc52_max_bytes = n },
+            "clipboard_history" => if let Ok(n) = value.parse() { self.clipboard_history = n },
+            "title_format" => self.title_format = value.to_owned(),
+            "mouse" => if let Ok(b) = value.parse() { self.mouse = b },
+            "scrolloff" => if let Ok(n) = value.parse() { self.scrolloff = n },
+            "key_hint_delay" => if let Ok(n) = value.parse() { self.key_hint_delay = n },
+            "tooltip_delay" => if let Ok(n) = value.parse() { self.tooltip_delay = n },
+            "max_fps" => if let Ok(n) = value.parse() { self.max_fps = n },
+            "screen_reader" => if let Ok(b) = value.parse() { self.screen_reader = b },
+            "colors" => self.colors = value.parse().ok(),
+            "theme" => self.theme = value.parse().ok(),
+            "cursor_shape" => self.cursor_shape = value.parse().ok(),
+            "menu_cursor_shape" => self.menu_cursor_shape = value.parse().ok(),
+            "cursor_blink" => if let Ok(b) = value.parse() { self.cursor_blink = b },
+            "images" => self.images = value.parse().ok(),
+            "language" => self.language = Some(value.to_owned()),
+            "log_level" => if let Ok(level) = value.parse() { self.log_level = level },
+            "persistent_undo" => if let Ok(b) = value.parse() { self.persistent_undo = b },
+            "persistent_undo_max_bytes" => if let Ok(n) = value.parse() { self.persistent_undo_max_bytes = n },
+            "remember_positions" => if let Ok(b) = value.parse() { self.remember_positions = b },
+            "remember_positions_max" => if let Ok(n) = value.parse() { self.remember_positions_max = n },
+            "restore_session" => if let Ok(b) = value.parse() { self.restore_session = b },
+            "keep_untitled" => if let Ok(b) = value.parse() { self.keep_untitled = b },
+            "autosave_on_focus_loss" => if let Ok(b) = value.parse() { self.autosave_on_focus_loss = b },
+            "complete_paths" => if let Ok(b) = value.parse() { self.complete_paths = b },
+            "indentation" => match value {
+                "tabs" => self.indentation = Indentation::Tabs,
+                _ => if let Ok(n @ 1..=16) = value.parse() { self.indentation = Indentation::Spaces(n) },
+            },
+            "detect_indentation" => if let Ok(b) = value.parse() { self.detect_indentation = b },
+            "indentation_warnings" => if let Ok(b) = value.parse() { self.indentation_warnings = b },
+            "line_numbers" => if let Ok(b) = value.parse() { self.line_numbers = b },
+            "word_wrap" => if let Ok(b) = value.parse() { self.word_wrap = b },
+            "wrap_indent" => match value {
+                "none" => self.wrap_indent = WrapIndent::None,
+                "same" => self.wrap_indent = WrapIndent::Same,
+                _ => if let Ok(n) = value.parse() { self.wrap_indent = WrapIndent::Hanging(n) },
+            },
+            "show_whitespace" => if let Ok(b) = value.parse() { self.show_whitespace = b },
+            "minimap" => if let Ok(b) = value.parse() { self.minimap = b },
+            "sticky_scope" => if let Ok(b) = value.parse() { self.sticky_scope = b },
+            "breadcrumbs" => if let Ok(b) = value.parse() { self.breadcrumbs = b },
+            "auto_close_brackets" => if let Ok(b) = value.parse() { self.auto_close_brackets = b },
+            "paste_reindent" => if let Ok(b) = value.parse() { self.paste_reindent = b },
+            "auto_indent" => if let Ok(b) = value.parse() { self.auto_indent = b },
+            "normalize_input" => if let Ok(b) = value.parse() { self.normalize_input = b },
+            "dead_keys" => if let Ok(b) = value.parse() { self.dead_keys = b },
+            "bracket_colors" => if let Ok(b) = value.parse() { self.bracket_colors = b },
+            "syntax_colors" => if let Ok(b) = value.parse() { self.syntax_colors = b },
+            "marker_color" => if let Ok(color) = value.parse() { self.marker_color = color },
+            "color_swatches" => if let Ok(b) = value.parse() { self.color_swatches = b },
+            "rainbow_columns" => if let Ok(b) = value.parse() { self.rainbow_columns = b },
+            "tail_highlight" => self.tail_highlight = Some(value.to_owned()).filter(|pattern| !pattern.is_empty()),
+            "check_command" => self.check_command = Some(value.to_owned()).filter(|command| !command.is_empty()),
+            "inline_diagnostics" => if let Ok(b) = value.parse() { self.inline_diagnostics = b },
+            "overlong_line_count" => if let Ok(b) = value.parse() { self.overlong_line_count = b },
+            "date_format" => self.date_format = value.to_owned(),
+            "author" => self.author = Some(value.to_owned()),
+            _ if key == "line_length" || key.starts_with("line_length.") => if let Ok(n) = value.parse() {
+                let extension = key.strip_prefix("line_length.").map(|e| e.trim().to_lowercase());
+                self.line_lengths.retain(|(e, _)| *e != extension);
+                self.line_lengths.push((extension, n));
+            },
+            _ if key.starts_with("indent_increase.") || key.starts_with("indent_decrease.") => {
+                let (rule, extension) = key.split_once('.').unwrap();
+                let rules = if rule == "indent_increase" { &mut self.indent_increase } else { &mut self.indent_decrease };
+                let extension = extension.trim().to_lowercase();
+                rules.retain(|(e, _)| *e != extension);
+                rules.push((extension, value.to_owned()));
+            }
+            _ if key.starts_with("template.") => {
+                let (name, text) = (key["template.".len()..].trim().to_owned(), unescape(value));
+                match self.templates.iter_mut().find(|(n, _)| *n == name) {
+                    Some(template) => template.1 = text,
+                    None => self.templates.push((name, text)),
+                }
+            }
+            _ if key.starts_with("abbreviation.") => {
+                let rest = key["abbreviation.".len()..].trim();
+                let (extension, word) = match rest.rsplit_once('.') {
+                    Some((extension, word)) => (Some(extension.to_lowercase()), word),
+                    None => (None, rest),
+                };
+                if !word.is_empty() && word.chars().all(crate::abbreviations::is_word_char) {
+                    self.abbreviations.retain(|a| !(a.extension == extension && a.word == word));
+                    self.abbreviations.push(Abbreviation { extension, word: word.to_owned(), expansion: unescape(value) });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Turn `\n`, `\t` and `\\` in a value into a newline, a tab and a backslash.
+fn unescape(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => { out.push('\n'); chars.next(); }
+            ('\\', Some('t')) => { out.push('\t'); chars.next(); }
+            ('\\', Some('\\')) => { out.push('\\'); chars.next(); }
+            (c, _) => out.push(c),
+        }
+    }
+    out
+}
+
+/// The directory holding the user's configuration files.
+pub fn config_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("qedit")),
+        _ => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("qedit")),
+    }
+}
+
+/// The directory holding files the editor keeps for itself, like its log.
+pub fn data_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("qedit")),
+        _ => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share").join("qedit")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_lines_starting_with_a_hash_are_comments() {
+        let mut config = Config::default();
+        config.read("# A comment\n  # Another = one\ntemplate.c = #include <stdio.h>\ntemplate.sh = #!/bin/sh\\n\ntitle_format = {name} #1\n", &[]);
+        let template = |name: &str| config.templates.iter().find(|(n, _)| n == name).map(|(_, text)| text.as_str());
+        assert_eq!(template("c"), Some("#include <stdio.h>"));
+        assert_eq!(template("sh"), Some("#!/bin/sh\n"));
+        assert_eq!(config.title_format, "{name} #1");
+        assert!(template("# Another").is_none());
+    }
+}
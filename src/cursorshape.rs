@@ -0,0 +1,66 @@
+//! The shape of the terminal's cursor, and whether it blinks, set with DECSCUSR escape
+//! sequences. It can differ between editing text and picking from menus and dialogs, as set with
+//! `cursor_shape` and `menu_cursor_shape`; unless either is set, the terminal's own is left be.
+
+use crossterm::{queue, style};
+
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// What the cursor looks like.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Shape {
+    Block,
+    Underline,
+    Bar,
+}
+
+impl std::str::FromStr for Shape {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Shape, ()> {
+        match s {
+            "block" => Ok(Shape::Block),
+            "underline" => Ok(Shape::Underline),
+            "bar" => Ok(Shape::Bar),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where the keys go, which the cursor's shape can tell apart.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    /// Typing into the text, the search bar or the terminal.
+    Edit,
+    /// Menus, and the dialogs they open.
+    Menu,
+}
+
+// The DECSCUSR parameter last sent, or 0 if none was, which is also what puts the terminal's own
+// cursor back.
+static SENT: AtomicU8 = AtomicU8::new(0);
+
+/// Give the cursor the shape configured for a mode, if it has not got it already.
+pub fn apply<S: Write>(s: &mut S, mode: Mode) {
+    let config = crate::config::get();
+    let shape = match mode {
+        Mode::Edit => config.cursor_shape,
+        Mode::Menu => config.menu_cursor_shape.or(config.cursor_shape),
+    };
+    // Each shape has a blinking code and a steady one after it
+    let code = match shape {
+        Some(shape) => 1 + 2 * shape as u8 + !config.cursor_blink as u8,
+        None => return,
+    };
+    if SENT.swap(code, Ordering::Relaxed) != code {
+        queue!(s, style::Print(format!("\x1b[{} q", code)));
+    }
+}
+
+/// Put back the terminal's own cursor, as the editor quits.
+pub fn restore<S: Write>(s: &mut S) {
+    if SENT.swap(0, Ordering::Relaxed) != 0 {
+        queue!(s, style::Print("\x1b[0 q"));
+    }
+}
@@ -0,0 +1,51 @@
+//! Errors and warnings about the project's files, from the checker set with `check_command`, like
+//! `cargo check --message-format=short` or `eslint --format unix .`. It runs in the working folder
+//! after each save, or with Go → Check project, and each line it prints in the form
+//! `path:line:column: message` or `path:line: message` is taken for a diagnostic. The first one on
+//! a line of an open file is shown after the line's text, unless `inline_diagnostics` is off.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Something the checker had to say about a line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    /// Zero-based.
+    pub line: usize,
+    pub message: String,
+}
+
+/// Run the checker in a folder, and gather what it said from its output and errors together.
+/// It failing is taken to mean it found errors; only it not running at all is an error here.
+pub fn check(command: &str, root: &Path) -> io::Result<Vec<Diagnostic>> {
+    let output = crate::ranges::shell(command).current_dir(root).stdin(std::process::Stdio::null()).output()?;
+    let text = String::from_utf8_lossy(&output.stdout).into_owned() + &String::from_utf8_lossy(&output.stderr);
+    Ok(parse(&text, root))
+}
+
+/// The diagnostics in a checker's output, with their paths made full from the folder it ran in.
+pub fn parse(output: &str, root: &Path) -> Vec<Diagnostic> {
+    output.lines().filter_map(|line| parse_line(line, root)).collect()
+}
+
+fn parse_line(line: &str, root: &Path) -> Option<Diagnostic> {
+    // The path ends at the first colon followed by a line number, which leaves Windows drive
+    // letters in it
+    let (path, rest) = line.match_indices(':')
+        .map(|(i, _)| (&line[..i], &line[i + 1..]))
+        .find(|(path, rest)| !path.trim().is_empty() && rest.starts_with(|c: char| c.is_ascii_digit()))?;
+    let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+    let number: usize = rest[..digits].parse().ok()?;
+    let mut message = rest[digits..].strip_prefix(':')?;
+    // Then the column, if it is given
+    if let Some(after) = message.find(|c: char| !c.is_ascii_digit()).filter(|&i| i > 0 && message[i..].starts_with(':')) {
+        message = &message[after + 1..];
+    }
+    let message = message.trim();
+    if number == 0 || message.is_empty() {
+        return None;
+    }
+    let path = root.join(path.trim());
+    Some(Diagnostic { path: path.canonicalize().unwrap_or(path), line: number - 1, message: message.to_owned() })
+}
@@ -0,0 +1,103 @@
+//! The differences between two texts, line by line.
+
+/// How many lines of context are kept around each change in a unified diff.
+static CONTEXT: usize = 3;
+/// The most pairs of differing lines compared one by one. Beyond it, the differing part is
+/// taken as removed and added whole, rather than use up time and memory on it.
+static MAX_COMPARISONS: usize = 4_000_000;
+
+/// Where a line of a diff comes from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Change {
+    /// In both texts.
+    Same,
+    /// Only in the old text.
+    Removed,
+    /// Only in the new text.
+    Added,
+}
+
+/// The lines of both texts in order, each marked with which of them it is in. Lines that are in
+/// both come first among their neighbours, then the removed ones, then the added ones.
+pub fn lines<'a>(old: &'a str, new: &'a str) -> Vec<(Change, &'a str)> {
+    let (old, new): (Vec<&str>, Vec<&str>) = (old.lines().collect(), new.lines().collect());
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (a, b) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let mut diff: Vec<(Change, &str)> = old[..prefix].iter().map(|l| (Change::Same, *l)).collect();
+    if a.len() * b.len() > MAX_COMPARISONS {
+        diff.extend(a.iter().map(|l| (Change::Removed, *l)));
+        diff.extend(b.iter().map(|l| (Change::Added, *l)));
+    } else {
+        // The longest common subsequence of what is left, from the end of both
+        let width = b.len() + 1;
+        let mut common = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                common[i * width + j] = match a[i] == b[j] {
+                    true => common[(i + 1) * width + j + 1] + 1,
+                    false => common[(i + 1) * width + j].max(common[i * width + j + 1]),
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                diff.push((Change::Same, a[i]));
+                i += 1;
+                j += 1;
+            } else if j == b.len() || (i < a.len() && common[(i + 1) * width + j] >= common[i * width + j + 1]) {
+                diff.push((Change::Removed, a[i]));
+                i += 1;
+            } else {
+                diff.push((Change::Added, b[j]));
+                j += 1;
+            }
+        }
+    }
+    diff.extend(old[old.len() - suffix..].iter().map(|l| (Change::Same, *l)));
+    diff
+}
+
+/// The differences between two texts in the unified format of `diff -u`, with the names given
+/// for each. Empty if they have the same lines.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let diff = lines(old, new);
+    let changed: Vec<usize> = (0..diff.len()).filter(|&i| diff[i].0 != Change::Same).collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    // Changes close enough together share a hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changed {
+        let (start, end) = (i.saturating_sub(CONTEXT), (i + CONTEXT + 1).min(diff.len()));
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (start, end) in hunks {
+        let in_old = |range: std::ops::Range<usize>| diff[range].iter().filter(|(c, _)| *c != Change::Added).count();
+        let in_new = |range: std::ops::Range<usize>| diff[range].iter().filter(|(c, _)| *c != Change::Removed).count();
+        let (old_len, new_len) = (in_old(start..end), in_new(start..end));
+        // Empty ranges are numbered by the line before them
+        let old_start = in_old(0..start) + (old_len > 0) as usize;
+        let new_start = in_new(0..start) + (new_len > 0) as usize;
+        out.push_str(&format!("@@ -{},{} +{},{} @@\n", old_start, old_len, new_start, new_len));
+        for (change, line) in &diff[start..end] {
+            let mark = match change {
+                Change::Same => ' ',
+                Change::Removed => '-',
+                Change::Added => '+',
+            };
+            out.push(mark);
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
@@ -0,0 +1,167 @@
+//! A buffer as it is shared by the viewports showing it. The text, its undo history and its
+//! marks belong to the document, while each viewport keeps its own cursor, selections and
+//! scrolling, so a file can be shown beside itself. Every open document is kept in the
+//! `BufferStore`, and can stay open with no viewport showing it.
+
+use crate::brackets::BracketDepths;
+use crate::history::History;
+use crate::lines::LineIndex;
+use crate::marks::Marks;
+use crate::syntax::Syntax;
+
+use scribe::buffer::{Position, Range};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+pub struct Document {
+    /// Unique for the session, like a viewport's id.
+    pub id: usize,
+    pub buffer: scribe::Buffer,
+    pub history: History,
+    pub marks: Marks,
+    pub bracket_depths: BracketDepths,
+    pub syntax: Syntax,
+    /// Where the lines of the buffer start, moved along with each edit.
+    pub line_index: LineIndex,
+    /// What separates the values of the text, when picked rather than told from the file.
+    pub delimiter: Option<char>,
+    /// When the file was last modified, as of when it was opened or last saved.
+    pub disk_modified: Option<SystemTime>,
+}
+
+impl Document {
+    /// Take over a buffer, picking up the undo history kept for its file if there is one.
+    fn new(id: usize, buffer: scribe::Buffer) -> Document {
+        let text = buffer.data();
+        let history = buffer.path.as_ref().and_then(|path| History::load(path, &text)).unwrap_or_else(|| History::new(text));
+        let disk_modified = buffer.path.as_deref().and_then(modified_time);
+        let syntax = Syntax::new(buffer.path.as_deref());
+        Document {
+            id,
+            buffer,
+            history,
+            marks: Marks::default(),
+            bracket_depths: BracketDepths::default(),
+            syntax,
+            line_index: LineIndex::default(),
+            delimiter: None,
+            disk_modified,
+        }
+    }
+
+    /// The file's name, or "Untitled" if it has none yet.
+    pub fn name(&self) -> String {
+        self.buffer.file_name().unwrap_or_else(|| "Untitled".to_owned())
+    }
+
+    /// What separates the values of the text, if it is taken for delimited values: the delimiter
+    /// picked for it, or the one told from a file of delimited values.
+    pub fn delimiter(&self) -> Option<char> {
+        match self.delimiter {
+            Some(delimiter) => Some(delimiter),
+            None if crate::table::is_delimited(self.buffer.path.as_deref()) => Some(crate::table::delimiter(self.buffer.path.as_deref(), &self.buffer.data())),
+            None => None,
+        }
+    }
+
+    /// How many lines the buffer has, going by its line index.
+    pub fn line_count(&mut self) -> usize {
+        let text = self.buffer.data();
+        self.line_index.lines(&text).len()
+    }
+
+    /// Whether the buffer has changes that are not saved to disk.
+    pub fn is_modified(&self) -> bool {
+        self.buffer.path.is_none() || !self.history.is_saved()
+    }
+
+    /// Whether the file was changed by someone else since it was opened or last saved.
+    pub fn changed_on_disk(&self) -> bool {
+        match (&self.buffer.path, self.disk_modified) {
+            (Some(path), Some(then)) => modified_time(path).is_some_and(|now| now != then),
+            _ => false,
+        }
+    }
+
+    /// Take the buffer as it is now for what its file holds, once it has been saved.
+    pub fn mark_saved(&mut self) {
+        self.history.mark_saved();
+        if let Some(path) = self.buffer.path.clone() {
+            self.history.persist(&path);
+            self.disk_modified = modified_time(&path);
+        }
+    }
+
+    /// Read the file again, as a change that can be undone like any other, and take it as saved.
+    pub fn reload(&mut self) -> io::Result<()> {
+        let Some(path) = self.buffer.path.clone() else { return Ok(()) };
+        let text = std::fs::read_to_string(&path)?;
+        let old = self.buffer.data();
+        self.buffer.delete_range(Range::new(Position::new(), crate::util::position_at(&old, old.len())));
+        self.buffer.cursor.move_to(Position::new());
+        self.buffer.insert(text);
+        if let Some((line, edit)) = self.history.commit(self.buffer.data()) {
+            self.marks.lines_changed(line, edit.inserted.matches('\n').count() as isize - edit.removed.matches('\n').count() as isize);
+            self.line_index.edit(&edit);
+            self.bracket_depths.invalidate(line);
+            self.syntax.edit(self.history.text(), line, &edit);
+        }
+        self.mark_saved();
+        Ok(())
+    }
+
+    /// Record the buffer as it is now in the undo history. Call this once an edit is complete.
+    pub fn edited(&mut self) {
+        if let Some((line, edit)) = self.history.commit(self.buffer.data()) {
+            self.line_index.edit(&edit);
+            self.bracket_depths.invalidate(line);
+            self.syntax.edit(self.history.text(), line, &edit);
+        }
+    }
+}
+
+/// When a file was last modified, if it can be told.
+pub fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// The documents open in the editor, by id, in the order they were opened.
+#[derive(Default)]
+pub struct BufferStore {
+    documents: BTreeMap<usize, Rc<RefCell<Document>>>,
+    next_id: usize,
+}
+
+impl BufferStore {
+    /// Open a buffer as a new document.
+    pub fn add(&mut self, buffer: scribe::Buffer) -> Rc<RefCell<Document>> {
+        self.next_id += 1;
+        let document = Rc::new(RefCell::new(Document::new(self.next_id, buffer)));
+        self.documents.insert(self.next_id, document.clone());
+        document
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Rc<RefCell<Document>>> {
+        self.documents.get(&id)
+    }
+
+    /// The document of a file, if it is open.
+    pub fn find(&self, path: &Path) -> Option<&Rc<RefCell<Document>>> {
+        let path = path.canonicalize().ok()?;
+        self.documents.values().find(|d| d.borrow().buffer.path.as_ref().is_some_and(|p| p.canonicalize().ok().as_ref() == Some(&path)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rc<RefCell<Document>>> {
+        self.documents.values()
+    }
+
+    /// Close a document. Any viewports still showing it keep it alive until they are closed too.
+    pub fn remove(&mut self, id: usize) -> Option<Rc<RefCell<Document>>> {
+        self.documents.remove(&id)
+    }
+}
@@ -0,0 +1,2313 @@
+//! The editor as a whole: everything on screen, and what each event does to it.
+
+use crossterm::{*, event::{KeyEvent, KeyCode, Event, MouseButton, MouseEvent, MouseEventKind}};
+use regex::Regex;
+
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crate::align;
+use crate::backend;
+use crate::breadcrumbs::{self, Kind, Segment};
+use crate::calc;
+use crate::clock::DateTime;
+use crate::compare::{Base, ComparePanel};
+use crate::compose;
+use crate::config;
+use crate::cursorshape::{self, Mode};
+use crate::diagnostics::Diagnostic;
+use crate::diff;
+use crate::document::Document;
+use crate::filetree::FileTree;
+use crate::frames::Frames;
+use crate::indent;
+use crate::jobs::{self, JobEvent, JobOutput, JobPool};
+use crate::keymap;
+use crate::locale::{self, tr};
+use crate::logging;
+use crate::perf::{Measurement, PerfOverlay};
+use crate::ranges;
+use crate::menu::{self, Action, Menu, MenuAction, MenuBar};
+use crate::search::{self, Counter, RecentList, Search};
+use crate::session;
+use crate::statusbar::StatusBar;
+use crate::table;
+use crate::tail::Tail;
+use crate::templates;
+use crate::terminal::TerminalPanel;
+use crate::theme::{self, Theme};
+use crate::tooltip;
+use crate::transform::Transform;
+use crate::unicode;
+use crate::untitled;
+use crate::util;
+use crate::viewport::{LineAt, Viewport, ViewportData, ViewportManager};
+use crate::welcome;
+
+/// How often the main loop wakes up to do background work when no input arrives.
+static TICK_RATE: Duration = Duration::from_millis(100);
+/// How quickly clicks must follow each other on the same cell to count as a double or triple click.
+static MULTI_CLICK_TIME: Duration = Duration::from_millis(400);
+/// How often untitled buffers are written out to be kept, if they changed.
+static UNTITLED_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The panels that can take the keys instead of the viewports.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Panel {
+    FileTree,
+    Terminal,
+    Compare,
+}
+
+/// Where everything goes on the screen, as origins and sizes.
+struct Layout {
+    /// The area of the viewport manager, which counts one row more than it draws on.
+    viewports: ((u16, u16), (usize, usize)),
+    file_tree: Option<((u16, u16), (usize, usize))>,
+    terminal: Option<((u16, u16), (usize, usize))>,
+    compare: Option<((u16, u16), (usize, usize))>,
+}
+
+pub struct Editor {
+    pub viewport_manager: ViewportManager,
+    pub menu_bar: MenuBar,
+    pub status_bar: StatusBar,
+    pub in_menu_mode: bool,
+    pub jobs: JobPool,
+    pub perf: PerfOverlay,
+    /// The last search, shared by every viewport.
+    pub search: Search,
+    /// Whether mouse input is asked for from the terminal.
+    pub mouse: bool,
+    /// Patterns searched for before, kept across sessions.
+    pub recent_searches: RecentList,
+    /// Replacements used before, kept across sessions.
+    pub recent_replacements: RecentList,
+    /// The terminal window's title, as last set.
+    window_title: String,
+    /// When and where the mouse was last clicked, and how many clicks in a row that made.
+    last_click: Option<(Instant, (u16, u16), usize)>,
+    /// When Esc last put the editor in menu mode, and whether the keys it takes have been listed
+    /// since. Any other key press forgets it.
+    menu_hint: Option<(Instant, bool)>,
+    // Where the mouse pointer was last seen and when, and whether its tooltip is showing.
+    pointer: Option<(Instant, (u16, u16), bool)>,
+    /// The files of the working folder down the left side, when shown.
+    pub file_tree: Option<FileTree>,
+    /// The panel for running commands along the bottom, when shown.
+    pub terminal: Option<TerminalPanel>,
+    /// The files that differ between two folders, below the file tree if that is shown too.
+    pub compare: Option<ComparePanel>,
+    /// The panel keys go to, if not the viewports.
+    panel_focus: Option<Panel>,
+    /// Whether accents wait for the letter they go on (see `compose`).
+    pub dead_keys: bool,
+    /// The accent typed with dead keys on, waiting for its letter.
+    compose: Option<char>,
+    /// What the last check of the project found, kept for the files opened since.
+    diagnostics: Vec<Diagnostic>,
+    /// The files this session keeps its untitled buffers in, the texts last written to them, and
+    /// when that was last looked into.
+    untitled_files: Vec<std::path::PathBuf>,
+    untitled_texts: Vec<String>,
+    untitled_saved: Instant,
+    /// The file the editor was started with `--wait` to edit, as for Git or `$VISUAL`, which it
+    /// quits once that is closed.
+    pub waiting_for: Option<std::path::PathBuf>,
+    redraw: bool,
+}
+
+/// Make a folder opened as a project the working folder, that the terminal and the project's
+/// searches and checks start from, and read its config file over the user's settings before
+/// anything takes them. Returns whether it has a config file.
+pub fn enter_folder(root: &std::path::Path) -> std::io::Result<bool> {
+    std::env::set_current_dir(root)?;
+    Ok(config::load_project(root))
+}
+
+impl Editor {
+    /// Create an editor filling the terminal, with no viewports open.
+    pub fn new() -> Editor {
+        let size = backend::size();
+        Editor {
+            viewport_manager: ViewportManager::new((0, 1), (size.0 as usize, size.1 as usize - 1)),
+            menu_bar: default_menu_bar(),
+            status_bar: StatusBar::default(),
+            in_menu_mode: false,
+            jobs: JobPool::default(),
+            perf: PerfOverlay::default(),
+            search: Search::default(),
+            mouse: config::get().mouse,
+            recent_searches: RecentList::load("search_history"),
+            recent_replacements: RecentList::load("replace_history"),
+            window_title: String::new(),
+            last_click: None,
+            menu_hint: None,
+            pointer: None,
+            file_tree: None,
+            terminal: None,
+            compare: None,
+            panel_focus: None,
+            dead_keys: config::get().dead_keys,
+            compose: None,
+            diagnostics: Vec::new(),
+            untitled_files: Vec::new(),
+            untitled_texts: Vec::new(),
+            untitled_saved: Instant::now(),
+            waiting_for: None,
+            redraw: true,
+        }
+    }
+
+    /// Open a viewport for the buffer, and focus it.
+    pub fn open_buffer(&mut self, buf: scribe::Buffer) {
+        let path = buf.path.clone();
+        let index = self.viewport_manager.open_buffer(buf);
+        self.viewport_manager.focus_viewport(index);
+        self.show_diagnostics(path.as_deref());
+    }
+
+    /// Open a folder as the project: list its files down the left, with the keys going to the
+    /// list, and show its start screen. The folder should be entered first (see `enter_folder`).
+    pub fn open_folder(&mut self, root: std::path::PathBuf, has_config: bool) {
+        let root = root.canonicalize().unwrap_or(root);
+        self.file_tree = Some(FileTree::new(root.clone()));
+        let width = self.layout(backend::size()).viewports.1.0.saturating_sub(2);
+        self.viewport_manager.open_page(welcome::page(&root, has_config, width));
+        self.panel_focus = Some(Panel::FileTree);
+    }
+
+    /// Check the project with the configured checker in the background, if there is one.
+    fn check_project(&mut self) {
+        let Some(command) = config::get().check_command.clone() else { return };
+        match std::env::current_dir() {
+            Ok(root) => self.jobs.spawn(move |progress| jobs::check(command, root, progress)),
+            Err(e) => log::warn!("Could not tell which directory to check: {}", e),
+        }
+    }
+
+    /// Attach what the last check found to the open document of a file, or to every open
+    /// document if none is given.
+    fn show_diagnostics(&mut self, path: Option<&std::path::Path>) {
+        for document in self.viewport_manager.buffers.iter() {
+            let mut document = document.borrow_mut();
+            let Some(own) = document.buffer.path.as_ref().map(|p| p.canonicalize().unwrap_or_else(|_| p.clone())) else { continue };
+            if path.is_some_and(|path| path.canonicalize().ok().as_ref() != Some(&own)) {
+                continue;
+            }
+            let found = self.diagnostics.iter().filter(|d| d.path == own).map(|d| (d.line, d.message.clone()));
+            document.marks.set_diagnostics(found);
+        }
+    }
+
+    /// Write every buffer with unsaved changes to the recovery folder. Returns a message
+    /// saying where each one went, for the user. Meant for when the editor has panicked, so this
+    /// tries its best to carry on if some buffer turns out to be broken.
+    pub fn write_recovery_files(&mut self) -> String {
+        let mut report = Vec::new();
+        // Every document, including those no viewport shows any more
+        for document in self.viewport_manager.buffers.iter() {
+            let document = match document.try_borrow() {
+                Ok(document) if document.is_modified() => document,
+                _ => continue,
+            };
+            let name = document.name();
+            let text = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| document.buffer.data()));
+            let line = match text.map(|text| crate::recovery::write(&name, &text)) {
+                Ok(Ok(path)) => format!("{} was saved to {}", name, path.display()),
+                Ok(Err(e)) => format!("{} could not be saved: {}", name, e),
+                Err(_) => format!("{} could not be saved: its buffer is broken", name),
+            };
+            log::error!("{}", line);
+            report.push(line);
+        }
+        if report.is_empty() {
+            "There were no unsaved changes.".to_owned()
+        } else {
+            report.join("\n")
+        }
+    }
+
+    /// Handle events from the terminal until the user quits.
+    pub fn run<S: Write>(&mut self, s: &mut S) {
+        // Untitled buffers kept from before are offered over the editor as it first shows, unless
+        // it was only started to edit one file
+        self.render(s);
+        if self.waiting_for.is_none() {
+            self.restore_untitled(s);
+        }
+        let mut frames = Frames::new(config::get().max_fps);
+        loop {
+            let waiting = backend::poll(Duration::from_millis(0));
+            if self.redraw && frames.due(waiting) {
+                self.redraw = false;
+                self.render(s);
+                if let Some(latency) = frames.drawn() {
+                    self.perf.key_shown(latency);
+                }
+            }
+
+            // Wait for input, but only until the frame asked for can be drawn, or else for so long,
+            // so that things can happen while the user is idle.
+            if !waiting {
+                let timeout = if self.redraw { frames.until_due() } else { TICK_RATE };
+                if !backend::poll(timeout) {
+                    if !self.redraw {
+                        self.redraw = self.tick(s);
+                    }
+                    continue;
+                }
+            }
+            self.redraw = true; // Nearly every event changes what is on screen
+
+            let event = backend::read();
+            frames.handled(matches!(event, Event::Key(_)));
+            if !self.handle_event(s, event) || self.waiting_for.as_deref().is_some_and(|path| !self.viewport_manager.shows(path)) {
+                break;
+            }
+        }
+        self.viewport_manager.remember_positions();
+        if self.waiting_for.is_none() {
+            session::save(&self.viewport_manager.workspace_files(), self.viewport_manager.workspace);
+        }
+        self.keep_untitled();
+    }
+
+    /// Save every modified buffer that has a file, as when the terminal loses focus. A file
+    /// changed on disk since it was read is left alone, so as not to overwrite that unasked.
+    fn save_modified_files(&mut self) {
+        let mut saved = Vec::new();
+        for document in self.viewport_manager.buffers.iter() {
+            let mut document = document.borrow_mut();
+            if document.buffer.path.is_none() || !document.is_modified() || document.changed_on_disk() {
+                continue;
+            }
+            match document.buffer.save() {
+                Ok(()) => {
+                    document.mark_saved();
+                    saved.push(document.name());
+                }
+                Err(e) => log::error!("Could not save {}: {}", document.name(), e),
+            }
+        }
+        if !saved.is_empty() {
+            self.status_bar.set_message(format!("Saved {}", saved.join(", ")));
+            self.check_project();
+        }
+    }
+
+    /// Read the files changed on disk since they were read or saved again, as when the terminal
+    /// gets focus back. Those with changes of their own here are only pointed out.
+    fn read_changed_files(&mut self) {
+        let (mut reloaded, mut conflicting) = (Vec::new(), Vec::new());
+        for document in self.viewport_manager.buffers.iter() {
+            let mut document = document.borrow_mut();
+            if !document.changed_on_disk() {
+                continue;
+            }
+            if document.is_modified() {
+                conflicting.push(document.name());
+                continue;
+            }
+            match document.reload() {
+                Ok(()) => reloaded.push((document.id, document.name())),
+                Err(e) => log::warn!("Could not read {} again: {}", document.name(), e),
+            }
+        }
+        // What was selected may be gone
+        for viewport in self.viewport_manager.all_viewports_mut() {
+            if viewport.document().is_some_and(|document| reloaded.iter().any(|&(id, _)| id == document.id)) {
+                viewport.selection_anchor = None;
+                viewport.extra_selections.clear();
+            }
+        }
+        if !conflicting.is_empty() {
+            self.status_bar.set_message(format!("{} was changed on disk", conflicting.join(", ")));
+        } else if !reloaded.is_empty() {
+            let names: Vec<String> = reloaded.into_iter().map(|(_, name)| name).collect();
+            self.status_bar.set_message(format!("Read {} again, as it was changed on disk", names.join(", ")));
+        }
+    }
+
+    /// Whether a file has changes that were not saved, as when the user quit without saving them.
+    pub fn unsaved_changes(&self) -> bool {
+        self.viewport_manager.buffers.iter().any(|document| {
+            let document = document.borrow();
+            document.buffer.path.is_some() && document.is_modified()
+        })
+    }
+
+    /// Offer to open the untitled buffers kept by earlier sessions again, if there are any.
+    pub fn restore_untitled<S: Write>(&mut self, s: &mut S) {
+        let kept = untitled::load();
+        if kept.is_empty() {
+            return;
+        }
+        let choices = [
+            format!("{} ({})", tr("Open them again"), kept.len()),
+            tr("Discard them"),
+            tr("Ask again next time"),
+        ];
+        let (paths, texts): (Vec<_>, Vec<_>) = kept.into_iter().unzip();
+        match util::choose(s, "Untitled buffers from last time", &choices, 0) {
+            Some(0) => {
+                // They take the place of the empty buffer the editor starts with
+                let manager = &mut self.viewport_manager;
+                if manager.viewports.len() == 1 && manager.viewports[0].document().is_some_and(|d| d.buffer.path.is_none() && d.buffer.data().is_empty()) {
+                    manager.close_viewport(0);
+                }
+                for (n, text) in texts.into_iter().enumerate() {
+                    self.open_text((format!("Untitled-{} (restored)", n + 1), text));
+                }
+                untitled::forget(&paths);
+            }
+            Some(1) => untitled::forget(&paths),
+            _ => {}
+        }
+    }
+
+    /// Write the untitled buffers out to be kept for the next session, if `keep_untitled` is on
+    /// and they changed since the last time.
+    fn keep_untitled(&mut self) {
+        self.untitled_saved = Instant::now();
+        if !config::get().keep_untitled {
+            return;
+        }
+        let texts: Vec<String> = self.viewport_manager.buffers.iter()
+            .map(|document| document.borrow())
+            .filter(|document| document.buffer.path.is_none())
+            .map(|document| document.buffer.data())
+            .filter(|text| !text.is_empty())
+            .collect();
+        if texts == self.untitled_texts {
+            return;
+        }
+        match untitled::save(&texts, &self.untitled_files) {
+            Ok(files) => {
+                self.untitled_files = files;
+                self.untitled_texts = texts;
+            }
+            Err(e) => log::warn!("Could not keep the untitled buffers: {}", e),
+        }
+    }
+
+    /// Open the workspaces and files of the last session again, if `restore_session` is on and
+    /// there is one. Returns whether there was.
+    pub fn restore_session(&mut self) -> bool {
+        let Some((workspaces, active)) = session::load() else { return false };
+        let mut switch_to = 0;
+        for (i, (name, files)) in workspaces.into_iter().enumerate().filter(|(_, (_, files))| !files.is_empty()) {
+            let manager = &mut self.viewport_manager;
+            match manager.viewports.is_empty() {
+                true => manager.workspaces[manager.workspace].name = name,
+                false => {
+                    let index = manager.add_workspace(name);
+                    manager.switch_workspace(index);
+                }
+            }
+            if i == active {
+                switch_to = manager.workspace;
+            }
+            for (path, compact) in files {
+                self.open_file(path);
+                if let Some(viewport) = self.viewport_manager.get_focused_viewport_mut() {
+                    viewport.compact = compact;
+                }
+            }
+        }
+        self.viewport_manager.switch_workspace(switch_to);
+        true
+    }
+
+    /// Handle synthetic events, as if the user had entered them, then wait for any background
+    /// jobs they started and render the result. Any dialog opened along the way takes its input
+    /// from the same events. Meant for headless sessions (see `backend::start_headless`).
+    /// Returns false if the user quit.
+    pub fn feed<S: Write, I: IntoIterator<Item = Event>>(&mut self, s: &mut S, events: I) -> bool {
+        backend::push_events(events);
+        while let Some(event) = backend::next_queued_event() {
+            if !self.handle_event(s, event) {
+                return false;
+            }
+        }
+        while let Some(event) = self.jobs.next_event() {
+            self.handle_job_event(s, event);
+        }
+        self.render(s);
+        true
+    }
+
+    pub fn render<S: Write>(&mut self, s: &mut S) {
+        let measurement = Measurement::start();
+        let size = backend::size();
+        let zen = self.viewport_manager.view.zen;
+        self.viewport_manager.retitle();
+
+        if self.viewport_manager.viewports.is_empty() { // If no open editors
+            if self.panel_focus.is_none() {
+                self.in_menu_mode = true;
+            }
+
+            queue!(s, style::SetForegroundColor(style::Color::White), style::SetBackgroundColor(style::Color::Black));
+            let shade = if config::get().screen_reader { " " } else { "▒" };
+            for line in 0..size.1 {
+                queue!(s, cursor::MoveTo(0, 1 + line), style::Print(shade.repeat(size.0 as usize)));
+            }
+        }
+
+        // Set the default terminal colors
+        // TODO: We need better coloring infrastructure
+        queue!(s, style::SetForegroundColor(style::Color::White), style::SetBackgroundColor(style::Color::Blue));
+
+        queue!(s, cursor::Hide);
+
+        // Update the menu bar and the status bar, before the focused viewport places the cursor.
+        // In zen mode they only show in menu mode, over the text.
+        if !zen {
+            self.render_bars(s, size);
+        }
+
+        // Update the panels, then all viewports beside them, so that the cursor ends up in
+        // whichever has focus
+        let layout = self.layout(size);
+        let focused_panel = self.panel_focus.filter(|_| !self.in_menu_mode);
+        if let (Some(tree), Some((origin, size))) = (&mut self.file_tree, layout.file_tree) {
+            tree.render(s, origin, size, focused_panel == Some(Panel::FileTree));
+        }
+        if let (Some(compare), Some((origin, size))) = (&mut self.compare, layout.compare) {
+            compare.render(s, origin, size, focused_panel == Some(Panel::Compare));
+        }
+        let terminal_cursor = match (&mut self.terminal, layout.terminal) {
+            (Some(terminal), Some((origin, size))) => Some(terminal.render(s, origin, size)),
+            _ => None,
+        };
+        (self.viewport_manager.origin, self.viewport_manager.size) = layout.viewports;
+        self.viewport_manager.render(s, !self.in_menu_mode && self.panel_focus.is_none());
+        if let (Some(Panel::Terminal), Some((x, y))) = (focused_panel, terminal_cursor) {
+            queue!(s, cursor::MoveTo(x, y), cursor::Show);
+        }
+        // The accent waiting for its letter shows underlined where the letter will go
+        if let (Some(accent), None, false) = (self.compose, self.panel_focus, self.in_menu_mode) {
+            if let Some((x, y)) = self.viewport_manager.viewports.get(self.viewport_manager.focus_index).and_then(|v| v.cursor_cell()) {
+                queue!(s,
+                    cursor::MoveTo(x, y), style::SetBackgroundColor(style::Color::Blue), style::SetForegroundColor(style::Color::White),
+                    style::SetAttribute(style::Attribute::Underlined), style::Print(accent), style::SetAttribute(style::Attribute::NoUnderline),
+                    cursor::MoveTo(x, y),
+                );
+            }
+        }
+        if zen && self.in_menu_mode {
+            self.render_bars(s, size);
+        }
+
+        // Terminals can be slow to take a new title, so it is only sent when it changes
+        let title = self.format_window_title();
+        if title != self.window_title {
+            queue!(s, terminal::SetTitle(&title));
+            self.window_title = title;
+        }
+
+        if let Some((since, shown)) = &mut self.menu_hint {
+            if self.in_menu_mode && key_hints_due(*since) {
+                self.menu_bar.render_key_hints(s, (size.0, size.1 - 1));
+                *shown = true;
+            }
+        }
+
+        if let Some((since, cell, shown)) = &mut self.pointer {
+            if !self.in_menu_mode && tooltip_due(*since) {
+                *shown = true;
+                if let Some(text) = self.viewport_manager.tooltip_at(cell.0, cell.1) {
+                    tooltip::render(s, &text, *cell);
+                    if let Some((x, y)) = self.viewport_manager.viewports.get(self.viewport_manager.focus_index).and_then(|v| v.cursor_cell()) {
+                        queue!(s, cursor::MoveTo(x, y));
+                    }
+                }
+            }
+        }
+
+        self.perf.frame = measurement.finish();
+        if self.perf.enabled {
+            self.perf.render(s, size.0, &self.viewport_manager);
+        }
+
+        // Screen readers follow the terminal cursor, so it stays where typing would go
+        if config::get().screen_reader && self.panel_focus.is_none() {
+            match self.viewport_manager.viewports.get(self.viewport_manager.focus_index).and_then(|v| v.cursor_cell()) {
+                Some((x, y)) => queue!(s, cursor::MoveTo(x, y), cursor::Show),
+                None => queue!(s, cursor::Hide),
+            };
+        }
+        cursorshape::apply(s, if self.in_menu_mode { Mode::Menu } else { Mode::Edit });
+
+        s.flush().unwrap();
+        log::trace!("Rendered a frame in {:?}", self.perf.frame.time);
+    }
+
+    /// Type a character into the focused viewport, or keep it back as the accent waiting for its
+    /// letter with dead keys on.
+    fn type_char<S: Write>(&mut self, s: &mut S, key: KeyEvent) {
+        let KeyCode::Char(c) = key.code else { unreachable!() };
+        let mut text = String::new();
+        self.compose_into(&mut text, c);
+        // Each character goes the usual way, so that it does whatever typing it would do
+        for c in text.chars() {
+            self.viewport_manager.handle_key_event(s, KeyEvent { code: KeyCode::Char(c), ..key });
+        }
+    }
+
+    /// Put the text pasted into the terminal where keys would go. Many terminals paste in the
+    /// paths of files dropped onto them, which can be opened instead.
+    fn paste<S: Write>(&mut self, s: &mut S, text: String) {
+        match self.panel_focus {
+            Some(Panel::Terminal) => if let Some(terminal) = &mut self.terminal {
+                for c in text.chars() {
+                    let code = if c == '\n' { KeyCode::Enter } else { KeyCode::Char(c) };
+                    terminal.handle_key(KeyEvent::new(code, event::KeyModifiers::NONE));
+                }
+            },
+            Some(_) => {}
+            None => match util::dropped_files(&text) {
+                files if !files.is_empty() => self.open_dropped(s, files, &text),
+                _ => if let Some(viewport) = self.viewport_manager.get_focused_viewport_mut() {
+                    viewport.insert_str(&text);
+                },
+            },
+        }
+    }
+
+    // Ask whether to open the files dropped onto the terminal, or type in their paths after all.
+    fn open_dropped<S: Write>(&mut self, s: &mut S, files: Vec<std::path::PathBuf>, text: &str) {
+        let names: Vec<String> = files.iter().map(|file| file.file_name().unwrap_or(file.as_os_str()).to_string_lossy().into_owned()).collect();
+        let items = [format!("{} {}", tr("Open"), names.join(", ")), tr("Insert the paths as text")];
+        match util::choose(s, "Dropped files", &items, 0) {
+            Some(0) => for file in files {
+                self.open_file(file);
+            },
+            Some(_) => if let Some(viewport) = self.viewport_manager.get_focused_viewport_mut() {
+                viewport.insert_str(text);
+            },
+            None => {}
+        }
+    }
+
+    // Add a typed character to some text, or to the accent waiting for it with dead keys on.
+    fn compose_into(&mut self, text: &mut String, c: char) {
+        match self.compose.take() {
+            Some(accent) => match compose::compose(accent, c) {
+                Some(composed) => text.push(composed),
+                // Space, or the accent again, types the accent itself
+                None if c == ' ' || c == accent => text.push(accent),
+                None => {
+                    text.push(accent);
+                    self.compose_into(text, c);
+                }
+            },
+            None if self.dead_keys && !self.viewport_manager.paste_mode && compose::is_dead_key(c) => self.compose = Some(c),
+            None => text.push(c),
+        }
+    }
+
+    /// Draw the menu bar on top of the screen and the status bar at the bottom.
+    fn render_bars<S: Write>(&mut self, s: &mut S, size: (u16, u16)) {
+        self.menu_bar.render(s, (0, 0), size.0 as usize, self.in_menu_mode);
+        // The matches are counted afresh each time, as the text may have changed under them
+        let counter = self.viewport_manager.highlight.is_some().then(|| self.match_counter());
+        let manager = &self.viewport_manager;
+        let workspace = if manager.workspaces.len() > 1 { Some(manager.workspace_name()) } else { None };
+        self.status_bar.render(s, (0, size.1 - 1), size.0 as usize, manager.viewports.get(manager.focus_index), workspace, counter);
+    }
+
+    /// Share out the screen between the viewports and the panels shown. In zen mode the viewports
+    /// have all of it.
+    fn layout(&self, size: (u16, u16)) -> Layout {
+        let (width, height) = (size.0 as usize, size.1 as usize);
+        if self.viewport_manager.view.zen {
+            return Layout { viewports: ((0, 0), (width, height + 1)), file_tree: None, terminal: None, compare: None };
+        }
+        // Below the menu bar and above the status bar
+        let (top, mut rows) = (1, height.saturating_sub(2));
+        let mut left = 0;
+        let mut layout = Layout { viewports: ((0, 0), (0, 0)), file_tree: None, terminal: None, compare: None };
+        if (self.file_tree.is_some() || self.compare.is_some()) && width >= 40 {
+            left = (width / 4).clamp(20, 40);
+            // The file tree and the comparison share the left side, the tree on top
+            let tree_rows = match (&self.file_tree, &self.compare) {
+                (Some(_), Some(_)) => rows / 2,
+                (Some(_), None) => rows,
+                _ => 0,
+            };
+            if tree_rows > 0 {
+                layout.file_tree = Some(((0, top), (left, tree_rows)));
+            }
+            if self.compare.is_some() {
+                layout.compare = Some(((0, top + tree_rows as u16), (left, rows - tree_rows)));
+            }
+        }
+        if self.terminal.is_some() && rows >= 12 {
+            let terminal_rows = rows / 3;
+            rows -= terminal_rows;
+            layout.terminal = Some(((left as u16, top + rows as u16), (width - left, terminal_rows)));
+        }
+        layout.viewports = ((left as u16, top), (width - left, rows + 1));
+        layout
+    }
+
+    /// The panel drawn on a cell of the screen, if any is.
+    fn panel_at(&self, x: u16, y: u16) -> Option<Panel> {
+        let layout = self.layout(backend::size());
+        let within = |area: Option<((u16, u16), (usize, usize))>| area.is_some_and(|(origin, size)| {
+            x >= origin.0 && y >= origin.1 && ((x - origin.0) as usize) < size.0 && ((y - origin.1) as usize) < size.1
+        });
+        match (within(layout.file_tree), within(layout.terminal), within(layout.compare)) {
+            (true, _, _) => Some(Panel::FileTree),
+            (_, true, _) => Some(Panel::Terminal),
+            (_, _, true) => Some(Panel::Compare),
+            _ => None,
+        }
+    }
+
+    /// Give the keys to the next of the viewports and the panels shown, with F6.
+    fn cycle_focus(&mut self) {
+        let layout = self.layout(backend::size());
+        let mut order = Vec::new();
+        if !self.viewport_manager.viewports.is_empty() {
+            order.push(None);
+        }
+        if layout.file_tree.is_some() {
+            order.push(Some(Panel::FileTree));
+        }
+        if layout.compare.is_some() {
+            order.push(Some(Panel::Compare));
+        }
+        if layout.terminal.is_some() {
+            order.push(Some(Panel::Terminal));
+        }
+        if let Some(i) = order.iter().position(|&p| p == self.panel_focus) {
+            self.panel_focus = order[(i + 1) % order.len()];
+        }
+    }
+
+    /// Handle a key for the focused panel.
+    fn panel_key(&mut self, key: KeyEvent, panel: Panel) {
+        match panel {
+            Panel::FileTree => if let Some(path) = self.file_tree.as_mut().and_then(|tree| tree.handle_key(key.code)) {
+                self.open_file(path);
+            },
+            Panel::Terminal => if let Some(terminal) = &mut self.terminal {
+                terminal.handle_key(key);
+            },
+            Panel::Compare => if let Some(diff) = self.compare.as_mut().and_then(|compare| compare.handle_key(key.code)) {
+                self.open_text(diff);
+            },
+        }
+    }
+
+    /// Show a file, reading it in the background unless a viewport already has it. Its viewport
+    /// shows straight away, and can be closed or left for another while the file is read. The
+    /// keys go back to the viewports.
+    pub fn open_file(&mut self, path: std::path::PathBuf) {
+        self.panel_focus = None;
+        self.in_menu_mode = false;
+        match self.viewport_manager.buffers.find(&path).map(|document| document.borrow().id) {
+            Some(id) => self.viewport_manager.show_document(id),
+            None => if self.viewport_manager.open_loading(path.clone()) {
+                self.jobs.spawn(move |progress| jobs::read_file(path, progress));
+            },
+        }
+    }
+
+    /// Check the menu items that are on.
+    fn update_menu_checks(&mut self) {
+        let view = self.viewport_manager.view;
+        let (file_tree, terminal, compare) = (self.file_tree.is_some(), self.terminal.is_some(), self.compare.is_some());
+        let (perf, dead_keys, auto_close, paste_mode) = (self.perf.enabled, self.dead_keys, self.viewport_manager.auto_close, self.viewport_manager.paste_mode);
+        let focused = self.viewport_manager.viewports.get(self.viewport_manager.focus_index);
+        let compact = focused.is_some_and(|v| v.compact);
+        let unsaved = focused.is_some_and(|v| v.unsaved_changes.is_some());
+        let table = focused.and_then(|v| v.table.as_ref());
+        let (table, pin_header) = (table.is_some(), table.is_some_and(|table| table.pin_header));
+        self.menu_bar.update_checks(|action| match action {
+            Action::ToggleLineNumbers => view.line_numbers,
+            Action::ToggleWordWrap => view.word_wrap,
+            Action::ToggleWhitespace => view.whitespace,
+            Action::ToggleMinimap => view.minimap,
+            Action::ToggleStickyScope => view.sticky_scope,
+            Action::ToggleBreadcrumbs => view.breadcrumbs,
+            Action::ToggleUnsavedChanges => unsaved,
+            Action::ToggleTable => table,
+            Action::TogglePinHeader => pin_header,
+            Action::ToggleFileTree => file_tree,
+            Action::ToggleTerminal => terminal,
+            Action::ToggleCompare => compare,
+            Action::ToggleCompact => compact,
+            Action::ToggleZenMode => view.zen,
+            Action::ToggleLightTheme => theme::current() == Theme::Light,
+            Action::TogglePerfOverlay => perf,
+            Action::ToggleDeadKeys => dead_keys,
+            Action::ToggleAutoClose => auto_close,
+            Action::TogglePasteMode => paste_mode,
+            _ => false,
+        });
+    }
+
+    /// Tell a screen reader whether keys now go to the menus or the text.
+    fn announce_mode(&mut self) {
+        if !config::get().screen_reader {
+            return;
+        }
+        let message = match self.viewport_manager.viewports.get(self.viewport_manager.focus_index) {
+            Some(viewport) if !self.in_menu_mode => format!("Editing {}", viewport.title),
+            _ => "Menu mode: press a menu's letter, or Esc to go back to editing".to_owned(),
+        };
+        self.status_bar.set_message(message);
+    }
+
+    /// The title for the terminal window, from the configured format and the focused viewport.
+    fn format_window_title(&self) -> String {
+        let viewport = match self.viewport_manager.viewports.get(self.viewport_manager.focus_index) {
+            Some(viewport) => viewport,
+            None => return "QEdit".to_owned(),
+        };
+        let path = match &viewport.data {
+            ViewportData::Buffer(document, _) => document.borrow().buffer.path.as_ref().map(|path| path.display().to_string()),
+            ViewportData::Loading(path, _) => Some(path.display().to_string()),
+            ViewportData::Image(image) => Some(image.path.display().to_string()),
+            ViewportData::Tail(tail) => Some(tail.path.display().to_string()),
+            ViewportData::Terminal(_) | ViewportData::Page(_) => None,
+        };
+        let title = config::get().title_format
+            .replace("{filename}", &viewport.title)
+            .replace("{path}", path.as_deref().unwrap_or(&viewport.title))
+            .replace("{modified}", if viewport.is_modified() { " •" } else { "" });
+        util::sanitize(&title)
+    }
+
+    /// Let time pass, and pick up what background jobs have sent back. Returns true if anything
+    /// on screen changed.
+    pub fn tick<S: Write>(&mut self, s: &mut S) -> bool {
+        let mut changed = self.status_bar.tick();
+        if let Some((since, false)) = self.menu_hint {
+            changed |= self.in_menu_mode && key_hints_due(since);
+        }
+        if let Some((since, _, false)) = self.pointer {
+            changed |= !self.in_menu_mode && tooltip_due(since);
+        }
+        if let Some(terminal) = &mut self.terminal {
+            changed |= terminal.poll();
+        }
+        changed |= self.viewport_manager.poll_tails();
+        if self.untitled_saved.elapsed() >= UNTITLED_INTERVAL {
+            self.keep_untitled();
+        }
+        while let Some(event) = self.jobs.try_next_event() {
+            self.handle_job_event(s, event);
+            changed = true;
+        }
+        self.read_syntax();
+        changed
+    }
+
+    /// Read the comments and strings of the documents' lines out of view in the background, so
+    /// that they are ready to draw when scrolled to.
+    fn read_syntax(&mut self) {
+        for document in self.viewport_manager.buffers.iter() {
+            let mut document = document.borrow_mut();
+            let document = &mut *document;
+            if !document.syntax.has_unread() {
+                continue;
+            }
+            let text = document.buffer.data();
+            let lines = document.line_index.lines(&text);
+            if let Some(unread) = document.syntax.unread(&lines) {
+                let id = document.id;
+                self.jobs.spawn(move |_| jobs::read_syntax(id, unread));
+            }
+        }
+    }
+
+    fn handle_job_event<S: Write>(&mut self, s: &mut S, event: JobEvent) {
+        match event {
+            JobEvent::Progress(message) => self.status_bar.set_message(message),
+            JobEvent::Failed(message) => {
+                log::error!("A background job panicked: {}", message);
+                self.status_bar.set_message(format!("A background job failed: {}", message));
+            }
+            JobEvent::Reading(path, percent) => self.viewport_manager.loading_progress(&path, percent),
+            JobEvent::Finished(JobOutput::FileRead { path, result }) => match result {
+                // Nothing is done with a file whose viewport was closed while it was read
+                Ok((full_path, text)) => {
+                    let mut buf = scribe::Buffer::new();
+                    buf.insert(text);
+                    buf.cursor.move_to(scribe::buffer::Position { line: 0, offset: 0 });
+                    buf.path = Some(full_path.clone());
+                    if self.viewport_manager.finish_loading(&path, buf) {
+                        self.show_diagnostics(Some(&full_path));
+                    }
+                }
+                Err(e) => {
+                    self.viewport_manager.cancel_loading(&path);
+                    open_failed(s, &path, e);
+                }
+            },
+            JobEvent::Finished(JobOutput::ImageRead { path, image }) => {
+                self.viewport_manager.finish_loading_image(&path, image);
+            }
+            JobEvent::Finished(JobOutput::Comparison { folder, result }) => match result {
+                Ok(comparison) => {
+                    self.status_bar.set_message(format!("{} files differ", comparison.changes.len()));
+                    self.compare = Some(ComparePanel::new(comparison));
+                    self.panel_focus = Some(Panel::Compare);
+                    self.in_menu_mode = false;
+                }
+                Err(e) => {
+                    log::error!("Could not compare {:?}: {}", folder, e);
+                    util::alert(s, "Compare folders", &format!("Could not compare {:?}: {}", folder, e));
+                }
+            },
+            JobEvent::Finished(JobOutput::ManPage { topic, result }) => match result {
+                Ok(page) => {
+                    self.viewport_manager.open_page(page);
+                    self.panel_focus = None;
+                    self.in_menu_mode = false;
+                }
+                Err(e) => {
+                    log::warn!("Could not look up {:?}: {}", topic, e);
+                    util::alert(s, "Open man page", &format!("Could not look up {}: {}", topic.trim(), e));
+                }
+            },
+            JobEvent::Finished(JobOutput::Diagnostics { command, result }) => match result {
+                Ok(diagnostics) => {
+                    self.status_bar.set_message(match diagnostics.len() {
+                        0 => format!("{} found no problems", command),
+                        1 => format!("{} found 1 problem", command),
+                        n => format!("{} found {} problems", command, n),
+                    });
+                    self.diagnostics = diagnostics;
+                    self.show_diagnostics(None);
+                }
+                Err(e) => {
+                    log::error!("Could not run {}: {}", command, e);
+                    self.status_bar.set_message(format!("Could not run {}: {}", command, e));
+                }
+            },
+            JobEvent::Finished(JobOutput::Syntax { document, highlights }) => {
+                if let Some(document) = self.viewport_manager.buffers.get(document) {
+                    document.borrow_mut().syntax.take_in(highlights);
+                }
+            }
+            JobEvent::Finished(JobOutput::Markers { root, result }) => match result {
+                Ok(markers) if markers.is_empty() => util::alert(s, "Project markers", &format!("There are no TODO, FIXME, HACK or NOTE markers in {}.", root.display())),
+                Ok(markers) => {
+                    let items: Vec<String> = markers.iter().map(|m| format!("{}:{}: {}", m.path.strip_prefix(&root).unwrap_or(&m.path).display(), m.line + 1, m.text)).collect();
+                    if let Some(choice) = util::choose(s, &format!("{} markers", markers.len()), &items, 0) {
+                        self.open_at(s, &markers[choice].path, markers[choice].line);
+                    }
+                }
+                Err(e) => {
+                    log::error!("Could not search {:?} for markers: {}", root, e);
+                    util::alert(s, "Project markers", &format!("Could not search {:?}: {}", root, e));
+                }
+            },
+        }
+    }
+
+    /// Give the focused viewport a title of its own, or with none, have it follow its file again.
+    fn rename_tab<S: Write>(&mut self, s: &mut S) {
+        let Some(viewport) = self.viewport_manager.viewports.get(self.viewport_manager.focus_index) else { return };
+        let Some(name) = util::input(s, "Rename tab", viewport.title.clone(), util::InputType::Any) else { return };
+        let viewport = &mut self.viewport_manager.viewports[self.viewport_manager.focus_index];
+        match name.trim() {
+            "" => {
+                viewport.renamed = false;
+                viewport.title = viewport.data.title();
+            }
+            name => {
+                viewport.renamed = true;
+                viewport.title = name.to_owned();
+            }
+        }
+    }
+
+    /// Show a text that is not from a file, such as differences, in a viewport of its own with
+    /// the given title.
+    fn open_text(&mut self, (title, text): (String, String)) {
+        let mut buf = scribe::Buffer::new();
+        buf.insert(text);
+        buf.cursor.move_to(scribe::buffer::Position { line: 0, offset: 0 });
+        let index = self.viewport_manager.open_buffer(buf);
+        self.viewport_manager.focus_viewport(index);
+        self.viewport_manager.viewports[index].title = title;
+        self.panel_focus = None;
+    }
+
+    /// Ask what to compare, the working folder with its last commit or two folders, and compare
+    /// them in the background. The panel shows when they have been.
+    fn start_comparison<S: Write>(&mut self, s: &mut S) {
+        let here = std::env::current_dir().unwrap_or_default();
+        let choices = [tr("The working folder with its last commit"), tr("Two folders ...")];
+        let (base, folder) = match util::choose(s, "Compare folders", &choices, 0) {
+            Some(0) => (Base::Head, here),
+            Some(_) => {
+                let Some(old) = util::input(s, "Compare the folder", String::new(), util::InputType::Path) else { return };
+                let Some(new) = util::input(s, "With the folder", here.display().to_string(), util::InputType::Path) else { return };
+                (Base::Folder(old.into()), new.into())
+            }
+            None => return,
+        };
+        self.jobs.spawn(move |progress| jobs::compare(base, folder, progress));
+    }
+
+    /// Show a file at the given line, opening it unless a viewport already has it.
+    fn open_at<S: Write>(&mut self, s: &mut S, path: &std::path::Path, line: usize) {
+        let open = self.viewport_manager.buffers.find(path).map(|document| document.borrow().id);
+        match open {
+            Some(id) => self.viewport_manager.show_document(id),
+            None => match scribe::Buffer::from_file(path) {
+                Ok(buf) => self.open_buffer(buf),
+                Err(e) => return open_failed(s, path, e),
+            },
+        }
+        self.in_menu_mode = false;
+        self.viewport_manager.record_jump();
+        if let Some(viewport) = self.viewport_manager.get_focused_viewport_mut() {
+            viewport.goto_line(line);
+        }
+    }
+
+    /// Go to something beside a segment of the focused viewport's breadcrumbs, picked from a list:
+    /// another entry of the folder a folder or the file is in, or another scope in the same scope.
+    fn pick_breadcrumb<S: Write>(&mut self, s: &mut S, segment: Segment) {
+        let (mut folder, mut selected) = match segment.kind {
+            Kind::Scope(line) => return self.viewport_manager.pick_scope(s, line),
+            Kind::Folder(path) | Kind::File(Some(path)) => match path.parent() {
+                Some(parent) => (parent.to_owned(), Some(path)),
+                None => (path, None),
+            },
+            Kind::File(None) => (std::env::current_dir().unwrap_or_default(), None),
+        };
+        // Picking a folder lists its entries in turn, until a file is picked to open
+        loop {
+            let entries = breadcrumbs::entries(&folder);
+            if entries.is_empty() {
+                return;
+            }
+            let items: Vec<String> = entries.iter().map(|(name, _)| name.clone()).collect();
+            let current = selected.and_then(|path| entries.iter().position(|(_, p)| *p == path)).unwrap_or(0);
+            let Some(choice) = util::choose(s, &folder.display().to_string(), &items, current) else { return };
+            let path = entries[choice].1.clone();
+            if !path.is_dir() {
+                return self.open_file(path);
+            }
+            // Going back up picks out the folder come from
+            selected = Some(std::mem::replace(&mut folder, path));
+        }
+    }
+
+    /// Open a closed file again, with the cursor where it was left, and take it off the list of
+    /// closed files.
+    fn reopen_closed<S: Write>(&mut self, s: &mut S, index: usize) {
+        let (path, position) = self.viewport_manager.closed_files.remove(index);
+        self.open_at(s, &path, position.cursor.line);
+        if let Some(viewport) = self.viewport_manager.get_focused_viewport_mut() {
+            if viewport.get_buffer().is_some_and(|buf| buf.path.as_deref() == Some(path.as_path())) {
+                viewport.restore_position(position);
+            }
+        }
+    }
+
+    /// List the closed files, the most recently closed first, to reopen one.
+    fn closed_file_list<S: Write>(&mut self, s: &mut S) {
+        let closed = &self.viewport_manager.closed_files;
+        if closed.is_empty() {
+            return self.status_bar.set_message("No closed files to reopen".to_owned());
+        }
+        let items: Vec<String> = closed.iter().rev().map(|(path, position)| format!(
+            "{}:{}  {}",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            position.cursor.line + 1,
+            path.parent().map_or_else(String::new, |dir| dir.display().to_string()),
+        )).collect();
+        if let Some(choice) = util::choose(s, "Closed files", &items, 0) {
+            self.reopen_closed(s, items.len() - 1 - choice);
+        }
+    }
+
+    /// List the untitled buffers closed without saving, the most recently closed first, to open
+    /// one again.
+    fn discarded_list<S: Write>(&mut self, s: &mut S) {
+        let discarded = &self.viewport_manager.discarded;
+        if discarded.is_empty() {
+            return self.status_bar.set_message("No discarded buffers to get back".to_owned());
+        }
+        let items: Vec<String> = discarded.iter().rev().map(|(title, text)| {
+            let first_line = text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+            let lines = crate::lines::Lines::new(text).len();
+            format!("{}  ({} line{})  {}", title, lines, if lines == 1 { "" } else { "s" }, util::sanitize(first_line))
+        }).collect();
+        if let Some(choice) = util::choose(s, "Discarded buffers", &items, 0) {
+            let discarded = self.viewport_manager.discarded.remove(items.len() - 1 - choice);
+            self.open_text(discarded);
+        }
+    }
+
+    /// Compare the focused viewport's text with that of another open buffer, or of a file, side by
+    /// side.
+    fn compare_with<S: Write>(&mut self, s: &mut S) {
+        let first = self.viewport_manager.focus_index;
+        let others: Vec<usize> = (0..self.viewport_manager.viewports.len())
+            .filter(|&i| i != first && self.viewport_manager.viewports[i].text().is_some())
+            .collect();
+        let mut items: Vec<String> = others.iter().map(|&i| self.viewport_manager.viewports[i].title.clone()).collect();
+        items.push(tr("Another file ..."));
+        let Some(choice) = util::choose(s, "Compare with", &items, 0) else { return };
+        let other = match others.get(choice) {
+            Some(&other) => other,
+            None => {
+                let Some(path) = util::input(s, "Compare with file", String::new(), util::InputType::Path).filter(|path| !path.is_empty()) else { return };
+                self.open_file(std::path::PathBuf::from(path));
+                self.viewport_manager.focus_index
+            }
+        };
+        if other == first {
+            return util::alert(s, "Compare with", "The file is already open in the viewport compared.");
+        }
+        let title = self.viewport_manager.viewports[other].title.clone();
+        self.viewport_manager.focus_viewport(first);
+        self.viewport_manager.compare_with(other);
+        self.status_bar.set_message(format!("Comparing with {}", title));
+    }
+
+    /// Returns false if the user quit the editor.
+    pub fn handle_event<S: Write>(&mut self, s: &mut S, event: Event) -> bool {
+        let measurement = Measurement::start();
+        let keep_going = self.dispatch_event(s, event);
+        self.perf.event = measurement.finish();
+        keep_going
+    }
+
+    fn dispatch_event<S: Write>(&mut self, s: &mut S, event: Event) -> bool {
+        let in_menu_mode = self.in_menu_mode;
+        if let Event::Key(_) = event {
+            self.menu_hint = None;
+        }
+        // Any key takes a tooltip away, and the mouse moves it to where the pointer is now
+        self.pointer = match event {
+            Event::Mouse(MouseEvent { kind: MouseEventKind::Moved | MouseEventKind::Down(_) | MouseEventKind::Up(_) | MouseEventKind::Drag(_), column: x, row: y, .. }) => Some((Instant::now(), (x, y), false)),
+            _ => None,
+        };
+        // Any key but a letter, or a paste, puts down the accent waiting for one as it is, and Esc
+        // drops it
+        let puts_down_accent = match &event {
+            &Event::Key(key) => !is_typed(key),
+            Event::Paste(_) => true,
+            _ => false,
+        };
+        if let (Some(accent), true) = (self.compose, puts_down_accent) {
+            self.compose = None;
+            if let Event::Key(KeyEvent { code: KeyCode::Esc, .. }) = event {
+                return true;
+            }
+            if let Some(viewport) = self.viewport_manager.get_focused_viewport_mut() {
+                viewport.insert(accent);
+            }
+        }
+        match event {
+            Event::Key(KeyEvent { code: KeyCode::F(12), .. }) => self.perf.enabled = !self.perf.enabled,
+            Event::Key(KeyEvent { code: KeyCode::Esc, .. }) if !in_menu_mode && self.panel_focus == Some(Panel::FileTree) && self.file_tree.as_mut().is_some_and(FileTree::clear_filter) => {}
+            Event::Key(KeyEvent { code: KeyCode::Esc, .. }) if !in_menu_mode && self.panel_focus.is_none() && self.viewport_manager.get_focused_viewport_mut().is_some_and(|v| v.completion.take().is_some()) => {}
+            Event::Key(KeyEvent { code: KeyCode::Esc, .. }) if !in_menu_mode && self.viewport_manager.highlight.is_some() => self.viewport_manager.highlight = None,
+            Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => {
+                self.in_menu_mode = !self.in_menu_mode;
+                if self.in_menu_mode {
+                    self.menu_hint = Some((Instant::now(), false));
+                }
+                self.announce_mode();
+            }
+            Event::Key(KeyEvent { code: KeyCode::Char('q'), modifiers: event::KeyModifiers::CONTROL, .. }) if in_menu_mode => return false, // Quit the entire editor TODO: should prompt for save
+            Event::Key(KeyEvent { code: KeyCode::Tab, .. }) if in_menu_mode => self.viewport_manager.next_tab(),
+            Event::Key(KeyEvent { code: KeyCode::F(6), .. }) if !in_menu_mode => self.cycle_focus(),
+            Event::Key(k) if !in_menu_mode && self.panel_focus.is_some() => self.panel_key(k, self.panel_focus.unwrap()),
+            Event::Key(KeyEvent { code: KeyCode::Char('f'), modifiers: event::KeyModifiers::CONTROL, .. }) if !in_menu_mode => self.find(s),
+            // Most terminals send Ctrl+Shift+T just like Ctrl+T, so either one reopens the last closed file
+            Event::Key(KeyEvent { code: KeyCode::Char('t' | 'T'), modifiers, .. }) if modifiers.contains(event::KeyModifiers::CONTROL) => return self.handle_action(s, Action::ReopenClosed),
+            Event::Key(KeyEvent { code: KeyCode::F(3), modifiers, .. }) if !in_menu_mode => {
+                self.viewport_manager.record_jump();
+                self.find_next(!modifiers.contains(event::KeyModifiers::SHIFT));
+            }
+            Event::Key(k) if !in_menu_mode && keymap::action_for(k).is_some() => return self.handle_action(s, keymap::action_for(k).unwrap()),
+            Event::Key(k) if !in_menu_mode && is_typed(k) && !self.viewport_manager.viewports.is_empty() => self.type_char(s, k),
+            Event::Key(k) if !in_menu_mode => self.viewport_manager.handle_key_event(s, k),
+            Event::Mouse(MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), column: x, row: y, .. }) if self.panel_at(x, y).is_some() => {
+                self.in_menu_mode = false;
+                let panel = self.panel_at(x, y).unwrap();
+                self.panel_focus = Some(panel);
+                let layout = self.layout(backend::size());
+                match panel {
+                    Panel::FileTree => if let Some(path) = self.file_tree.as_mut().and_then(|tree| tree.click((y - layout.file_tree.unwrap().0.1) as usize)) {
+                        self.open_file(path);
+                    },
+                    Panel::Compare => {
+                        let row = (y - layout.compare.unwrap().0.1) as usize;
+                        if let Some(diff) = self.compare.as_mut().and_then(|compare| compare.click(row)) {
+                            self.open_text(diff);
+                        }
+                    }
+                    Panel::Terminal => {}
+                }
+            }
+            Event::Mouse(MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), column: x, row: y, .. }) if self.viewport_manager.breadcrumb_at(x, y).is_some() => {
+                let (index, segment) = self.viewport_manager.breadcrumb_at(x, y).unwrap();
+                self.panel_focus = None;
+                self.in_menu_mode = false;
+                self.viewport_manager.focus_viewport(index);
+                self.pick_breadcrumb(s, segment);
+            }
+            Event::Mouse(MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), column: x, row: y, modifiers }) if !self.viewport_manager.viewports.is_empty() => {
+                self.panel_focus = None;
+                let count = match self.last_click {
+                    Some((time, cell, count)) if cell == (x, y) && time.elapsed() < MULTI_CLICK_TIME => count % 3 + 1,
+                    _ => 1,
+                };
+                self.last_click = Some((Instant::now(), (x, y), count));
+                self.in_menu_mode = false;
+                self.viewport_manager.click(x, y, count, modifiers.contains(event::KeyModifiers::SHIFT));
+            }
+            Event::Mouse(MouseEvent { kind: MouseEventKind::Drag(MouseButton::Left), column: x, row: y, .. }) if !in_menu_mode => self.viewport_manager.drag(x, y),
+            Event::Paste(text) if !in_menu_mode => self.paste(s, text),
+            Event::FocusLost if config::get().autosave_on_focus_loss => self.save_modified_files(),
+            Event::FocusGained => self.read_changed_files(),
+            Event::Key(k) => {
+                // High-level action handling
+                if let Some((menu_idx, x_offset)) = self.menu_bar.maybe_handle_key_press(k) {
+                    // The menu bar should have set its selection index to the menu at this point, and is re-rendered all while calling 'maybe_handle_key_press'
+                    self.menu_bar.render(s, (0, 0), backend::size().0 as usize, in_menu_mode);
+
+                    self.update_menu_checks();
+                    if let Some(action) = self.menu_bar.menus[menu_idx].1.take_over(s, (x_offset, 1)).copied() {
+                        if !self.handle_action(s, action) {
+                            return false;
+                        }
+
+                        if !self.viewport_manager.viewports.is_empty() || self.panel_focus.is_some() {
+                            self.in_menu_mode = false; // Go into insert mode automatically when an action has been completed, if there are open viewports.
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Show the search bar in place of the status bar, selecting the first match after the cursor
+    /// as the pattern is typed. Enter and F3 go to the next match, Shift+F3 to the previous one,
+    /// Home and End to the first and last, and Esc closes the bar, leaving the match selected.
+    fn find<S: Write>(&mut self, s: &mut S) {
+        // Searching starts from the selection, so the match already selected stays that way
+        let origin = match self.viewport_manager.get_focused_viewport_mut() {
+            Some(viewport) => {
+                let selection = viewport.selection();
+                match viewport.get_buffer() {
+                    Some(buf) => util::offset_at(&buf.data(), selection.map_or(buf.cursor.position, |range| range.start())),
+                    None => return,
+                }
+            }
+            None => return,
+        };
+        self.viewport_manager.record_jump();
+        self.goto_match(origin, true);
+        // Up and Down step through the recent searches; the pattern being typed is kept aside
+        let mut recalled: Option<usize> = None;
+        let mut typed = self.search.pattern.clone();
+
+        loop {
+            self.render(s);
+            let size = backend::size();
+            let counter = self.match_counter();
+            search::render_bar(s, (0, size.1 - 1), size.0 as usize, &self.search, counter);
+            s.flush().unwrap();
+
+            match backend::read() {
+                Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => break,
+                Event::Key(KeyEvent { code: KeyCode::Enter, .. }) => self.find_next(true),
+                Event::Key(KeyEvent { code: KeyCode::Up, .. }) => {
+                    let next = recalled.map_or(0, |i| i + 1);
+                    if next < self.recent_searches.entries.len() {
+                        if recalled.is_none() {
+                            typed = self.search.pattern.clone();
+                        }
+                        recalled = Some(next);
+                        self.search.pattern = self.recent_searches.entries[next].clone();
+                        self.goto_match(origin, true);
+                    }
+                }
+                Event::Key(KeyEvent { code: KeyCode::Down, .. }) => if let Some(i) = recalled {
+                    recalled = i.checked_sub(1);
+                    self.search.pattern = match recalled {
+                        Some(i) => self.recent_searches.entries[i].clone(),
+                        None => typed.clone(),
+                    };
+                    self.goto_match(origin, true);
+                }
+                Event::Key(KeyEvent { code: KeyCode::F(3), modifiers, .. }) => self.find_next(!modifiers.contains(event::KeyModifiers::SHIFT)),
+                Event::Key(KeyEvent { code: KeyCode::Home, .. }) => self.find_first(false),
+                Event::Key(KeyEvent { code: KeyCode::End, .. }) => self.find_first(true),
+                Event::Key(KeyEvent { code: KeyCode::Char(c), modifiers, .. }) if modifiers.contains(event::KeyModifiers::ALT) && search::toggle_option(&mut self.search.options, c) => {
+                    self.goto_match(origin, true);
+                }
+                Event::Key(KeyEvent { code: KeyCode::Char(c), modifiers, .. }) if !modifiers.intersects(event::KeyModifiers::CONTROL | event::KeyModifiers::ALT) => {
+                    self.search.pattern.push(c);
+                    recalled = None;
+                    self.goto_match(origin, true);
+                }
+                Event::Key(KeyEvent { code: KeyCode::Backspace, .. }) => {
+                    self.search.pattern.pop();
+                    recalled = None;
+                    self.goto_match(origin, true);
+                }
+                _ => {}
+            }
+        }
+        self.recent_searches.add(&self.search.pattern);
+    }
+
+    /// Ask for a range of lines by number, and what to do with them.
+    fn line_range<S: Write>(&mut self, s: &mut S) {
+        let viewport = match self.viewport_manager.get_focused_viewport_mut() {
+            Some(viewport) => viewport,
+            None => return,
+        };
+        let selected = viewport.selected_lines();
+        let (text, path, current) = match viewport.get_buffer() {
+            Some(buf) => (buf.data(), buf.path.clone(), buf.cursor.line),
+            None => return,
+        };
+        let lines = crate::lines::Lines::new(&text);
+        let initial = match selected {
+            Some((first, last)) => format!("{},{}", first + 1, last + 1),
+            None => ".".to_owned(),
+        };
+        let input = match util::input(s, "Lines (like 10,50 or .,$)", initial, util::InputType::Any) {
+            Some(input) => input,
+            None => return,
+        };
+        let (first, last) = match ranges::parse(&input, current, lines.len()) {
+            Ok(range) => range,
+            Err(message) => return util::alert(s, "Line range", &message),
+        };
+
+        let operations: Vec<String> = ["Select", "Delete", "Indent", "Unindent", "Toggle comment", "Pipe through command ..."].iter().map(|&o| o.to_owned()).collect();
+        let title = match first == last {
+            true => format!("Line {}", first + 1),
+            false => format!("Lines {} to {}", first + 1, last + 1),
+        };
+        let choice = match util::choose(s, &title, &operations, 0) {
+            Some(choice) => choice,
+            None => return,
+        };
+        let indentation = viewport.indentation;
+        match choice {
+            0 => viewport.select_lines(first, last),
+            1 => viewport.delete_lines(first, last),
+            2 => viewport.transform_lines(first, last, |text| ranges::indent(text, indentation)),
+            3 => viewport.transform_lines(first, last, |text| ranges::unindent(text, indentation)),
+            4 => viewport.transform_lines(first, last, |text| ranges::toggle_comment(text, ranges::comment_prefix(path.as_deref()))),
+            _ => if let Some(command) = util::input(s, "Pipe through command", String::new(), util::InputType::Any).filter(|c| !c.trim().is_empty()) {
+                match ranges::pipe(lines.between(first, last), &command) {
+                    Ok(output) => viewport.transform_lines(first, last, |_| output),
+                    Err(message) => util::alert(s, "Pipe through command", &message),
+                }
+            },
+        }
+    }
+
+    /// Move and resize the focused viewport with the arrow keys, if it floats, until Enter or Esc.
+    fn move_window<S: Write>(&mut self, s: &mut S) {
+        if !self.viewport_manager.get_focused_viewport_mut().is_some_and(|v| v.floating) {
+            util::alert(s, "Move and resize", "Only a floating viewport can be moved. Use Window → Float or dock to pop this one out first.");
+            return;
+        }
+        self.in_menu_mode = false;
+        self.status_bar.set_message("Arrows move the window, Shift+arrows resize it, Enter when done".to_owned());
+        loop {
+            self.render(s);
+            let (code, modifiers) = match backend::read() {
+                Event::Key(KeyEvent { code: KeyCode::Enter, .. }) | Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => break,
+                Event::Key(KeyEvent { code, modifiers, .. }) => (code, modifiers),
+                _ => continue,
+            };
+            let step = match code {
+                KeyCode::Left => (-1, 0),
+                KeyCode::Right => (1, 0),
+                KeyCode::Up => (0, -1),
+                KeyCode::Down => (0, 1),
+                _ => continue,
+            };
+            match modifiers.contains(event::KeyModifiers::SHIFT) {
+                true => self.viewport_manager.move_floating((0, 0), step),
+                false => self.viewport_manager.move_floating(step, (0, 0)),
+            }
+        }
+    }
+
+    /// List every open document, including those no viewport shows any more, to show or close one.
+    fn buffer_list<S: Write>(&mut self, s: &mut S) {
+        let manager = &mut self.viewport_manager;
+        let documents: Vec<(usize, String, bool)> = manager.buffers.iter().map(|d| {
+            let d = d.borrow();
+            (d.id, d.name(), d.is_modified())
+        }).collect();
+        let items: Vec<String> = documents.iter().map(|(id, name, modified)| format!(
+            "{}{}{}",
+            if *modified { "*" } else { "" },
+            name,
+            match manager.viewers(*id) {
+                0 => "  (hidden)".to_owned(),
+                1 => String::new(),
+                n => format!("  ({} views)", n),
+            },
+        )).collect();
+        let choice = match util::choose(s, "Buffers", &items, 0) {
+            Some(choice) => choice,
+            None => return,
+        };
+        let (id, name, modified) = &documents[choice];
+        let actions: Vec<String> = vec!["Show".to_owned(), "Close".to_owned()];
+        match util::choose(s, name, &actions, 0) {
+            Some(0) => manager.show_document(*id),
+            Some(1) => {
+                let discard: Vec<String> = vec!["Close without saving".to_owned(), "Keep it open".to_owned()];
+                if !modified || util::choose(s, &format!("{} has unsaved changes", name), &discard, 1) == Some(0) {
+                    manager.close_document(*id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Let the user pick a workspace, or name a new one to make. The active workspace is left out
+    /// of the list unless `with_active` is set. Returns the workspace's index.
+    fn pick_workspace<S: Write>(&mut self, s: &mut S, title: &str, with_active: bool) -> Option<usize> {
+        let manager = &mut self.viewport_manager;
+        let indices: Vec<usize> = (0..manager.workspaces.len()).filter(|&i| with_active || i != manager.workspace).collect();
+        let mut items: Vec<String> = indices.iter().map(|&i| format!(
+            "{}{}  ({})",
+            if i == manager.workspace { "• " } else { "" },
+            manager.workspaces[i].name,
+            match manager.workspace_len(i) {
+                1 => "1 viewport".to_owned(),
+                n => format!("{} viewports", n),
+            },
+        )).collect();
+        items.push(tr("New workspace ..."));
+        let initial = indices.iter().position(|&i| i == manager.workspace).unwrap_or(0);
+        let choice = util::choose(s, title, &items, initial)?;
+        if let Some(&index) = indices.get(choice) {
+            return Some(index);
+        }
+        let name = util::input(s, "Workspace name", String::new(), util::InputType::Any)?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let manager = &mut self.viewport_manager;
+        Some(match manager.workspaces.iter().position(|w| w.name == name) {
+            Some(index) => index,
+            None => manager.add_workspace(name.to_owned()),
+        })
+    }
+
+    /// Ask for a pattern and what to put in its place, then replace every match in the buffer, or
+    /// in the selection if the user picks that. It is all one undo step.
+    fn replace<S: Write>(&mut self, s: &mut S) {
+        let selection = match self.viewport_manager.get_focused_viewport_mut() {
+            Some(viewport) if viewport.cursor_position().is_some() => viewport.selection(),
+            Some(_) => return,
+            None => return,
+        };
+        let pattern = match util::input(s, "Replace", self.search.pattern.clone(), util::InputType::Any) {
+            Some(pattern) if !pattern.is_empty() => pattern,
+            _ => return,
+        };
+        self.search.pattern = pattern;
+        self.recent_searches.add(&self.search.pattern);
+        let last_replacement = self.recent_replacements.entries.first().cloned().unwrap_or_default();
+        let replacement = match util::input(s, &format!("Replace \"{}\" with", self.search.pattern), last_replacement, util::InputType::Any) {
+            Some(replacement) => replacement,
+            None => return,
+        };
+        self.recent_replacements.add(&replacement);
+        let in_selection = match selection {
+            Some(_) => match util::choose(s, "Replace in", &["Selection".to_owned(), "Whole buffer".to_owned()], 0) {
+                Some(choice) => choice == 0,
+                None => return,
+            },
+            None => false,
+        };
+
+        let viewport = match self.viewport_manager.get_focused_viewport_mut() {
+            Some(viewport) => viewport,
+            None => return,
+        };
+        let text = match viewport.get_buffer() {
+            Some(buf) => buf.data(),
+            None => return,
+        };
+        let (start, end) = match selection {
+            Some(range) if in_selection => (util::offset_at(&text, range.start()), util::offset_at(&text, range.end())),
+            _ => (0, text.len()),
+        };
+        let matches: Vec<(usize, usize)> = self.search.find_all(&text).into_iter()
+            .filter(|&(match_start, match_end)| match_start >= start && match_end <= end)
+            .collect();
+        if matches.is_empty() {
+            self.status_bar.set_message(format!("No matches of \"{}\"", self.search.pattern));
+            return;
+        }
+
+        let mut new = String::with_capacity(end - start);
+        let mut last = start;
+        for &(match_start, match_end) in &matches {
+            new.push_str(&text[last..match_start]);
+            new.push_str(&replacement);
+            last = match_end;
+        }
+        new.push_str(&text[last..end]);
+        viewport.replace_bytes(start, end, &new);
+        self.status_bar.set_message(format!("Replaced {} occurrence{}", matches.len(), if matches.len() == 1 { "" } else { "s" }));
+    }
+
+    /// Select the next (or previous) match of the last search after (or before) the selection.
+    fn find_next(&mut self, forward: bool) {
+        let from = match self.viewport_manager.get_focused_viewport_mut() {
+            Some(viewport) => {
+                let selection = viewport.selection();
+                match viewport.get_buffer() {
+                    Some(buf) => {
+                        let text = buf.data();
+                        match (forward, selection) {
+                            (false, Some(range)) => util::offset_at(&text, range.start()),
+                            _ => util::offset_at(&text, buf.cursor.position),
+                        }
+                    }
+                    None => return,
+                }
+            }
+            None => return,
+        };
+        if !self.goto_match(from, forward) {
+            self.status_bar.set_message(format!("No more matches of \"{}\"", self.search.pattern));
+        }
+    }
+
+    /// Select the first (or last) match of the last search in the text.
+    fn find_first(&mut self, last: bool) {
+        let text = match self.viewport_manager.get_focused_viewport_mut().and_then(|v| v.get_buffer().map(|buf| buf.data())) {
+            Some(text) => text,
+            None => return,
+        };
+        if self.search.find_all(&text).is_empty() {
+            return self.status_bar.set_message(format!("No matches of \"{}\"", self.search.pattern));
+        }
+        self.goto_match(if last { text.len() } else { 0 }, !last);
+    }
+
+    /// Select the first match of the last search from a byte offset on (or back). Returns false if
+    /// there was none, in which case the cursor goes back to the offset.
+    fn goto_match(&mut self, from: usize, forward: bool) -> bool {
+        self.viewport_manager.highlight = Some(self.search.clone());
+        if let Some(viewport) = self.viewport_manager.get_focused_viewport_mut() {
+            let text = match viewport.get_buffer() {
+                Some(buf) => buf.data(),
+                None => return false,
+            };
+            let matches = self.search.find_all(&text);
+            match self.search.next_match(&matches, from, forward) {
+                Some(i) => {
+                    viewport.select_bytes(matches[i].0, matches[i].1);
+                    return true;
+                }
+                None => viewport.select_bytes(from, from),
+            }
+        }
+        false
+    }
+
+    /// Which of the highlighted matches is selected, for the search bar and the status bar.
+    fn match_counter(&mut self) -> Counter {
+        let search = match &self.viewport_manager.highlight {
+            Some(search) => search.clone(),
+            None => return Counter::NoMatches,
+        };
+        let viewport = match self.viewport_manager.get_focused_viewport_mut() {
+            Some(viewport) => viewport,
+            None => return Counter::NoMatches,
+        };
+        let selection = viewport.selection();
+        let text = match viewport.get_buffer() {
+            Some(buf) => buf.data(),
+            None => return Counter::NoMatches,
+        };
+        let matches = search.find_all(&text);
+        let selected = selection.map(|range| (util::offset_at(&text, range.start()), util::offset_at(&text, range.end())));
+        match matches.iter().position(|&m| Some(m) == selected) {
+            Some(i) => Counter::At(i, matches.len()),
+            None if matches.is_empty() => Counter::NoMatches,
+            None => Counter::Found(matches.len()),
+        }
+    }
+
+    /// Do what an action does, as when it is picked from the menu bar or its shortcut is pressed.
+    /// Returns false if the action quit the editor.
+    pub fn handle_action<S: Write>(&mut self, s: &mut S, action: Action) -> bool {
+        use Action::*;
+        let viewport_manager = &mut self.viewport_manager;
+        match action {
+            // Quitting from an empty workspace goes back to the others, if there are any
+            Close => match viewport_manager.viewports.is_empty() {
+                true if viewport_manager.workspaces.len() > 1 => {
+                    let previous = if viewport_manager.workspace == 0 { 1 } else { viewport_manager.workspace - 1 };
+                    viewport_manager.switch_workspace(previous);
+                }
+                true => return false,
+                false => viewport_manager.close_focused_viewport(),
+            },
+
+            New => {
+                let index = viewport_manager.open_buffer(scribe::Buffer::new()); // Add viewport
+                viewport_manager.focus_viewport(index);
+            }
+            Save => {
+                let mut diff = None;
+                if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                    if viewport.is_modified() { // Only do this code if the buffer is dirty
+                        // Someone else saved the file since we read it: do not overwrite their work unasked
+                        let choice = match viewport.document().is_some_and(|document| document.changed_on_disk()) {
+                            true => {
+                                let choices = [tr("View the differences"), tr("Overwrite it"), tr("Save as ...")];
+                                util::choose(s, &format!("{} was changed on disk", viewport.title), &choices, 0)
+                            }
+                            false => Some(1),
+                        };
+                        match choice {
+                            Some(0) => diff = viewport.document().map(|document| disk_diff(&document)),
+                            Some(1) => {
+                                let saved = viewport.get_buffer().map(|mut buf| buf.file_name().map(|name| (name, buf.save())));
+                                match saved {
+                                    Some(Some((name, Ok(())))) => { // This buffer points to a file on disk
+                                        viewport.mark_saved();
+                                        self.status_bar.set_message(format!("Saved {}", name));
+                                        self.check_project();
+                                    }
+                                    Some(Some((name, Err(e)))) => {
+                                        log::error!("Could not save {}: {}", name, e);
+                                        util::alert(s, "Save", &format!("Could not save {}: {}", name, e));
+                                    }
+                                    Some(None) if viewport_save_as(s, viewport) => { // This buffer points to no files on disk
+                                        self.status_bar.set_message(format!("Saved {}", viewport.title));
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            Some(_) if viewport_save_as(s, viewport) => self.status_bar.set_message(format!("Saved {}", viewport.title)),
+                            _ => {}
+                        }
+                    }
+                }
+                if let Some(diff) = diff {
+                    self.open_text(diff);
+                }
+            }
+            SaveAs => {
+                if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                    if viewport.get_buffer().is_some() && viewport_save_as(s, viewport) {
+                        self.status_bar.set_message(format!("Saved {}", viewport.title));
+                        self.check_project();
+                    }
+                }
+            }
+            Open => {
+                if let Some(path) = util::input(s, "Open file", String::new(), util::InputType::Path) {
+                    let path = std::path::PathBuf::from(path);
+                    if path.is_file() {
+                        self.open_file(path);
+                    } else {
+                        util::alert(s, "Only accepts files", &format!("You entered {:?}, which is a directory.", path));
+                    }
+                }
+            }
+            FollowFile => {
+                // The focused file is followed, unless another is typed
+                let focused = viewport_manager.get_focused_viewport_mut().and_then(|v| v.document().and_then(|d| d.buffer.path.clone()));
+                if let Some(path) = util::input(s, "Follow file", focused.map(|p| p.display().to_string()).unwrap_or_default(), util::InputType::Path) {
+                    let highlight = config::get().tail_highlight.as_deref().and_then(|pattern| Regex::new(pattern).ok());
+                    match Tail::open(path.clone().into(), highlight) {
+                        Ok(tail) => {
+                            self.viewport_manager.open_tail(tail);
+                            self.panel_focus = None;
+                            self.in_menu_mode = false;
+                        }
+                        Err(e) => util::alert(s, "Follow file", &format!("Could not follow {}: {}", path, e)),
+                    }
+                }
+            }
+
+            Undo => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.undo() },
+            Redo => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.redo() },
+            UndoHistory => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                let entries = match viewport.document() {
+                    Some(document) => document.history.entries(),
+                    None => return true,
+                };
+                let items: Vec<String> = entries.iter().map(|e| format!(
+                    "{}{} {:>8}  {}{}",
+                    "  ".repeat(e.depth),
+                    if e.current { '●' } else { '○' },
+                    format_age(e.age),
+                    e.summary,
+                    if e.saved { " (saved)" } else { "" },
+                )).collect();
+                let current = entries.iter().position(|e| e.current).unwrap_or(0);
+                if let Some(choice) = util::choose(s, "Undo history", &items, current) {
+                    viewport.restore(entries[choice].id);
+                }
+            }
+            Cut => viewport_manager.cut(s),
+            Copy => viewport_manager.copy(s),
+            Paste => viewport_manager.paste(),
+            PasteFromHistory => viewport_manager.paste_from_history(s),
+            CopyToRegister | CutToRegister => if !viewport_manager.viewports.is_empty() {
+                let title = if matches!(action, CutToRegister) { "Cut to register (a to z)" } else { "Copy to register (a to z)" };
+                if let Some(name) = util::input(s, title, String::new(), util::InputType::Any).and_then(|n| n.chars().next()) {
+                    viewport_manager.copy_to_register(s, name, matches!(action, CutToRegister));
+                }
+            }
+            PasteFromRegister => if !viewport_manager.viewports.is_empty() {
+                if let Some(name) = util::input(s, "Paste from register", String::new(), util::InputType::Any).and_then(|n| n.chars().next()) {
+                    viewport_manager.paste_from_register(s, name);
+                }
+            }
+            Registers => if !viewport_manager.viewports.is_empty() {
+                viewport_manager.choose_register(s);
+            }
+
+            Find => self.find(s),
+            Replace => self.replace(s),
+            ClearHighlights => self.viewport_manager.highlight = None,
+            SearchHistory => {
+                if self.recent_searches.entries.is_empty() {
+                    util::alert(s, "Search history", "Nothing has been searched for yet.");
+                } else if let Some(i) = util::choose(s, "Search history", &self.recent_searches.entries, 0) {
+                    self.search.pattern = self.recent_searches.entries[i].clone();
+                    self.recent_searches.add(&self.search.pattern);
+                    self.viewport_manager.record_jump();
+                    self.find_next(true);
+                }
+            }
+            FindNext | FindPrevious => {
+                self.viewport_manager.record_jump();
+                self.find_next(matches!(action, FindNext));
+            }
+            FirstMatch | LastMatch => {
+                self.viewport_manager.record_jump();
+                self.find_first(matches!(action, LastMatch));
+            }
+
+            InsertUnicode => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                let items: Vec<String> = unicode::CHARACTERS.iter().map(|(c, name, category)| format!("{}  U+{:04X}  {} ({})", c, *c as u32, name, category)).collect();
+                if let Some(choice) = util::choose(s, "Unicode character", &items, 0) {
+                    viewport.insert(unicode::CHARACTERS[choice].0);
+                }
+            }
+            InsertDate => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                let format = config::get().date_format.clone();
+                viewport.insert_str(&DateTime::now_local().format(&format));
+            }
+            InsertDateInFormat => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                let now = DateTime::now_local();
+                let configured = config::get().date_format.clone();
+                let formats = std::iter::once(configured.as_str()).chain(templates::DATE_FORMATS.iter().copied().filter(|&f| f != configured));
+                let items: Vec<String> = formats.map(|f| now.format(f)).collect();
+                if let Some(choice) = util::choose(s, "Date in format", &items, 0) {
+                    viewport.insert_str(&items[choice]);
+                }
+            }
+            InsertTemplate => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                let templates = config::get().templates.clone();
+                let items: Vec<String> = templates.iter().map(|(name, _)| name.clone()).collect();
+                if templates.is_empty() {
+                    util::alert(s, "Template", "There are no templates. Add some to the config file with `template.<name> = text`.");
+                } else if let Some(choice) = util::choose(s, "Template", &items, 0) {
+                    viewport.insert_str(&templates::expand(&templates[choice].1, &viewport.title));
+                }
+            }
+
+            ToggleBookmark => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.toggle_bookmark() },
+            NextBookmark => {
+                viewport_manager.record_jump();
+                if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.goto_bookmark(true) }
+            }
+            PreviousBookmark => {
+                viewport_manager.record_jump();
+                if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.goto_bookmark(false) }
+            }
+            ClearBookmarks => if let Some(mut document) = viewport_manager.get_focused_viewport_mut().and_then(|v| v.document()) { document.marks.clear_bookmarks() },
+            SetMark => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                if let Some(name) = util::input(s, "Set mark (any letter)", String::new(), util::InputType::Any).and_then(|n| n.chars().next()) {
+                    viewport.set_mark(name);
+                }
+            }
+            JumpToMark => if !viewport_manager.viewports.is_empty() {
+                if let Some(name) = util::input(s, "Jump to mark", String::new(), util::InputType::Any).and_then(|n| n.chars().next()) {
+                    viewport_manager.record_jump();
+                    if !viewport_manager.get_focused_viewport_mut().unwrap().goto_mark(name) {
+                        util::alert(s, "Jump to mark", &format!("There is no mark named '{}' in this buffer.", name));
+                    }
+                }
+            }
+
+            SelectNextOccurrence => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.select_next_occurrence(false) },
+            SkipOccurrence => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.select_next_occurrence(true) },
+            GrowSelection => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.grow_selection() },
+            NarrowSelection => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.narrow_selection() },
+            SetIndentation => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                let items: Vec<String> = indent::CHOICES.iter().map(|i| i.to_string()).collect();
+                let current = indent::CHOICES.iter().position(|&i| i == viewport.indentation).unwrap_or(0);
+                if let Some(choice) = util::choose(s, "Indentation", &items, current) {
+                    viewport.indentation = indent::CHOICES[choice];
+                }
+            }
+            AlignOn => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                match viewport.selected_lines() {
+                    Some((first, last)) => if let Some(c) = util::input(s, "Align on character", String::new(), util::InputType::Any).and_then(|c| c.chars().next()) {
+                        viewport.transform_lines(first, last, |text| align::align_on(text, c));
+                    }
+                    None => util::alert(s, "Align on", "Select the lines to align first."),
+                }
+            }
+
+            LineRange => self.line_range(s),
+            AddToNumbers | NumberSequence => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                let (title, initial) = match action {
+                    AddToNumbers => ("Add to numbers (like 10 or -3)", "1"),
+                    _ => ("Number as sequence, counting by", "1"),
+                };
+                if let Some(input) = util::input(s, title, initial.to_owned(), util::InputType::Any) {
+                    match input.trim().parse::<i64>() {
+                        Ok(by) => if !viewport.add_to_numbers(by, matches!(action, NumberSequence)) {
+                            self.status_bar.set_message("There is no number at the cursor".to_owned());
+                        }
+                        Err(_) => util::alert(s, "Add to numbers", &format!("'{}' is not a whole number.", input.trim())),
+                    }
+                }
+            }
+
+            // The selected expression is replaced by its result, which is otherwise put in at the cursor
+            Evaluate => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                let selected = viewport.selected_text();
+                let expression = match &selected {
+                    Some(text) => Some(text.clone()),
+                    None => util::input(s, "Evaluate expression", String::new(), util::InputType::Any),
+                };
+                if let Some(expression) = expression.filter(|e| !e.trim().is_empty()) {
+                    match calc::evaluate(&expression) {
+                        Ok(answer) => {
+                            let result = answer.to_string();
+                            self.status_bar.set_message(format!("{} = {}", expression.trim(), result));
+                            viewport.insert_str(&result);
+                        }
+                        Err(message) => util::alert(s, "Evaluate expression", &message),
+                    }
+                }
+            }
+            // The selection is replaced by what it encodes or decodes to, and stays selected to go on with
+            Transform(transform) => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                match viewport.selected_text().map(|text| transform.apply(&text)) {
+                    Some(Ok(text)) => viewport.replace_selection(&text),
+                    Some(Err(message)) => util::alert(s, "Transform text", &message),
+                    None => util::alert(s, "Transform text", "Select the text to transform first."),
+                }
+            }
+            Statistics => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                let selection = viewport.selected_text();
+                if let Some(buf) = viewport.get_buffer() {
+                    util::alert(s, "Statistics", &crate::stats::summary(&buf.data(), selection.as_deref()));
+                }
+            }
+            NormalizeUnicode => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                match viewport.normalize_text() {
+                    Some(0) => self.status_bar.set_message("Normalized the text to its composed form".to_owned()),
+                    Some(n) => self.status_bar.set_message(format!("Normalized the text to its composed form, {} characters shorter", n)),
+                    None => self.status_bar.set_message("The text is already in its composed form".to_owned()),
+                }
+            },
+            NormalizeIndentation => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                match viewport.normalize_indentation() {
+                    Some(0) => self.status_bar.set_message("The indentation is already consistent".to_owned()),
+                    Some(n) => self.status_bar.set_message(format!("Reindented {} lines", n)),
+                    None => util::alert(s, "Normalize indentation", "Indentation is only checked in Python and YAML files."),
+                }
+            },
+            ToggleAutoClose => viewport_manager.auto_close = !viewport_manager.auto_close,
+            TogglePasteMode => {
+                viewport_manager.paste_mode = !viewport_manager.paste_mode;
+                self.status_bar.paste_mode = viewport_manager.paste_mode;
+                self.compose = None;
+                self.status_bar.set_message(match viewport_manager.paste_mode {
+                    true => "Paste mode on until Alt+V".to_owned(),
+                    false => "Paste mode off".to_owned(),
+                });
+            }
+            ToggleDeadKeys => {
+                self.dead_keys = !self.dead_keys;
+                self.compose = None;
+            }
+
+            GotoLine => viewport_manager.prompt_goto_line(s),
+            NextFunction | PreviousFunction => {
+                viewport_manager.record_jump();
+                if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.goto_definition(matches!(action, NextFunction)) }
+            }
+            BlockStart => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.goto_block_edge(false) },
+            BlockEnd => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.goto_block_edge(true) },
+            PreviousEdit => {
+                viewport_manager.record_jump();
+                if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.goto_previous_change() }
+            }
+            JumpBack => viewport_manager.jump_back(),
+            JumpForward => viewport_manager.jump_forward(),
+            ScrollLock => if viewport_manager.scroll_link.take().is_none() {
+                let others: Vec<usize> = (0..viewport_manager.viewports.len()).filter(|&i| i != viewport_manager.focus_index).collect();
+                let titles: Vec<String> = others.iter().map(|&i| viewport_manager.viewports[i].title.clone()).collect();
+                let choice = match others.len() {
+                    0 => {
+                        util::alert(s, "Scroll lock", "Scroll lock needs another viewport to scroll with, and only one is open.");
+                        None
+                    }
+                    1 => Some(0),
+                    _ => util::choose(s, "Scroll together with", &titles, 0),
+                };
+                if let Some(choice) = choice {
+                    viewport_manager.link_scrolling(others[choice]);
+                    self.status_bar.set_message(format!("Scrolling together with {}", titles[choice]));
+                }
+            } else {
+                self.status_bar.set_message("Scrolling separately".to_owned());
+            },
+            CenterCursorLine => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.scroll_cursor_line(LineAt::Center) },
+            CursorLineToTop => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.scroll_cursor_line(LineAt::Top) },
+            CursorLineToBottom => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.scroll_cursor_line(LineAt::Bottom) },
+            Breadcrumbs => if let Some(segments) = viewport_manager.get_focused_viewport_mut().map(|v| v.breadcrumbs()).filter(|segments| !segments.is_empty()) {
+                let items: Vec<String> = segments.iter().map(|segment| segment.name.clone()).collect();
+                if let Some(choice) = util::choose(s, "Breadcrumbs", &items, items.len() - 1) {
+                    self.pick_breadcrumb(s, segments[choice].clone());
+                }
+            },
+
+            ToggleFloating => viewport_manager.toggle_floating(),
+            SplitView => viewport_manager.split_view(),
+            CompareWith => if !viewport_manager.viewports.is_empty() { self.compare_with(s) },
+            Buffers => self.buffer_list(s),
+            Workspaces => if let Some(index) = self.pick_workspace(s, "Workspaces", true) {
+                self.viewport_manager.switch_workspace(index);
+                self.in_menu_mode = self.viewport_manager.viewports.is_empty();
+            },
+            MoveToWorkspace => if !viewport_manager.viewports.is_empty() {
+                if let Some(index) = self.pick_workspace(s, "Put in workspace", false) {
+                    self.viewport_manager.move_to_workspace(index);
+                    self.status_bar.set_message(format!("Put in workspace {}", self.viewport_manager.workspaces[index].name));
+                }
+            },
+            RenameTab => self.rename_tab(s),
+            MoveWindow => self.move_window(s),
+            CloseOthers => match viewport_manager.close_others() {
+                0 => {}
+                1 => self.status_bar.set_message("Kept 1 viewport with unsaved changes".to_owned()),
+                kept => self.status_bar.set_message(format!("Kept {} viewports with unsaved changes", kept)),
+            },
+            CloseUnmodified => viewport_manager.close_unmodified(),
+            ReopenClosed => match viewport_manager.closed_files.len() {
+                0 => self.status_bar.set_message("No closed files to reopen".to_owned()),
+                n => self.reopen_closed(s, n - 1),
+            },
+            ClosedFiles => self.closed_file_list(s),
+            DiscardedBuffers => self.discarded_list(s),
+
+            ToggleLineNumbers => viewport_manager.view.line_numbers = !viewport_manager.view.line_numbers,
+            ToggleWordWrap => viewport_manager.view.word_wrap = !viewport_manager.view.word_wrap,
+            ToggleWhitespace => viewport_manager.view.whitespace = !viewport_manager.view.whitespace,
+            ToggleMinimap => viewport_manager.view.minimap = !viewport_manager.view.minimap,
+            ToggleStickyScope => viewport_manager.view.sticky_scope = !viewport_manager.view.sticky_scope,
+            ToggleBreadcrumbs => viewport_manager.view.breadcrumbs = !viewport_manager.view.breadcrumbs,
+            ToggleUnsavedChanges => if let Some(v) = viewport_manager.get_focused_viewport_mut() {
+                if let Err(e) = v.toggle_unsaved_changes() {
+                    self.status_bar.set_message(format!("Cannot show the unsaved changes of {}: {}", v.title, e));
+                }
+            },
+            ToggleFold => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.toggle_fold() },
+            UnfoldAll => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.unfold_all() },
+            ToggleTable => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.toggle_table() },
+            Delimiter => if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
+                let current = viewport.document().and_then(|document| document.delimiter);
+                let items: Vec<String> = std::iter::once(tr("Told from the file"))
+                    .chain(table::DELIMITERS.iter().map(|(_, name)| tr(name)))
+                    .collect();
+                let selected = current.and_then(|c| table::DELIMITERS.iter().position(|&(d, _)| d == c)).map_or(0, |i| i + 1);
+                if let Some(choice) = util::choose(s, "Delimiter", &items, selected) {
+                    viewport.set_delimiter(choice.checked_sub(1).map(|i| table::DELIMITERS[i].0));
+                }
+            },
+            TogglePinHeader => match viewport_manager.get_focused_viewport_mut().and_then(|v| v.table.as_mut()) {
+                Some(table) => table.pin_header = !table.pin_header,
+                None => self.status_bar.set_message("The header is only pinned in a table".to_owned()),
+            },
+            ToggleFileTree => match self.file_tree {
+                Some(_) => {
+                    self.file_tree = None;
+                    self.panel_focus = self.panel_focus.filter(|&p| p != Panel::FileTree);
+                }
+                None => match std::env::current_dir() {
+                    Ok(root) => {
+                        self.file_tree = Some(FileTree::new(root));
+                        self.panel_focus = Some(Panel::FileTree);
+                    }
+                    Err(e) => util::alert(s, "File tree", &format!("Could not tell which folder to list: {}", e)),
+                },
+            },
+            ToggleTerminal => match self.terminal {
+                Some(_) => {
+                    self.terminal = None;
+                    self.panel_focus = self.panel_focus.filter(|&p| p != Panel::Terminal);
+                }
+                None => {
+                    self.terminal = Some(TerminalPanel::new(std::env::current_dir().unwrap_or_default()));
+                    self.panel_focus = Some(Panel::Terminal);
+                }
+            },
+            ToggleCompare => match self.compare {
+                Some(_) => {
+                    self.compare = None;
+                    self.panel_focus = self.panel_focus.filter(|&p| p != Panel::Compare);
+                }
+                None => self.start_comparison(s),
+            },
+            ToggleCompact => if let Some(v) = viewport_manager.get_focused_viewport_mut() { v.compact = !v.compact },
+            ToggleZenMode => {
+                viewport_manager.view.zen = !viewport_manager.view.zen;
+                self.panel_focus = None; // The panels are hidden in zen mode
+            }
+            ToggleLightTheme => theme::set(match theme::current() {
+                Theme::Dark => Theme::Light,
+                Theme::Light => Theme::Dark,
+            }),
+
+            TogglePerfOverlay => self.perf.enabled = !self.perf.enabled,
+            Language => {
+                let languages = locale::languages();
+                let current = languages.iter().position(|l| *l == locale::language()).unwrap_or(0);
+                if let Some(choice) = util::choose(s, "Language", &languages, current) {
+                    locale::set_language(&languages[choice]);
+                    let selection_index = self.menu_bar.selection_index;
+                    self.menu_bar = default_menu_bar();
+                    self.menu_bar.selection_index = selection_index;
+                }
+            }
+            CheckProject => match config::get().check_command.is_some() {
+                true => self.check_project(),
+                false => util::alert(s, "Check project", "Set check_command in the config file to the command that checks the project, like cargo check --message-format=short."),
+            },
+            FindMarkers => match std::env::current_dir() {
+                Ok(root) => self.jobs.spawn(move |progress| jobs::find_markers(root, progress)),
+                Err(e) => util::alert(s, "Project markers", &format!("Could not tell which directory to search: {}", e)),
+            },
+
+            ManPage => {
+                // What is selected is looked up, unless something else is typed
+                let selected = viewport_manager.get_focused_viewport_mut().and_then(|v| v.selected_text()).filter(|text| !text.contains('\n'));
+                if let Some(topic) = util::input(s, "Open man page", selected.unwrap_or_default(), util::InputType::Any).filter(|topic| !topic.trim().is_empty()) {
+                    let width = viewport_manager.size.0.saturating_sub(2).max(20);
+                    self.jobs.spawn(move |progress| jobs::man_page(topic, width, progress));
+                }
+            }
+            ViewLog => if let Some(path) = logging::path() {
+                log::logger().flush();
+                self.in_menu_mode = false;
+                // Highlights afresh each time, as the log has grown since
+                if self.viewport_manager.open_loading(path.clone()) {
+                    self.jobs.spawn(move |progress| jobs::read_file(path, progress));
+                }
+            }
+            About => util::alert(s, "About QEdit", "QEdit Text Editor\nVersion 0.1\nCopyright © 2019 Luke Wilson.\nLicensed under the MIT License."),
+            _ => util::alert(s, "Unimplemented action selected", &format!("{:?}", action)),
+        }
+        true
+    }
+}
+
+/// How long ago something happened, roughly, like `5m ago`.
+fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        0..=4 => "just now".to_owned(),
+        s @ 5..=59 => format!("{}s ago", s),
+        s @ 60..=3599 => format!("{}m ago", s / 60),
+        s => format!("{}h ago", s / 3600),
+    }
+}
+
+impl Default for Editor {
+    fn default() -> Editor {
+        Editor::new()
+    }
+}
+
+/// Whether a key types its character, rather than being a shortcut.
+fn is_typed(key: KeyEvent) -> bool {
+    matches!(key.code, KeyCode::Char(_)) && !key.modifiers.intersects(event::KeyModifiers::CONTROL | event::KeyModifiers::ALT)
+}
+
+/// Whether menu mode, entered at the given time, has waited long enough for a key to list them.
+fn key_hints_due(since: Instant) -> bool {
+    let delay = config::get().key_hint_delay;
+    delay > 0 && since.elapsed() >= Duration::from_millis(delay)
+}
+
+/// Whether the mouse pointer, seen last at the given time, has rested long enough for a tooltip.
+fn tooltip_due(since: Instant) -> bool {
+    let delay = config::get().tooltip_delay;
+    delay > 0 && since.elapsed() >= Duration::from_millis(delay)
+}
+
+fn open_failed<S: Write>(s: &mut S, path: &std::path::Path, error: std::io::Error) {
+    log::error!("Could not open {:?}: {}", path, error);
+    util::alert(s, "Open file", &format!("Could not open {:?}: {}", path, error));
+}
+
+/// A title and the differences between a document's file on disk and its text here.
+fn disk_diff(document: &Document) -> (String, String) {
+    let path = document.buffer.path.as_ref().expect("Only a document with a file can differ from it");
+    let on_disk = std::fs::read_to_string(path).unwrap_or_default();
+    let name = path.display().to_string();
+    let text = diff::unified(&on_disk, &document.buffer.data(), &format!("{} (on disk)", name), &format!("{} (unsaved)", name));
+    (format!("Changes to {}", document.name()), text)
+}
+
+/// Returns true if the Viewport actually saved the file, or false if the user cancelled.
+fn viewport_save_as<S: Write>(s: &mut S, viewport: &mut Viewport) -> bool {
+    if let Some(file_path_str) = util::input(s, &format!("Save file '{}'", "Untitled"), "./Untitled".to_owned(), util::InputType::Any) {
+        let file_path = std::path::PathBuf::from(file_path_str);
+        let mut file = std::fs::File::create(&file_path).unwrap(); // Create the file on disk
+        let mut buf = viewport.get_buffer().expect("Cannot save a Viewport with no buffer.");
+        file.write_all(buf.data().as_bytes()).expect("Failed to write buffer data into new save file on disk!");
+        buf.path = Some(file_path.canonicalize().unwrap_or(file_path));
+        let title = buf.file_name().unwrap_or_else(|| "Untitled".to_owned());
+        drop(buf);
+        viewport.title = title;
+        viewport.mark_saved();
+        true
+    } else { // If the user inputs no save file path, we do nothing
+        false
+    }
+}
+
+/// Create and instantiate the default menu bar, in the current language
+fn default_menu_bar() -> MenuBar {
+    let item = |name: &str, action: Action| (tr(name), MenuAction::Action(action));
+    let check = |name: &str, action: Action| (tr(name), MenuAction::Check(action, false));
+    let separator = || (String::new(), MenuAction::Separator);
+    let file = (
+        tr("_File"),
+        Menu {
+            children: vec!(
+                item("_New", Action::New),
+                item("_Open", Action::Open),
+                item("_Follow file ...", Action::FollowFile),
+                separator(),
+                item("_Save", Action::Save),
+                item("Save _as ...", Action::SaveAs),
+                separator(),
+                item("_Quit", Action::Close),
+            ),
+        },
+    );
+    let edit = (
+        tr("_Edit"),
+        Menu {
+            children: vec!(
+                item("_Undo", Action::Undo),
+                item("_Redo", Action::Redo),
+                item("Undo _history ...", Action::UndoHistory),
+                separator(),
+                item("Cu_t", Action::Cut),
+                item("_Copy", Action::Copy),
+                item("_Paste", Action::Paste),
+                item("Paste _from history ...", Action::PasteFromHistory),
+                (tr("_Yank registers"), MenuAction::SubMenu(Menu {
+                    children: vec!(
+                        item("C_opy to register ...", Action::CopyToRegister),
+                        item("Cu_t to register ...", Action::CutToRegister),
+                        item("_Paste from register ...", Action::PasteFromRegister),
+                        item("_All registers ...", Action::Registers),
+                    ),
+                })),
+                separator(),
+                item("Select _next occurrence", Action::SelectNextOccurrence),
+                item("S_kip occurrence", Action::SkipOccurrence),
+                item("_Grow selection", Action::GrowSelection),
+                item("Narro_w selection", Action::NarrowSelection),
+                separator(),
+                item("_Indentation ...", Action::SetIndentation),
+                item("_Align on ...", Action::AlignOn),
+                item("_Line range ...", Action::LineRange),
+                item("Add to nu_mbers ...", Action::AddToNumbers),
+                item("Number as se_quence ...", Action::NumberSequence),
+                item("_Evaluate expression ...", Action::Evaluate),
+                (tr("Transform te_xt"), MenuAction::SubMenu(Menu {
+                    children: vec!(
+                        item("Base64 _encode", Action::Transform(Transform::Base64Encode)),
+                        item("Base64 _decode", Action::Transform(Transform::Base64Decode)),
+                        separator(),
+                        item("URL en_code", Action::Transform(Transform::UrlEncode)),
+                        item("URL dec_ode", Action::Transform(Transform::UrlDecode)),
+                        separator(),
+                        item("JSON e_scape", Action::Transform(Transform::JsonEscape)),
+                        item("JSON u_nescape", Action::Transform(Transform::JsonUnescape)),
+                        separator(),
+                        item("HTML esc_ape", Action::Transform(Transform::HtmlEscape)),
+                        item("HTML unesca_pe", Action::Transform(Transform::HtmlUnescape)),
+                    ),
+                })),
+                item("_Statistics", Action::Statistics),
+                separator(),
+                item("N_ormalize Unicode", Action::NormalizeUnicode),
+                item("Normali_ze indentation", Action::NormalizeIndentation),
+                check("Auto-close _brackets", Action::ToggleAutoClose),
+                check("_Dead keys for accents", Action::ToggleDeadKeys),
+                check("_Verbatim typing (paste mode)", Action::TogglePasteMode),
+            ),
+        },
+    );
+    let search = (
+        tr("_Search"),
+        Menu {
+            children: vec!(
+                item("_Find ...", Action::Find),
+                item("Find _next", Action::FindNext),
+                item("Find _previous", Action::FindPrevious),
+                item("First _match", Action::FirstMatch),
+                item("Las_t match", Action::LastMatch),
+                item("_Replace ...", Action::Replace),
+                item("Search _history ...", Action::SearchHistory),
+                item("_Clear highlights", Action::ClearHighlights),
+            ),
+        },
+    );
+    let view = (
+        tr("_View"),
+        Menu {
+            children: vec!(
+                check("_Line numbers", Action::ToggleLineNumbers),
+                check("Word _wrap", Action::ToggleWordWrap),
+                check("_Show whitespace", Action::ToggleWhitespace),
+                check("_Minimap", Action::ToggleMinimap),
+                check("Stic_ky scope", Action::ToggleStickyScope),
+                check("Brea_dcrumbs", Action::ToggleBreadcrumbs),
+                check("U_nsaved changes", Action::ToggleUnsavedChanges),
+                separator(),
+                item("Toggle f_old", Action::ToggleFold),
+                item("_Unfold all", Action::UnfoldAll),
+                separator(),
+                check("Ta_ble", Action::ToggleTable),
+                check("Pin table _header", Action::TogglePinHeader),
+                item("Delim_iter ...", Action::Delimiter),
+                separator(),
+                check("_File tree", Action::ToggleFileTree),
+                check("_Terminal", Action::ToggleTerminal),
+                check("_Compare folders ...", Action::ToggleCompare),
+                separator(),
+                check("Comp_act", Action::ToggleCompact),
+                check("_Zen mode", Action::ToggleZenMode),
+                check("Light th_eme", Action::ToggleLightTheme),
+            ),
+        },
+    );
+    let insert = (
+        tr("_Insert"),
+        Menu {
+            children: vec!(
+                item("_Unicode character ...", Action::InsertUnicode),
+                separator(),
+                item("_Date and time", Action::InsertDate),
+                item("Date in _format ...", Action::InsertDateInFormat),
+                item("_Template ...", Action::InsertTemplate),
+            ),
+        },
+    );
+    let go = (
+        tr("_Go"),
+        Menu {
+            children: vec!(
+                item("_Toggle bookmark", Action::ToggleBookmark),
+                item("_Next bookmark", Action::NextBookmark),
+                item("_Previous bookmark", Action::PreviousBookmark),
+                item("_Clear bookmarks", Action::ClearBookmarks),
+                separator(),
+                item("Set _mark ...", Action::SetMark),
+                item("_Jump to mark ...", Action::JumpToMark),
+                separator(),
+                item("Go to _line ...", Action::GotoLine),
+                item("Last _edit", Action::PreviousEdit),
+                item("_Back", Action::JumpBack),
+                item("_Forward", Action::JumpForward),
+                separator(),
+                item("Center c_ursor line", Action::CenterCursorLine),
+                item("Cursor line to t_op", Action::CursorLineToTop),
+                item("Cu_rsor line to bottom", Action::CursorLineToBottom),
+                item("_Scroll lock ...", Action::ScrollLock),
+                item("Brea_dcrumbs ...", Action::Breadcrumbs),
+                separator(),
+                item("Ne_xt function", Action::NextFunction),
+                item("Pre_vious function", Action::PreviousFunction),
+                item("Enclos_ing block start", Action::BlockStart),
+                item("Enclosin_g block end", Action::BlockEnd),
+                separator(),
+                item("Project mar_kers ...", Action::FindMarkers),
+                item("C_heck project", Action::CheckProject),
+            ),
+        },
+    );
+    let window = (
+        tr("_Window"),
+        Menu {
+            children: vec!(
+                item("_Float or dock", Action::ToggleFloating),
+                item("_Move and resize ...", Action::MoveWindow),
+                item("_Split view", Action::SplitView),
+                item("_Compare with ...", Action::CompareWith),
+                item("_Buffers ...", Action::Buffers),
+                item("_Workspaces ...", Action::Workspaces),
+                item("_Put in workspace ...", Action::MoveToWorkspace),
+                item("Re_name tab ...", Action::RenameTab),
+                separator(),
+                item("Close _others", Action::CloseOthers),
+                item("Close _unmodified", Action::CloseUnmodified),
+                item("_Reopen closed file", Action::ReopenClosed),
+                item("Closed fi_les ...", Action::ClosedFiles),
+                item("_Discarded buffers ...", Action::DiscardedBuffers),
+            ),
+        },
+    );
+    let help = (
+        tr("_Help"),
+        Menu {
+            children: vec!(
+                item("Open _man page ...", Action::ManPage),
+                item("View _log", Action::ViewLog),
+                check("_Performance overlay", Action::TogglePerfOverlay),
+                item("_Language ...", Action::Language),
+                item("_About", Action::About),
+            ),
+        },
+    );
+    menu::MenuBar { selection_index: 0, menus: vec!(file, edit, search, view, insert, go, window, help) }
+}
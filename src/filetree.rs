@@ -0,0 +1,229 @@
+//! A sidebar listing the files under a folder, to open them from. Typing finds the entries
+//! whose names have the typed text in them, among those listed, and selects the best of them.
+
+use crossterm::{*, event::KeyCode, style::Color};
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// A file or folder listed in the tree.
+struct Entry {
+    path: PathBuf,
+    name: String,
+    /// How many folders down from the root it is.
+    depth: usize,
+    is_dir: bool,
+}
+
+/// The files and folders under a root folder, as an outline whose folders open and close.
+pub struct FileTree {
+    pub root: PathBuf,
+    // What is listed, in order: the root's entries, with those of each open folder after it.
+    entries: Vec<Entry>,
+    open: HashSet<PathBuf>,
+    selected: usize,
+    // The first entry in view, and how many fit, as of the last render.
+    scroll: usize,
+    height: usize,
+    // What has been typed to find entries by, with Backspace taking it back and Esc clearing it.
+    filter: String,
+}
+
+impl FileTree {
+    pub fn new(root: PathBuf) -> FileTree {
+        let mut tree = FileTree { root, entries: Vec::new(), open: HashSet::new(), selected: 0, scroll: 0, height: 1, filter: String::new() };
+        tree.refresh();
+        tree
+    }
+
+    /// Read the folders again, keeping the same ones open and the same entry selected.
+    pub fn refresh(&mut self) {
+        let selected = self.entries.get(self.selected).map(|e| e.path.clone());
+        self.entries.clear();
+        let root = self.root.clone();
+        self.list(&root, 0);
+        self.selected = selected.and_then(|path| self.entries.iter().position(|e| e.path == path))
+            .unwrap_or(self.selected)
+            .min(self.entries.len().saturating_sub(1));
+    }
+
+    // List the entries of a folder, folders first, each open one followed by its own entries.
+    fn list(&mut self, dir: &Path, depth: usize) {
+        let mut children: Vec<Entry> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok())
+                .map(|e| Entry { path: e.path(), name: e.file_name().to_string_lossy().into_owned(), depth, is_dir: e.path().is_dir() })
+                .filter(|e| e.name != ".git")
+                .collect(),
+            Err(e) => return log::warn!("Could not list the files in {:?}: {}", dir, e),
+        };
+        children.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+        for entry in children {
+            let open = entry.is_dir && self.open.contains(&entry.path);
+            let path = entry.path.clone();
+            self.entries.push(entry);
+            if open {
+                self.list(&path, depth + 1);
+            }
+        }
+    }
+
+    /// Open or close the selected folder.
+    fn toggle(&mut self) {
+        if let Some(entry) = self.entries.get(self.selected).filter(|e| e.is_dir) {
+            let path = entry.path.clone();
+            if !self.open.remove(&path) {
+                self.open.insert(path);
+            }
+            self.refresh();
+        }
+    }
+
+    /// Where the typed text is in an entry's name, as a byte range of it, ignoring case.
+    fn find_in(&self, entry: &Entry) -> Option<(usize, usize)> {
+        if self.filter.is_empty() {
+            return None;
+        }
+        // Lower case letters that take as many bytes keep the range the same in the name itself
+        let fold = |text: &str| -> String { text.chars().map(|c| c.to_lowercase().next().filter(|l| l.len_utf8() == c.len_utf8()).unwrap_or(c)).collect() };
+        let start = fold(&entry.name).find(&fold(&self.filter))?;
+        Some((start, start + self.filter.len()))
+    }
+
+    /// Select the best entry for what has been typed: the first whose name starts with it, or
+    /// else the first with it anywhere in the name.
+    fn select_best_match(&mut self) {
+        let matches: Vec<(usize, usize)> = self.entries.iter().enumerate()
+            .filter_map(|(i, entry)| self.find_in(entry).map(|(start, _)| (i, start)))
+            .collect();
+        if let Some(&(i, _)) = matches.iter().find(|&&(_, start)| start == 0).or(matches.first()) {
+            self.selected = i;
+        }
+    }
+
+    /// Forget what was typed to find entries by. Returns false if nothing was.
+    pub fn clear_filter(&mut self) -> bool {
+        !std::mem::take(&mut self.filter).is_empty()
+    }
+
+    /// Handle a key while the tree has focus. Returns the file picked with Enter, if one was.
+    pub fn handle_key(&mut self, key: KeyCode) -> Option<PathBuf> {
+        let last = self.entries.len().saturating_sub(1);
+        match key {
+            KeyCode::Char(c) => {
+                self.filter.push(c);
+                self.select_best_match();
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+                self.select_best_match();
+            }
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(last),
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(self.height),
+            KeyCode::PageDown => self.selected = (self.selected + self.height).min(last),
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = last,
+            KeyCode::F(5) => self.refresh(),
+            KeyCode::Enter => {
+                self.filter.clear();
+                match self.entries.get(self.selected) {
+                    Some(entry) if !entry.is_dir => return Some(entry.path.clone()),
+                    _ => self.toggle(),
+                }
+            }
+            // Right opens a folder, then goes into it
+            KeyCode::Right => match self.entries.get(self.selected) {
+                Some(entry) if entry.is_dir && !self.open.contains(&entry.path) => self.toggle(),
+                Some(entry) if entry.is_dir => self.selected = (self.selected + 1).min(last),
+                _ => {}
+            },
+            // Left closes a folder, or goes up to the one it is in
+            KeyCode::Left => match self.entries.get(self.selected) {
+                Some(entry) if entry.is_dir && self.open.contains(&entry.path) => self.toggle(),
+                Some(entry) => {
+                    let depth = entry.depth;
+                    if let Some(parent) = self.entries[..self.selected].iter().rposition(|e| e.depth < depth) {
+                        self.selected = parent;
+                    }
+                }
+                None => {}
+            },
+            _ => {}
+        }
+        None
+    }
+
+    /// Select the entry on a row of the tree, counting from its top edge. A folder opens or
+    /// closes, and a file is returned to be opened.
+    pub fn click(&mut self, row: usize) -> Option<PathBuf> {
+        let index = (self.scroll + row.checked_sub(1)?).min(self.entries.len().checked_sub(1)?);
+        self.selected = index;
+        match self.entries[index].is_dir {
+            true => {
+                self.toggle();
+                None
+            }
+            false => Some(self.entries[index].path.clone()),
+        }
+    }
+
+    /// Draw the tree, with the root folder's name on top and a line down its right edge.
+    pub fn render<S: Write>(&mut self, s: &mut S, origin: (u16, u16), size: (usize, usize), focused: bool) {
+        crate::util::draw_rectangle(s, &Color::DarkBlue, origin, size);
+        let width = size.0.saturating_sub(1);
+        // What is typed to find entries takes the bottom row while there is any
+        let finding = !self.filter.is_empty() && size.1 > 2;
+        self.height = size.1.saturating_sub(1 + finding as usize).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + self.height {
+            self.scroll = self.selected + 1 - self.height;
+        }
+
+        let root = self.root.file_name().map_or_else(|| self.root.display().to_string(), |name| name.to_string_lossy().into_owned());
+        let title: String = crate::util::sanitize(&format!(" {} ", root)).chars().take(width).collect();
+        queue!(s, cursor::MoveTo(origin.0, origin.1), style::SetBackgroundColor(Color::Grey), style::SetForegroundColor(Color::Black), style::Print(format!("{:<1$}", title, width)));
+
+        for (row, entry) in self.entries.iter().enumerate().skip(self.scroll).take(self.height) {
+            let mark = match entry.is_dir {
+                true if self.open.contains(&entry.path) => "▾ ",
+                true => "▸ ",
+                false => "  ",
+            };
+            let (bg, fg) = match row == self.selected {
+                true if focused => (Color::Grey, Color::Black),
+                true => (Color::Blue, Color::White),
+                false => (Color::DarkBlue, if entry.is_dir { Color::White } else { Color::Grey }),
+            };
+            // The part of the name that was typed stands out
+            let (before, found, after) = match self.find_in(entry) {
+                Some((start, end)) => (&entry.name[..start], &entry.name[start..end], &entry.name[end..]),
+                None => (entry.name.as_str(), "", ""),
+            };
+            let indent = format!("{}{}", "  ".repeat(entry.depth), mark);
+            let mut room = width;
+            queue!(s, cursor::MoveTo(origin.0, origin.1 + 1 + (row - self.scroll) as u16), style::SetBackgroundColor(bg));
+            for (part, color) in [(indent.as_str(), fg), (before, fg), (found, Color::Yellow), (after, fg)] {
+                let text: String = crate::util::sanitize(part).chars().take(room).collect();
+                room -= text.chars().count();
+                queue!(s, style::SetForegroundColor(if color == Color::Yellow && row == self.selected { Color::DarkRed } else { color }), style::Print(text));
+            }
+            queue!(s, style::Print(" ".repeat(room)));
+        }
+
+        if finding {
+            let text: String = crate::util::sanitize(&format!("{}: {}", crate::locale::tr("Find"), self.filter)).chars().take(width).collect();
+            queue!(s,
+                cursor::MoveTo(origin.0, origin.1 + size.1 as u16 - 1), style::SetBackgroundColor(Color::Grey), style::SetForegroundColor(Color::Black),
+                style::Print(format!("{:<1$}", text, width)),
+            );
+        }
+
+        let edge = if crate::config::get().screen_reader { ' ' } else { '│' };
+        queue!(s, style::SetBackgroundColor(Color::DarkBlue), style::SetForegroundColor(Color::Grey));
+        for y in 0..size.1 {
+            queue!(s, cursor::MoveTo(origin.0 + width as u16, origin.1 + y as u16), style::Print(edge));
+        }
+    }
+}
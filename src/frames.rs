@@ -0,0 +1,58 @@
+//! When the main loop draws a frame. Changes to what is on screen only ask for a frame; it is
+//! drawn once the last one is far enough behind for the frame rate cap (`max_fps`), and not while
+//! more events are already waiting to be handled, up to a point. So a held key or a burst of
+//! output is drawn once per frame rather than once per event, and the loop sleeps in between.
+
+use std::time::{Duration, Instant};
+
+/// Events already waiting are handled together before the screen is drawn again, so that it does
+/// not fall behind a held key on a slow terminal, but it is drawn at least this often.
+static MAX_BATCH: Duration = Duration::from_millis(50);
+
+#[derive(Default)]
+pub struct Frames {
+    // The shortest time from one frame to the next.
+    interval: Duration,
+    last: Option<Instant>,
+    // When the first event handled since the last frame came in, and the first key among them,
+    // to tell how long keys take to show.
+    batch: Option<Instant>,
+    first_key: Option<Instant>,
+}
+
+impl Frames {
+    /// Draw at most so many frames a second, or as often as asked if it is `0`.
+    pub fn new(max_fps: u64) -> Frames {
+        let interval = match max_fps {
+            0 => Duration::ZERO,
+            fps => Duration::from_secs(1) / fps.min(1000) as u32,
+        };
+        Frames { interval, ..Default::default() }
+    }
+
+    /// Note an event handled, which is drawn with the next frame.
+    pub fn handled(&mut self, key: bool) {
+        let now = Instant::now();
+        self.batch.get_or_insert(now);
+        if key {
+            self.first_key.get_or_insert(now);
+        }
+    }
+
+    /// Whether a frame asked for is to be drawn now, given whether events are waiting.
+    pub fn due(&self, waiting: bool) -> bool {
+        self.until_due().is_zero() && !(waiting && self.batch.is_some_and(|since| since.elapsed() < MAX_BATCH))
+    }
+
+    /// How long until the frame rate lets the next frame be drawn.
+    pub fn until_due(&self) -> Duration {
+        self.last.map_or(Duration::ZERO, |last| self.interval.saturating_sub(last.elapsed()))
+    }
+
+    /// Note a frame drawn. Returns how long the first key drawn in it took to show, if it had any.
+    pub fn drawn(&mut self) -> Option<Duration> {
+        self.last = Some(Instant::now());
+        self.batch = None;
+        self.first_key.take().map(|since| since.elapsed())
+    }
+}
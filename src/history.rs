@@ -0,0 +1,535 @@
+//! Undo history, kept as a tree rather than a stack: making a change after undoing starts a new
+//! branch instead of throwing away what was undone, so every state the buffer has been in can
+//! be returned to.
+//!
+//! Characters typed one after another are one change, up to the start of the next word, a new
+//! line or a pause in typing. So is a run of backspaces or deletes.
+//!
+//! Unless turned off with `persistent_undo = false`, the history of a file is written to the
+//! data directory whenever the file is saved, and picked up again the next time it is opened,
+//! as long as the file has not been changed by anything else in the meantime.
+
+use crate::config;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How many characters of changed text a summary shows before it is cut short.
+static SUMMARY_LENGTH: usize = 20;
+/// How long typing can pause and still be part of the same change.
+static GROUP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A change to the text: `removed`, found at byte offset `start`, was replaced by `inserted`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub start: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Edit {
+    /// The smallest single edit turning `old` into `new`, or `None` if they are the same. It
+    /// starts and ends between graphemes in both, as the buffer only goes by whole graphemes: a
+    /// combining accent typed after a letter replaces the letter with the accented one.
+    fn between(old: &str, new: &str) -> Option<Edit> {
+        if old == new {
+            return None;
+        }
+        let mut prefix = old.char_indices().zip(new.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(old.len().min(new.len()), |((i, _), _)| i);
+        while !(is_boundary(old, prefix) && is_boundary(new, prefix)) {
+            prefix -= old[..prefix].chars().next_back().map_or(prefix, char::len_utf8);
+        }
+        let max_suffix = old.len().min(new.len()) - prefix;
+        let mut suffix = old[prefix..].char_indices().rev().zip(new[prefix..].chars().rev())
+            .find(|((i, a), b)| a != b || old.len() - prefix - i > max_suffix)
+            .map_or(max_suffix, |((i, c), _)| old.len() - prefix - i - c.len_utf8());
+        while !(is_boundary(old, old.len() - suffix) && is_boundary(new, new.len() - suffix)) {
+            suffix -= old[old.len() - suffix..].chars().next().map_or(suffix, char::len_utf8);
+        }
+        Some(Edit {
+            start: prefix,
+            removed: old[prefix..old.len() - suffix].to_owned(),
+            inserted: new[prefix..new.len() - suffix].to_owned(),
+        })
+    }
+
+    /// The edit undoing this one.
+    pub fn inverse(&self) -> Edit {
+        Edit { start: self.start, removed: self.inserted.clone(), inserted: self.removed.clone() }
+    }
+
+    /// The one character typed or deleted by this edit, if that is all it does.
+    fn single_char(&self) -> Option<char> {
+        let changed = if self.removed.is_empty() { &self.inserted } else if self.inserted.is_empty() { &self.removed } else { return None };
+        let mut chars = changed.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c != '\n' => Some(c),
+            _ => None,
+        }
+    }
+
+    /// Add a following single-character edit to this one, if it carries on from it: typing right
+    /// after what was typed, or deleting right next to what was deleted, within the same word.
+    fn extend(&mut self, next: &Edit) -> bool {
+        let c = match next.single_char() {
+            Some(c) => c,
+            None => return false,
+        };
+        // A group takes in a word and the whitespace after it, so the next word starts a new one
+        let starts_word = |before: Option<char>| before.is_some_and(char::is_whitespace) && !c.is_whitespace();
+        if self.removed.is_empty() && next.removed.is_empty() && next.start == self.start + self.inserted.len() {
+            if starts_word(self.inserted.chars().next_back()) {
+                return false;
+            }
+            self.inserted.push(c);
+        } else if self.inserted.is_empty() && next.inserted.is_empty() && next.start + next.removed.len() == self.start {
+            // Backspace
+            if starts_word(self.removed.chars().next()) {
+                return false;
+            }
+            self.start = next.start;
+            self.removed.insert(0, c);
+        } else if self.inserted.is_empty() && next.inserted.is_empty() && next.start == self.start {
+            // Delete
+            if starts_word(self.removed.chars().next_back()) {
+                return false;
+            }
+            self.removed.push(c);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    fn apply(&self, text: &mut String) {
+        text.replace_range(self.start..self.start + self.removed.len(), &self.inserted);
+    }
+
+    /// A short description, like `Typed "foo"`.
+    pub fn summary(&self) -> String {
+        let quote = |s: &str| {
+            let mut quoted: String = s.chars().take(SUMMARY_LENGTH).map(|c| if c == '\n' { '⏎' } else { c }).collect();
+            if s.chars().count() > SUMMARY_LENGTH {
+                quoted.push('…');
+            }
+            format!("\"{}\"", quoted)
+        };
+        match (self.removed.is_empty(), self.inserted.is_empty()) {
+            (true, _) => format!("Typed {}", quote(&self.inserted)),
+            (false, true) => format!("Deleted {}", quote(&self.removed)),
+            (false, false) => format!("Replaced {} with {}", quote(&self.removed), quote(&self.inserted)),
+        }
+    }
+}
+
+/// Whether a byte offset into some text falls between two graphemes.
+fn is_boundary(text: &str, offset: usize) -> bool {
+    // A grapheme never goes on past a new line, so only the line the offset is in is read
+    let start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = text[offset..].find('\n').map_or(text.len(), |i| offset + i + 1);
+    offset == text.len() || text[start..end].grapheme_indices(true).any(|(i, _)| start + i == offset)
+}
+
+struct Node {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // The child we last came back up from, which redo returns to.
+    redo_child: Option<usize>,
+    // The change from the parent's text to this node's. Empty for the root.
+    edit: Edit,
+    time: SystemTime,
+    // Whether more typing can still be added to this change.
+    open: bool,
+}
+
+/// One state of the buffer, as listed by `History::entries`.
+pub struct Entry {
+    pub id: usize,
+    /// How many branches deep this state is, for indenting it under the state it branched from.
+    pub depth: usize,
+    pub age: Duration,
+    pub summary: String,
+    pub current: bool,
+    pub saved: bool,
+}
+
+pub struct History {
+    nodes: Vec<Node>,
+    current: usize,
+    saved: Option<usize>,
+    // The text as of the current node.
+    text: String,
+}
+
+impl History {
+    /// Start the history of a buffer with the given text, which is taken to be saved.
+    pub fn new(text: String) -> History {
+        History {
+            nodes: vec![Node { parent: None, children: Vec::new(), redo_child: None, edit: Edit { start: 0, removed: String::new(), inserted: String::new() }, time: SystemTime::now(), open: false }],
+            current: 0,
+            saved: Some(0),
+            text,
+        }
+    }
+
+    /// Record the buffer's text after a change, as a new state following the current one. Returns
+    /// the line the change began on and the change, or `None` if nothing changed.
+    pub fn commit(&mut self, text: String) -> Option<(usize, Edit)> {
+        let edit = Edit::between(&self.text, &text)?;
+        let line = text[..edit.start].matches('\n').count();
+
+        // Carry on with the current change where the edit follows on from it, unless it has been
+        // saved or branched from since
+        let saved = self.saved == Some(self.current);
+        let node = &mut self.nodes[self.current];
+        let recent = node.time.elapsed().is_ok_and(|age| age < GROUP_TIMEOUT);
+        if node.open && recent && !saved && node.children.is_empty() && node.edit.extend(&edit) {
+            node.time = SystemTime::now();
+            self.text = text;
+            return Some((line, edit));
+        }
+
+        let id = self.nodes.len();
+        let open = edit.single_char().is_some();
+        self.nodes.push(Node { parent: Some(self.current), children: Vec::new(), redo_child: None, edit: edit.clone(), time: SystemTime::now(), open });
+        self.nodes[self.current].children.push(id);
+        self.nodes[self.current].redo_child = Some(id);
+        self.current = id;
+        self.text = text;
+        Some((line, edit))
+    }
+
+    /// The text as of the current state.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Step back to the previous state. Returns the edit to make to the buffer to get there.
+    pub fn undo(&mut self) -> Option<Edit> {
+        let parent = self.nodes[self.current].parent?;
+        let edit = self.nodes[self.current].edit.inverse();
+        edit.apply(&mut self.text);
+        self.nodes[parent].redo_child = Some(self.current);
+        self.current = parent;
+        Some(edit)
+    }
+
+    /// Step forward again, along the branch most recently undone. Returns the edit to make to
+    /// the buffer to get there.
+    pub fn redo(&mut self) -> Option<Edit> {
+        let child = self.nodes[self.current].redo_child?;
+        let edit = self.nodes[child].edit.clone();
+        edit.apply(&mut self.text);
+        self.current = child;
+        Some(edit)
+    }
+
+    /// Travel to any state, up to where its branch splits off and down again. Returns the edits
+    /// to make to the buffer to get there, in order.
+    pub fn restore(&mut self, id: usize) -> Vec<Edit> {
+        let ancestors = |mut id: usize| {
+            let mut path = vec![id];
+            while let Some(parent) = self.nodes[id].parent {
+                path.push(parent);
+                id = parent;
+            }
+            path
+        };
+        let target_path = ancestors(id);
+        let mut edits = Vec::new();
+        while !target_path.contains(&self.current) {
+            edits.extend(self.undo());
+        }
+        let descent = target_path.iter().position(|&n| n == self.current).unwrap();
+        for &node in target_path[..descent].iter().rev() {
+            self.nodes[self.current].redo_child = Some(node);
+            edits.extend(self.redo());
+        }
+        edits
+    }
+
+    /// Remember that the current state is what is on disk.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.current);
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.current)
+    }
+
+    /// Every state, each followed by the states branching off it, depth first. Of a state's
+    /// branches, the oldest continues at the same depth and the others are indented.
+    pub fn entries(&self) -> Vec<Entry> {
+        let mut entries = Vec::new();
+        let mut stack = vec![(0, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            let node = &self.nodes[id];
+            entries.push(Entry {
+                id,
+                depth,
+                age: node.time.elapsed().unwrap_or_default(),
+                summary: if id == 0 { "Earliest".to_owned() } else { node.edit.summary() },
+                current: id == self.current,
+                saved: self.saved == Some(id),
+            });
+            for (i, &child) in node.children.iter().enumerate().rev() {
+                stack.push((child, if i == 0 { depth } else { depth + 1 }));
+            }
+        }
+        entries
+    }
+
+    /// Load the history saved for a file, if there is one and it still matches the file's text.
+    pub fn load(path: &Path, text: &str) -> Option<History> {
+        if !config::get().persistent_undo {
+            return None;
+        }
+        let bytes = fs::read(undo_file(path)?).ok()?;
+        let history = History::from_bytes(&bytes, text);
+        if history.is_none() {
+            log::warn!("Ignoring the undo history of {}, which does not match the file", path.display());
+        }
+        history
+    }
+
+    /// Write the history of a file to the data directory, where `load` will find it. The current
+    /// state must be what is on disk. The oldest states are left out to keep it under the size
+    /// limit, though they stay in the history itself.
+    pub fn persist(&self, path: &Path) {
+        let (enabled, max_bytes) = {
+            let config = config::get();
+            (config.persistent_undo, config.persistent_undo_max_bytes)
+        };
+        if let (true, Some(undo_file)) = (enabled, undo_file(path)) {
+            let bytes = match self.first_kept(max_bytes) {
+                0 => self.to_bytes(),
+                first => self.starting_at(first).to_bytes(),
+            };
+            let result = fs::create_dir_all(undo_file.parent().unwrap()).and_then(|()| fs::write(&undo_file, bytes));
+            if let Err(e) = result {
+                log::error!("Could not write the undo history of {} to {}: {}", path.display(), undo_file.display(), e);
+            }
+        }
+    }
+
+    /// The oldest state on the way to the current one that the history can start from and still
+    /// be written out in `max_bytes`, or the current state if even that is too much. Each state
+    /// is measured as written now, which only gets shorter as the states before it are left out.
+    fn first_kept(&self, max_bytes: usize) -> usize {
+        // Children come after their parents, so each state's size takes in those below it
+        let mut sizes: Vec<usize> = self.nodes.iter().map(|node| {
+            let mut bytes = Vec::new();
+            write_node(node, &mut bytes);
+            bytes.len()
+        }).collect();
+        for id in (1..self.nodes.len()).rev() {
+            sizes[self.nodes[id].parent.unwrap()] += sizes[id];
+        }
+        let header = self.header().len();
+        let mut path = vec![self.current];
+        while let Some(parent) = self.nodes[*path.last().unwrap()].parent {
+            path.push(parent);
+        }
+        path.into_iter().rev().find(|&id| header + sizes[id] <= max_bytes).unwrap_or(self.current)
+    }
+
+    /// The history from the given state on, which becomes the first, without the states before it
+    /// or any branches off them.
+    fn starting_at(&self, first: usize) -> History {
+        // Everything descending from the first state is kept, in the same order
+        let mut kept = vec![false; self.nodes.len()];
+        let mut stack = vec![first];
+        while let Some(id) = stack.pop() {
+            kept[id] = true;
+            stack.extend(&self.nodes[id].children);
+        }
+        let mut new_ids = vec![0; self.nodes.len()];
+        for (new_id, id) in (0..self.nodes.len()).filter(|&id| kept[id]).enumerate() {
+            new_ids[id] = new_id;
+        }
+
+        let nodes = self.nodes.iter().enumerate().filter(|(id, _)| kept[*id]).map(|(id, node)| Node {
+            parent: if id == first { None } else { node.parent.map(|p| new_ids[p]) },
+            children: node.children.iter().map(|&c| new_ids[c]).collect(),
+            redo_child: node.redo_child.map(|c| new_ids[c]),
+            edit: if id == first { Edit { start: 0, removed: String::new(), inserted: String::new() } } else { node.edit.clone() },
+            time: node.time,
+            open: false,
+        }).collect();
+        History {
+            nodes,
+            current: new_ids[self.current],
+            saved: self.saved.filter(|&s| kept[s]).map(|s| new_ids[s]),
+            text: self.text.clone(),
+        }
+    }
+
+    fn header(&self) -> String {
+        format!("{}\n{:016x} {} {}\n", UNDO_FILE_HEADER, checksum(&self.text), self.nodes.len(), self.current)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header().into_bytes();
+        for node in &self.nodes {
+            write_node(node, &mut bytes);
+        }
+        bytes
+    }
+
+    /// Read back what `to_bytes` wrote, for a buffer holding the given text.
+    fn from_bytes(bytes: &[u8], text: &str) -> Option<History> {
+        let mut rest = bytes;
+        if take_line(&mut rest)?.join(" ") != UNDO_FILE_HEADER {
+            return None;
+        }
+        let summary = take_line(&mut rest)?;
+        if summary.len() != 3 || summary[0] != format!("{:016x}", checksum(text)) {
+            return None;
+        }
+        let (count, current): (usize, usize) = (summary[1].parse().ok()?, summary[2].parse().ok()?);
+
+        let optional = |s: &str| if s == "-" { Some(None) } else { s.parse().ok().map(Some) };
+        let mut nodes: Vec<Node> = Vec::with_capacity(count);
+        for id in 0..count {
+            let fields = take_line(&mut rest)?;
+            if fields.len() != 6 {
+                return None;
+            }
+            let (parent, redo_child): (Option<usize>, Option<usize>) = (optional(&fields[0])?, optional(&fields[1])?);
+            let time = UNIX_EPOCH + Duration::from_secs(fields[2].parse().ok()?);
+            let start: usize = fields[3].parse().ok()?;
+            let (removed_len, inserted_len): (usize, usize) = (fields[4].parse().ok()?, fields[5].parse().ok()?);
+            if rest.len() < removed_len + inserted_len + 1 || parent.is_some_and(|p| p >= id) || (id > 0) != parent.is_some() {
+                return None;
+            }
+            let removed = String::from_utf8(rest[..removed_len].to_vec()).ok()?;
+            let inserted = String::from_utf8(rest[removed_len..removed_len + inserted_len].to_vec()).ok()?;
+            rest = &rest[removed_len + inserted_len + 1..];
+
+            if let Some(parent) = parent {
+                nodes[parent].children.push(id);
+            }
+            nodes.push(Node { parent, children: Vec::new(), redo_child, edit: Edit { start, removed, inserted }, time, open: false });
+        }
+        if current >= count || nodes.iter().any(|n| n.redo_child.is_some_and(|c| c >= count)) {
+            return None;
+        }
+
+        Some(History { nodes, current, saved: Some(current), text: text.to_owned() })
+    }
+}
+
+static UNDO_FILE_HEADER: &str = "qedit-undo 1";
+
+/// Write out a node for `History::to_bytes`: a line with its fields, then the text it removed and
+/// inserted, then a new line.
+fn write_node(node: &Node, bytes: &mut Vec<u8>) {
+    let optional = |n: Option<usize>| n.map_or("-".to_owned(), |n| n.to_string());
+    let time = node.time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    bytes.extend(format!(
+        "{} {} {} {} {} {}\n",
+        optional(node.parent), optional(node.redo_child), time, node.edit.start, node.edit.removed.len(), node.edit.inserted.len(),
+    ).as_bytes());
+    bytes.extend(node.edit.removed.as_bytes());
+    bytes.extend(node.edit.inserted.as_bytes());
+    bytes.push(b'\n');
+}
+
+/// Split the next line off the bytes, into its space-separated fields.
+fn take_line(rest: &mut &[u8]) -> Option<Vec<String>> {
+    let end = rest.iter().position(|&b| b == b'\n')?;
+    let line = std::str::from_utf8(&rest[..end]).ok()?.split(' ').map(|s| s.to_owned()).collect();
+    *rest = &rest[end + 1..];
+    Some(line)
+}
+
+/// Where the undo history of the given file is kept: in the data directory, named after the
+/// file's full path with its slashes turned into percent signs.
+fn undo_file(path: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().ok()?;
+    let name = path.to_string_lossy().replace('%', "%%").replace(std::path::MAIN_SEPARATOR, "%");
+    config::data_dir().map(|dir| dir.join("undo").join(name))
+}
+
+/// The 64-bit FNV-1a hash of some text, to tell whether a file changed since its history was saved.
+fn checksum(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_start_and_end_between_graphemes() {
+        let edit = Edit::between("ex", "ex\u{301}").unwrap();
+        assert_eq!(edit, Edit { start: 1, removed: "x".to_owned(), inserted: "x\u{301}".to_owned() });
+        let edit = Edit::between("a\u{301}b", "a\u{301}\u{300}b").unwrap();
+        assert_eq!(edit, Edit { start: 0, removed: "a\u{301}".to_owned(), inserted: "a\u{301}\u{300}".to_owned() });
+        let edit = Edit::between("\u{301}x", "y\u{301}x").unwrap();
+        assert_eq!(edit, Edit { start: 0, removed: "\u{301}".to_owned(), inserted: "y\u{301}".to_owned() });
+        assert_eq!(Edit::between("abc", "abc"), None);
+    }
+
+    #[test]
+    fn undo_and_redo_give_back_each_state() {
+        let states = ["ex", "ex\u{301}", "e\u{301}x\u{301}", "e\u{301}", "", "a\nb\u{301}\nc"];
+        let mut history = History::new(states[0].to_owned());
+        for (i, state) in states.iter().enumerate().skip(1) {
+            let (line, edit) = history.commit(state.to_string()).unwrap();
+            let mut text = states[i - 1].to_owned();
+            edit.apply(&mut text);
+            assert_eq!(text, *state);
+            assert_eq!(line, state[..edit.start].matches('\n').count());
+            history.nodes[history.current].open = false;
+        }
+        for i in (0..states.len() - 1).rev() {
+            let mut text = history.text().to_owned();
+            history.undo().unwrap().apply(&mut text);
+            assert_eq!((text.as_str(), history.text()), (states[i], states[i]));
+        }
+        assert_eq!(history.undo(), None);
+        for state in &states[1..] {
+            history.redo().unwrap();
+            assert_eq!(history.text(), *state);
+        }
+    }
+
+    #[test]
+    fn typing_is_grouped_by_word() {
+        let mut history = History::new(String::new());
+        for text in ["a", "ab", "ab ", "ab c", "ab cd"] {
+            history.commit(text.to_owned());
+        }
+        let summaries: Vec<String> = history.entries().into_iter().map(|entry| entry.summary).collect();
+        assert_eq!(summaries, ["Earliest", "Typed \"ab \"", "Typed \"cd\""]);
+    }
+
+    #[test]
+    fn the_history_written_out_is_cut_to_size_but_kept_whole() {
+        let mut history = History::new(String::new());
+        for text in ["one", "one two", "one two three", "one two three four"] {
+            history.commit(text.to_owned());
+            history.nodes[history.current].open = false;
+        }
+        let whole = history.to_bytes();
+        assert_eq!(history.first_kept(whole.len()), 0);
+
+        let first = history.first_kept(whole.len() - 1);
+        assert!(first > 0 && first < history.current);
+        let cut = history.starting_at(first).to_bytes();
+        assert!(cut.len() < whole.len());
+        assert_eq!(history.to_bytes(), whole);
+        let mut read = History::from_bytes(&cut, history.text()).unwrap();
+        assert_eq!(read.nodes.len(), history.nodes.len() - first);
+        while read.undo().is_some() {}
+        assert_eq!(read.text(), "one");
+
+        assert_eq!(history.first_kept(0), history.current);
+    }
+}
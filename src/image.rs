@@ -0,0 +1,471 @@
+//! PNG and JPEG images opened as files. Terminals that can draw pictures get them with the kitty
+//! graphics protocol or as sixels; the others, and screen readers, get a line saying what the
+//! image is instead of its bytes as text.
+//!
+//! kitty decodes PNG itself. For sixels, PNG images are decoded here, as long as they are not
+//! interlaced; JPEG images are only described.
+
+use crossterm::{*, style::Color};
+use lazy_static::*;
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// How big a character cell is taken to be in pixels, for sizing images in cells.
+static CELL_PIXELS: (usize, usize) = (10, 20);
+/// kitty takes the image's data in pieces of at most this many bytes of base64.
+static KITTY_CHUNK: usize = 4096;
+/// Images with more pixels than this are not decoded, as they would take up too much memory.
+static MAX_PIXELS: usize = 1 << 24;
+
+/// The ways of drawing pictures in a terminal.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Protocol {
+    Kitty,
+    Sixel,
+    /// None at all: images are described in words.
+    None,
+}
+
+impl Protocol {
+    /// Go by what the environment says about the terminal: `TERM`, `TERM_PROGRAM` and whether
+    /// it is kitty. Inside tmux nothing is drawn, as it would not pass the pictures on.
+    pub fn detect() -> Protocol {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+        if std::env::var_os("TMUX").is_some() {
+            Protocol::None
+        } else if std::env::var_os("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" || matches!(program.as_str(), "WezTerm" | "ghostty") {
+            Protocol::Kitty
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") || term == "contour" {
+            Protocol::Sixel
+        } else {
+            Protocol::None
+        }
+    }
+}
+
+impl std::str::FromStr for Protocol {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Protocol, ()> {
+        match s {
+            "kitty" => Ok(Protocol::Kitty),
+            "sixel" => Ok(Protocol::Sixel),
+            "none" => Ok(Protocol::None),
+            _ => Err(()),
+        }
+    }
+}
+
+lazy_static! {
+    static ref PROTOCOL: Protocol = {
+        let config = crate::config::get();
+        match config.screen_reader {
+            true => Protocol::None,
+            false => config.images.unwrap_or_else(Protocol::detect),
+        }
+    };
+}
+
+/// How the terminal we run in draws images, as configured or else detected.
+pub fn protocol() -> Protocol {
+    *PROTOCOL
+}
+
+// Whether kitty has been asked to show any image since they were last taken off the screen.
+static KITTY_SHOWN: AtomicBool = AtomicBool::new(false);
+static NEXT_KITTY_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Take every image kitty was asked to show off the screen, ready for drawing the next frame.
+/// Their data stays with kitty, so they can be shown again.
+pub fn clear<S: Write>(s: &mut S) {
+    if KITTY_SHOWN.swap(false, Ordering::Relaxed) {
+        queue!(s, style::Print("\x1b_Ga=d,d=a,q=2\x1b\\"));
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+    Png,
+    Jpeg,
+}
+
+/// The kind of image a file holds, from the bytes it starts with.
+pub fn sniff(bytes: &[u8]) -> Option<Format> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(Format::Png)
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some(Format::Jpeg)
+    } else {
+        None
+    }
+}
+
+/// An image file, ready to draw in a viewport.
+pub struct Image {
+    pub path: PathBuf,
+    pub format: Format,
+    /// The size in pixels, if the file says.
+    pub size: Option<(usize, usize)>,
+    bytes: Vec<u8>,
+    // The image's number with kitty, once its data has been sent.
+    kitty_id: Option<u32>,
+    // The pixels as red, green, blue and alpha, decoded for sixels, or why they could not be, and
+    // the sixels last drawn with the number of cells they fit.
+    pixels: Option<std::result::Result<Vec<u8>, &'static str>>,
+    sixels: Option<((usize, usize), String)>,
+}
+
+impl Image {
+    /// Take in an image file's bytes, decoding them now if they will be needed as pixels. Returns
+    /// `None` if they are not of an image.
+    pub fn new(path: PathBuf, bytes: Vec<u8>) -> Option<Image> {
+        let format = sniff(&bytes)?;
+        let size = match format {
+            Format::Png => png_size(&bytes),
+            Format::Jpeg => jpeg_size(&bytes),
+        };
+        let pixels = match (format, protocol()) {
+            (Format::Png, Protocol::Sixel) => Some(decode_png(&bytes).inspect_err(|e| log::warn!("Could not decode {}: {}", path.display(), e))),
+            _ => None,
+        };
+        Some(Image { path, format, size, bytes, kitty_id: None, pixels, sixels: None })
+    }
+
+    /// What the image is, in a few words: its kind, size in pixels and size on disk.
+    pub fn describe(&self) -> String {
+        let kind = match self.format {
+            Format::Png => "PNG",
+            Format::Jpeg => "JPEG",
+        };
+        let bytes = match self.bytes.len() {
+            n if n < 1024 => format!("{} bytes", n),
+            n if n < 1024 * 1024 => format!("{:.1} KiB", n as f64 / 1024.0),
+            n => format!("{:.1} MiB", n as f64 / (1024.0 * 1024.0)),
+        };
+        match self.size {
+            Some((width, height)) => format!("{} image, {} × {} pixels, {}", kind, width, height, bytes),
+            None => format!("{} image, {}", kind, bytes),
+        }
+    }
+
+    /// Draw the image as big as fits in the area, and no bigger than it is, with what it is on
+    /// the row below. Where it cannot be drawn, there is only the description.
+    pub fn render<S: Write>(&mut self, s: &mut S, origin: (u16, u16), size: (usize, usize)) {
+        let describe = |s: &mut S, row: usize, text: &str| {
+            let text: String = crate::util::sanitize(text).chars().take(size.0).collect();
+            let x = origin.0 + (size.0.saturating_sub(text.chars().count()) / 2) as u16;
+            queue!(s, cursor::MoveTo(x, origin.1 + row as u16), style::SetBackgroundColor(Color::Blue), style::SetForegroundColor(Color::Grey), style::Print(text));
+        };
+        let area = (size.0, size.1.saturating_sub(2));
+        let cells = match (self.size, protocol(), self.format) {
+            (Some(pixels), Protocol::Kitty, Format::Png) if area.0 > 0 && area.1 > 0 => Some(fit(pixels, area)),
+            (Some(pixels), Protocol::Sixel, _) if matches!(self.pixels, Some(Ok(_))) && area.0 > 0 && area.1 > 0 => Some(fit(pixels, area)),
+            _ => None,
+        };
+        let Some(cells) = cells else {
+            describe(s, (size.1 / 2).saturating_sub(1), &self.describe());
+            let why = match self.pixels {
+                Some(Err(why)) => why,
+                _ => "This terminal cannot show images",
+            };
+            describe(s, size.1 / 2, &crate::locale::tr(why));
+            return;
+        };
+
+        let at = (origin.0 + ((area.0 - cells.0) / 2) as u16, origin.1 + ((area.1 - cells.1) / 2) as u16);
+        describe(s, at.1 as usize - origin.1 as usize + cells.1, &self.describe());
+        queue!(s, cursor::MoveTo(at.0, at.1));
+        match protocol() {
+            Protocol::Kitty => {
+                let id = match self.kitty_id {
+                    Some(id) => id,
+                    None => {
+                        let id = NEXT_KITTY_ID.fetch_add(1, Ordering::Relaxed);
+                        let data = crate::util::base64_encode(&self.bytes);
+                        let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+                        for (i, chunk) in chunks.iter().enumerate() {
+                            let more = (i + 1 < chunks.len()) as u8;
+                            let keys = match i {
+                                0 => format!("a=t,f=100,i={},q=2,m={}", id, more),
+                                _ => format!("m={}", more),
+                            };
+                            queue!(s, style::Print(format!("\x1b_G{};{}\x1b\\", keys, String::from_utf8_lossy(chunk))));
+                        }
+                        self.kitty_id = Some(id);
+                        id
+                    }
+                };
+                queue!(s, style::Print(format!("\x1b_Ga=p,i={},p=1,c={},r={},C=1,q=2\x1b\\", id, cells.0, cells.1)));
+                KITTY_SHOWN.store(true, Ordering::Relaxed);
+            }
+            Protocol::Sixel => {
+                if self.sixels.as_ref().is_none_or(|(drawn, _)| *drawn != cells) {
+                    let (Some(Ok(pixels)), Some((width, height))) = (&self.pixels, self.size) else { return };
+                    let scaled = scale(pixels, (width, height), (cells.0 * CELL_PIXELS.0, cells.1 * CELL_PIXELS.1));
+                    self.sixels = Some((cells, sixels(&scaled.0, scaled.1)));
+                }
+                queue!(s, style::Print(&self.sixels.as_ref().unwrap().1));
+            }
+            Protocol::None => {}
+        }
+    }
+}
+
+/// How many cells an image of a size in pixels takes up in an area, keeping its shape. Images are
+/// made smaller to fit, but never bigger than they are.
+fn fit(pixels: (usize, usize), area: (usize, usize)) -> (usize, usize) {
+    let (width, height) = (pixels.0 as f64 / CELL_PIXELS.0 as f64, pixels.1 as f64 / CELL_PIXELS.1 as f64);
+    let scale = (area.0 as f64 / width).min(area.1 as f64 / height).min(1.0);
+    (((width * scale).round() as usize).clamp(1, area.0), ((height * scale).round() as usize).clamp(1, area.1))
+}
+
+fn png_size(bytes: &[u8]) -> Option<(usize, usize)> {
+    let header = bytes.get(16..24)?;
+    let number = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize;
+    Some((number(&header[..4]), number(&header[4..])))
+}
+
+// The size given by the frame header that starts the image data.
+fn jpeg_size(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut i = 2;
+    while i + 9 < bytes.len() {
+        if bytes[i] != 0xff {
+            return None;
+        }
+        let marker = bytes[i + 1];
+        let len = u16::from_be_bytes([bytes[i + 2], bytes[i + 3]]) as usize;
+        // Start of frame markers, leaving out those for the huffman and arithmetic tables
+        if (0xc0..=0xcf).contains(&marker) && !matches!(marker, 0xc4 | 0xc8 | 0xcc) {
+            let height = u16::from_be_bytes([bytes[i + 5], bytes[i + 6]]) as usize;
+            let width = u16::from_be_bytes([bytes[i + 7], bytes[i + 8]]) as usize;
+            return Some((width, height));
+        }
+        i += 2 + len;
+    }
+    None
+}
+
+/// Decode a PNG image into its pixels, as red, green, blue and alpha bytes, row after row.
+/// Fails with why for anything broken, for interlaced images, which are not supported, and for
+/// images of more than `MAX_PIXELS`, whatever size the file says they are.
+fn decode_png(bytes: &[u8]) -> std::result::Result<Vec<u8>, &'static str> {
+    const BROKEN: &str = "The image is broken";
+    let (mut header, mut palette, mut transparency, mut data) = (None, Vec::new(), Vec::new(), Vec::new());
+    let mut i = 8;
+    while i + 8 <= bytes.len() {
+        let len = u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]) as usize;
+        let body = bytes.get(i + 8..i + 8 + len).ok_or(BROKEN)?;
+        match &bytes[i + 4..i + 8] {
+            b"IHDR" => header = Some(body),
+            b"PLTE" => palette = body.to_vec(),
+            b"tRNS" => transparency = body.to_vec(),
+            b"IDAT" => data.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        i += 12 + len;
+    }
+    let header = header.filter(|h| h.len() >= 13).ok_or(BROKEN)?;
+    let (width, height) = png_size(bytes).ok_or(BROKEN)?;
+    let (depth, color, interlaced) = (header[8] as usize, header[9], header[12] != 0);
+    let channels = match color {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(BROKEN),
+    };
+    if interlaced {
+        return Err("Interlaced images cannot be shown");
+    }
+    if !matches!(depth, 1 | 2 | 4 | 8 | 16) {
+        return Err(BROKEN);
+    }
+    if width.checked_mul(height).is_none_or(|pixels| pixels > MAX_PIXELS) {
+        return Err("The image is too big to show");
+    }
+    // No more is inflated than the rows take, each with its filter byte, however much the data
+    // would inflate to
+    let row_len = (width * channels * depth).div_ceil(8);
+    let data = inflate(&data, (row_len + 1) * height).ok_or(BROKEN)?;
+
+    // Undo each row's filter, which predicts every byte from the ones left of it and above it
+    let row_len = (width * channels * depth).div_ceil(8);
+    let step = (channels * depth / 8).max(1);
+    let mut rows = vec![0u8; row_len * height];
+    for y in 0..height {
+        let filter = data[y * (row_len + 1)];
+        let line = &data[y * (row_len + 1) + 1..(y + 1) * (row_len + 1)];
+        for x in 0..row_len {
+            let left = if x >= step { rows[y * row_len + x - step] } else { 0 };
+            let up = if y > 0 { rows[(y - 1) * row_len + x] } else { 0 };
+            let up_left = if x >= step && y > 0 { rows[(y - 1) * row_len + x - step] } else { 0 };
+            let prediction = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(BROKEN),
+            };
+            rows[y * row_len + x] = line[x].wrapping_add(prediction);
+        }
+    }
+
+    // Each sample, brought to 8 bits
+    let sample = |row: &[u8], n: usize| -> u8 {
+        match depth {
+            16 => row[n * 2],
+            8 => row[n],
+            _ => {
+                let bits = n * depth;
+                let value = (row[bits / 8] >> (8 - depth - bits % 8)) & ((1 << depth) - 1) as u8;
+                if color == 3 { value } else { (value as usize * 255 / ((1 << depth) - 1)) as u8 }
+            }
+        }
+    };
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for row in rows.chunks(row_len) {
+        for x in 0..width {
+            let n = x * channels;
+            let pixel = match color {
+                0 => [sample(row, n); 3].iter().copied().chain([255]).collect::<Vec<u8>>(),
+                2 => vec![sample(row, n), sample(row, n + 1), sample(row, n + 2), 255],
+                3 => {
+                    let index = sample(row, n) as usize;
+                    let rgb = palette.get(index * 3..index * 3 + 3).ok_or(BROKEN)?;
+                    vec![rgb[0], rgb[1], rgb[2], *transparency.get(index).unwrap_or(&255)]
+                }
+                4 => vec![sample(row, n), sample(row, n), sample(row, n), sample(row, n + 1)],
+                _ => vec![sample(row, n), sample(row, n + 1), sample(row, n + 2), sample(row, n + 3)],
+            };
+            pixels.extend_from_slice(&pixel);
+        }
+    }
+    Ok(pixels)
+}
+
+/// Inflate zlib data into as many bytes as are wanted, and no more. Fails if the data is
+/// broken or inflates to fewer.
+fn inflate(data: &[u8], len: usize) -> Option<Vec<u8>> {
+    use miniz_oxide::inflate::{TINFLStatus, core::{decompress, inflate_flags, DecompressorOxide}};
+    let mut inflated = vec![0u8; len];
+    let flags = inflate_flags::TINFL_FLAG_PARSE_ZLIB_HEADER | inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+    let (status, _, written) = decompress(&mut Box::<DecompressorOxide>::default(), data, &mut std::io::Cursor::new(inflated.as_mut_slice()), flags);
+    match status {
+        TINFLStatus::Done | TINFLStatus::HasMoreOutput if written == len => Some(inflated),
+        _ => None,
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+}
+
+/// Shrink pixels to fit in a size, keeping their shape, by taking the nearest pixel for each.
+/// Returns the new pixels and their size.
+fn scale(pixels: &[u8], from: (usize, usize), within: (usize, usize)) -> (Vec<u8>, (usize, usize)) {
+    let factor = (within.0 as f64 / from.0 as f64).min(within.1 as f64 / from.1 as f64).min(1.0);
+    let to = (((from.0 as f64 * factor) as usize).max(1), ((from.1 as f64 * factor) as usize).max(1));
+    let mut out = Vec::with_capacity(to.0 * to.1 * 4);
+    for y in 0..to.1 {
+        for x in 0..to.0 {
+            let i = ((y * from.1 / to.1) * from.0 + x * from.0 / to.0) * 4;
+            out.extend_from_slice(&pixels[i..i + 4]);
+        }
+    }
+    (out, to)
+}
+
+/// Encode pixels as sixels, in the 216 colors of a 6×6×6 cube. Pixels that are mostly
+/// transparent are left out.
+fn sixels(pixels: &[u8], (width, height): (usize, usize)) -> String {
+    let level = |v: u8| (v as usize * 5 + 127) / 255;
+    let colors: Vec<Option<usize>> = pixels.chunks(4)
+        .map(|p| (p[3] >= 128).then(|| level(p[0]) * 36 + level(p[1]) * 6 + level(p[2])))
+        .collect();
+
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+    for color in 0..216 {
+        let percent = |n: usize| n * 100 / 5;
+        out.push_str(&format!("#{};2;{};{};{}", color, percent(color / 36), percent(color / 6 % 6), percent(color % 6)));
+    }
+    for band in (0..height).step_by(6) {
+        let rows = band..(band + 6).min(height);
+        let mut used: Vec<usize> = rows.clone().flat_map(|y| colors[y * width..(y + 1) * width].iter().flatten().copied()).collect();
+        used.sort_unstable();
+        used.dedup();
+        for color in used {
+            out.push_str(&format!("#{}", color));
+            // Each column's six pixels of the color make one character, repeats counted with !
+            let mut run: Option<(u8, usize)> = None;
+            let flush = |out: &mut String, run: Option<(u8, usize)>| match run {
+                Some((c, n)) if n > 3 => out.push_str(&format!("!{}{}", n, c as char)),
+                Some((c, n)) => out.extend(std::iter::repeat_n(c as char, n)),
+                None => {}
+            };
+            for x in 0..width {
+                let bits = rows.clone().enumerate().filter(|&(_, y)| colors[y * width + x] == Some(color)).fold(0, |bits, (i, _)| bits | 1 << i);
+                let c = 63 + bits as u8;
+                match &mut run {
+                    Some((last, n)) if *last == c => *n += 1,
+                    _ => {
+                        flush(&mut out, run);
+                        run = Some((c, 1));
+                    }
+                }
+            }
+            flush(&mut out, run);
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PNG file of 8-bit grey pixels, of the size given and with the rows given.
+    fn png(size: (u32, u32), rows: &[u8]) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut chunk = |kind: &[u8], body: &[u8]| {
+            bytes.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(kind);
+            bytes.extend_from_slice(body);
+            bytes.extend_from_slice(&[0; 4]); // The checksum, which is not looked at
+        };
+        let mut header = Vec::new();
+        header.extend_from_slice(&size.0.to_be_bytes());
+        header.extend_from_slice(&size.1.to_be_bytes());
+        header.extend_from_slice(&[8, 0, 0, 0, 0]);
+        chunk(b"IHDR", &header);
+        chunk(b"IDAT", &miniz_oxide::deflate::compress_to_vec_zlib(rows, 6));
+        chunk(b"IEND", &[]);
+        bytes
+    }
+
+    #[test]
+    fn decodes_grey_pixels() {
+        let pixels = decode_png(&png((2, 1), &[0, 10, 200])).unwrap();
+        assert_eq!(pixels, [10, 10, 10, 255, 200, 200, 200, 255]);
+    }
+
+    #[test]
+    fn refuses_sizes_too_big_without_allocating_them() {
+        assert_eq!(decode_png(&png((1 << 30, 1 << 30), &[0, 1])), Err("The image is too big to show"));
+        assert_eq!(decode_png(&png((u32::MAX, u32::MAX), &[0, 1])), Err("The image is too big to show"));
+    }
+
+    #[test]
+    fn refuses_data_cut_short() {
+        assert_eq!(decode_png(&png((4, 4), &[0, 1, 2, 3, 4])), Err("The image is broken"));
+    }
+}
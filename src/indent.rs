@@ -0,0 +1,220 @@
+//! How a buffer is indented, and guessing it from the leading whitespace of a file so that the
+//! Tab key keeps to the file's own style. Each language also has rules for indenting new lines,
+//! as patterns of the lines that open a block and those that close one.
+
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+
+use std::fmt;
+use std::path::Path;
+
+/// What pressing Tab inserts.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Indentation {
+    Tabs,
+    /// This many columns of spaces.
+    Spaces(usize),
+}
+
+/// The choices offered when changing a buffer's indentation by hand.
+pub static CHOICES: &[Indentation] = &[Indentation::Tabs, Indentation::Spaces(2), Indentation::Spaces(4), Indentation::Spaces(8)];
+
+/// Look at no more lines than this when guessing, which is plenty and keeps opening huge files quick.
+static LINES_TO_EXAMINE: usize = 10_000;
+
+impl Indentation {
+    /// Guess the indentation of some text, or `None` if too little of it is indented to tell.
+    ///
+    /// Tabs win if more lines begin with a tab than with spaces. Otherwise the width is the most
+    /// common step between the indentation of one line and the next, ignoring the single spaces
+    /// that line up block comments.
+    pub fn detect(text: &str) -> Option<Indentation> {
+        let (mut tab_lines, mut space_lines) = (0, 0);
+        let mut steps = [0usize; 9]; // How often each increase in indentation, of 2 to 8 spaces, was seen
+        let mut previous = 0;
+        for line in text.lines().take(LINES_TO_EXAMINE) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.starts_with('\t') {
+                tab_lines += 1;
+                previous = 0;
+                continue;
+            }
+            let spaces = line.len() - line.trim_start_matches(' ').len();
+            if spaces > 1 {
+                space_lines += 1;
+            }
+            if spaces > previous && (2..=8).contains(&(spaces - previous)) {
+                steps[spaces - previous] += 1;
+            }
+            if spaces != 1 {
+                previous = spaces;
+            }
+        }
+
+        if tab_lines > space_lines {
+            Some(Indentation::Tabs)
+        } else {
+            let (width, count) = steps.iter().enumerate().max_by_key(|&(_, count)| *count)?;
+            if *count == 0 { None } else { Some(Indentation::Spaces(width)) }
+        }
+    }
+
+    /// What to insert for one level of indentation at the given column.
+    pub fn text(self, column: usize) -> String {
+        match self {
+            Indentation::Tabs => "\t".to_owned(),
+            Indentation::Spaces(width) => " ".repeat(width - column % width),
+        }
+    }
+}
+
+/// Re-indent lines of pasted text to sit at the indentation of the line they are pasted into,
+/// keeping how far each is indented past the others. `before` is the text on that line before
+/// the cursor, and `indent` its leading whitespace.
+///
+/// The first line carries on from the cursor as it is, unless there is only whitespace before
+/// the cursor, when it is indented like the others. Text of a single line is left alone.
+pub fn reindent(text: &str, before: &str, indent: &str) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    if lines.len() < 2 {
+        return text.to_owned();
+    }
+    let at_indentation = before.trim().is_empty();
+    let leading = |line: &str| line.chars().take_while(|c| c.is_whitespace()).count();
+    let first_indented = at_indentation && lines[0].starts_with(char::is_whitespace);
+    // How indented the pasted lines were where they were copied from: the least of them, counting
+    // the first only if it was copied from the start of its line
+    let base = lines.iter().enumerate()
+        .filter(|&(i, line)| (i > 0 || first_indented) && !line.trim().is_empty())
+        .map(|(_, line)| leading(line))
+        .min()
+        .unwrap_or_else(|| leading(lines[0]));
+
+    let mut out = String::with_capacity(text.len());
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        if i == 0 && !at_indentation {
+            out.push_str(line);
+            continue;
+        }
+        // The first line already has the whitespace before the cursor in front of it
+        let indent = if i == 0 { indent.get(before.len()..).unwrap_or("") } else { indent };
+        let strip = leading(line).min(base);
+        out.push_str(indent);
+        out.push_str(&line[line.char_indices().nth(strip).map_or(line.len(), |(i, _)| i)..]);
+    }
+    out
+}
+
+/// When to indent a line further or less than the one before it, in a file of some language.
+pub struct Rules {
+    /// Lines after which the next is indented a level further, like `if x {`.
+    pub increase: Option<Regex>,
+    /// Lines indented a level less than those before them, like `}`.
+    pub decrease: Option<Regex>,
+}
+
+impl Rules {
+    /// The rules for a file: its language's, unless the config gives patterns of its own for
+    /// the file's extension.
+    pub fn for_path(path: Option<&Path>) -> Rules {
+        let language = crate::syntax::language_for(path);
+        let extension = path.and_then(Path::extension).map(|e| e.to_string_lossy().to_lowercase());
+        let config = crate::config::get();
+        let pattern = |overrides: &[(String, String)], default: Option<&'static str>| {
+            let pattern = match overrides.iter().find(|(e, _)| Some(e) == extension.as_ref()) {
+                Some((_, pattern)) => pattern.as_str(),
+                None => default?,
+            };
+            match Regex::new(pattern) {
+                _ if pattern.is_empty() => None,
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    log::warn!("Ignoring the indent pattern {:?}: {}", pattern, e);
+                    None
+                }
+            }
+        };
+        Rules {
+            increase: pattern(&config.indent_increase, language.and_then(|l| l.indent_increase)),
+            decrease: pattern(&config.indent_decrease, language.and_then(|l| l.indent_decrease)),
+        }
+    }
+
+    pub fn increases(&self, line: &str) -> bool {
+        self.increase.as_ref().is_some_and(|regex| regex.is_match(line))
+    }
+
+    pub fn decreases(&self, line: &str) -> bool {
+        self.decrease.as_ref().is_some_and(|regex| regex.is_match(line))
+    }
+}
+
+/// The whitespace a line starts with, as whole graphemes: a space with an accent on it is not
+/// whitespace.
+pub fn leading(line: &str) -> &str {
+    &line[..line.graphemes(true).take_while(|g| g.chars().all(char::is_whitespace)).map(str::len).sum()]
+}
+
+/// Indentation one level less than `indent`: without its last tab, or without as many of its
+/// last spaces as a level takes, or what is left of them.
+pub fn outdent(indent: &str, indentation: Indentation) -> &str {
+    if let Some(rest) = indent.strip_suffix('\t') {
+        return rest;
+    }
+    let width = match indentation {
+        Indentation::Tabs => 8,
+        Indentation::Spaces(width) => width,
+    };
+    let spaces = indent.len() - indent.trim_end_matches(' ').len();
+    &indent[..indent.len() - spaces.min(width)]
+}
+
+impl fmt::Display for Indentation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Indentation::Tabs => write!(f, "Tabs"),
+            Indentation::Spaces(width) => write!(f, "Spaces: {}", width),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leading_whitespace_is_whole_graphemes() {
+        assert_eq!(leading("\t  x "), "\t  ");
+        assert_eq!(leading(" \u{301}b"), "");
+        assert_eq!(leading("  \u{301}b"), " ");
+        assert_eq!(leading("   "), "   ");
+    }
+
+    #[test]
+    fn languages_indent_after_blocks_open_and_outdent_as_they_close() {
+        let rust = Rules::for_path(Some(Path::new("main.rs")));
+        assert!(rust.increases("fn main() {") && rust.increases("    call(") && !rust.increases("let x = 1;"));
+        assert!(rust.decreases("    }") && rust.decreases("]);") && !rust.decreases("x }"));
+        let python = Rules::for_path(Some(Path::new("main.py")));
+        assert!(python.increases("def f(x):  # comment") && !python.increases("x = {'a': 1}"));
+        assert!(python.decreases("    else:") && python.decreases("elif x:") && !python.decreases("elsewhere()"));
+        let text = Rules::for_path(Some(Path::new("notes.txt")));
+        assert!(!text.increases("{") && !text.decreases("}"));
+    }
+
+    #[test]
+    fn outdenting_takes_a_level_off() {
+        assert_eq!(outdent("\t\t", Indentation::Tabs), "\t");
+        assert_eq!(outdent("      ", Indentation::Spaces(4)), "  ");
+        assert_eq!(outdent("  ", Indentation::Spaces(4)), "");
+        assert_eq!(outdent("\t  ", Indentation::Spaces(4)), "\t");
+    }
+}
@@ -0,0 +1,116 @@
+//! Checking the indentation of files where it has meaning, as in Python and YAML: lines mixing
+//! tabs and spaces, indented with the other of the two than the buffer is, indented by part of a
+//! level, or taken back to where no line before them was. Lines inside brackets go on from the
+//! line they started on and only have their tabs and spaces looked at.
+
+use crate::indent::{self, Indentation};
+use crate::lines::Lines;
+use crate::locale::tr;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The extensions of the files checked, and whether tabs are allowed in their indentation.
+static CHECKED: &[(&str, bool)] = &[("py", true), ("pyw", true), ("yaml", false), ("yml", false)];
+
+/// Tabs go on to the next multiple of this many columns, as Python takes them.
+static TAB_WIDTH: usize = 8;
+
+/// Whether the indentation of a file is checked, and if so whether tabs are allowed in it.
+pub fn tabs_allowed(path: Option<&Path>) -> Option<bool> {
+    let extension = path?.extension()?.to_string_lossy().to_lowercase();
+    CHECKED.iter().find(|(e, _)| *e == extension).map(|&(_, tabs)| tabs)
+}
+
+/// The indentation a file is kept to: the buffer's own, or two spaces where tabs are not allowed.
+fn style(indentation: Indentation, tabs: bool) -> Indentation {
+    match indentation {
+        Indentation::Tabs if !tabs => Indentation::Spaces(2),
+        indentation => indentation,
+    }
+}
+
+/// How many columns some leading whitespace takes.
+fn columns(whitespace: &str) -> usize {
+    whitespace.chars().fold(0, |column, c| match c {
+        '\t' => (column / TAB_WIDTH + 1) * TAB_WIDTH,
+        _ => column + 1,
+    })
+}
+
+/// The lines of a file as the checks see them, with their leading whitespace, whether they go on
+/// inside brackets from a line before, and for the others how many levels in they are and whether
+/// they were taken back to where no line before them was.
+fn scan<'a>(lines: &'a Lines<'a>) -> impl Iterator<Item = (usize, &'a str, Option<(usize, bool)>)> + 'a {
+    let (mut depth, mut levels) = (0, vec![0]);
+    lines.iter().enumerate().filter(|(_, line)| !line.trim().is_empty()).map(move |(i, line)| {
+        let whitespace = indent::leading(line);
+        let inside_brackets = depth > 0;
+        depth = line.chars().fold(depth, crate::brackets::step);
+        if inside_brackets {
+            return (i, whitespace, None);
+        }
+        let column = columns(whitespace);
+        let deeper = levels.len();
+        levels.retain(|&level| level <= column);
+        let unmatched = levels.len() < deeper && levels.last() != Some(&column);
+        if levels.last() != Some(&column) {
+            levels.push(column);
+        }
+        (i, whitespace, Some((levels.len() - 1, unmatched)))
+    })
+}
+
+/// What is wrong with the indentation of the lines of a file, by line, going by the buffer's
+/// indentation and whether tabs are allowed in the file.
+pub fn problems(lines: &Lines, indentation: Indentation, tabs: bool) -> BTreeMap<usize, String> {
+    let style = style(indentation, tabs);
+    scan(lines).filter_map(|(i, whitespace, level)| {
+        let (has_tabs, has_spaces) = (whitespace.contains('\t'), whitespace.contains(' '));
+        let problem = if has_tabs && !tabs {
+            tr("Tabs are not allowed in the indentation")
+        } else if level.is_none() {
+            return None;
+        } else if has_tabs && has_spaces {
+            tr("The indentation mixes tabs and spaces")
+        } else if has_tabs && style != Indentation::Tabs {
+            tr("Indented with tabs, not spaces")
+        } else if has_spaces && style == Indentation::Tabs {
+            tr("Indented with spaces, not tabs")
+        } else if level.is_some_and(|(_, unmatched)| unmatched) {
+            tr("Taken back to where no line before it was")
+        } else {
+            match style {
+                Indentation::Spaces(width) if !columns(whitespace).is_multiple_of(width) => format!("{} {}", tr("Indentation is not a multiple of"), width),
+                _ => return None,
+            }
+        };
+        Some((i, problem))
+    }).collect()
+}
+
+/// The text of a file with its indentation redone in the buffer's style, each line as many levels
+/// in as it was. Lines inside brackets move along with the line they started on.
+pub fn fix(text: &str, indentation: Indentation, tabs: bool) -> String {
+    let lines = Lines::new(text);
+    let level = match style(indentation, tabs) {
+        Indentation::Tabs => "\t".to_owned(),
+        Indentation::Spaces(width) => " ".repeat(width),
+    };
+    let mut fixed: Vec<String> = lines.iter().map(str::to_owned).collect();
+    // The columns the last line outside brackets was at, and its new indentation
+    let mut last = (0, String::new());
+    for (i, whitespace, levels) in scan(&lines) {
+        let rest = &lines[i][whitespace.len()..];
+        let new = match levels {
+            Some((levels, _)) => {
+                let new = level.repeat(levels);
+                last = (columns(whitespace), new.clone());
+                new
+            }
+            None => format!("{}{}", last.1, " ".repeat(columns(whitespace).saturating_sub(last.0))),
+        };
+        fixed[i] = new + rest;
+    }
+    fixed.join("\n")
+}
@@ -0,0 +1,255 @@
+//! Background jobs. Anything that could keep the UI waiting (reading files, searching a
+//! project, running git, talking to a language server) runs on a small pool of worker threads
+//! instead. Jobs send their progress and their output back over a channel, and the main loop
+//! picks those up as events between key presses.
+
+use std::any::Any;
+use std::io::{self, Read};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc::{self, Receiver, Sender}};
+use std::thread;
+use std::time::Instant;
+
+use crate::compare::{self, Base, Comparison};
+use crate::diagnostics::Diagnostic;
+use crate::image::Image;
+use crate::manpage::Page;
+use crate::syntax::{Highlights, Unread};
+use crate::todos::Marker;
+
+/// Files are read in chunks this big, reporting progress after each one.
+static READ_CHUNK_SIZE: usize = 1 << 20;
+
+/// What a finished job hands back to the main loop.
+pub enum JobOutput {
+    /// A file's text, with its full path, read for the viewport waiting for it.
+    FileRead { path: PathBuf, result: io::Result<(PathBuf, String)> },
+    /// An image file, read instead of text.
+    ImageRead { path: PathBuf, image: Image },
+    /// The TODO (and similar) markers found under a directory.
+    Markers { root: PathBuf, result: io::Result<Vec<Marker>> },
+    /// The files that differ between a folder and what it was compared with.
+    Comparison { folder: PathBuf, result: io::Result<Comparison> },
+    /// The manual page looked up on a topic, or the command's help.
+    ManPage { topic: String, result: io::Result<Page> },
+    /// What the checker found wrong with the project.
+    Diagnostics { command: String, result: io::Result<Vec<Diagnostic>> },
+    /// The comments and strings of the lines of a document that were not in view.
+    Syntax { document: usize, highlights: Highlights },
+}
+
+pub enum JobEvent {
+    Progress(String),
+    /// How much of a file has been read so far, in percent.
+    Reading(PathBuf, usize),
+    Finished(JobOutput),
+    /// A job panicked, with what it said, and handed nothing back.
+    Failed(String),
+}
+
+/// Given to a running job, to tell the main loop how it is getting along.
+pub struct Progress {
+    events: Sender<JobEvent>,
+}
+
+impl Progress {
+    pub fn report(&self, message: String) {
+        let _ = self.events.send(JobEvent::Progress(message));
+    }
+
+    pub fn reading(&self, path: &Path, percent: usize) {
+        let _ = self.events.send(JobEvent::Reading(path.to_path_buf(), percent));
+    }
+}
+
+type Job = Box<dyn FnOnce(&Progress) -> JobOutput + Send>;
+
+/// A fixed set of worker threads taking jobs off a shared queue. Dropping the pool lets idle
+/// workers exit; busy ones are not waited on, so quitting is never held up by a slow job.
+pub struct JobPool {
+    jobs: Sender<Job>,
+    events: Receiver<JobEvent>,
+    // Jobs spawned that have not finished yet.
+    running: usize,
+}
+
+impl JobPool {
+    /// Start a pool with the given number of worker threads.
+    pub fn new(threads: usize) -> JobPool {
+        let (job_sender, jobs) = mpsc::channel::<Job>();
+        let jobs = Arc::new(Mutex::new(jobs));
+        let (event_sender, events) = mpsc::channel();
+
+        for i in 0..threads.max(1) {
+            let jobs = Arc::clone(&jobs);
+            let events = event_sender.clone();
+            thread::Builder::new().name(format!("worker {}", i)).spawn(move || loop {
+                let next = jobs.lock().unwrap().recv(); // The lock is released before the job runs
+                match next {
+                    // A job that panics is counted as done all the same, and the worker goes on
+                    Ok(job) => {
+                        let progress = Progress { events: events.clone() };
+                        let event = match panic::catch_unwind(panic::AssertUnwindSafe(|| job(&progress))) {
+                            Ok(output) => JobEvent::Finished(output),
+                            Err(payload) => JobEvent::Failed(panic_message(payload)),
+                        };
+                        let _ = events.send(event);
+                    }
+                    Err(_) => break, // The pool was dropped
+                }
+            }).expect("Failed to start a worker thread");
+        }
+
+        JobPool { jobs: job_sender, events, running: 0 }
+    }
+
+    /// Queue a job to run on the next free worker.
+    pub fn spawn<F: FnOnce(&Progress) -> JobOutput + Send + 'static>(&mut self, job: F) {
+        self.running += 1;
+        self.jobs.send(Box::new(job)).unwrap();
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.running > 0
+    }
+
+    /// The next event from a job, if one has arrived.
+    pub fn try_next_event(&mut self) -> Option<JobEvent> {
+        let event = self.events.try_recv().ok();
+        self.count(event)
+    }
+
+    /// Wait for the next event from a job. Returns `None` straight away if no job is running.
+    pub fn next_event(&mut self) -> Option<JobEvent> {
+        if !self.is_busy() {
+            return None;
+        }
+        let event = self.events.recv().ok();
+        self.count(event)
+    }
+
+    fn count(&mut self, event: Option<JobEvent>) -> Option<JobEvent> {
+        if let Some(JobEvent::Finished(_) | JobEvent::Failed(_)) = event {
+            self.running -= 1;
+        }
+        event
+    }
+}
+
+/// What a panic said, if it said it with a string.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or_else(|| "a job panicked".to_owned(), |message| (*message).to_owned()),
+    }
+}
+
+impl Default for JobPool {
+    fn default() -> JobPool {
+        JobPool::new(thread::available_parallelism().map_or(2, |n| n.get()).min(4))
+    }
+}
+
+/// Read a whole file as UTF-8 text, or as an image if it is one, reporting how far along it is on
+/// large files. Its full path is found here too, so that nothing waits on a slow disk once it has
+/// been read.
+pub fn read_file(path: PathBuf, progress: &Progress) -> JobOutput {
+    let started = Instant::now();
+    let read = (|| {
+        let mut file = std::fs::File::open(&path)?;
+        let len = file.metadata()?.len() as usize;
+        let mut bytes = Vec::with_capacity(len);
+        let mut chunk = vec![0; READ_CHUNK_SIZE];
+        loop {
+            let n = file.read(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&chunk[..n]);
+            if len > READ_CHUNK_SIZE {
+                progress.reading(&path, bytes.len() * 100 / len.max(bytes.len()));
+            }
+        }
+        Ok((path.canonicalize()?, bytes))
+    })();
+    let result = match read {
+        Ok((full_path, bytes)) if crate::image::sniff(&bytes).is_some() => {
+            log::info!("Read the image {} ({} bytes) in {:?}", path.display(), bytes.len(), started.elapsed());
+            return JobOutput::ImageRead { image: Image::new(full_path, bytes).unwrap(), path };
+        }
+        Ok((full_path, bytes)) => String::from_utf8(bytes).map(|text| (full_path, text)).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) => Err(e),
+    };
+    if let Ok((_, contents)) = &result {
+        log::info!("Read {} ({} bytes) in {:?}", path.display(), contents.len(), started.elapsed());
+    }
+    JobOutput::FileRead { path, result }
+}
+
+pub fn find_markers(root: PathBuf, progress: &Progress) -> JobOutput {
+    let started = Instant::now();
+    let result = crate::todos::search(&root, progress);
+    if let Ok(markers) = &result {
+        log::info!("Found {} markers under {} in {:?}", markers.len(), root.display(), started.elapsed());
+    }
+    JobOutput::Markers { root, result }
+}
+
+pub fn compare(base: Base, folder: PathBuf, progress: &Progress) -> JobOutput {
+    let started = Instant::now();
+    let result = compare::compare(base, &folder, progress);
+    if let Ok(comparison) = &result {
+        log::info!("Found {} differing files in {} in {:?}", comparison.changes.len(), folder.display(), started.elapsed());
+    }
+    JobOutput::Comparison { folder, result }
+}
+
+pub fn man_page(topic: String, width: usize, progress: &Progress) -> JobOutput {
+    let started = Instant::now();
+    progress.report(format!("Looking up {}", topic.trim()));
+    let result = crate::manpage::look_up(&topic, width);
+    if let Ok(page) = &result {
+        log::info!("Looked up {} in {:?}", page.title, started.elapsed());
+    }
+    JobOutput::ManPage { topic, result }
+}
+
+pub fn check(command: String, root: PathBuf, progress: &Progress) -> JobOutput {
+    let started = Instant::now();
+    progress.report(format!("Running {}", command));
+    let result = crate::diagnostics::check(&command, &root);
+    if let Ok(diagnostics) = &result {
+        log::info!("{} found {} diagnostics in {:?}", command, diagnostics.len(), started.elapsed());
+    }
+    JobOutput::Diagnostics { command, result }
+}
+
+pub fn read_syntax(document: usize, unread: Unread) -> JobOutput {
+    let started = Instant::now();
+    let highlights = unread.read();
+    log::debug!("Read the syntax of {} lines in {:?}", highlights.len(), started.elapsed());
+    JobOutput::Syntax { document, highlights }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_panicking_job_still_finishes() {
+        let mut pool = JobPool::new(1);
+        pool.spawn(|_| panic!("broken"));
+        match pool.next_event() {
+            Some(JobEvent::Failed(message)) => assert_eq!(message, "broken"),
+            _ => panic!("expected the job to fail"),
+        }
+        assert!(!pool.is_busy());
+        assert!(pool.next_event().is_none());
+
+        // The worker is still there for the next job
+        pool.spawn(|_| JobOutput::ManPage { topic: String::new(), result: Err(io::ErrorKind::NotFound.into()) });
+        assert!(matches!(pool.next_event(), Some(JobEvent::Finished(JobOutput::ManPage { .. }))));
+        assert!(!pool.is_busy());
+    }
+}
@@ -0,0 +1,70 @@
+use scribe::buffer::Position;
+
+static MAX_JUMPS: usize = 100;
+
+/// A place the cursor jumped away from: a position within the viewport with the given ID.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Jump {
+    pub viewport_id: usize,
+    pub position: Position,
+}
+
+/// The history of significant cursor jumps for the session, which can be walked back and
+/// forth much like a web browser's history.
+#[derive(Default)]
+pub struct JumpList {
+    jumps: Vec<Jump>,
+    // Index of the jump we last travelled to. Equal to the length when we are at "the present".
+    current: usize,
+}
+
+impl JumpList {
+    /// Remember a location the cursor is about to jump away from. This discards any jumps we
+    /// had travelled back past.
+    pub fn record(&mut self, from: Jump) {
+        self.jumps.truncate(self.current);
+        self.push(from);
+        self.current = self.jumps.len();
+    }
+
+    /// Travel back to the previous jump, given where the cursor is right now.
+    pub fn back(&mut self, here: Jump) -> Option<Jump> {
+        if self.current == self.jumps.len() {
+            // Leaving the present, so remember it to be able to come forward again.
+            self.push(here);
+            self.current = self.jumps.len() - 1;
+        }
+        if self.current == 0 {
+            return None;
+        }
+        self.current -= 1;
+        Some(self.jumps[self.current])
+    }
+
+    /// Travel forward again, after having travelled back.
+    pub fn forward(&mut self) -> Option<Jump> {
+        if self.current + 1 < self.jumps.len() {
+            self.current += 1;
+            Some(self.jumps[self.current])
+        } else {
+            None
+        }
+    }
+
+    /// Drop every jump into a viewport that has been closed.
+    pub fn forget_viewport(&mut self, viewport_id: usize) {
+        let before_current = self.jumps.iter().take(self.current).filter(|j| j.viewport_id == viewport_id).count();
+        self.jumps.retain(|j| j.viewport_id != viewport_id);
+        self.current -= before_current;
+        self.current = self.current.min(self.jumps.len());
+    }
+
+    fn push(&mut self, jump: Jump) {
+        if self.jumps.last() != Some(&jump) {
+            self.jumps.push(jump);
+            if self.jumps.len() > MAX_JUMPS {
+                self.jumps.remove(0);
+            }
+        }
+    }
+}
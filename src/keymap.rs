@@ -0,0 +1,51 @@
+//! What the keys do where it is not obvious from the screen, for showing as hints, and the keys
+//! that do the actions of the menus while editing.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::menu::Action;
+
+/// A key, as written for people, and what pressing it does.
+pub struct Binding {
+    pub keys: &'static str,
+    pub action: &'static str,
+}
+
+/// The keys of menu mode, besides each menu's underlined letter.
+pub static MENU_MODE: &[Binding] = &[
+    Binding { keys: "←/→", action: "Choose a menu" },
+    Binding { keys: "Enter", action: "Open the chosen menu" },
+    Binding { keys: "Tab", action: "Next tab" },
+    Binding { keys: "F12", action: "Performance overlay" },
+    Binding { keys: "Ctrl+Q", action: "Quit" },
+    Binding { keys: "Esc", action: "Back to editing" },
+];
+
+/// A key that does an action of the menus while editing.
+pub struct Shortcut {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+    pub action: Action,
+}
+
+/// The keys for going around the structure of the text and the matches of the last search, and
+/// for the registers, while editing.
+pub static EDITING: &[Shortcut] = &[
+    Shortcut { code: KeyCode::Up, modifiers: KeyModifiers::ALT, action: Action::GrowSelection },
+    Shortcut { code: KeyCode::Down, modifiers: KeyModifiers::ALT, action: Action::NarrowSelection },
+    Shortcut { code: KeyCode::PageDown, modifiers: KeyModifiers::CONTROL, action: Action::NextFunction },
+    Shortcut { code: KeyCode::PageUp, modifiers: KeyModifiers::CONTROL, action: Action::PreviousFunction },
+    Shortcut { code: KeyCode::Home, modifiers: KeyModifiers::ALT, action: Action::BlockStart },
+    Shortcut { code: KeyCode::End, modifiers: KeyModifiers::ALT, action: Action::BlockEnd },
+    Shortcut { code: KeyCode::PageUp, modifiers: KeyModifiers::ALT, action: Action::FirstMatch },
+    Shortcut { code: KeyCode::PageDown, modifiers: KeyModifiers::ALT, action: Action::LastMatch },
+    Shortcut { code: KeyCode::Char('y'), modifiers: KeyModifiers::ALT, action: Action::CopyToRegister },
+    Shortcut { code: KeyCode::Char('x'), modifiers: KeyModifiers::ALT, action: Action::CutToRegister },
+    Shortcut { code: KeyCode::Char('p'), modifiers: KeyModifiers::ALT, action: Action::PasteFromRegister },
+    Shortcut { code: KeyCode::Char('v'), modifiers: KeyModifiers::ALT, action: Action::TogglePasteMode },
+];
+
+/// The action a key does while editing, if it has one.
+pub fn action_for(key: KeyEvent) -> Option<Action> {
+    EDITING.iter().find(|shortcut| shortcut.code == key.code && shortcut.modifiers == key.modifiers).map(|shortcut| shortcut.action)
+}
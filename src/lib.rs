@@ -0,0 +1,85 @@
+//! QEdit as a library. The `qedit` binary is a thin `main` around the `Editor` from here, and
+//! other programs can embed it the same way: make an `Editor`, open buffers in it, hand it the
+//! events to handle with `Editor::handle_event` or do an `Action` with `Editor::handle_action`,
+//! and `Editor::render` it to any `Write`. Under `backend::start_headless` it needs no terminal
+//! at all, which is how the core can be driven from tests and benchmarks.
+//!
+//! The documents open in the editor are kept in its `ViewportManager`'s `BufferStore`.
+
+// Abandon all hope, ye who enter here:
+// When you need a color, set it before writing anything. Never reset colors.
+
+// Everything we queue goes straight to the terminal; if writing to it fails there is
+// nobody left to tell, so the results of `queue!` and `execute!` are ignored throughout.
+#![allow(unused_must_use)]
+
+mod abbreviations;
+mod align;
+pub mod backend;
+mod brackets;
+mod breadcrumbs;
+mod calc;
+mod clipboard;
+mod clock;
+mod compare;
+pub mod colors;
+mod completion;
+mod compose;
+mod config;
+pub mod cursorshape;
+mod diagnostics;
+mod diff;
+mod document;
+pub mod editor;
+mod filetree;
+mod frames;
+mod history;
+mod image;
+mod indent;
+mod indentcheck;
+mod jobs;
+mod jumplist;
+mod keymap;
+pub mod lines;
+mod locale;
+pub mod logging;
+mod manpage;
+mod marks;
+mod menu;
+mod minimap;
+mod numbers;
+pub mod perf;
+mod positions;
+mod ranges;
+mod recovery;
+mod registers;
+mod scopes;
+mod search;
+mod session;
+mod stats;
+mod statusbar;
+mod swatches;
+mod syntax;
+mod table;
+mod tail;
+mod templates;
+mod terminal;
+pub mod theme;
+mod todos;
+mod tooltip;
+mod transform;
+mod unicode;
+mod unsaved;
+mod untitled;
+pub mod util;
+mod viewport;
+mod welcome;
+// mod render;
+
+// use render::*;
+
+pub use document::{BufferStore, Document};
+pub use editor::Editor;
+pub use menu::Action;
+pub use transform::Transform;
+pub use viewport::{Viewport, ViewportManager};
@@ -0,0 +1,192 @@
+//! The lines of a text, found by where each one starts. A document keeps the starts of its lines
+//! and moves them along with each edit, so rendering, searching and going to a line can look
+//! lines up without splitting the whole text again every time.
+
+use crate::history::Edit;
+
+use scribe::buffer::Position;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use std::borrow::Cow;
+use std::ops::{Index, Range};
+
+/// The byte offsets the lines of a text start at, kept up to date with the edits made to it.
+#[derive(Default)]
+pub struct LineIndex {
+    starts: Vec<usize>,
+    // The length of the text the starts are of, to tell when it changed without an edit.
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> LineIndex {
+        LineIndex { starts: starts(text), len: text.len() }
+    }
+
+    /// Move the starts along with an edit made to the text. Only the inserted text is looked
+    /// through for new lines; the lines after the edit just move by as much as it grew or shrank.
+    pub fn edit(&mut self, edit: &Edit) {
+        let end = edit.start + edit.removed.len();
+        // An index that missed an edit before this one is found again from the whole text
+        if self.starts.is_empty() || self.len < end {
+            return self.starts.clear();
+        }
+        // The lines starting after a newline the edit removed go, and those after one it
+        // inserted come in their place
+        let first = self.starts.partition_point(|&start| start <= edit.start);
+        let last = self.starts.partition_point(|&start| start <= end);
+        let inserted: Vec<usize> = edit.inserted.match_indices('\n').map(|(i, _)| edit.start + i + 1).collect();
+        let moved = first + inserted.len();
+        self.starts.splice(first..last, inserted);
+        for start in &mut self.starts[moved..] {
+            *start = *start + edit.inserted.len() - edit.removed.len();
+        }
+        self.len = self.len + edit.inserted.len() - edit.removed.len();
+    }
+
+    /// The lines of the text, which is found through again if it changed without the index
+    /// being told.
+    pub fn lines<'a>(&'a mut self, text: &'a str) -> Lines<'a> {
+        if self.starts.is_empty() || self.len != text.len() {
+            *self = LineIndex::new(text);
+        }
+        Lines { text, starts: Cow::Borrowed(&self.starts) }
+    }
+}
+
+/// Where the lines of some text start: the start of the text, and after each newline in it.
+fn starts(text: &str) -> Vec<usize> {
+    std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect()
+}
+
+/// The lines of a text, without their newlines. There is always at least one, and a text ending
+/// in a newline ends in an empty line.
+pub struct Lines<'a> {
+    text: &'a str,
+    starts: Cow<'a, [usize]>,
+}
+
+impl<'a> Lines<'a> {
+    /// Find the lines of a text that has no index kept for it.
+    pub fn new(text: &'a str) -> Lines<'a> {
+        Lines { text, starts: Cow::Owned(starts(text)) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Whether there is no text, only the one empty line.
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn get(&self, line: usize) -> Option<&'a str> {
+        self.range(line).map(|range| &self.text[range])
+    }
+
+    /// A line, which must be there, like indexing a slice of lines.
+    pub fn line(&self, line: usize) -> &'a str {
+        match self.get(line) {
+            Some(line) => line,
+            None => panic!("line {} is past the last of {}", line, self.len()),
+        }
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &'a str> + ExactSizeIterator + '_ {
+        (0..self.len()).map(move |line| self.line(line))
+    }
+
+    /// The text of the lines from the first to the last, without the newline after the last.
+    pub fn between(&self, first: usize, last: usize) -> &'a str {
+        &self.text[self.start(first)..self.range(last).map_or(self.text.len(), |range| range.end)]
+    }
+
+    /// The byte offset a line starts at, or the length of the text for the line after the last.
+    pub fn start(&self, line: usize) -> usize {
+        self.starts.get(line).copied().unwrap_or(self.text.len())
+    }
+
+    /// The line and column of a byte offset, like `util::position_at`.
+    pub fn position_at(&self, offset: usize) -> Position {
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        Position { line, offset: crate::util::columns(&self.text[self.starts[line]..offset]) }
+    }
+
+    /// Whether a line and column are in the text, going through no more of it than that line up
+    /// to the column.
+    pub fn contains(&self, position: Position) -> bool {
+        let Some(line) = self.get(position.line) else { return false };
+        match line.as_bytes().get(..position.offset) {
+            Some(bytes) if bytes.is_ascii() => true,
+            _ => position.offset == 0 || line.graphemes(true).nth(position.offset - 1).is_some(),
+        }
+    }
+
+    /// The bytes of a line, leaving out its newline.
+    fn range(&self, line: usize) -> Option<Range<usize>> {
+        let start = *self.starts.get(line)?;
+        Some(start..self.starts.get(line + 1).map_or(self.text.len(), |next| next - 1))
+    }
+}
+
+impl Index<usize> for Lines<'_> {
+    type Output = str;
+
+    fn index(&self, line: usize) -> &str {
+        self.line(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Make an edit to a text and its index, and check the index against one found afresh.
+    fn check(text: &str, start: usize, removed: usize, inserted: &str) {
+        let mut index = LineIndex::new(text);
+        index.edit(&Edit { start, removed: text[start..start + removed].to_owned(), inserted: inserted.to_owned() });
+        let mut edited = text.to_owned();
+        edited.replace_range(start..start + removed, inserted);
+        let expected = LineIndex::new(&edited);
+        assert_eq!((&index.starts, index.len), (&expected.starts, expected.len), "{:?} edited into {:?}", text, edited);
+    }
+
+    #[test]
+    fn edits_at_the_starts_of_lines() {
+        check("ab\ncd\nef", 3, 0, "x");
+        check("ab\ncd\nef", 3, 0, "x\n");
+        check("ab\ncd\nef", 0, 0, "\n");
+        check("ab\ncd\nef", 3, 2, "");
+        check("ab\ncd\nef", 3, 3, "");
+        // The newline before a line takes it away
+        check("ab\ncd\nef", 2, 1, "");
+    }
+
+    #[test]
+    fn edits_at_the_end_of_the_text() {
+        check("ab\ncd", 5, 0, "\n");
+        check("ab\ncd\n", 6, 0, "ef");
+        check("ab\ncd\n", 5, 1, "");
+        check("ab\n", 0, 3, "");
+        check("", 0, 0, "a\nb\n");
+    }
+
+    #[test]
+    fn edits_across_several_lines() {
+        check("ab\ncd\nef\ngh", 1, 7, "");
+        check("ab\ncd\nef\ngh", 1, 7, "x\ny");
+        check("ab\ncd\nef\ngh", 4, 1, "1\n2\n3\n4");
+        check("a\n\n\n\nb", 1, 3, "\n\n");
+    }
+
+    #[test]
+    fn lines_changed_without_an_edit_are_found_again() {
+        let mut index = LineIndex::new("ab\ncd");
+        index.edit(&Edit { start: 9, removed: "x".to_owned(), inserted: String::new() });
+        assert!(index.starts.is_empty());
+        assert_eq!(index.lines("ab\ncd\nef").len(), 3);
+        assert_eq!(LineIndex::new("ab").lines("ab\ncd").line(1), "cd");
+    }
+}
@@ -0,0 +1,123 @@
+//! Translations of the menus and dialogs.
+//!
+//! Strings are looked up by their English text, which is also what is shown when there is no
+//! translation. A language is a file of `English = translation` lines, in the same format as
+//! the config file. Some come built in, and more can be added or the built-in ones replaced in
+//! `$XDG_CONFIG_HOME/qedit/locale/<language>`. Menu names mark their shortcut letter with `_`,
+//! and a translation of one must mark its own, or it is left out.
+
+use lazy_static::*;
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// The languages shipped with the editor, besides English.
+static BUILT_IN: &[(&str, &str)] = &[
+    ("de", include_str!("../assets/locale/de")),
+];
+
+struct Locale {
+    language: String,
+    strings: HashMap<String, String>,
+}
+
+lazy_static! {
+    static ref LOCALE: RwLock<Locale> = RwLock::new(Locale::load(&initial_language()));
+}
+
+impl Locale {
+    fn load(language: &str) -> Locale {
+        let text = std::fs::read_to_string(crate::config::config_dir().unwrap_or_default().join("locale").join(language)).ok()
+            .or_else(|| BUILT_IN.iter().find(|(name, _)| *name == language).map(|(_, text)| text.to_string()));
+        let strings = match text {
+            Some(text) => parse(&text),
+            None => {
+                if language != "en" {
+                    log::warn!("There is no translation into {:?}", language);
+                }
+                HashMap::new()
+            }
+        };
+        Locale { language: language.to_owned(), strings }
+    }
+}
+
+/// Read the translations of a locale file.
+fn parse(text: &str) -> HashMap<String, String> {
+    let mut strings = HashMap::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.splitn(2, '=');
+        if let (Some(english), Some(translation)) = (parts.next(), parts.next()) {
+            let (english, translation) = (english.trim(), translation.trim());
+            if has_shortcut(english) != has_shortcut(translation) {
+                log::warn!("The translation {:?} of {:?} must mark a shortcut letter with '_' if and only if the English does", translation, english);
+                continue;
+            }
+            strings.insert(english.to_owned(), translation.to_owned());
+        }
+    }
+    strings
+}
+
+/// Whether a menu name marks a shortcut letter, with a `_` before it.
+fn has_shortcut(name: &str) -> bool {
+    name.split_once('_').is_some_and(|(_, after)| !after.is_empty())
+}
+
+/// The language to start in: the one configured, or else the one of the environment's locale,
+/// like `de` for `de_DE.UTF-8`.
+fn initial_language() -> String {
+    if let Some(language) = &crate::config::get().language {
+        return language.clone();
+    }
+    ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| value.split(['_', '.', '@']).next().unwrap().to_owned())
+        .filter(|language| language != "C" && language != "POSIX")
+        .unwrap_or_else(|| "en".to_owned())
+}
+
+/// The text to show for some English text, in the current language.
+pub fn tr(english: &str) -> String {
+    LOCALE.read().unwrap().strings.get(english).cloned().unwrap_or_else(|| english.to_owned())
+}
+
+/// The current language, like `en` or `de`.
+pub fn language() -> String {
+    LOCALE.read().unwrap().language.clone()
+}
+
+/// Switch to another language. Anything already on screen keeps its text until it is made again.
+pub fn set_language(language: &str) {
+    *LOCALE.write().unwrap() = Locale::load(language);
+}
+
+/// The languages there are translations into, English first.
+pub fn languages() -> Vec<String> {
+    let mut languages: Vec<String> = BUILT_IN.iter().map(|(name, _)| name.to_string()).collect();
+    if let Ok(entries) = std::fs::read_dir(crate::config::config_dir().unwrap_or_default().join("locale")) {
+        languages.extend(entries.filter_map(|e| e.ok()?.file_name().into_string().ok()));
+    }
+    languages.retain(|language| language != "en");
+    languages.sort();
+    languages.dedup();
+    languages.insert(0, "en".to_owned());
+    languages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_hash_is_only_a_comment_at_the_start_of_a_line() {
+        let strings = parse("# Comment = Kommentar\nLine #{} = Zeile #{}\n");
+        assert_eq!(strings.get("Line #{}").map(String::as_str), Some("Zeile #{}"));
+        assert_eq!(strings.len(), 1);
+    }
+}
@@ -0,0 +1,67 @@
+//! The log file, `qedit.log` in the data directory. Whatever the `log` macros record at or
+//! above the configured `log_level` is appended to it with a timestamp, which makes it the place
+//! to look for errors, timings and anything else that is no use to show in a dialog.
+
+use crate::clock::DateTime;
+use crate::config;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// A log file bigger than this when the editor starts is moved to `qedit.log.old`.
+static MAX_LOG_SIZE: u64 = 1 << 20;
+
+struct FileLogger {
+    file: Mutex<Option<File>>,
+}
+
+static LOGGER: FileLogger = FileLogger { file: Mutex::new(None) };
+
+impl log::Log for FileLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let _ = writeln!(file, "{} {:<5} [{}] {}", timestamp(), record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Where the log file is kept.
+pub fn path() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join("qedit.log"))
+}
+
+/// Start logging to the log file. If it cannot be opened, log messages go nowhere.
+pub fn init() {
+    if let Some(path) = path() {
+        let _ = fs::create_dir_all(path.parent().unwrap());
+        if fs::metadata(&path).is_ok_and(|m| m.len() > MAX_LOG_SIZE) {
+            let _ = fs::rename(&path, path.with_extension("log.old"));
+        }
+        *LOGGER.file.lock().unwrap() = OpenOptions::new().create(true).append(true).open(&path).ok();
+    }
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(config::get().log_level);
+    }
+    log::info!("Started version {}", env!("CARGO_PKG_VERSION"));
+}
+
+/// The current time in UTC, like `2019-06-01 13:45:07.250`.
+fn timestamp() -> String {
+    let now = DateTime::now_utc();
+    format!("{}.{:03}", now.format("%F %T"), now.millisecond)
+}
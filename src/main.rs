@@ -1,186 +1,94 @@
-// Abandon all hope, ye who enter here:
-// When you need a color, set it before writing anything. Never reset colors.
+// Everything we queue goes straight to the terminal; if writing to it fails there is
+// nobody left to tell, so the results of `queue!` and `execute!` are ignored throughout.
+#![allow(unused_must_use)]
 
-use crossterm::{*, event::{KeyEvent, KeyCode, Event}};
+use crossterm::*;
+use diesel::{colors, cursorshape, editor, logging, perf, theme, util};
 
-use std::io::{stdin, stdout, Write};
+use std::io::stdout;
 use std::panic;
+use std::path::Path;
+use std::sync::Mutex;
 
-mod menu;
-mod util;
-mod viewport;
-// mod render;
-
-use viewport::{Viewport, ViewportData, ViewportManager};
-// use render::*;
-
-/// Returns true if the Viewport actually saved the file, or false if the user cancelled.
-fn viewport_save_as(viewport: &mut Viewport) -> bool {
-    if let Some(file_path_str) = util::input(&mut stdout(), &format!("Save file '{}'", "Untitled"), "./Untitled".to_owned(), util::InputType::Any) {
-        let file_path = std::path::PathBuf::from(file_path_str);
-        let mut file = std::fs::File::create(&file_path).unwrap(); // Create the file on disk
-        file.write_all(viewport.get_buffer().expect("Cannot save a Viewport with no buffer.").data().as_bytes()).expect("Failed to write buffer data into new save file on disk!");
-        viewport.data = ViewportData::Buffer(Box::new(scribe::Buffer::from_file(&file_path).unwrap()));
-        true
-    } else { // If the user inputs no save file path, we do nothing
-        false
-    }
-}
+#[global_allocator]
+static ALLOCATOR: perf::CountingAllocator = perf::CountingAllocator;
+
+/// What the last panic on the main thread said, for the alert shown once it has unwound.
+static PANIC_MESSAGE: Mutex<Option<String>> = Mutex::new(None);
 
 fn main() {
+    logging::init();
+
     panic::set_hook(Box::new(|panic_info| {
-        util::alert(&mut stdout(), "Panic!", &format!("{}", panic_info));
-        execute!(stdout(), cursor::RestorePosition, terminal::LeaveAlternateScreen, cursor::Show);
+        log::error!("{}", panic_info);
+        log::logger().flush();
+        if std::thread::current().name() == Some("main") { // A panicking worker only loses its job
+            *PANIC_MESSAGE.lock().unwrap() = Some(panic_info.to_string());
+        }
     }));
 
-    terminal::enable_raw_mode().unwrap();
-    execute!(stdout(), cursor::SavePosition, terminal::EnterAlternateScreen);
+    // With `--wait`, as for Git or `$VISUAL`, the editor quits once the file given is closed
+    let mut argv = std::env::args().collect::<Vec<String>>();
+    let wait = argv.iter().any(|arg| arg == "--wait");
+    argv.retain(|arg| arg != "--wait");
+
+    // A folder is opened as a project, whose own settings are read before anything takes them
+    let folder = argv.get(1).and_then(|path| Path::new(path).canonicalize().ok()).filter(|path| path.is_dir());
+    let has_config = match &folder {
+        Some(folder) => editor::enter_folder(folder).unwrap_or_else(|e| {
+            log::error!("Could not open {} as a project: {}", folder.display(), e);
+            false
+        }),
+        None => false,
+    };
 
-    let mut screen = stdout();
+    terminal::enable_raw_mode().unwrap();
+    execute!(stdout(), cursor::SavePosition, terminal::EnterAlternateScreen, event::EnableFocusChange, event::EnableBracketedPaste);
+    theme::init();
 
-    let mut size = terminal::size().unwrap();
-    
-    let mut viewport_manager = ViewportManager {
-        origin: (0, 1),
-        size: (size.0 as usize, size.1 as usize),
-        viewports: Vec::new(),
-        focus_index: 0,
-    };
+    let mut screen = colors::Degrade::new(stdout(), colors::support());
 
-    let argv = std::env::args().collect::<Vec<String>>();
-    let buf = if argv.len() <= 1 {
-        scribe::Buffer::new()
-    } else {
-        scribe::Buffer::from_file(std::path::Path::new(&argv[1])).unwrap()
-    };
-    viewport_manager.new_viewport(ViewportData::Buffer(Box::new(buf)));
-
-    // Create and instantiate the default menu bar
-    let file = (
-        "_File".to_owned(),
-        menu::Menu {
-            children: vec!(
-                ("_New".to_owned(), menu::MenuAction::Action(menu::Action::New)),
-                ("_Open".to_owned(), menu::MenuAction::Action(menu::Action::Open)),
-                ("".to_owned(), menu::MenuAction::Separator),
-                ("_Save".to_owned(), menu::MenuAction::Action(menu::Action::Save)),
-                ("Save _as ...".to_owned(), menu::MenuAction::Action(menu::Action::SaveAs)),
-                ("".to_owned(), menu::MenuAction::Separator),
-                ("_Quit".to_owned(), menu::MenuAction::Action(menu::Action::Close)),
-            ),
-        },
-    );
-    let edit = (
-        "_Edit".to_owned(),
-        menu::Menu {
-            children: vec!(
-                ("_Undo".to_owned(), menu::MenuAction::Action(menu::Action::Undo)),
-                ("_Redo".to_owned(), menu::MenuAction::Action(menu::Action::Redo)),
-            ),
-        },
-    );
-    let help = (
-        "_Help".to_owned(),
-        menu::Menu {
-            children: vec!(("_About".to_owned(), menu::MenuAction::Action(menu::Action::About))),
-        },
-    );
-    let mut menu_bar = menu::MenuBar { selection_index: 0, menus: vec!(file, edit, help) };
-
-    let mut in_menu_mode = false;
-
-    loop {
-        size = terminal::size().unwrap();
-
-        if viewport_manager.viewports.is_empty() { // If no open editors
-            in_menu_mode = true;
-
-            queue!(screen, style::SetForegroundColor(style::Color::White), style::SetBackgroundColor(style::Color::Black));
-            for line in 0..size.1 {
-                queue!(screen, cursor::MoveTo(0, 1 + line as u16), style::Print("▒".repeat(size.0 as usize)));
-            }
-        }
+    let mut editor = editor::Editor::new();
+    if editor.mouse {
+        execute!(screen, event::EnableMouseCapture);
+    }
 
-        // Set the default terminal colors
-        // TODO: We need better coloring infrastructure
-        queue!(screen, style::SetForegroundColor(style::Color::White), style::SetBackgroundColor(style::Color::Blue)).unwrap();
-
-        queue!(screen, cursor::Hide);
-
-        // Update the menu bar
-        menu_bar.render(&mut screen, (0, 0), size.0 as usize, in_menu_mode);
-
-        // Update all viewports
-        viewport_manager.size = (size.0 as usize, size.1 as usize);
-        viewport_manager.render(&mut screen, !in_menu_mode);
-
-        screen.flush().unwrap();
-
-        match event::read().unwrap() {
-            Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => in_menu_mode = !in_menu_mode,
-            Event::Key(KeyEvent { code: KeyCode::Char('q'), modifiers: event::KeyModifiers::CONTROL }) if in_menu_mode => break, // Quit the entire editor TODO: should prompt for save
-            Event::Key(KeyEvent { code: KeyCode::Tab, .. }) if in_menu_mode => viewport_manager.next_tab(),
-            Event::Key(k) if !in_menu_mode => viewport_manager.handle_key_event(k),
-            Event::Key(k) => {
-                // High-level action handling
-                if let Some((menu_idx, x_offset)) = menu_bar.maybe_handle_key_press(k) {
-                    // The menu bar should have set its selection index to the menu at this point, and is re-rendered all while calling 'maybe_handle_key_press'
-                    menu_bar.render(&mut screen, (0, 0), size.0 as usize, in_menu_mode);
-
-                    if let Some(action) = menu_bar.menus[menu_idx].1.take_over(&mut screen, x_offset) {
-                        use menu::Action::*;
-                        match action {
-                            Close => if viewport_manager.viewports.is_empty() { break } else { viewport_manager.close_focused_viewport() },
-
-                            New => {
-                                viewport_manager.new_viewport(ViewportData::Buffer(Box::new(scribe::Buffer::new()))); // Add viewport
-                                viewport_manager.focus_index = viewport_manager.viewports.len()-1; // Set focus to last viewport
-                            }
-                            Save => {
-                                if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
-                                    if let Some(buf) = viewport.get_buffer() {
-                                        if buf.modified() { // Only do this code if the buffer is dirty
-                                            if buf.file_name().is_some() { // This buffer points to a file on disk
-                                                buf.save().unwrap();
-                                            } else { // This buffer points to no files on disk
-                                                viewport_save_as(viewport);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            SaveAs => {
-                                if let Some(viewport) = viewport_manager.get_focused_viewport_mut() {
-                                    if viewport.get_buffer().is_some() {
-                                        viewport_save_as(viewport);
-                                    }
-                                }
-                            }
-                            Open => {
-                                if let Some(path) = util::input(&mut screen, "Open file", String::new(), util::InputType::Path) {
-                                    let path = std::path::PathBuf::from(path);
-                                    if path.is_file() {
-                                        let buf = scribe::Buffer::from_file(&path).unwrap();
-                                        viewport_manager.new_viewport(ViewportData::Buffer(Box::new(buf)));
-                                    } else {
-                                        util::alert(&mut screen, "Only accepts files", &format!("You entered {:?}, which is a directory.", path));
-                                    }
-                                }
-                            }
-
-                            About => util::alert(&mut screen, "About QEdit", "QEdit Text Editor\nVersion 0.1\nCopyright © 2019 Luke Wilson.\nLicensed under the MIT License."),
-                            _ => util::alert(&mut screen, "Unimplemented action selected", &format!("{:?}", action)),
-                        }
-
-                        if !viewport_manager.viewports.is_empty() {
-                            in_menu_mode = false; // Go into insert mode automatically when an action has been completed, if there are open viewports.
-                        }
-                    }
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        match (folder, argv.get(1)) {
+            (Some(folder), _) => editor.open_folder(folder, has_config),
+            (None, Some(path)) => {
+                let path = Path::new(path).canonicalize().unwrap_or_else(|_| path.into());
+                if wait {
+                    editor.waiting_for = Some(path.clone());
                 }
+                editor.open_file(path);
             }
-            _ => {}
+            (None, None) => if !editor.restore_session() {
+                editor.open_buffer(scribe::Buffer::new());
+            },
         }
+
+        editor.run(&mut screen);
+    }));
+
+    // We panicked: rescue what we can before going down
+    if result.is_err() {
+        let message = PANIC_MESSAGE.lock().unwrap().take().unwrap_or_default();
+        let recovered = editor.write_recovery_files();
+        util::alert(&mut screen, "Panic!", &format!("{}\n\n{}", message, recovered));
     }
 
-    execute!(screen, cursor::RestorePosition, terminal::LeaveAlternateScreen, cursor::Show); // Show the cursor so it is not hidden when out of the editor.
+    if editor.mouse {
+        execute!(screen, event::DisableMouseCapture);
+    }
+    cursorshape::restore(&mut screen);
+    execute!(screen, event::DisableFocusChange, event::DisableBracketedPaste, cursor::RestorePosition, terminal::LeaveAlternateScreen, cursor::Show); // Show the cursor so it is not hidden when out of the editor.
+
+    // Quitting without saving while waited for tells whoever started the editor that the edit
+    // was given up, so that Git does not go on with a message left as it was
+    if result.is_err() {
+        std::process::exit(101);
+    } else if wait && editor.unsaved_changes() {
+        std::process::exit(1);
+    }
 }
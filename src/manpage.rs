@@ -0,0 +1,196 @@
+//! Reading a manual page, or what a command prints for `--help` when there is none, in a viewport
+//! of its own. The bold and underlined text they are typeset with is kept, whether it comes as
+//! terminal escape sequences or as characters struck over with backspaces.
+
+use crossterm::{*, event::KeyCode, style::{Attribute, Color}};
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Tabs in the output are taken to stop every this many columns.
+static TAB_WIDTH: usize = 8;
+
+/// A run of text on a line of the page, all in the same style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub bold: bool,
+    pub underline: bool,
+}
+
+/// The formatted text of a manual page, or a command's help, scrolled through in a viewport.
+pub struct Page {
+    /// What was looked up, like `ls` or `git --help`.
+    pub title: String,
+    lines: Vec<Vec<Span>>,
+    // The first line and column in view, and how many lines fit, as of the last render.
+    top: usize,
+    left: usize,
+    height: usize,
+}
+
+/// Look up the manual page on a topic, like `ls` or `3 printf`, typeset to fit so many columns.
+/// A topic with no page is run as a command with `--help` instead, its words as the arguments.
+pub fn look_up(topic: &str, width: usize) -> io::Result<Page> {
+    let words: Vec<&str> = topic.split_whitespace().collect();
+    if words.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Nothing to look up"));
+    }
+    // Keeping the formatting, man writes its bold and underlines as escape sequences or backspaces
+    let man = Command::new("man").arg("-P").arg("cat").args(&words)
+        .env("MANWIDTH", width.to_string()).env("MAN_KEEP_FORMATTING", "1")
+        .stdin(Stdio::null()).output();
+    if let Ok(output) = man {
+        if output.status.success() && !output.stdout.is_empty() {
+            return Ok(Page::new(format!("man {}", topic.trim()), &String::from_utf8_lossy(&output.stdout)));
+        }
+    }
+
+    let output = Command::new(words[0]).args(&words[1..]).arg("--help")
+        .env("COLUMNS", width.to_string())
+        .stdin(Stdio::null()).output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(e.kind(), "no manual page or command has that name"),
+            _ => e,
+        })?;
+    // Some commands print their help as an error
+    let text = match output.stdout.is_empty() {
+        true => output.stderr,
+        false => output.stdout,
+    };
+    match text.is_empty() {
+        true => Err(io::Error::other(format!("{} --help printed nothing", topic.trim()))),
+        false => Ok(Page::new(format!("{} --help", topic.trim()), &String::from_utf8_lossy(&text))),
+    }
+}
+
+/// Split output into lines of styled text. Bold and underlining are taken from the SGR escape
+/// sequences for them and from overstriking: a character struck over with itself is bold, and
+/// one struck over with `_` is underlined. Other escape sequences and control characters are left
+/// out, and tabs become spaces.
+pub fn parse(output: &str) -> Vec<Vec<Span>> {
+    let mut lines = Vec::new();
+    for line in output.lines() {
+        let mut cells: Vec<(char, bool, bool)> = Vec::new();
+        let (mut bold, mut underline) = (false, false);
+        let mut struck: Option<(char, bool, bool)> = None;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => match chars.next() {
+                    // Control sequences run to a letter, and SGR ones end in `m`
+                    Some('[') => {
+                        let mut params = String::new();
+                        for c in chars.by_ref() {
+                            match c {
+                                '0'..='9' | ';' | ':' | '?' | '>' | '=' => params.push(c),
+                                'm' => {
+                                    for param in params.split(';') {
+                                        match param.parse::<u32>().unwrap_or(0) {
+                                            0 => (bold, underline) = (false, false),
+                                            1 => bold = true,
+                                            22 => bold = false,
+                                            4 => underline = true,
+                                            24 => underline = false,
+                                            _ => {}
+                                        }
+                                    }
+                                    break;
+                                }
+                                _ => break,
+                            }
+                        }
+                    }
+                    // Operating system commands, like links, end with BEL or ST
+                    Some(']') => while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    },
+                    _ => {}
+                },
+                '\x08' => struck = cells.pop(),
+                '\t' => {
+                    let spaces = TAB_WIDTH - cells.len() % TAB_WIDTH;
+                    cells.extend(std::iter::repeat_n((' ', bold, underline), spaces));
+                }
+                c if c.is_control() => {}
+                c => cells.push(match struck.take() {
+                    Some((under, ..)) if under == c => (c, true, underline),
+                    Some(('_', under_bold, _)) => (c, bold || under_bold, true),
+                    Some((under, under_bold, _)) if c == '_' => (under, bold || under_bold, true),
+                    _ => (c, bold, underline),
+                }),
+            }
+        }
+
+        let mut spans: Vec<Span> = Vec::new();
+        for (c, bold, underline) in cells {
+            match spans.last_mut() {
+                Some(span) if (span.bold, span.underline) == (bold, underline) => span.text.push(c),
+                _ => spans.push(Span { text: c.to_string(), bold, underline }),
+            }
+        }
+        lines.push(spans);
+    }
+    lines
+}
+
+impl Page {
+    pub fn new(title: String, output: &str) -> Page {
+        Page { title, lines: parse(output), top: 0, left: 0, height: 1 }
+    }
+
+    /// Scroll with a key. Returns whether the key was one that does.
+    pub fn handle_key(&mut self, key: KeyCode) -> bool {
+        let last = self.lines.len().saturating_sub(self.height);
+        match key {
+            KeyCode::Up => self.top = self.top.saturating_sub(1),
+            KeyCode::Down | KeyCode::Enter => self.top = (self.top + 1).min(last),
+            KeyCode::PageUp => self.top = self.top.saturating_sub(self.height),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.top = (self.top + self.height).min(last),
+            KeyCode::Home => (self.top, self.left) = (0, 0),
+            KeyCode::End => self.top = last,
+            KeyCode::Left => self.left = self.left.saturating_sub(TAB_WIDTH),
+            KeyCode::Right => self.left += TAB_WIDTH,
+            _ => return false,
+        }
+        true
+    }
+
+    /// How far down the page is scrolled, from 0 to 1.
+    pub fn scroll_percent(&self) -> f32 {
+        match self.lines.len() {
+            0 => 1.0,
+            lines => ((self.top + self.height) as f32 / lines as f32).min(1.0),
+        }
+    }
+
+    pub fn render<S: Write>(&mut self, s: &mut S, origin: (u16, u16), size: (usize, usize), focused: bool) {
+        self.height = size.1.max(1);
+        self.top = self.top.min(self.lines.len().saturating_sub(self.height));
+        let fg = if focused { Color::White } else { Color::Grey };
+        queue!(s, style::SetBackgroundColor(Color::Blue), style::SetForegroundColor(fg));
+        for (row, line) in self.lines.iter().skip(self.top).take(self.height).enumerate() {
+            queue!(s, cursor::MoveTo(origin.0, origin.1 + row as u16));
+            // Skip the columns scrolled off to the left, and leave off those past the right edge
+            let (mut column, end) = (0, self.left + size.0);
+            for span in line {
+                let text: String = span.text.chars().enumerate()
+                    .filter(|&(i, _)| column + i >= self.left && column + i < end)
+                    .map(|(_, c)| c)
+                    .collect();
+                column += span.text.chars().count();
+                if text.is_empty() {
+                    continue;
+                }
+                queue!(s,
+                    style::SetAttribute(if span.bold { Attribute::Bold } else { Attribute::NormalIntensity }),
+                    style::SetAttribute(if span.underline { Attribute::Underlined } else { Attribute::NoUnderline }),
+                    style::Print(text),
+                );
+            }
+            queue!(s, style::SetAttribute(Attribute::NormalIntensity), style::SetAttribute(Attribute::NoUnderline));
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use scribe::buffer::Position;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+static MAX_CHANGES: usize = 50;
+
+/// The bookmarked lines, named marks, recent edit locations, diagnostics and folds of a single
+/// buffer.
+/// These only live as long as the editor session, and follow the text around as lines are added
+/// and removed above them.
+#[derive(Default)]
+pub struct Marks {
+    bookmarks: BTreeSet<usize>,
+    named: HashMap<char, Position>,
+    // Where the buffer was edited, most recent last. Edits on the same line count only once.
+    changes: Vec<Position>,
+    // How far back through the changes we have travelled since the last edit.
+    changes_visited: usize,
+    // The first diagnostic message on each line, as of the last check.
+    diagnostics: BTreeMap<usize, String>,
+    // The folded ranges of lines, by the line they are folded under to the last one hidden.
+    folds: BTreeMap<usize, usize>,
+}
+
+impl Marks {
+    pub fn is_bookmarked(&self, line: usize) -> bool {
+        self.bookmarks.contains(&line)
+    }
+
+    pub fn toggle_bookmark(&mut self, line: usize) {
+        if !self.bookmarks.remove(&line) {
+            self.bookmarks.insert(line);
+        }
+    }
+
+    pub fn clear_bookmarks(&mut self) {
+        self.bookmarks.clear();
+    }
+
+    /// The first bookmark after the given line, wrapping around to the first bookmark.
+    pub fn next_bookmark(&self, line: usize) -> Option<usize> {
+        self.bookmarks.range(line + 1..).next().or_else(|| self.bookmarks.iter().next()).copied()
+    }
+
+    /// The last bookmark before the given line, wrapping around to the last bookmark.
+    pub fn previous_bookmark(&self, line: usize) -> Option<usize> {
+        self.bookmarks.range(..line).next_back().or_else(|| self.bookmarks.iter().next_back()).copied()
+    }
+
+    pub fn set_mark(&mut self, name: char, position: Position) {
+        self.named.insert(name, position);
+    }
+
+    pub fn mark(&self, name: char) -> Option<Position> {
+        self.named.get(&name).copied()
+    }
+
+    /// The name of a mark placed on the given line, if any.
+    pub fn mark_on_line(&self, line: usize) -> Option<char> {
+        self.named.iter().filter(|(_, p)| p.line == line).map(|(&c, _)| c).min()
+    }
+
+    /// Remember that the buffer was just edited at the given position.
+    pub fn record_change(&mut self, position: Position) {
+        self.changes_visited = 0;
+        if let Some(last) = self.changes.last_mut() {
+            if last.line == position.line {
+                *last = position;
+                return;
+            }
+        }
+        self.changes.push(position);
+        if self.changes.len() > MAX_CHANGES {
+            self.changes.remove(0);
+        }
+    }
+
+    /// Whether the line is one of the recent edit locations.
+    pub fn is_changed(&self, line: usize) -> bool {
+        self.changes.iter().any(|p| p.line == line)
+    }
+
+    /// Step back to an earlier edit location away from the given line, cycling around to the
+    /// most recent one after reaching the oldest.
+    pub fn previous_change(&mut self, line: usize) -> Option<Position> {
+        for _ in 0..self.changes.len() {
+            let position = self.changes[self.changes.len() - 1 - self.changes_visited % self.changes.len()];
+            self.changes_visited += 1;
+            if position.line != line {
+                return Some(position);
+            }
+        }
+        None
+    }
+
+    /// Replace the diagnostics with those from a new check, keeping the first one on each line.
+    pub fn set_diagnostics(&mut self, diagnostics: impl IntoIterator<Item = (usize, String)>) {
+        self.diagnostics.clear();
+        for (line, message) in diagnostics {
+            self.diagnostics.entry(line).or_insert(message);
+        }
+    }
+
+    /// The first diagnostic message on a line, if the last check had one.
+    pub fn diagnostic(&self, line: usize) -> Option<&str> {
+        self.diagnostics.get(&line).map(String::as_str)
+    }
+
+    /// Fold the lines after `first` up to `last` away under it.
+    pub fn fold(&mut self, first: usize, last: usize) {
+        if last > first {
+            self.folds.insert(first, last);
+        }
+    }
+
+    /// Unfold the lines folded under a line. Returns false if there were none.
+    pub fn unfold(&mut self, first: usize) -> bool {
+        self.folds.remove(&first).is_some()
+    }
+
+    /// Unfold whatever hides a line, so it can be seen.
+    pub fn unfold_around(&mut self, line: usize) {
+        self.folds.retain(|&first, &mut last| line <= first || line > last);
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.folds.clear();
+    }
+
+    /// The folded ranges of lines, from the line each is folded under to the last one hidden.
+    pub fn folds(&self) -> Vec<(usize, usize)> {
+        self.folds.iter().map(|(&first, &last)| (first, last)).collect()
+    }
+
+    /// Whether a line is folded away.
+    pub fn is_hidden(&self, line: usize) -> bool {
+        self.folds.range(..line).any(|(_, &last)| line <= last)
+    }
+
+    /// Keep everything attached to the same text after `delta` lines were added (or removed,
+    /// when negative) just below `line`. Marks on removed lines are dropped.
+    pub fn lines_changed(&mut self, line: usize, delta: isize) {
+        if delta == 0 {
+            return;
+        }
+        let removed = line + 1..=line + (-delta).max(0) as usize;
+        let shift = |l: usize| if l > line { (l as isize + delta) as usize } else { l };
+
+        self.bookmarks = self.bookmarks.iter().filter(|l| !removed.contains(l)).map(|&l| shift(l)).collect();
+        self.named.retain(|_, p| !removed.contains(&p.line));
+        for p in self.named.values_mut() {
+            p.line = shift(p.line);
+        }
+        self.diagnostics = std::mem::take(&mut self.diagnostics).into_iter().filter(|(l, _)| !removed.contains(l)).map(|(l, m)| (shift(l), m)).collect();
+        // A fold keeps what is left of its lines, and goes once they are all removed
+        self.folds = std::mem::take(&mut self.folds).into_iter()
+            .filter(|(first, _)| !removed.contains(first))
+            .map(|(first, last)| (shift(first), if removed.contains(&last) { line } else { shift(last) }))
+            .filter(|(first, last)| last > first)
+            .collect();
+        for p in self.changes.iter_mut() {
+            if removed.contains(&p.line) { // Edits on removed lines are remembered where those lines went
+                *p = Position { line, offset: 0 };
+            } else {
+                p.line = shift(p.line);
+            }
+        }
+    }
+}
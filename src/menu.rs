@@ -16,26 +16,45 @@ pub struct Menu {
     pub children: Vec<(String, MenuAction)>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum Action {
     // Hardcoded menus //
 
     // File
-    Close, New, Save, SaveAs, Open,
+    Close, New, Save, SaveAs, Open, FollowFile,
 
     // Edit
-    Undo, Redo,
+    Undo, Redo, UndoHistory, Cut, Copy, Paste, PasteFromHistory, CopyToRegister, CutToRegister, PasteFromRegister, Registers, SelectNextOccurrence, SkipOccurrence, GrowSelection, NarrowSelection, SetIndentation, AlignOn, LineRange, AddToNumbers, NumberSequence, Evaluate, Transform(crate::transform::Transform), Statistics, NormalizeUnicode, NormalizeIndentation, ToggleAutoClose, ToggleDeadKeys, TogglePasteMode,
+
+    // Search
+    Find, FindNext, FindPrevious, FirstMatch, LastMatch, Replace, SearchHistory, ClearHighlights,
+
+    // View
+    ToggleLineNumbers, ToggleWordWrap, ToggleWhitespace, ToggleMinimap, ToggleStickyScope, ToggleBreadcrumbs, ToggleUnsavedChanges, ToggleFold, UnfoldAll, ToggleTable, TogglePinHeader, Delimiter, ToggleFileTree, ToggleTerminal, ToggleCompare, ToggleCompact, ToggleZenMode, ToggleLightTheme,
+
+    // Insert
+    InsertUnicode, InsertDate, InsertDateInFormat, InsertTemplate,
+
+    // Go
+    ToggleBookmark, NextBookmark, PreviousBookmark, ClearBookmarks, SetMark, JumpToMark, GotoLine, PreviousEdit, JumpBack, JumpForward, ScrollLock, CenterCursorLine, CursorLineToTop, CursorLineToBottom, NextFunction, PreviousFunction, BlockStart, BlockEnd, Breadcrumbs, FindMarkers, CheckProject,
+
+    // Window
+    ToggleFloating, MoveWindow, SplitView, CompareWith, Buffers, Workspaces, MoveToWorkspace, RenameTab, CloseOthers, CloseUnmodified, ReopenClosed, ClosedFiles, DiscardedBuffers,
 
     // Help
-    About,
+    ManPage, ViewLog, TogglePerfOverlay, Language, About,
 
     // A script made this action (we need to call it)
+    #[allow(dead_code)]
     Scripted,
 }
 
 pub enum MenuAction {
     Separator,
     Action(Action),
+    /// An action that turns something on or off, drawn with a check mark while it is on.
+    Check(Action, bool),
+    /// A menu of its own, opened beside the item.
     SubMenu(Menu),
 }
 
@@ -56,53 +75,81 @@ impl MenuBar {
         crate::util::draw_rectangle(s, &Color::Grey, origin, (h_size, 1));
         queue!(s, style::SetBackgroundColor(Color::Grey));
 
-        queue!(s, cursor::MoveTo(origin.0 + 1, origin.1));
+        let mut x = origin.0 as usize + 1;
         for (i, (name, _)) in self.menus.iter().enumerate() {
-            let is_help: bool;
-            if &name[..] == "_Help" { // This is the help menu, we place it at the far right
-                is_help = true;
-                queue!(s, cursor::SavePosition, cursor::MoveTo(origin.0 + h_size as u16 - name.len() as u16 - 2, origin.1));
-            } else {
-                is_help = false;
+            let is_help = i + 1 == self.menus.len(); // This is the help menu, we place it at the far right
+            let label = format!(" {} ", name.replace('_', ""));
+            let at = if is_help { (origin.0 as usize + h_size).saturating_sub(label.chars().count() + 1) } else { x };
+            x += label.chars().count();
+            // On a narrow screen, the menus that do not fit are cut off at its edge
+            let room = (origin.0 as usize + h_size).saturating_sub(at);
+            if room == 0 {
+                continue;
             }
 
             let (bg, fg) = if focused && i == self.selection_index { (Color::Black, Color::White) } else { (Color::White, Color::Black) };
-            queue!(s, style::SetForegroundColor(fg), style::SetBackgroundColor(bg));
-            queue!(s, style::Print(" "));
-            { // TODO: comment
-                // let mut formatted = String::new();
-                let mut chars = name.chars();
-                while let Some(c) = chars.next() {
-                    if c == '_' {
-                        if focused {
-                            queue!(s, style::Print(chars.next().unwrap()));
-                        } else {
-                            queue!(s, style::Print(chars.next().unwrap()));
-                        }
-                    } else {
-                        queue!(s, style::Print(c));
-                    }
-                }
-            }
-            queue!(s, style::Print(" "));
-            
-            if is_help {
-                queue!(s, cursor::RestorePosition); // If we skipped to the end to print help, let's go back
-            }
+            queue!(s,
+                cursor::MoveTo(at as u16, origin.1), style::SetForegroundColor(fg), style::SetBackgroundColor(bg),
+                style::Print(label.chars().take(room).collect::<String>()),
+            );
         }
     }
 
     fn get_origin_x_of_menu(&self, idx: usize) -> u16 {
         assert!(!self.menus.is_empty());
-        if self.menus[idx].0 == "_Help" { // Annoying, Help is planted on the far right for style
-            terminal::size().unwrap().0 - 7
+        if idx + 1 == self.menus.len() { // Annoying, Help is planted on the far right for style
+            crate::backend::size().0.saturating_sub(self.menus[idx].0.chars().count() as u16 + 2)
         } else {
-            (self.menus.iter().take(idx).map(|(name, _)| name.len()).sum::<usize>() // We have a single space before menus are listed off
+            (self.menus.iter().take(idx).map(|(name, _)| name.chars().count()).sum::<usize>() // We have a single space before menus are listed off
             + (idx + 1)) // For spaces before and after names (number of items)
             as u16
         }
     }
 
+    /// Check or uncheck the checkbox items of every menu, by whether `is_on` says what their
+    /// action toggles is on.
+    pub fn update_checks<F: Fn(Action) -> bool>(&mut self, is_on: F) {
+        fn update<F: Fn(Action) -> bool>(menu: &mut Menu, is_on: &F) {
+            for (_, item) in menu.children.iter_mut() {
+                match item {
+                    MenuAction::Check(action, checked) => *checked = is_on(*action),
+                    MenuAction::SubMenu(menu) => update(menu, is_on),
+                    _ => {}
+                }
+            }
+        }
+        for (_, menu) in self.menus.iter_mut() {
+            update(menu, &is_on);
+        }
+    }
+
+    /// List the keys menu mode takes, each menu's letter first, in a box in the bottom right
+    /// corner of the area of the given size.
+    pub fn render_key_hints<S: Write>(&self, s: &mut S, size: (u16, u16)) {
+        let mut hints: Vec<(String, String)> = self.menus.iter()
+            .map(|(name, _)| (get_menu_shortcut_from_name(name).to_uppercase().to_string(), name.replace('_', "")))
+            .collect();
+        hints.extend(crate::keymap::MENU_MODE.iter().map(|b| (b.keys.to_owned(), crate::locale::tr(b.action))));
+
+        let keys_width = hints.iter().map(|(keys, _)| keys.chars().count()).max().unwrap_or(0);
+        let width = hints.iter().map(|(_, action)| keys_width + 2 + action.chars().count()).max().unwrap_or(0) + 2;
+        let height = hints.len() + 2;
+        if width + 1 > size.0 as usize || height + 1 > size.1 as usize {
+            return; // Better no hints than hints over the menu bar
+        }
+        let origin = (size.0 - 1 - width as u16, size.1 - height as u16);
+
+        crate::util::draw_rectangle(s, &Color::Grey, origin, (width, height));
+        queue!(s, style::SetForegroundColor(Color::Black));
+        for (i, (keys, action)) in hints.iter().enumerate() {
+            queue!(s,
+                cursor::MoveTo(origin.0 + 1, origin.1 + 1 + i as u16),
+                style::SetForegroundColor(Color::DarkBlue), style::Print(format!("{:<1$}", keys, keys_width)),
+                style::SetForegroundColor(Color::Black), style::Print(format!("  {}", action)),
+            );
+        }
+    }
+
     /// Returns a menu index and the origin X offset of the menu, for rendering the menu in the correct position.
     pub fn maybe_handle_key_press(&mut self, key: event::KeyEvent) -> Option<(usize, u16)> {
         use event::KeyCode;
@@ -131,18 +178,23 @@ impl MenuBar {
 }
 
 impl Menu {
-    pub fn render<S: Write>(&self, s: &mut S, origin: (u16, u16), selection_index: usize) {
-        let width = self.get_menu_width();
+    /// Draw the menu, from the item at `top` down as far as fits on the screen, with what has been
+    /// typed to find an item on its bottom edge. Arrows on its edges tell of items scrolled out of
+    /// view.
+    pub fn render<S: Write>(&self, s: &mut S, origin: (u16, u16), selection_index: usize, top: usize, typed: &str) {
+        let width = self.width_at(origin);
+        let rows = self.rows_at(origin);
+        let has_checks = self.has_checks();
 
         // Render background box
-        crate::util::draw_rectangle(s, &Color::Grey, origin, (width, self.children.len() + 2));
+        crate::util::draw_rectangle(s, &Color::Grey, origin, (width, rows + 2));
 
         // Render box outline
-        crate::util::draw_thin_unfilled_rectangle(s, &Color::Black, &Color::Grey, origin, (width, self.children.len() + 2));
+        crate::util::draw_thin_unfilled_rectangle(s, &Color::Black, &Color::Grey, origin, (width, rows + 2));
 
-        for (i, (name, a)) in self.children.iter().enumerate() {
+        for (i, (name, a)) in self.children.iter().enumerate().skip(top).take(rows) {
             // goto, print name ; note the spaces before and after name (padding)
-            queue!(s, cursor::MoveTo(origin.0 + 1, origin.1 + 1 + i as u16)); // + 1 makes list appear inside menu bounds
+            queue!(s, cursor::MoveTo(origin.0 + 1, origin.1 + 1 + (i - top) as u16)); // + 1 makes list appear inside menu bounds
             // Background of a selected item is brighter than others
             let (bg, fg) = if i == selection_index { (Color::Black, Color::Grey) } else { (Color::Grey, Color::Black) };
             queue!(s, style::SetForegroundColor(fg), style::SetBackgroundColor(bg));
@@ -150,62 +202,115 @@ impl Menu {
             match a {
                 MenuAction::Separator => queue!(s, style::Print("─".repeat(width - 2))).unwrap(), // width - 2 is the maximum name length
                 _ => {
+                    match a {
+                        MenuAction::Check(_, true) => queue!(s, style::Print("✓ ")),
+                        _ if has_checks => queue!(s, style::Print("  ")),
+                        _ => Ok(()),
+                    };
+                    // Names too long for a menu squeezed onto a narrow screen are cut short
+                    let is_submenu = matches!(a, MenuAction::SubMenu(_));
+                    let room = (width - 2).saturating_sub(has_checks as usize * 2 + is_submenu as usize);
+                    let mut printed = 0;
                     let mut chars = name.chars();
-                    while let Some(c) = chars.next() {
+                    while let Some(c) = chars.next().filter(|_| printed < room) {
                         if c == '_' {
                             queue!(s, style::SetForegroundColor(Color::White), style::Print(chars.next().unwrap()), style::SetForegroundColor(fg));
                         } else {
                             queue!(s, style::Print(c));
                         }
+                        printed += 1;
+                    }
+                    queue!(s, style::Print(" ".repeat(room - printed)));
+                    if is_submenu {
+                        queue!(s, style::Print('▸'));
                     }
-                    queue!(s, style::Print(" ".repeat(width - 2 - if name.contains('_') { name.len() - 1 } else { name.len() } )));
                 }
             }
         }
+
+        queue!(s, style::SetForegroundColor(Color::Black), style::SetBackgroundColor(Color::Grey));
+        if top > 0 {
+            queue!(s, cursor::MoveTo(origin.0 + width as u16 - 2, origin.1), style::Print('▲'));
+        }
+        if top + rows < self.children.len() {
+            queue!(s, cursor::MoveTo(origin.0 + width as u16 - 2, origin.1 + 1 + rows as u16), style::Print('▼'));
+        }
+
+        if !typed.is_empty() {
+            let found = self.starting_with(typed).next().is_some();
+            let text: String = format!(" {} ", typed).chars().take(width - 2).collect();
+            queue!(s,
+                cursor::MoveTo(origin.0 + 1, origin.1 + 1 + rows as u16),
+                style::SetForegroundColor(if found { Color::Black } else { Color::DarkRed }), style::SetBackgroundColor(Color::White),
+                style::Print(text),
+            );
+        }
     }
 
-    /// Take over the current thread and handle the menu's input. This causes recursion when expanding
-    /// sub-menus.
-    pub fn take_over<S: Write>(&self, s: &mut S, x_offset: u16) -> Option<&Action> {
+    /// Take over the current thread and handle the menu's input, drawing the menu with its top left
+    /// corner at `origin`. This causes recursion when expanding sub-menus, which open beside the
+    /// item that expands them.
+    ///
+    /// A shortcut letter picks its item straight away, unless another item's name starts with it
+    /// too. Then, and for any other letters, typing jumps to the first item whose name starts with
+    /// what was typed, and Enter picks it.
+    ///
+    /// A menu taller than the screen scrolls to keep the selected item in view, and one too wide
+    /// for the space right of `origin` is moved left.
+    pub fn take_over<S: Write>(&self, s: &mut S, origin: (u16, u16)) -> Option<&Action> {
         use event::{KeyCode, KeyEvent, Event};
+        let origin = (origin.0.min(crate::backend::size().0.saturating_sub(self.get_menu_width() as u16)), origin.1);
         let mut selection_index = 0usize;
+        let mut top = 0usize;
+        let mut typed = String::new();
         loop {
-            self.render(s, (x_offset, 1), selection_index);
+            top = self.scrolled_to(origin, top, selection_index);
+            self.render(s, origin, selection_index, top, &typed);
 
             s.flush().unwrap();
 
             // All of the input code for a graphical menu.
-            match event::read().unwrap() {
-                Event::Key(KeyEvent { code: KeyCode::Up, .. }) => selection_index = self.previous(selection_index),
-                Event::Key(KeyEvent { code: KeyCode::Down, .. }) => selection_index = self.next(selection_index),
+            match crate::backend::read() {
+                Event::Key(KeyEvent { code: KeyCode::Up, .. }) => { selection_index = self.previous(selection_index); typed.clear(); }
+                Event::Key(KeyEvent { code: KeyCode::Down, .. }) => { selection_index = self.next(selection_index); typed.clear(); }
+                Event::Key(KeyEvent { code: KeyCode::Backspace, .. }) if !typed.is_empty() => {
+                    typed.pop();
+                    if let Some(index) = self.starting_with(&typed).next().filter(|_| !typed.is_empty()) {
+                        selection_index = index;
+                    }
+                }
 
                 // Activate an action or sub-menu expansion using the enter key.
                 Event::Key(KeyEvent { code: KeyCode::Enter, .. }) => match &self.children[selection_index].1 {
                     MenuAction::Separator => unreachable!(),
-                    MenuAction::Action(action) => return Some(action),
-                    MenuAction::SubMenu(menu) => if let Some(action) = menu.take_over(s, x_offset + self.get_menu_width() as u16) {
+                    MenuAction::Action(action) | MenuAction::Check(action, _) => return Some(action),
+                    MenuAction::SubMenu(menu) => if let Some(action) = menu.take_over(s, self.submenu_origin(origin, menu, selection_index - top)) {
                         return Some(action);
                     } // We don't want to close this menu if they exited out of the sub-child one.
                 },
 
                 // Activate an action or sub-menu expansion using a shortcut.
-                Event::Key(KeyEvent { code: KeyCode::Char(c), .. }) => if let Some(menu_index) = self.maybe_handle_key_press(c) {
+                Event::Key(KeyEvent { code: KeyCode::Char(c), .. }) => if let Some(menu_index) = self.maybe_handle_key_press(c).filter(|&i| typed.is_empty() && self.starting_with(&c.to_string()).all(|j| j == i)) {
                     // Update selection index to the menu action we just pressed
                     selection_index = menu_index;
                     // Redraw with new selection index
-                    self.render(s, (x_offset, 1), selection_index);
+                    top = self.scrolled_to(origin, top, selection_index);
+                    self.render(s, origin, selection_index, top, "");
 
                     let menu_action = &self.children[menu_index].1;
                     match menu_action {
                         MenuAction::Separator => unreachable!(),
-                        MenuAction::Action(action) => return Some(action),
-                        MenuAction::SubMenu(menu) => match menu.take_over(s, x_offset + self.get_menu_width() as u16) {
-                            Some(action) => return Some(action),
-                            _ => {} // We don't want to close the menu... same as above ^
-                        }
+                        MenuAction::Action(action) | MenuAction::Check(action, _) => return Some(action),
+                        MenuAction::SubMenu(menu) => if let Some(action) = menu.take_over(s, self.submenu_origin(origin, menu, selection_index - top)) {
+                            return Some(action);
+                        } // We don't want to close the menu... same as above ^
                     }
                 } else {
-                    break None; // For now, when you press an unknown key it will close the menu.
+                    // Look for the item by name instead
+                    typed.push(c);
+                    if let Some(index) = self.starting_with(&typed).next() {
+                        selection_index = index;
+                    }
                 },
 
                 _ => break None,
@@ -213,6 +318,31 @@ impl Menu {
         }
     }
 
+    /// Where a sub-menu opens: just right of this menu, with its first item level with the item
+    /// that opens it (`row` items down the menu as shown), or higher up if it would not fit below.
+    fn submenu_origin(&self, origin: (u16, u16), submenu: &Menu, row: usize) -> (u16, u16) {
+        let bottom = crate::backend::size().1.saturating_sub(submenu.children.len() as u16 + 2);
+        (origin.0 + self.width_at(origin) as u16, (origin.1 + row as u16).min(bottom).max(1))
+    }
+
+    /// How many items show at once with the menu's top left corner at `origin`: all of them, unless
+    /// the screen is too short.
+    fn rows_at(&self, origin: (u16, u16)) -> usize {
+        (crate::backend::size().1 as usize).saturating_sub(origin.1 as usize + 2).min(self.children.len()).max(1)
+    }
+
+    /// How wide the menu is drawn with its top left corner at `origin`, narrower than it would
+    /// like if the screen is.
+    fn width_at(&self, origin: (u16, u16)) -> usize {
+        self.get_menu_width().min(crate::backend::size().0.saturating_sub(origin.0) as usize).max(4)
+    }
+
+    /// The first item shown, scrolled from `top` just enough to show the selected item.
+    fn scrolled_to(&self, origin: (u16, u16), top: usize, selection_index: usize) -> usize {
+        let rows = self.rows_at(origin);
+        top.min(selection_index).max((selection_index + 1).saturating_sub(rows))
+    }
+
     fn previous(&self, mut selection_index: usize) -> usize {
         // Perform reverse wrapping
         if selection_index as isize - 1 < 0 { selection_index = self.children.len()-1; } else { selection_index -= 1; }
@@ -231,13 +361,29 @@ impl Menu {
         selection_index
     }
 
-    /// Returns the minimum width of the menu, without counting any underscores.
+    /// Returns the minimum width of the menu, without counting any underscores, but with room
+    /// for check marks if it has checkbox items, and for the arrow of any sub-menus.
     fn get_menu_width(&self) -> usize {
-        2 + self.children.iter().map(|(name, _)| if name.contains('_') { name.len() - 1 } else { name.len() }).max().expect(
+        let has_submenus = self.children.iter().any(|(_, a)| matches!(a, MenuAction::SubMenu(_)));
+        2 + self.has_checks() as usize * 2 + has_submenus as usize * 2 + self.children.iter().map(|(name, _)| if name.contains('_') { name.chars().count() - 1 } else { name.chars().count() }).max().expect(
             "Empty menu has no width"
         )
     }
 
+    /// Whether any of the items is a checkbox, so that every name is moved over to line up.
+    fn has_checks(&self) -> bool {
+        self.children.iter().any(|(_, a)| matches!(a, MenuAction::Check(..)))
+    }
+
+    /// The indices of the items whose names start with some text, ignoring case.
+    fn starting_with<'a>(&'a self, prefix: &str) -> impl Iterator<Item = usize> + 'a {
+        let prefix = prefix.to_lowercase();
+        self.children.iter().enumerate()
+            .filter(|(_, (_, a))| !matches!(a, MenuAction::Separator))
+            .filter(move |(_, (name, _))| name.replace('_', "").to_lowercase().starts_with(&prefix))
+            .map(|(i, _)| i)
+    }
+
     /// Returns `true` if the key press was correctly handled,
     /// or `false` if the key could not be handled (or was not recognized).
     fn maybe_handle_key_press(&self, key: char) -> Option<usize> {
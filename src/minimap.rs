@@ -0,0 +1,107 @@
+//! An overview of a whole buffer down the right side of its viewport. Each cell is a braille
+//! character whose dots stand for text, so that the shape of the code shows: a dot is four
+//! columns wide, and one line tall or as many as it takes for the whole buffer to fit. Lines
+//! worth finding are colored, and the lines in view are drawn on a lighter background. Clicking
+//! the minimap goes to the lines there.
+
+use crossterm::{cursor, queue, style::{self, Color}};
+
+use crate::lines::Lines;
+
+use std::io::Write;
+use std::ops::Range;
+
+/// How many columns the minimap takes up, besides the one keeping it apart from the text.
+pub static WIDTH: usize = 12;
+
+/// How many columns of text each dot stands for.
+static COLUMNS_PER_DOT: usize = 4;
+
+/// The width below which a viewport has no room for a minimap.
+pub static MIN_VIEW_WIDTH: usize = 60;
+
+/// Why a line stands out in the minimap. Where a cell has lines of more than one kind, the last
+/// kind here wins.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Highlight {
+    Marker,
+    Change,
+    Bookmark,
+    Cursor,
+    Match,
+}
+
+impl Highlight {
+    fn color(self) -> Color {
+        match self {
+            Highlight::Marker => crate::config::get().marker_color,
+            Highlight::Change => Color::Green,
+            Highlight::Bookmark => Color::Yellow,
+            Highlight::Cursor => Color::White,
+            Highlight::Match => Color::Cyan,
+        }
+    }
+}
+
+/// How many lines each row of dots stands for, so that the whole buffer fits in the rows.
+fn lines_per_dot(line_count: usize, rows: usize) -> usize {
+    line_count.div_ceil(rows.max(1) * 4).max(1)
+}
+
+/// The line a row of the minimap starts at, for clicking on it. None if the buffer ends above it.
+pub fn line_at_row(row: usize, line_count: usize, rows: usize) -> Option<usize> {
+    let line = row * 4 * lines_per_dot(line_count, rows);
+    (line < line_count).then_some(line)
+}
+
+/// Draw the minimap of some lines, in so many rows from the top left cell given. `shown` are the
+/// lines in view, and `highlight` tells which others stand out.
+pub fn render<S: Write>(s: &mut S, origin: (u16, u16), rows: usize, lines: &Lines, shown: Range<usize>, highlight: impl Fn(usize) -> Option<Highlight>) {
+    let per_dot = lines_per_dot(lines.len(), rows);
+    for row in 0..rows {
+        let first = row * 4 * per_dot;
+        let cell_lines = first.min(lines.len())..(first + 4 * per_dot).min(lines.len());
+        let in_view = cell_lines.start < shown.end && shown.start < cell_lines.end;
+        let color = cell_lines.clone().filter_map(&highlight).max().map_or(Color::Grey, Highlight::color);
+
+        // The dots of each cell, as the bits of a braille pattern
+        let mut cells = [0u8; WIDTH];
+        for line in cell_lines {
+            let dot_row = (line - first) / per_dot;
+            for (column, c) in columns(&lines[line]) {
+                let dot_column = column / COLUMNS_PER_DOT;
+                if dot_column >= WIDTH * 2 {
+                    break;
+                }
+                if !c.is_whitespace() {
+                    cells[dot_column / 2] |= dot(dot_row, dot_column % 2);
+                }
+            }
+        }
+        let text: String = cells.iter().map(|&bits| std::char::from_u32(0x2800 + bits as u32).unwrap()).collect();
+        queue!(s,
+            cursor::MoveTo(origin.0, origin.1 + row as u16),
+            style::SetBackgroundColor(if in_view { Color::Blue } else { Color::DarkBlue }), style::SetForegroundColor(color),
+            style::Print(text),
+        );
+    }
+}
+
+/// The bit of a braille pattern for a dot, by its row of four and column of two.
+fn dot(row: usize, column: usize) -> u8 {
+    match (row, column) {
+        (3, 0) => 0x40,
+        (3, _) => 0x80,
+        (row, 0) => 1 << row,
+        (row, _) => 1 << (row + 3),
+    }
+}
+
+/// The characters of a line with the column each is at, tabs taking up as many columns as dots do.
+fn columns(line: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    line.chars().scan(0, |column, c| {
+        let at = *column;
+        *column += if c == '\t' { COLUMNS_PER_DOT } else { 1 };
+        Some((at, c))
+    })
+}
@@ -0,0 +1,103 @@
+//! Adding to the number under the cursor, like `42`, `-7`, `007` or `0xFF`.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Find the number the cursor is on or touching, or else the first one after it on the line, as
+/// a byte range of the line. A `-` right before a number makes it negative, unless it follows a
+/// letter or digit, as in `x-1`. Digits with an accent on the first or last of them are not
+/// taken for a number, as the buffer cannot split the accent off.
+pub fn find(line: &str, at: usize) -> Option<(usize, usize)> {
+    let bytes = line.as_bytes();
+    let boundaries: Vec<usize> = line.grapheme_indices(true).map(|(i, _)| i).chain(std::iter::once(line.len())).collect();
+    let is_boundary = |offset: usize| boundaries.binary_search(&offset).is_ok();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+        let mut start = i;
+        let end = match hex_digits(&bytes[i..]) {
+            Some(n) => i + 2 + n,
+            None => {
+                let follows_word = start > 1 && (bytes[start - 2].is_ascii_alphanumeric() || bytes[start - 2] == b'_');
+                if start > 0 && bytes[start - 1] == b'-' && !follows_word {
+                    start -= 1;
+                }
+                i + bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count()
+            }
+        };
+        if end >= at && is_boundary(start) && is_boundary(end) {
+            return Some((start, end));
+        }
+        i = end;
+    }
+    None
+}
+
+/// How many hex digits follow a `0x` at the start of the bytes, if they start with one.
+fn hex_digits(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < 3 || bytes[0] != b'0' || !(bytes[1] == b'x' || bytes[1] == b'X') {
+        return None;
+    }
+    match bytes[2..].iter().take_while(|b| b.is_ascii_hexdigit()).count() {
+        0 => None,
+        n => Some(n),
+    }
+}
+
+/// Add to a number, keeping the way it is written: hex stays hex in the same case, and numbers
+/// padded with zeros keep their width.
+pub fn add(number: &str, delta: i64) -> String {
+    if let Some(n) = hex_digits(number.as_bytes()) {
+        let digits = &number[2..2 + n];
+        let value = u64::from_str_radix(digits, 16).unwrap_or(0).wrapping_add(delta as u64);
+        let new = match digits.chars().any(|c| c.is_ascii_uppercase()) {
+            true => format!("{:0width$X}", value, width = n),
+            false => format!("{:0width$x}", value, width = n),
+        };
+        return format!("{}{}", &number[..2], new);
+    }
+    let digits = number.trim_start_matches('-');
+    let value = number.parse::<i64>().unwrap_or(0).saturating_add(delta);
+    let width = match digits.len() > 1 && digits.starts_with('0') {
+        true => digits.len(),
+        false => 0,
+    };
+    match value < 0 {
+        true => format!("-{:0width$}", value.unsigned_abs(), width = width),
+        false => format!("{:0width$}", value, width = width),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_are_found_among_other_text() {
+        assert_eq!(find("x = 42;", 0), Some((4, 6)));
+        assert_eq!(find("a-1 b -1", 4), Some((6, 8)));
+        assert_eq!(find("0xFF", 1), Some((0, 4)));
+        assert_eq!(find("é 12 ü", 0), Some((3, 5)));
+        assert_eq!(find("日本 7", 7), Some((7, 8)));
+        assert_eq!(find("none", 0), None);
+    }
+
+    #[test]
+    fn digits_under_accents_are_left_be() {
+        // The 1 carries the accent, so only the 0 after it can be added to
+        assert_eq!(find("1\u{301}0", 0), Some((3, 4)));
+        assert_eq!(find("7\u{301}", 0), None);
+        assert_eq!(find("12\u{301} 5", 0), Some((5, 6)));
+    }
+
+    #[test]
+    fn numbers_keep_the_way_they_are_written() {
+        assert_eq!(add("41", 1), "42");
+        assert_eq!(add("007", 5), "012");
+        assert_eq!(add("-1", 2), "1");
+        assert_eq!(add("0xff", 1), "0x100");
+        assert_eq!(add("0x0F", 1), "0x10");
+    }
+}
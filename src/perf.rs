@@ -0,0 +1,137 @@
+//! Measuring the editor itself: how long frames and events take, how much they allocate, and how
+//! long keys take to show on screen. Toggling the performance overlay (F12) shows the numbers in
+//! the top right corner.
+
+use crossterm::{*, style::Color};
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::viewport::{ViewportData, ViewportManager};
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, counting every allocation made through it. Install it with
+/// `#[global_allocator]` for the overlay to show allocation counts.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// What a piece of work cost.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Sample {
+    pub time: Duration,
+    pub allocations: usize,
+    pub allocated_bytes: usize,
+}
+
+/// Measures the work done between `start` and `finish`.
+pub struct Measurement {
+    started: Instant,
+    allocations: usize,
+    allocated_bytes: usize,
+}
+
+impl Measurement {
+    pub fn start() -> Measurement {
+        Measurement {
+            started: Instant::now(),
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn finish(self) -> Sample {
+        Sample {
+            time: self.started.elapsed(),
+            allocations: ALLOCATIONS.load(Ordering::Relaxed) - self.allocations,
+            allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed) - self.allocated_bytes,
+        }
+    }
+}
+
+/// How many of the last keys the worst latency is of.
+static LATENCY_KEYS: usize = 100;
+
+#[derive(Default)]
+pub struct PerfOverlay {
+    pub enabled: bool,
+    /// The last frame rendered.
+    pub frame: Sample,
+    /// The last event handled.
+    pub event: Sample,
+    /// How long the last keys took from being read to being on screen, the latest last.
+    latencies: VecDeque<Duration>,
+}
+
+impl PerfOverlay {
+    /// Note how long a key took from being read to its frame being drawn. Keys handled together
+    /// in one frame count once, by the first of them.
+    pub fn key_shown(&mut self, latency: Duration) {
+        if self.latencies.len() == LATENCY_KEYS {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency);
+        log::trace!("A key took {:?} to show", latency);
+    }
+
+    /// Draw the overlay in the top right corner of the area below the menu bar, leaving the
+    /// cursor where it was.
+    pub fn render<S: Write>(&self, s: &mut S, width: u16, viewport_manager: &ViewportManager) {
+        let mut lines = vec![
+            format!("frame  {}", describe(&self.frame)),
+            format!("event  {}", describe(&self.event)),
+        ];
+        if let (Some(last), Some(worst)) = (self.latencies.back(), self.latencies.iter().max()) {
+            lines.push(format!("keys   {:>8.3} ms, worst {:.3} ms of {}", last.as_secs_f64() * 1000.0, worst.as_secs_f64() * 1000.0, self.latencies.len()));
+        }
+        if let Some(ViewportData::Buffer(document, _)) = viewport_manager.viewports.get(viewport_manager.focus_index).map(|v| &v.data) {
+            let buf = &document.borrow().buffer;
+            lines.push(format!("buffer {} lines, {}", buf.line_count(), bytes(buf.data().len())));
+        }
+        let buffers = &viewport_manager.buffers;
+        let total: usize = buffers.iter().map(|document| document.borrow().buffer.data().len()).sum();
+        lines.push(format!("all    {} buffers, {}", buffers.iter().count(), bytes(total)));
+        lines.push(format!("heap   {} allocations", ALLOCATIONS.load(Ordering::Relaxed)));
+
+        let overlay_width = lines.iter().map(|l| l.chars().count()).max().unwrap() + 2;
+        let x = width.saturating_sub(overlay_width as u16 + 1);
+        queue!(s, cursor::SavePosition, style::SetBackgroundColor(Color::Black), style::SetForegroundColor(Color::Yellow));
+        for (i, line) in lines.iter().enumerate() {
+            queue!(s, cursor::MoveTo(x, 1 + i as u16), style::Print(format!(" {:<width$} ", line, width = overlay_width - 2)));
+        }
+        queue!(s, cursor::RestorePosition);
+    }
+}
+
+fn describe(sample: &Sample) -> String {
+    format!("{:>8.3} ms {:>6} allocs {:>9}", sample.time.as_secs_f64() * 1000.0, sample.allocations, bytes(sample.allocated_bytes))
+}
+
+fn bytes(n: usize) -> String {
+    match n {
+        0..=1023 => format!("{} B", n),
+        1024..=1_048_575 => format!("{:.1} KB", n as f64 / 1024.0),
+        _ => format!("{:.1} MB", n as f64 / 1_048_576.0),
+    }
+}
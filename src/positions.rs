@@ -0,0 +1,75 @@
+//! Where the cursor and the view were in each file when it was last closed, kept in the data
+//! directory so that opening the file again goes back there. Turned off with
+//! `remember_positions = false`.
+//!
+//! They are kept in a single file, one line for each file, the most recently closed first: the
+//! line and column of the cursor, the first line in view and the file's full path, separated by
+//! tabs. Only the latest `remember_positions_max` files are kept. The messages Git has written
+//! are left out, as each is new.
+
+use scribe::buffer::Position;
+
+use crate::config;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the cursor was left in a file, and the first line in view.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Remembered {
+    pub cursor: Position,
+    pub first_line: usize,
+}
+
+/// Where the cursor was left in a file, if that is known.
+pub fn recall(path: &Path) -> Option<Remembered> {
+    if !config::get().remember_positions || crate::syntax::is_git_message(path) {
+        return None;
+    }
+    let path = path.canonicalize().ok()?;
+    read().into_iter().find(|(p, _)| *p == path).map(|(_, remembered)| remembered)
+}
+
+/// Remember where the cursor was left in a file, forgetting the files closed longest ago if
+/// there are too many.
+pub fn remember(path: &Path, remembered: Remembered) {
+    let (enabled, max) = {
+        let config = config::get();
+        (config.remember_positions, config.remember_positions_max)
+    };
+    let (Some(file), Ok(path)) = (positions_file(), path.canonicalize()) else { return };
+    if !enabled || crate::syntax::is_git_message(&path) {
+        return;
+    }
+    let mut entries = read();
+    entries.retain(|(p, _)| *p != path);
+    entries.insert(0, (path, remembered));
+    entries.truncate(max);
+
+    let text: String = entries.iter()
+        .map(|(path, r)| format!("{}\t{}\t{}\t{}\n", r.cursor.line, r.cursor.offset, r.first_line, path.display()))
+        .collect();
+    let result = fs::create_dir_all(file.parent().unwrap()).and_then(|()| fs::write(&file, text));
+    if let Err(e) = result {
+        log::warn!("Could not write the cursor positions to {}: {}", file.display(), e);
+    }
+}
+
+// Every file's position, the most recently closed first. Lines that cannot be read are skipped.
+fn read() -> Vec<(PathBuf, Remembered)> {
+    let text = match positions_file().map(fs::read_to_string) {
+        Some(Ok(text)) => text,
+        _ => return Vec::new(),
+    };
+    text.lines().filter_map(|line| {
+        let mut fields = line.splitn(4, '\t');
+        let mut number = || fields.next()?.parse().ok();
+        let (line, offset, first_line) = (number()?, number()?, number()?);
+        let path = PathBuf::from(fields.next()?);
+        Some((path, Remembered { cursor: Position { line, offset }, first_line }))
+    }).collect()
+}
+
+fn positions_file() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join("positions"))
+}
@@ -0,0 +1,156 @@
+//! Operations on a range of lines given by number, like `10,50`, rather than by selecting them.
+
+use crate::indent::Indentation;
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Line comment markers by file extension. Anything else is commented with `//`.
+static COMMENT_PREFIXES: &[(&str, &[&str])] = &[
+    ("#", &["py", "sh", "bash", "zsh", "fish", "rb", "pl", "toml", "yaml", "yml", "conf", "cfg", "ini", "r", "cmake", "mk", "nim", "ex", "exs"]),
+    ("--", &["lua", "sql", "hs", "elm", "ada"]),
+    (";", &["lisp", "el", "clj", "scm", "asm", "s"]),
+    ("%", &["tex", "erl", "m"]),
+];
+
+/// Read a range of lines, as zero-based line indices. A range is a line or two separated by a
+/// comma, where a line is a number counting from 1, `.` for the cursor's line or `$` for the
+/// last, and either can be followed by `+n` or `-n`.
+pub fn parse(input: &str, current: usize, line_count: usize) -> Result<(usize, usize), String> {
+    let line = |part: &str| -> Result<usize, String> {
+        let part = part.trim();
+        let split = part.find(['+', '-']).filter(|&i| i > 0).unwrap_or(part.len());
+        let (base, offset) = part.split_at(split);
+        let base = match base.trim() {
+            "." => current as isize + 1,
+            "$" => line_count as isize,
+            n => n.parse::<isize>().map_err(|_| format!("'{}' is not a line number.", part))?,
+        };
+        let offset: isize = match offset.trim() {
+            "" => 0,
+            o => o.parse().map_err(|_| format!("'{}' is not a line number.", part))?,
+        };
+        match base + offset {
+            n if n >= 1 && n <= line_count as isize => Ok(n as usize - 1),
+            _ => Err(format!("There is no line {} (the buffer has {}).", base + offset, line_count)),
+        }
+    };
+    let (first, last) = match input.split_once(',') {
+        Some((first, last)) => (line(first)?, line(last)?),
+        None => (line(input)?, line(input)?),
+    };
+    Ok((first.min(last), first.max(last)))
+}
+
+/// The line comment marker for a file.
+pub fn comment_prefix(path: Option<&Path>) -> &'static str {
+    let extension = path.and_then(|p| p.extension()).and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    COMMENT_PREFIXES.iter()
+        .find(|(_, extensions)| extensions.contains(&extension.as_str()))
+        .map_or("//", |(prefix, _)| prefix)
+}
+
+/// Comment out the lines, or uncomment them if every line that is not blank already is.
+pub fn toggle_comment(text: &str, prefix: &str) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let commented = lines.iter().filter(|l| !l.trim().is_empty()).all(|l| l.trim_start().starts_with(prefix));
+    // Comments go after the indentation all lines share, so they line up
+    let column = lines.iter().filter(|l| !l.trim().is_empty())
+        .map(|l| &l[..l.len() - l.trim_start().len()])
+        .reduce(|shared, indent| {
+            let same = shared.char_indices().zip(indent.chars()).take_while(|((_, a), b)| a == b).last();
+            &shared[..same.map_or(0, |((i, c), _)| i + c.len_utf8())]
+        })
+        .map_or(0, str::len);
+    lines.iter().map(|l| {
+        if l.trim().is_empty() {
+            l.to_string()
+        } else if commented {
+            let indent = l.len() - l.trim_start().len();
+            let rest = &l[indent + prefix.len()..];
+            format!("{}{}", &l[..indent], rest.strip_prefix(' ').unwrap_or(rest))
+        } else {
+            format!("{}{} {}", &l[..column], prefix, &l[column..])
+        }
+    }).collect::<Vec<_>>().join("\n")
+}
+
+/// Indent every line that is not blank by one level.
+pub fn indent(text: &str, indentation: Indentation) -> String {
+    text.split('\n').map(|l| match l.is_empty() {
+        true => String::new(),
+        false => format!("{}{}", indentation.text(0), l),
+    }).collect::<Vec<_>>().join("\n")
+}
+
+/// Take one level of indentation off every line that has any.
+pub fn unindent(text: &str, indentation: Indentation) -> String {
+    let width = match indentation {
+        Indentation::Tabs => 1,
+        Indentation::Spaces(width) => width,
+    };
+    text.split('\n').map(|l| {
+        let remove = match l.chars().next() {
+            Some('\t') => 1,
+            _ => l.chars().take(width).take_while(|&c| c == ' ').count(),
+        };
+        &l[remove..]
+    }).collect::<Vec<_>>().join("\n")
+}
+
+/// Run a shell command with the text as its input, giving back what it writes out, or what it
+/// complained about if it failed.
+pub fn pipe(text: &str, command: &str) -> Result<String, String> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run the shell: {}", e))?;
+    // Writing from another thread, so a command that answers before reading everything cannot
+    // leave us both waiting on each other
+    let mut stdin = child.stdin.take().unwrap();
+    let input = text.to_owned() + "\n";
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    let _ = writer.join();
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("`{}` failed ({}):\n{}", command, output.status, message.trim_end()));
+    }
+    let out = String::from_utf8_lossy(&output.stdout);
+    Ok(out.strip_suffix('\n').unwrap_or(&out).to_owned())
+}
+
+/// A command running another through the system's shell.
+#[cfg(unix)]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_go_after_the_shared_indentation() {
+        assert_eq!(toggle_comment("    a\n\n  b", "//"), "  //   a\n\n  // b");
+        assert_eq!(toggle_comment("  //   a\n\n  // b", "//"), "    a\n\n  b");
+        assert_eq!(toggle_comment("\ta\n    b", "#"), "# \ta\n#     b");
+    }
+
+    #[test]
+    fn indentation_of_wide_spaces_is_not_cut() {
+        assert_eq!(toggle_comment("\rTODO>.\n\u{a0}0_Z//", "//"), "// \rTODO>.\n// \u{a0}0_Z//");
+        assert_eq!(toggle_comment("\u{3000}\u{3000}a\n\u{3000} b", "#"), "\u{3000}# \u{3000}a\n\u{3000}#  b");
+        assert_eq!(toggle_comment("\u{a0}a\n\u{a0}b", "--"), "\u{a0}-- a\n\u{a0}-- b");
+    }
+}
@@ -0,0 +1,40 @@
+//! Last-ditch rescue of unsaved work. When the editor panics, whatever had not been saved is
+//! written to the `recovery` folder of the data directory before the editor goes down.
+
+use crate::config;
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn recovery_dir() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join("recovery"))
+}
+
+/// Write the text of a buffer with the given title to a new file in the recovery folder, never
+/// replacing an earlier one. Returns where it went.
+pub fn write(title: &str, text: &str) -> io::Result<PathBuf> {
+    let dir = recovery_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "There is no home directory"))?;
+    fs::create_dir_all(&dir)?;
+
+    let title: String = title.chars().map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c }).collect();
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    for n in 0.. {
+        let name = match n {
+            0 => format!("{}.{}.{}", title, time, std::process::id()),
+            n => format!("{}.{}.{}.{}", title, time, std::process::id(), n),
+        };
+        let path = dir.join(name);
+        // Opening with `create_new` makes sure we never write over another recovered file
+        match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                io::Write::write_all(&mut file, text.as_bytes())?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
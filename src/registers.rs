@@ -0,0 +1,44 @@
+//! Named registers, in the manner of vim: text copied into one of the registers `a` to `z` stays
+//! there, apart from the clipboard, until something else is copied into it, and copying into a
+//! capital letter adds to the end of its register instead. The digits name what went on the
+//! clipboard: `0` is what is on it now, and `1` to `9` what was copied before, as Paste from
+//! history lists them.
+
+use crate::clipboard::Clipboard;
+
+use std::collections::BTreeMap;
+
+#[derive(Default)]
+pub struct Registers {
+    named: BTreeMap<char, String>,
+}
+
+impl Registers {
+    /// Put text in a register, or add it to the end of the register for a capital letter. Returns
+    /// false if the name is not a letter.
+    pub fn set(&mut self, name: char, text: String) -> bool {
+        if !name.is_ascii_alphabetic() {
+            return false;
+        }
+        let register = self.named.entry(name.to_ascii_lowercase()).or_default();
+        match name.is_ascii_uppercase() {
+            true => register.push_str(&text),
+            false => *register = text,
+        }
+        true
+    }
+
+    /// The text in a register, if it has any.
+    pub fn get<'a>(&'a self, name: char, clipboard: &'a Clipboard) -> Option<&'a str> {
+        match name.to_digit(10) {
+            Some(n) => clipboard.history().get(n as usize).copied(),
+            None => self.named.get(&name.to_ascii_lowercase()).map(String::as_str),
+        }
+    }
+
+    /// Every register with text in it, the letters first, by name.
+    pub fn filled<'a>(&'a self, clipboard: &'a Clipboard) -> Vec<(char, &'a str)> {
+        let numbered = clipboard.history().into_iter().take(10).enumerate().map(|(n, text)| (std::char::from_digit(n as u32, 10).unwrap(), text));
+        self.named.iter().map(|(&name, text)| (name, text.as_str())).chain(numbered).collect()
+    }
+}
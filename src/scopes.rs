@@ -0,0 +1,120 @@
+//! The scopes a line is in, told from indentation alone: the nearest line above that is indented
+//! less starts the scope, and so on outwards. This works for most code and for outlines, without
+//! knowing the language.
+
+use crate::lines::Lines;
+
+/// How far a line is indented, in columns with tabs to the next multiple of four. None if it is
+/// blank, which says nothing about the scope it is in.
+fn indentation(line: &str) -> Option<usize> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    let mut columns = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => columns += 1,
+            '\t' => columns += 4 - columns % 4,
+            _ => break,
+        }
+    }
+    Some(columns)
+}
+
+/// Whether a line can start a scope, rather than being only a bracket like an Allman-style `{`.
+fn is_header(line: &str) -> bool {
+    line.chars().any(char::is_alphanumeric)
+}
+
+/// The lines starting the scopes a line is in, outermost first. A blank line is taken to be in
+/// the scope of the next line that is not blank.
+pub fn enclosing(lines: &Lines, line: usize) -> Vec<usize> {
+    let Some(mut indent) = lines.iter().skip(line).find_map(indentation) else { return Vec::new() };
+    let mut headers = Vec::new();
+    for i in (0..line.min(lines.len())).rev() {
+        if indent == 0 {
+            break;
+        }
+        match indentation(&lines[i]) {
+            Some(less) if less < indent && is_header(&lines[i]) => {
+                headers.push(i);
+                indent = less;
+            }
+            _ => {}
+        }
+    }
+    headers.reverse();
+    headers
+}
+
+/// The lines starting the scopes beside the one a line starts: those in the same scope, and as
+/// far indented. The line is among them.
+pub fn siblings(lines: &Lines, line: usize) -> Vec<usize> {
+    let Some(indent) = lines.get(line).and_then(indentation) else { return Vec::new() };
+    let within = match enclosing(lines, line).last() {
+        Some(&parent) => parent + 1..end(lines, parent),
+        None => 0..lines.len(),
+    };
+    within.filter(|&i| i == line || (indentation(&lines[i]) == Some(indent) && is_header(&lines[i]) && starts_scope(lines, i))).collect()
+}
+
+/// What to call the scope a line starts: the line without its indentation, and without the
+/// bracket or colon at the end of it opening the scope.
+pub fn name(line: &str) -> String {
+    line.trim().trim_end_matches(|c: char| matches!(c, '{' | '(' | '[' | ':') || c.is_whitespace()).to_owned()
+}
+
+/// The last line of the scope a line starts, leaving out the blank lines at the end of it, if it
+/// starts one.
+pub fn last_line(lines: &Lines, line: usize) -> Option<usize> {
+    if !is_header(&lines[line]) || !starts_scope(lines, line) {
+        return None;
+    }
+    (line + 1..end(lines, line)).rev().find(|&i| indentation(&lines[i]).is_some())
+}
+
+/// The next line after a line, or the last before it, that starts a scope defining something,
+/// like a function, a type or a class, rather than an `if` or a loop.
+pub fn next_definition(lines: &Lines, line: usize, forward: bool) -> Option<usize> {
+    let is_definition = |i: usize| is_header(&lines[i]) && !is_statement(&lines[i]) && starts_scope(lines, i);
+    match forward {
+        true => (line + 1..lines.len()).find(|&i| is_definition(i)),
+        false => (0..line.min(lines.len())).rev().find(|&i| is_definition(i)),
+    }
+}
+
+/// Whether a line starts with a keyword of control flow, or with something other than a word,
+/// like a comment or a closing bracket.
+fn is_statement(line: &str) -> bool {
+    static KEYWORDS: &[&str] = &[
+        "if", "else", "elif", "for", "foreach", "while", "loop", "do", "match", "switch", "case", "try", "catch",
+        "except", "finally", "with", "unless", "until", "return", "let", "begin", "select", "when",
+    ];
+    let line = line.trim_start();
+    let first_word = line.split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap_or("");
+    KEYWORDS.contains(&first_word) || !line.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+}
+
+/// Whether the lines after a line are indented more than it, past any blank ones and brackets on
+/// their own.
+fn starts_scope(lines: &Lines, line: usize) -> bool {
+    let Some(indent) = indentation(&lines[line]) else { return false };
+    for next in lines.iter().skip(line + 1) {
+        match indentation(next) {
+            None => {}
+            Some(more) if more > indent => return true,
+            Some(same) if same == indent && !is_header(next) => {}
+            Some(_) => return false,
+        }
+    }
+    false
+}
+
+// The line after the last one in the scope a line starts: the next one indented no more than it
+// that is not just a bracket.
+fn end(lines: &Lines, line: usize) -> usize {
+    let indent = indentation(&lines[line]).unwrap_or(0);
+    (line + 1..lines.len())
+        .find(|&i| indentation(&lines[i]).is_some_and(|less| less <= indent) && is_header(&lines[i]))
+        .unwrap_or(lines.len())
+}
@@ -0,0 +1,232 @@
+//! Finding text in a buffer, and the search bar that takes the place of the status bar while
+//! searching (Ctrl+F). The options are toggled from the bar with Alt+C (case), Alt+W (whole
+//! word), Alt+R (wrap around) and Alt+N (Unicode normalization), Up and Down recall earlier
+//! searches, and Home and End go to the first and last match. While the matches are highlighted,
+//! the status bar counts them.
+
+use crossterm::{*, style::Color};
+use unicode_normalization::{UnicodeNormalization, is_nfc};
+use unicode_segmentation::UnicodeSegmentation;
+
+use std::io::Write;
+
+/// How letters of different case match.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CaseMode {
+    /// Ignore case, unless the pattern has a capital letter in it.
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+impl CaseMode {
+    fn next(self) -> CaseMode {
+        match self {
+            CaseMode::Smart => CaseMode::Sensitive,
+            CaseMode::Sensitive => CaseMode::Insensitive,
+            CaseMode::Insensitive => CaseMode::Smart,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            CaseMode::Smart => "Smart case",
+            CaseMode::Sensitive => "Match case",
+            CaseMode::Insensitive => "Ignore case",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SearchOptions {
+    pub case: CaseMode,
+    /// Only match where the pattern is not part of a longer word.
+    pub whole_word: bool,
+    /// Carry on from the other end of the buffer after the last match.
+    pub wrap: bool,
+    /// Match characters however they are composed, like `é` as one character or as `e` and a
+    /// combining accent.
+    pub normalize: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> SearchOptions {
+        SearchOptions { case: CaseMode::Smart, whole_word: false, wrap: true, normalize: true }
+    }
+}
+
+/// The last thing searched for, which Find next and Find previous look for again.
+#[derive(Default, Clone)]
+pub struct Search {
+    pub pattern: String,
+    pub options: SearchOptions,
+}
+
+impl Search {
+    /// Every match in some text, as byte ranges in order.
+    pub fn find_all(&self, text: &str) -> Vec<(usize, usize)> {
+        if self.pattern.is_empty() {
+            return Vec::new();
+        }
+        let ignore_case = match self.options.case {
+            CaseMode::Smart => !self.pattern.chars().any(char::is_uppercase),
+            CaseMode::Sensitive => false,
+            CaseMode::Insensitive => true,
+        };
+        // Both are searched in their composed forms, with the matches taken back to the bytes of
+        // the text they came from
+        let composed = self.options.normalize && !(is_nfc(text) && is_nfc(&self.pattern));
+        let (haystack, origins) = match composed {
+            true => compose(text),
+            false => (text.to_owned(), Vec::new()),
+        };
+        let pattern: String = match composed {
+            true => self.pattern.nfc().collect(),
+            false => self.pattern.clone(),
+        };
+        let found: Vec<(usize, usize)> = if ignore_case {
+            // Folding keeps every byte offset the same, so the matches apply to the original text
+            fold_case(&haystack).match_indices(&fold_case(&pattern)).map(|(i, m)| (i, i + m.len())).collect()
+        } else {
+            haystack.match_indices(pattern.as_str()).map(|(i, m)| (i, i + m.len())).collect()
+        };
+        let matches: Vec<(usize, usize)> = match composed {
+            true => found.into_iter().map(|(start, end)| (origins[start], origins[end])).collect(),
+            false => found,
+        };
+        if self.options.whole_word {
+            let is_word = |c: char| c.is_alphanumeric() || c == '_';
+            matches.into_iter().filter(|&(start, end)| {
+                !text[..start].chars().next_back().is_some_and(is_word) && !text[end..].chars().next().is_some_and(is_word)
+            }).collect()
+        } else {
+            matches
+        }
+    }
+
+    /// The index of the first match starting at or after a byte offset, or of the last match
+    /// ending at or before it when going backwards, wrapping around if the options allow.
+    pub fn next_match(&self, matches: &[(usize, usize)], from: usize, forward: bool) -> Option<usize> {
+        let found = if forward {
+            matches.iter().position(|&(start, _)| start >= from)
+        } else {
+            matches.iter().rposition(|&(_, end)| end <= from)
+        };
+        match found {
+            None if self.options.wrap && !matches.is_empty() => Some(if forward { 0 } else { matches.len() - 1 }),
+            found => found,
+        }
+    }
+}
+
+/// Text in its composed form (NFC), with the byte of the text each of its bytes came from, and
+/// the end of the text after them. Graphemes are composed one at a time, so a match that starts
+/// or ends between graphemes does the same in the text.
+fn compose(text: &str) -> (String, Vec<usize>) {
+    let mut composed = String::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len() + 1);
+    for (i, grapheme) in text.grapheme_indices(true) {
+        composed.extend(grapheme.nfc());
+        origins.resize(composed.len(), i);
+    }
+    origins.push(text.len());
+    (composed, origins)
+}
+
+/// Lower case, for letters whose lower case letter takes as many bytes.
+fn fold_case(text: &str) -> String {
+    text.chars().map(|c| {
+        let mut lower = c.to_lowercase();
+        match (lower.next(), lower.next()) {
+            (Some(l), None) if l.len_utf8() == c.len_utf8() => l,
+            _ => c,
+        }
+    }).collect()
+}
+
+/// What the search bar, and the status bar while the matches are highlighted, show about them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Counter {
+    /// The current match, counting from zero, of so many.
+    At(usize, usize),
+    /// There are matches, but none is selected.
+    Found(usize),
+    NoMatches,
+}
+
+/// Draw the search bar over the status bar, leaving the cursor at the end of the pattern.
+pub fn render_bar<S: Write>(s: &mut S, origin: (u16, u16), width: usize, search: &Search, counter: Counter) {
+    crate::util::draw_rectangle(s, &Color::Grey, origin, (width, 1));
+    let check = |on: bool| if on { "[x]" } else { "[ ]" };
+    let counter = match counter {
+        Counter::At(i, n) => format!("{} of {}", i + 1, n),
+        Counter::Found(n) => format!("{} matches", n),
+        Counter::NoMatches if search.pattern.is_empty() => String::new(),
+        Counter::NoMatches => "No matches".to_owned(),
+    };
+    let right = format!(
+        "Alt+C {}  Alt+W {} Whole word  Alt+R {} Wrap  Alt+N {} Any form  {:>12}",
+        search.options.case.describe(), check(search.options.whole_word), check(search.options.wrap), check(search.options.normalize), counter,
+    );
+    let label = "Find: ";
+    let pattern = crate::util::sanitize(&search.pattern);
+    queue!(s, style::SetForegroundColor(Color::Black), style::SetBackgroundColor(Color::Grey));
+    if right.chars().count() + label.len() + 2 < width {
+        queue!(s, cursor::MoveTo(origin.0 + (width - right.chars().count() - 1) as u16, origin.1), style::Print(right));
+    }
+    queue!(s,
+        cursor::MoveTo(origin.0 + 1, origin.1), style::Print(label),
+        style::SetForegroundColor(Color::Blue), style::Print(&pattern),
+        cursor::Show,
+    );
+}
+
+/// Toggle the option an Alt+letter key stands for. Returns false if the key is not one of them.
+pub fn toggle_option(options: &mut SearchOptions, key: char) -> bool {
+    match key {
+        'c' => options.case = options.case.next(),
+        'w' => options.whole_word = !options.whole_word,
+        'r' => options.wrap = !options.wrap,
+        'n' => options.normalize = !options.normalize,
+        _ => return false,
+    }
+    true
+}
+
+/// Recently used strings, like search patterns, kept in a file in the data directory so they
+/// are there in the next session too. The most recent comes first.
+pub struct RecentList {
+    file_name: &'static str,
+    pub entries: Vec<String>,
+}
+
+/// No more than this many entries are remembered.
+static MAX_RECENT: usize = 100;
+
+impl RecentList {
+    /// Read the list kept in the data directory under the given file name. A missing or unreadable
+    /// file makes an empty list.
+    pub fn load(file_name: &'static str) -> RecentList {
+        let entries = crate::config::data_dir()
+            .and_then(|dir| std::fs::read_to_string(dir.join(file_name)).ok())
+            .map(|text| text.lines().filter(|l| !l.is_empty()).map(str::to_owned).take(MAX_RECENT).collect())
+            .unwrap_or_default();
+        RecentList { file_name, entries }
+    }
+
+    /// Put an entry at the top of the list, and save the list.
+    pub fn add(&mut self, entry: &str) {
+        if entry.is_empty() || entry.contains('\n') {
+            return;
+        }
+        self.entries.retain(|e| e != entry);
+        self.entries.insert(0, entry.to_owned());
+        self.entries.truncate(MAX_RECENT);
+        if let Some(dir) = crate::config::data_dir() {
+            let result = std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(dir.join(self.file_name), self.entries.join("\n") + "\n"));
+            if let Err(e) = result {
+                log::warn!("Could not save {}: {}", self.file_name, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! The workspaces open when the editor quit, and the files open in each, kept in the data
+//! directory so that starting the editor without a file opens them again. Turned on with
+//! `restore_session = true`.
+//!
+//! Each workspace is a line with its name after `workspace` and a tab, or `workspace*` for the
+//! one that was active, followed by a line for each of its files: `file`, a tab and the file's
+//! full path, or `file-compact` for one shown in compact mode.
+
+use crate::config;
+
+use std::fs;
+use std::path::PathBuf;
+
+/// A workspace by name, and the files open in it, each with whether it was shown in compact mode.
+pub type Workspace = (String, Vec<(PathBuf, bool)>);
+
+/// Write down the workspaces, and which of them is active.
+pub fn save(workspaces: &[Workspace], active: usize) {
+    let Some(file) = session_file() else { return };
+    if !config::get().restore_session {
+        return;
+    }
+    let mut text = String::new();
+    for (i, (name, files)) in workspaces.iter().enumerate() {
+        text.push_str(&format!("workspace{}\t{}\n", if i == active { "*" } else { "" }, name));
+        for (path, compact) in files {
+            text.push_str(&format!("file{}\t{}\n", if *compact { "-compact" } else { "" }, path.display()));
+        }
+    }
+    let result = fs::create_dir_all(file.parent().unwrap()).and_then(|()| fs::write(&file, text));
+    if let Err(e) = result {
+        log::warn!("Could not write the session to {}: {}", file.display(), e);
+    }
+}
+
+/// The workspaces of the last session and which of them was active, if there was one to
+/// restore. Files that no longer exist are left out, and lines that cannot be read are skipped.
+pub fn load() -> Option<(Vec<Workspace>, usize)> {
+    if !config::get().restore_session {
+        return None;
+    }
+    let text = fs::read_to_string(session_file()?).ok()?;
+    let mut workspaces: Vec<Workspace> = Vec::new();
+    let mut active = 0;
+    for line in text.lines() {
+        match line.split_once('\t') {
+            Some(("workspace", name)) => workspaces.push((name.to_owned(), Vec::new())),
+            Some(("workspace*", name)) => {
+                active = workspaces.len();
+                workspaces.push((name.to_owned(), Vec::new()));
+            }
+            Some((kind @ ("file" | "file-compact"), path)) => if let Some((_, files)) = workspaces.last_mut() {
+                let path = PathBuf::from(path);
+                if path.is_file() {
+                    files.push((path, kind == "file-compact"));
+                }
+            },
+            _ => {}
+        }
+    }
+    match workspaces.iter().any(|(_, files)| !files.is_empty()) {
+        true => Some((workspaces, active)),
+        false => None,
+    }
+}
+
+fn session_file() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join("session"))
+}
@@ -0,0 +1,53 @@
+/// Line, word, character and byte counts of a piece of text.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Counts {
+    pub lines: usize,
+    pub words: usize,
+    pub chars: usize,
+    pub bytes: usize,
+}
+
+impl Counts {
+    pub fn of(text: &str) -> Counts {
+        Counts {
+            lines: text.matches('\n').count() + 1,
+            words: text.split_whitespace().count(),
+            chars: text.chars().count(),
+            bytes: text.len(),
+        }
+    }
+}
+
+/// File extensions of buffers we consider prose, where a word count is more useful than not.
+static PROSE_EXTENSIONS: &[&str] = &["txt", "md", "markdown", "rst", "adoc", "org", "tex"];
+
+pub fn is_prose(path: &std::path::Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| PROSE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// A table of the document's counts, and the selection's if there is one, suitable for an alert.
+pub fn summary(document: &str, selection: Option<&str>) -> String {
+    let document = Counts::of(document);
+    let selection = selection.map(Counts::of);
+
+    let rows = [
+        ("Lines", document.lines, selection.map(|c| c.lines)),
+        ("Words", document.words, selection.map(|c| c.words)),
+        ("Characters", document.chars, selection.map(|c| c.chars)),
+        ("Bytes", document.bytes, selection.map(|c| c.bytes)),
+    ];
+
+    // Every row has the same width, so they line up when the alert centers them.
+    let mut out = String::new();
+    if selection.is_some() {
+        out.push_str(&format!("{:<10} {:>9} {:>10}\n", "", "Document", "Selection"));
+    }
+    for (name, in_document, in_selection) in rows.iter() {
+        match in_selection {
+            Some(n) => out.push_str(&format!("{:<10} {:>9} {:>10}\n", name, in_document, n)),
+            None => out.push_str(&format!("{:<10} {:>9}\n", name, in_document)),
+        }
+    }
+    out.pop(); // The trailing newline
+    out
+}
@@ -0,0 +1,113 @@
+use crossterm::{*, style::Color};
+
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crate::search::Counter;
+use crate::viewport::{SelectionSize, Viewport, ViewportData};
+
+/// How long a message stays in the status bar.
+static MESSAGE_DURATION: Duration = Duration::from_secs(3);
+
+/// A single row at the bottom of the screen describing the focused viewport, which can also
+/// briefly show a message instead of the viewport's title.
+#[derive(Default)]
+pub struct StatusBar {
+    message: Option<(String, Instant)>,
+    /// Whether paste mode is on, which the bar is a reminder of.
+    pub paste_mode: bool,
+}
+
+impl StatusBar {
+    /// Show a message for a few seconds.
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some((message, Instant::now()));
+    }
+
+    /// Update the status bar as time passes. Returns true if it needs to be drawn again.
+    pub fn tick(&mut self) -> bool {
+        match self.message {
+            Some((_, shown_at)) if shown_at.elapsed() >= MESSAGE_DURATION => {
+                self.message = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Draw the bar, with the active workspace named before the title once there is more than one,
+    /// the matches counted while a search is highlighted, and a reminder while in paste mode.
+    pub fn render<S: Write>(&self, s: &mut S, origin: (u16, u16), width: usize, viewport: Option<&Viewport>, workspace: Option<&str>, counter: Option<Counter>) {
+        crate::util::draw_rectangle(s, &Color::Grey, origin, (width, 1));
+        queue!(s, style::SetForegroundColor(Color::Black), style::SetBackgroundColor(Color::Grey));
+
+        let (left, right) = match viewport {
+            Some(Viewport { title, data: ViewportData::Buffer(document, cursor), indentation, .. }) => {
+                let buffer = &document.borrow().buffer;
+                let mut right = Vec::new();
+                if self.paste_mode {
+                    right.push("PASTE".to_owned());
+                }
+                match counter {
+                    Some(Counter::At(i, n)) => right.push(format!("Match {}/{}", i + 1, n)),
+                    Some(Counter::Found(n)) => right.push(format!("{} match{}", n, if n == 1 { "" } else { "es" })),
+                    Some(Counter::NoMatches) => right.push("No matches".to_owned()),
+                    None => {}
+                }
+                if buffer.path.as_ref().is_some_and(|p| crate::stats::is_prose(p)) {
+                    right.push(format!("{} words", buffer.data().split_whitespace().count()));
+                }
+                if let Some(size) = viewport.unwrap().selection_size() {
+                    right.push(describe_selection(size));
+                }
+                let config = crate::config::get();
+                if let Some(limit) = config.line_length(buffer.path.as_deref()).filter(|_| config.overlong_line_count) {
+                    let overlong = crate::lines::Lines::new(&buffer.data()).iter().filter(|l| crate::util::columns(l) > limit).count();
+                    if overlong > 0 {
+                        right.push(format!("{} line{} over {}", overlong, if overlong == 1 { "" } else { "s" }, limit));
+                    }
+                }
+                right.push(indentation.to_string());
+                match &viewport.unwrap().table {
+                    Some(table) => right.push(format!("Row {}, Col {}", table.row + 1, table.column + 1)),
+                    None => right.push(format!("Ln {}, Col {}", cursor.line + 1, cursor.offset + 1)),
+                }
+                right.push(format!("{:>3.0}%", viewport.unwrap().vertical_scroll_percent() * 100.0));
+                (crate::util::sanitize(title), right.join("  "))
+            }
+            Some(Viewport { title, data: ViewportData::Tail(tail), .. }) => {
+                let following = if tail.follow { "Following" } else { "Scrolled up, End follows" };
+                (crate::util::sanitize(title), format!("{}  {:>3.0}%", following, viewport.unwrap().vertical_scroll_percent() * 100.0))
+            }
+            Some(Viewport { title, .. }) => (crate::util::sanitize(title), String::new()),
+            None => (String::new(), String::new()),
+        };
+        let left = match workspace {
+            Some(name) => format!("[{}] {}", crate::util::sanitize(name), left),
+            None => left,
+        };
+
+        match &self.message {
+            Some((message, _)) if crate::config::get().screen_reader => {
+                // On its own, so that a screen reader reads out just the message
+                queue!(s, cursor::MoveTo(origin.0, origin.1), style::Print(crate::util::sanitize(message)));
+                return;
+            }
+            Some((message, _)) => queue!(s, cursor::MoveTo(origin.0 + 1, origin.1), style::SetForegroundColor(Color::Blue), style::Print(crate::util::sanitize(message)), style::SetForegroundColor(Color::Black)),
+            None => queue!(s, cursor::MoveTo(origin.0 + 1, origin.1), style::Print(left)),
+        };
+        if right.len() + 1 < width {
+            queue!(s, cursor::MoveTo(origin.0 + (width - right.len() - 1) as u16, origin.1), style::Print(right));
+        }
+    }
+}
+
+/// Like `3 lines, 42 chars selected`, or `4 lines × 5 columns selected` for a block.
+fn describe_selection(size: SelectionSize) -> String {
+    let plural = |n: usize, what: &str| format!("{} {}{}", n, what, if n == 1 { "" } else { "s" });
+    match size.block_columns {
+        Some(columns) => format!("{} × {} selected", plural(size.lines, "line"), plural(columns, "column")),
+        None if size.selections > 1 => format!("{}: {}, {}", plural(size.selections, "selection"), plural(size.lines, "line"), plural(size.chars, "char")),
+        None => format!("{}, {} selected", plural(size.lines, "line"), plural(size.chars, "char")),
+    }
+}
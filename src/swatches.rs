@@ -0,0 +1,60 @@
+//! Color literals like `#ff8800` and `rgb(255, 136, 0)` are drawn on a background of the color
+//! they describe, so editing a theme or a stylesheet shows the colors straight away.
+
+use crossterm::style::Color;
+
+/// The color literals in a line, as the character offset and length of each one, and the
+/// background and foreground to draw it with. Terminals without 24-bit color get the nearest
+/// color they have, see `colors::Degrade`.
+pub fn in_line(line: &str) -> Vec<(usize, usize, (Color, Color))> {
+    let mut found = Vec::new();
+    let mut i = 0;
+    while i < line.len() {
+        let rest = &line[i..];
+        let literal = if rest.starts_with('#') {
+            hex(rest)
+        } else if rest.starts_with("rgb") {
+            rgb(rest)
+        } else {
+            None
+        };
+        match literal {
+            // Not a swatch when it runs into more of a word, like `#fffabc_id`
+            Some((len, r, g, b)) if !rest[len..].starts_with(|c: char| c.is_alphanumeric() || c == '_') => {
+                found.push((crate::util::columns(&line[..i]), crate::util::columns(&rest[..len]), (Color::Rgb { r, g, b }, contrasting(r, g, b))));
+                i += len;
+            }
+            _ => i += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    found
+}
+
+/// A `#rgb` or `#rrggbb` literal at the start of some text, and its length.
+fn hex(text: &str) -> Option<(usize, u8, u8, u8)> {
+    let digits = text[1..].bytes().take_while(u8::is_ascii_hexdigit).count();
+    let digit = |i: usize, n: usize| u8::from_str_radix(&text[1 + i * n..1 + (i + 1) * n], 16).ok();
+    match digits {
+        3 => Some((4, digit(0, 1)? * 17, digit(1, 1)? * 17, digit(2, 1)? * 17)),
+        6 => Some((7, digit(0, 2)?, digit(1, 2)?, digit(2, 2)?)),
+        _ => None,
+    }
+}
+
+/// An `rgb(r, g, b)` or `rgba(r, g, b, a)` literal at the start of some text, and its length.
+/// The alpha is ignored.
+fn rgb(text: &str) -> Option<(usize, u8, u8, u8)> {
+    let open = if text.starts_with("rgba(") { 5 } else if text.starts_with("rgb(") { 4 } else { return None };
+    let close = open + text[open..].find(')')?;
+    let parts: Vec<&str> = text[open..close].split(',').map(str::trim).collect();
+    if parts.len() != 3 && parts.len() != 4 {
+        return None;
+    }
+    Some((close + 1, parts[0].parse().ok()?, parts[1].parse().ok()?, parts[2].parse().ok()?))
+}
+
+
+/// Black or white, whichever is easier to read on the given color.
+fn contrasting(r: u8, g: u8, b: u8) -> Color {
+    if 299 * r as u32 + 587 * g as u32 + 114 * b as u32 > 128_000 { Color::Black } else { Color::White }
+}
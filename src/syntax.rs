@@ -0,0 +1,855 @@
+//! The structure of a buffer's text, as far as its language's comments, strings and brackets
+//! tell it. Languages are told from the file's extension, or for Git's messages from the file's
+//! name. This is what draws comments and strings in colors of their own, grows the selection one
+//! node at a time, and finds the ranges of lines that can be folded away.
+//!
+//! The text is read a line at a time, and what each line starts in (code, a comment or a string)
+//! is remembered with the spans found in it, so that after an edit only the edited lines are read
+//! again, and those after them as far as what they start in changed. The lines in view are read
+//! as they are drawn; the rest are read on a worker thread while the editor is idle.
+//!
+//! Languages with a tree-sitter grammar are parsed instead, into a tree that is told of each edit
+//! and parsed again from it, reusing what the edit left be. That tree also colors keywords,
+//! functions, types and constants, and gives the nodes selections grow by and folds go by.
+
+use crossterm::style::Color;
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{InputEdit, Node, Parser, Point, Query, QueryCursor, Tree};
+
+use crate::history::Edit;
+use crate::lines::Lines;
+
+use std::path::Path;
+
+/// What the parser needs to know about a language.
+pub struct Language {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// The quotes whose strings go on past the end of a line.
+    multiline_quotes: &'static [char],
+    /// Whether a single quote only starts a string as a character literal, like `'a'`, which
+    /// leaves Rust's lifetimes be.
+    char_literals: bool,
+    /// Whether line comments only start at the start of a line, as in Git's messages, where a
+    /// `#` further on is part of the text.
+    whole_line_comments: bool,
+    /// The lines after which the next is indented a level further, and those indented a level
+    /// less than the lines before them, as patterns (see `indent::Rules`).
+    pub indent_increase: Option<&'static str>,
+    pub indent_decrease: Option<&'static str>,
+    /// The grammar the language is parsed with, if it has one, in place of reading it a line at
+    /// a time.
+    grammar: Option<Grammar>,
+}
+
+/// A tree-sitter grammar, with the query telling which of the nodes it parses are highlighted.
+pub struct Grammar {
+    language: fn() -> tree_sitter::Language,
+    highlights: &'static str,
+}
+
+/// Lines opening a bracket they do not close, and lines starting with a closing bracket.
+static BRACKETS_OPEN: &str = r"[{(\[]\s*$";
+static BRACKETS_CLOSE: &str = r"^\s*[})\]]";
+
+static LANGUAGES: &[Language] = &[
+    Language {
+        name: "Rust", extensions: &["rs"], line_comments: &["//"], block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''], multiline_quotes: &['"'], char_literals: true, whole_line_comments: false,
+        indent_increase: Some(BRACKETS_OPEN), indent_decrease: Some(BRACKETS_CLOSE),
+        grammar: Some(Grammar { language: || tree_sitter_rust::LANGUAGE.into(), highlights: tree_sitter_rust::HIGHLIGHTS_QUERY }),
+    },
+    Language {
+        name: "C", extensions: &["c", "h", "cc", "cpp", "cxx", "hpp", "hh", "m"], line_comments: &["//"], block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''], multiline_quotes: &[], char_literals: false, whole_line_comments: false,
+        indent_increase: Some(BRACKETS_OPEN), indent_decrease: Some(BRACKETS_CLOSE),
+        grammar: None,
+    },
+    Language {
+        name: "JavaScript", extensions: &["js", "jsx", "mjs", "ts", "tsx", "java", "go", "cs", "kt", "swift", "scala", "dart"], line_comments: &["//"], block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'], multiline_quotes: &['`'], char_literals: false, whole_line_comments: false,
+        indent_increase: Some(BRACKETS_OPEN), indent_decrease: Some(BRACKETS_CLOSE),
+        grammar: None,
+    },
+    Language {
+        name: "CSS", extensions: &["css", "scss", "less"], line_comments: &[], block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''], multiline_quotes: &[], char_literals: false, whole_line_comments: false,
+        indent_increase: Some(BRACKETS_OPEN), indent_decrease: Some(BRACKETS_CLOSE),
+        grammar: None,
+    },
+    Language {
+        name: "Python", extensions: &["py", "pyw"], line_comments: &["#"], block_comment: None,
+        quotes: &['"', '\''], multiline_quotes: &[], char_literals: false, whole_line_comments: false,
+        indent_increase: Some(r":\s*(#.*)?$"), indent_decrease: Some(r"^\s*(else|elif\W.*|except|except\W.*|finally)\s*:\s*(#.*)?$"),
+        grammar: Some(Grammar { language: || tree_sitter_python::LANGUAGE.into(), highlights: tree_sitter_python::HIGHLIGHTS_QUERY }),
+    },
+    Language {
+        name: "Shell", extensions: &["sh", "bash", "zsh", "fish", "rb", "pl", "r"], line_comments: &["#"], block_comment: None,
+        quotes: &['"', '\''], multiline_quotes: &['"', '\''], char_literals: false, whole_line_comments: false,
+        indent_increase: Some(r"(^|[\s;])(then|do|else)\s*$|[{(]\s*$"), indent_decrease: Some(r"^\s*(fi|done|esac|else|\}|\))\s*(;.*)?$|^\s*elif\W"),
+        grammar: None,
+    },
+    Language {
+        name: "Config", extensions: &["toml", "yaml", "yml", "conf", "properties"], line_comments: &["#"], block_comment: None,
+        quotes: &['"', '\''], multiline_quotes: &[], char_literals: false, whole_line_comments: false,
+        indent_increase: Some(r":\s*(#.*)?$|^\s*-\s*$|[{\[]\s*$"), indent_decrease: Some(r"^\s*[}\]]"),
+        grammar: None,
+    },
+    Language {
+        name: "INI", extensions: &["ini", "cfg"], line_comments: &["#", ";"], block_comment: None,
+        quotes: &['"'], multiline_quotes: &[], char_literals: false, whole_line_comments: false,
+        indent_increase: None, indent_decrease: None,
+        grammar: None,
+    },
+    Language {
+        name: "JSON", extensions: &["json"], line_comments: &[], block_comment: None,
+        quotes: &['"'], multiline_quotes: &[], char_literals: false, whole_line_comments: false,
+        indent_increase: Some(BRACKETS_OPEN), indent_decrease: Some(BRACKETS_CLOSE),
+        grammar: None,
+    },
+    Language {
+        name: "Lua", extensions: &["lua"], line_comments: &["--"], block_comment: Some(("--[[", "]]")),
+        quotes: &['"', '\''], multiline_quotes: &[], char_literals: false, whole_line_comments: false,
+        indent_increase: Some(r"(^|\W)(then|do|else|repeat)\s*$|(^|\W)function\W.*\)\s*$|[{(]\s*$"), indent_decrease: Some(r"^\s*(end|else|\}|\))\W*$|^\s*(elseif|until)\W"),
+        grammar: None,
+    },
+    Language {
+        name: "SQL", extensions: &["sql"], line_comments: &["--"], block_comment: Some(("/*", "*/")),
+        quotes: &['\''], multiline_quotes: &['\''], char_literals: false, whole_line_comments: false,
+        indent_increase: Some(BRACKETS_OPEN), indent_decrease: Some(BRACKETS_CLOSE),
+        grammar: None,
+    },
+    Language {
+        name: "Haskell", extensions: &["hs", "elm"], line_comments: &["--"], block_comment: Some(("{-", "-}")),
+        quotes: &['"'], multiline_quotes: &[], char_literals: false, whole_line_comments: false,
+        indent_increase: Some(r"(^|\W)(where|do|of|let)\s*$|=\s*$"), indent_decrease: None,
+        grammar: None,
+    },
+    Language {
+        name: "HTML", extensions: &["html", "htm", "xml", "svg", "xhtml"], line_comments: &[], block_comment: Some(("<!--", "-->")),
+        quotes: &[], multiline_quotes: &[], char_literals: false, whole_line_comments: false,
+        indent_increase: Some(r"<[a-zA-Z][^/>]*>\s*$"), indent_decrease: Some(r"^\s*</"),
+        grammar: None,
+    },
+];
+
+/// The messages Git has written in an editor, like that of a commit, with their instructions in
+/// comment lines. Git leaves those lines out of the message.
+static GIT_MESSAGE: Language = Language {
+    name: "Git message", extensions: &[], line_comments: &["#"], block_comment: None,
+    quotes: &[], multiline_quotes: &[], char_literals: false, whole_line_comments: true,
+    indent_increase: None, indent_decrease: None, grammar: None,
+};
+
+/// The names of the files Git has those messages written in.
+static GIT_MESSAGES: &[&str] = &["COMMIT_EDITMSG", "MERGE_MSG", "SQUASH_MSG", "TAG_EDITMSG", "EDIT_DESCRIPTION", "git-rebase-todo"];
+
+/// Whether a file is one Git has a message written in, which is new each time.
+pub fn is_git_message(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| GIT_MESSAGES.iter().any(|message| name == *message))
+}
+
+/// The language of a file, by its extension, or its name for Git's messages.
+pub fn language_for(path: Option<&Path>) -> Option<&'static Language> {
+    if path.is_some_and(is_git_message) {
+        return Some(&GIT_MESSAGE);
+    }
+    let extension = path?.extension()?.to_string_lossy().to_lowercase();
+    LANGUAGES.iter().find(|language| language.extensions.contains(&extension.as_str()))
+}
+
+/// What a part of a line is, besides code.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Kind {
+    Comment,
+    String,
+    Keyword,
+    /// The names of functions and macros, where they are defined and where they are called.
+    Function,
+    Type,
+    /// Numbers, and named constants such as `true` or `None`.
+    Constant,
+}
+
+impl Kind {
+    /// The color text of the kind is drawn in.
+    pub fn color(self) -> Color {
+        match self {
+            Kind::Comment => Color::DarkCyan,
+            Kind::String => Color::Green,
+            Kind::Keyword => Color::Magenta,
+            Kind::Function => Color::Blue,
+            Kind::Type => Color::Yellow,
+            Kind::Constant => Color::DarkYellow,
+        }
+    }
+
+    /// The kind a capture of a highlights query stands for, by its name, such as `function.macro`.
+    fn of_capture(name: &str) -> Option<Kind> {
+        match name.split('.').next()? {
+            "comment" => Some(Kind::Comment),
+            "string" | "escape" => Some(Kind::String),
+            "keyword" => Some(Kind::Keyword),
+            "function" => Some(Kind::Function),
+            "type" | "constructor" => Some(Kind::Type),
+            "constant" | "number" => Some(Kind::Constant),
+            _ => None,
+        }
+    }
+}
+
+/// What a line starts in, carried on from the line before.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum State {
+    Code,
+    Comment,
+    String(char),
+}
+
+/// A comment or string in a line, as the byte range it takes up, and whether it opens and closes
+/// in the line rather than going on from the line before or onto the next.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Span {
+    start: usize,
+    end: usize,
+    kind: Kind,
+    opens: bool,
+    closes: bool,
+}
+
+/// What reading a line found: its comments and strings, the brackets in its code by byte offset,
+/// and what the next line starts in.
+struct Line {
+    spans: Vec<Span>,
+    brackets: Vec<(usize, char)>,
+    end: State,
+}
+
+fn read_line(language: &Language, line: &str, mut state: State) -> Line {
+    let mut spans = Vec::new();
+    let mut brackets = Vec::new();
+    let mut i = 0;
+    // Where the comment or string being read started, and whether it started in this line
+    let mut start = (0, false);
+    while i < line.len() {
+        let rest = &line[i..];
+        let c = rest.chars().next().unwrap();
+        match state {
+            State::Code => {
+                // Block comments first, as Lua's start like its line comments do
+                if let Some((open, _)) = language.block_comment.filter(|(open, _)| rest.starts_with(open)) {
+                    state = State::Comment;
+                    start = (i, true);
+                    i += open.len();
+                    continue;
+                }
+                if (i == 0 || !language.whole_line_comments) && language.line_comments.iter().any(|start| rest.starts_with(start)) {
+                    spans.push(Span { start: i, end: line.len(), kind: Kind::Comment, opens: true, closes: true });
+                    return Line { spans, brackets, end: State::Code };
+                }
+                if language.quotes.contains(&c) && !(c == '\'' && language.char_literals && !is_char_literal(rest)) {
+                    state = State::String(c);
+                    start = (i, true);
+                } else if crate::brackets::step(1, c) != 1 {
+                    brackets.push((i, c));
+                }
+                i += c.len_utf8();
+            }
+            State::Comment => {
+                let close = language.block_comment.map_or("", |(_, close)| close);
+                match rest.find(close) {
+                    Some(at) => {
+                        i += at + close.len();
+                        spans.push(Span { start: start.0, end: i, kind: Kind::Comment, opens: start.1, closes: true });
+                        state = State::Code;
+                    }
+                    None => i = line.len(),
+                }
+            }
+            State::String(quote) => {
+                i += c.len_utf8();
+                if c == '\\' {
+                    i += rest[1..].chars().next().map_or(0, char::len_utf8);
+                } else if c == quote {
+                    spans.push(Span { start: start.0, end: i, kind: Kind::String, opens: start.1, closes: true });
+                    state = State::Code;
+                }
+            }
+        }
+    }
+    // A comment or string still open goes on to the next line, or ends with this one
+    let end = match state {
+        State::Code => State::Code,
+        State::Comment => State::Comment,
+        State::String(quote) if language.multiline_quotes.contains(&quote) => State::String(quote),
+        State::String(_) => State::Code,
+    };
+    if state != State::Code {
+        spans.push(Span { start: start.0, end: line.len(), kind: if state == State::Comment { Kind::Comment } else { Kind::String }, opens: start.1, closes: end == State::Code });
+    }
+    Line { spans, brackets, end }
+}
+
+/// Whether text starting with a single quote is a character literal, like `'a'` or `'\n'`, rather
+/// than a lifetime.
+fn is_char_literal(text: &str) -> bool {
+    let mut chars = text.chars().skip(1);
+    match chars.next() {
+        Some('\\') => true,
+        Some(_) => chars.next() == Some('\''),
+        None => false,
+    }
+}
+
+/// The comments and strings of a line, as ranges of character offsets.
+pub type Spans = Vec<(usize, usize, Kind)>;
+
+/// The structure of a buffer, kept with it and read again from where it is edited. The lines in
+/// view are read as they are drawn, and the rest in the background while the editor is idle.
+#[derive(Default)]
+pub struct Syntax {
+    language: Option<&'static Language>,
+    // What each line starts in, for the lines read so far.
+    line_states: Vec<State>,
+    // The comments and strings of the lines read so far, unless they were edited since.
+    spans: Vec<Option<Spans>>,
+    // Lines whose states may be wrong since an edit: from the first of them, and the line, never
+    // before it, from which on a state found to be as it was means the rest are as they were too.
+    unsettled: Option<(usize, usize)>,
+    // Whether every line of the buffer has been read.
+    read_all: bool,
+    // Counts the edits, to tell whether what was read in the background is still of the text.
+    generation: usize,
+    // The generation of the text being read in the background, if any is.
+    reading: Option<usize>,
+    // The tree a language with a grammar was parsed into, moved along with the edits since, the
+    // length of the text it is of by now, and whether it was parsed again since the last edit.
+    tree: Option<Tree>,
+    tree_len: usize,
+    parsed: bool,
+    // The grammar's highlights query, once it is needed.
+    highlights: Option<Query>,
+}
+
+impl Syntax {
+    /// The structure of a file, in the language its extension tells.
+    pub fn new(path: Option<&Path>) -> Syntax {
+        Syntax { language: language_for(path), ..Default::default() }
+    }
+
+    pub fn language(&self) -> Option<&'static Language> {
+        self.language
+    }
+
+    /// Move what was read along with an edit starting on a line, given the text from before or
+    /// after it, as only what comes before the edit is looked at. The lines it replaced are read
+    /// again, and those after it only as far as what they start in has changed.
+    pub fn edit(&mut self, text: &str, line: usize, edit: &Edit) {
+        let fits = edit.start + edit.removed.len() <= self.tree_len;
+        if let Some(tree) = &mut self.tree {
+            match text.get(..edit.start).filter(|_| fits) {
+                Some(before) => {
+                    let start = Point { row: line, column: edit.start - before.rfind('\n').map_or(0, |i| i + 1) };
+                    tree.edit(&InputEdit {
+                        start_byte: edit.start,
+                        old_end_byte: edit.start + edit.removed.len(),
+                        new_end_byte: edit.start + edit.inserted.len(),
+                        start_position: start,
+                        old_end_position: point_after(start, &edit.removed),
+                        new_end_position: point_after(start, &edit.inserted),
+                    });
+                    self.tree_len = self.tree_len + edit.inserted.len() - edit.removed.len();
+                    self.parsed = false;
+                }
+                // An edit that does not fit the tree means one was missed, so the text is parsed anew
+                None => self.tree = None,
+            }
+        }
+        self.generation += 1;
+        self.read_all = false;
+        let (removed, inserted) = (edit.removed.matches('\n').count(), edit.inserted.matches('\n').count());
+        if self.line_states.len() > line + 1 {
+            let end = (line + 1 + removed).min(self.line_states.len());
+            self.line_states.splice(line + 1..end, std::iter::repeat_n(State::Code, inserted));
+        }
+        if self.spans.len() > line {
+            let end = (line + 1 + removed).min(self.spans.len());
+            self.spans.splice(line..end, std::iter::repeat_n(None, inserted + 1));
+        }
+        let until = match self.unsettled {
+            Some((_, until)) if until > line + 1 + removed => until + inserted - removed,
+            _ => 0,
+        };
+        self.unsettled = Some((self.unsettled.map_or(line + 1, |(from, _)| from.min(line + 1)), until.max(line + 1 + inserted)));
+    }
+
+    /// The comments and strings of a line, as ranges of character offsets, given all the lines of
+    /// the buffer.
+    pub fn line_spans(&mut self, lines: &Lines, line: usize) -> Spans {
+        let Some(language) = self.language else { return Vec::new() };
+        let tree = if language.grammar.is_some() {
+            let Some(tree) = self.parse(lines.between(0, lines.len() - 1)) else { return Vec::new() };
+            Some(tree)
+        } else {
+            if self.line_states.is_empty() {
+                self.line_states.push(State::Code);
+            }
+            self.settle(language, lines, line);
+            while self.line_states.len() <= line {
+                let previous = self.line_states.len() - 1;
+                let state = lines.get(previous).map_or(State::Code, |l| read_line(language, l, self.line_states[previous]).end);
+                self.line_states.push(state);
+            }
+            None
+        };
+        let Some(text) = lines.get(line) else { return Vec::new() };
+        if let Some(Some(spans)) = self.spans.get(line) {
+            return spans.clone();
+        }
+        let spans = match tree {
+            Some(tree) => columns_of(text, self.highlight(&tree, lines, line)),
+            None => columns_of(text, read_line(language, text, self.line_states[line]).spans),
+        };
+        if self.spans.len() <= line {
+            self.spans.resize(line + 1, None);
+        }
+        self.spans[line] = Some(spans.clone());
+        spans
+    }
+
+    /// The tree of the text, if its language has a grammar, parsed again from the edits made
+    /// since it last was. The lines whose nodes that changed are highlighted again.
+    fn parse(&mut self, text: &str) -> Option<Tree> {
+        let grammar = self.language?.grammar.as_ref()?;
+        if self.tree.is_some() && self.tree_len != text.len() {
+            // The text changed without the tree being told, so it is parsed anew
+            self.tree = None;
+        }
+        if self.tree.is_none() {
+            self.spans.clear();
+        } else if self.parsed {
+            return self.tree.clone();
+        }
+        let mut parser = Parser::new();
+        parser.set_language(&(grammar.language)()).ok()?;
+        let tree = parser.parse(text, self.tree.as_ref())?;
+        if let Some(old) = &self.tree {
+            for range in old.changed_ranges(&tree) {
+                let (first, last) = (range.start_point.row, range.end_point.row);
+                self.spans.iter_mut().take(last + 1).skip(first).for_each(|spans| *spans = None);
+            }
+        }
+        self.tree = Some(tree);
+        self.tree_len = text.len();
+        self.parsed = true;
+        self.tree.clone()
+    }
+
+    /// The spans of a line as the grammar's highlights query captures them. Where captures
+    /// overlap the innermost node wins, and of those on the same node the first pattern does.
+    fn highlight(&mut self, tree: &Tree, lines: &Lines, line: usize) -> Vec<Span> {
+        let Some(grammar) = self.language.and_then(|language| language.grammar.as_ref()) else { return Vec::new() };
+        let query = self.highlights.get_or_insert_with(|| {
+            Query::new(&(grammar.language)(), grammar.highlights).expect("A grammar's highlights query is valid")
+        });
+        let (start, text) = (lines.start(line), lines.line(line));
+        let mut found = Vec::new();
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(start..start + text.len());
+        let mut captures = cursor.captures(query, tree.root_node(), lines.between(0, lines.len() - 1).as_bytes());
+        while let Some((found_match, index)) = captures.next() {
+            let capture = found_match.captures[*index];
+            if let Some(kind) = Kind::of_capture(query.capture_names()[capture.index as usize]) {
+                found.push((capture.node.start_byte(), capture.node.end_byte(), found_match.pattern_index, kind));
+            }
+        }
+        found.sort_by_key(|&(start, end, pattern, _)| (start, std::cmp::Reverse(end), pattern));
+        found.dedup_by_key(|&mut (start, end, _, _)| (start, end));
+        let mut kinds = vec![None; text.len()];
+        for (node_start, node_end, _, kind) in found {
+            let (from, to) = (node_start.saturating_sub(start), node_end.saturating_sub(start).min(text.len()));
+            kinds.iter_mut().take(to).skip(from).for_each(|k| *k = Some(kind));
+        }
+        let mut spans = Vec::new();
+        let mut offset = 0;
+        for run in kinds.chunk_by(|a, b| a == b) {
+            if let Some(kind) = run[0] {
+                spans.push(Span { start: offset, end: offset + run.len(), kind, opens: true, closes: true });
+            }
+            offset += run.len();
+        }
+        spans
+    }
+
+    /// Read the lines made unsettled by edits again, up to a line or until what one starts in
+    /// comes out as it was before.
+    fn settle(&mut self, language: &Language, lines: &Lines, line: usize) {
+        while let Some((from, until)) = self.unsettled {
+            if from > line {
+                return;
+            }
+            if from >= self.line_states.len() {
+                self.unsettled = None;
+                return;
+            }
+            let state = lines.get(from - 1).map_or(State::Code, |l| read_line(language, l, self.line_states[from - 1]).end);
+            if state == self.line_states[from] && from >= until {
+                self.unsettled = None;
+                return;
+            }
+            if state != self.line_states[from] {
+                self.line_states[from] = state;
+                if let Some(spans) = self.spans.get_mut(from) {
+                    *spans = None;
+                }
+            }
+            self.unsettled = Some((from + 1, until.max(from + 1)));
+        }
+    }
+
+    /// Whether there are lines still to read, and they are not being read.
+    pub fn has_unread(&self) -> bool {
+        self.language.is_some_and(|language| language.grammar.is_none()) && !(self.read_all && self.unsettled.is_none()) && self.reading != Some(self.generation)
+    }
+
+    /// The lines still to read, to read in the background, unless they are being read already.
+    /// They go from the first line whose state is not known for sure to the end.
+    pub fn unread(&mut self, lines: &Lines) -> Option<Unread> {
+        let language = self.language.filter(|_| self.has_unread())?;
+        if self.line_states.is_empty() {
+            self.line_states.push(State::Code);
+        }
+        let first = self.unsettled.map_or(self.line_states.len(), |(from, _)| from).min(self.line_states.len()) - 1;
+        if first >= lines.len() {
+            self.read_all = self.unsettled.is_none();
+            return None;
+        }
+        self.reading = Some(self.generation);
+        Some(Unread {
+            language,
+            text: lines.between(first, lines.len() - 1).to_owned(),
+            first,
+            state: self.line_states[first],
+            generation: self.generation,
+        })
+    }
+
+    /// Take in what was read in the background, if the text has not been edited since.
+    pub fn take_in(&mut self, highlights: Highlights) {
+        if self.reading == Some(highlights.generation) {
+            self.reading = None;
+        }
+        if highlights.generation != self.generation {
+            return;
+        }
+        self.line_states.truncate(highlights.first);
+        self.line_states.extend(highlights.states);
+        self.spans.resize(highlights.first, None);
+        self.spans.extend(highlights.spans.into_iter().map(Some));
+        self.unsettled = None;
+        self.read_all = true;
+    }
+
+    /// The smallest node of a text that takes in more than a byte range does: the word the range
+    /// is in, then with a grammar the nodes it parses into and what is inside their brackets and
+    /// quotes, or without one a comment or string, the inside of a pair of brackets or the pair
+    /// with its brackets.
+    pub fn enclosing_node(&mut self, text: &str, start: usize, end: usize) -> Option<(usize, usize)> {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let word_start = start - text[..start].chars().rev().take_while(|&c| is_word(c)).map(char::len_utf8).sum::<usize>();
+        let word_end = end + text[end..].chars().take_while(|&c| is_word(c)).map(char::len_utf8).sum::<usize>();
+        let word = Some((word_start, word_end)).filter(|&(s, e)| text[start..end].chars().all(is_word) && e - s > end - start);
+        let nodes = match self.parse(text) {
+            Some(tree) => nodes_around(&tree, start, end),
+            None => nodes(text, self.language).into_iter().map(|(s, e, _)| (s, e)).collect(),
+        };
+        word.into_iter().chain(nodes)
+            .filter(|&(s, e)| s <= start && e >= end && e - s > end - start)
+            .min_by_key(|&(s, e)| e - s)
+    }
+
+    /// The ranges of lines that can be folded away under their first line: with a grammar those
+    /// of the nodes it parses into, without one those of comments and the insides of brackets,
+    /// and where there are none those of the scopes told by indentation. The largest range
+    /// starting on a line is kept, and they come sorted.
+    pub fn fold_ranges(&mut self, text: &str) -> Vec<(usize, usize)> {
+        let mut ranges = match self.parse(text) {
+            Some(tree) => node_fold_ranges(text, &tree),
+            None => bracket_fold_ranges(text, self.language),
+        };
+        let lines = Lines::new(text);
+        for line in 0..lines.len() {
+            if ranges.iter().any(|&(first, _)| first == line) {
+                continue;
+            }
+            if let Some(last) = crate::scopes::last_line(&lines, line) {
+                ranges.push((line, last));
+            }
+        }
+        ranges.sort_by_key(|&(first, last)| (first, std::cmp::Reverse(last)));
+        ranges.dedup_by_key(|range| range.0);
+        ranges
+    }
+}
+
+/// Where the end of some text is, put at a point.
+fn point_after(start: Point, text: &str) -> Point {
+    match text.rfind('\n') {
+        Some(last) => Point { row: start.row + text.matches('\n').count(), column: text.len() - last - 1 },
+        None => Point { row: start.row, column: start.column + text.len() },
+    }
+}
+
+/// The spans read from a line, with their byte ranges turned into columns.
+fn columns_of(text: &str, spans: Vec<Span>) -> Spans {
+    let columns = crate::util::columns_at(text, spans.iter().flat_map(|span| [span.start, span.end]));
+    spans.iter().zip(columns.chunks(2)).map(|(span, columns)| (columns[0], columns[1], span.kind)).collect()
+}
+
+/// The lines of a buffer left to read, from one whose state is known, to be read off the main
+/// thread.
+pub struct Unread {
+    language: &'static Language,
+    text: String,
+    first: usize,
+    state: State,
+    generation: usize,
+}
+
+impl Unread {
+    /// Read the lines, as a worker thread does.
+    pub fn read(self) -> Highlights {
+        let mut state = self.state;
+        let (mut states, mut spans) = (Vec::new(), Vec::new());
+        for line in self.text.split('\n') {
+            states.push(state);
+            let read = read_line(self.language, line, state);
+            spans.push(columns_of(line, read.spans));
+            state = read.end;
+        }
+        Highlights { first: self.first, states, spans, generation: self.generation }
+    }
+}
+
+/// What each line from the first unread one starts in, and their spans.
+pub struct Highlights {
+    first: usize,
+    states: Vec<State>,
+    spans: Vec<Spans>,
+    generation: usize,
+}
+
+impl Highlights {
+    /// How many lines were read.
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+}
+
+/// What a node of a text is, as far as folding goes.
+#[derive(Copy, Clone, PartialEq)]
+enum Part {
+    Comment,
+    /// The inside of a pair of brackets.
+    Inside,
+    Other,
+}
+
+/// Read the whole of a text, giving the byte ranges of its comments and strings, with the
+/// ranges inside their quotes for strings, and of its pairs of brackets, both with and without
+/// the brackets. A bracket left open pairs with the end of the text.
+fn nodes(text: &str, language: Option<&Language>) -> Vec<(usize, usize, Part)> {
+    let mut nodes = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    let mut state = State::Code;
+    let mut token_start = 0;
+    let mut line_start = 0;
+    for line in text.split('\n') {
+        let read = match language {
+            Some(language) => read_line(language, line, state),
+            None => Line { spans: Vec::new(), brackets: line.char_indices().filter(|&(_, c)| crate::brackets::step(1, c) != 1).collect(), end: State::Code },
+        };
+        for span in &read.spans {
+            if span.opens {
+                token_start = line_start + span.start;
+            }
+            if span.closes {
+                let end = line_start + span.end;
+                match span.kind {
+                    Kind::Comment => nodes.push((token_start, end, Part::Comment)),
+                    Kind::String => {
+                        nodes.push((token_start, end, Part::Other));
+                        if end - token_start >= 2 {
+                            nodes.push((token_start + 1, end - 1, Part::Other));
+                        }
+                    }
+                    _ => {} // Only comments and strings are read a line at a time
+                }
+            }
+        }
+        for &(at, c) in &read.brackets {
+            match crate::brackets::closing(c) {
+                Some(_) => open.push(line_start + at + 1),
+                None => if let Some(inner) = open.pop() {
+                    nodes.push((inner, line_start + at, Part::Inside));
+                    nodes.push((inner - 1, line_start + at + 1, Part::Other));
+                },
+            }
+        }
+        state = read.end;
+        line_start += line.len() + 1;
+    }
+    for inner in open {
+        nodes.push((inner, text.len(), Part::Other));
+    }
+    nodes
+}
+
+/// The pairs of brackets in a text, by the byte offsets of the opening and closing brackets.
+/// Those in comments and strings do not count.
+pub fn bracket_pairs(text: &str, language: Option<&Language>) -> Vec<(usize, usize)> {
+    nodes(text, language).into_iter().filter(|&(_, _, part)| part == Part::Inside).map(|(start, end, _)| (start - 1, end)).collect()
+}
+
+/// The nodes of a tree around a byte range, innermost first, each after what is between its
+/// brackets or quotes if it starts and ends with them.
+fn nodes_around(tree: &Tree, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut nodes = Vec::new();
+    let mut node = tree.root_node().descendant_for_byte_range(start, end);
+    while let Some(around) = node {
+        if let Some(inside) = inside(around) {
+            nodes.push(inside);
+        }
+        nodes.push((around.start_byte(), around.end_byte()));
+        node = around.parent();
+    }
+    nodes
+}
+
+/// The byte range between a node's first and last children, if those are a pair of brackets or
+/// quotes.
+fn inside(node: Node) -> Option<(usize, usize)> {
+    static PAIRS: &[(&str, &str)] = &[("(", ")"), ("[", "]"), ("{", "}"), ("<", ">"), ("\"", "\""), ("'", "'"), ("string_start", "string_end")];
+    let (first, last) = (node.child(0)?, node.child(node.child_count() - 1)?);
+    Some((first.end_byte(), last.start_byte()))
+        .filter(|_| first.id() != last.id() && PAIRS.contains(&(first.kind(), last.kind())))
+}
+
+/// The ranges of lines the nodes of a tree take up that can be folded away under their first
+/// line. Those whose first line opens brackets and whose last line only closes them, like a
+/// function's body, fold up to the line before their last. A node starting with a child over
+/// several lines, like Python's blocks do with their first statement, leaves folding to it.
+fn node_fold_ranges(text: &str, tree: &Tree) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let root = tree.root_node();
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        let (first, mut last) = (node.start_position().row, node.end_position().row);
+        let (start, end) = (node.start_byte(), node.end_byte());
+        let first_line = &text[start..text[start..].find('\n').map_or(text.len(), |i| start + i)];
+        let last_line = &text[text[..end].rfind('\n').map_or(0, |i| i + 1)..end];
+        let opens = first_line.chars().fold(0, crate::brackets::step) > 0;
+        let closes = last_line.chars().all(|c| c.is_whitespace() || matches!(c, ')' | ']' | '}' | ';' | ','));
+        if last_line.is_empty() || opens && closes {
+            last = last.saturating_sub(1);
+        }
+        let headed_by_child = node.child(0).is_some_and(|child| child.start_byte() == start && child.end_position().row > first);
+        if last > first && node.id() != root.id() && !headed_by_child {
+            ranges.push((first, last));
+        }
+        // The nodes in a single line have none over several lines in them
+        if node.end_position().row > first && cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return ranges;
+            }
+        }
+    }
+}
+
+/// The ranges of lines taken up by comments over several lines, and by the insides of pairs of
+/// brackets up to the line the closing bracket is on, as read a line at a time.
+fn bracket_fold_ranges(text: &str, language: Option<&Language>) -> Vec<(usize, usize)> {
+    let line_starts: Vec<usize> = std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect();
+    let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset) - 1;
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (start, end, part) in nodes(text, language) {
+        let (first, last) = match part {
+            Part::Comment => (line_of(start), line_of(end.saturating_sub(1).max(start))),
+            Part::Inside => (line_of(start), line_of(end).saturating_sub(1)),
+            Part::Other => continue,
+        };
+        if last > first {
+            ranges.push((first, last));
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rust_is_highlighted_from_its_tree() {
+        let text = "fn main() {\n    let s = \"hi\"; // done\n}";
+        let mut syntax = Syntax::new(Some(Path::new("main.rs")));
+        let lines = Lines::new(text);
+        assert!(!syntax.has_unread());
+        let first = syntax.line_spans(&lines, 0);
+        assert!(first.contains(&(0, 2, Kind::Keyword)), "{:?}", first);
+        assert!(first.contains(&(3, 7, Kind::Function)), "{:?}", first);
+        let second = syntax.line_spans(&lines, 1);
+        assert!(second.contains(&(4, 7, Kind::Keyword)), "{:?}", second);
+        assert!(second.contains(&(12, 16, Kind::String)), "{:?}", second);
+        assert!(second.contains(&(18, 25, Kind::Comment)), "{:?}", second);
+    }
+
+    #[test]
+    fn edits_are_parsed_again_from_the_edited_tree() {
+        let text = "fn a() {}\nfn b() {}\n";
+        let mut syntax = Syntax::new(Some(Path::new("lib.rs")));
+        assert!(syntax.line_spans(&Lines::new(text), 1).contains(&(0, 2, Kind::Keyword)));
+
+        let edit = Edit { start: 10, removed: String::new(), inserted: "// ".to_owned() };
+        let edited = "fn a() {}\n// fn b() {}\n";
+        syntax.edit(edited, 1, &edit);
+        assert_eq!(syntax.line_spans(&Lines::new(edited), 1), vec![(0, 12, Kind::Comment)]);
+        assert_eq!(syntax.line_spans(&Lines::new(edited), 0)[0], (0, 2, Kind::Keyword));
+
+        // The tree is as that of the whole text parsed anew
+        let mut parser = Parser::new();
+        parser.set_language(&tree_sitter_rust::LANGUAGE.into()).unwrap();
+        let fresh = parser.parse(edited, None).unwrap();
+        assert_eq!(syntax.tree.as_ref().unwrap().root_node().to_sexp(), fresh.root_node().to_sexp());
+    }
+
+    #[test]
+    fn python_folds_and_selections_go_by_nodes() {
+        let text = "def f(x):\n    if x:\n        return [\n            1,\n        ]\n    return print(g(1, 2))\n";
+        let mut syntax = Syntax::new(Some(Path::new("f.py")));
+        assert_eq!(syntax.fold_ranges(text), vec![(0, 5), (1, 4), (2, 3)]);
+
+        let one = text.find("1, 2").unwrap();
+        let mut selected = (one, one);
+        let mut grown = Vec::new();
+        while let Some(node) = syntax.enclosing_node(text, selected.0, selected.1) {
+            grown.push(&text[node.0..node.1]);
+            selected = node;
+        }
+        assert_eq!(&grown[..6], ["1", "1, 2", "(1, 2)", "g(1, 2)", "(g(1, 2))", "print(g(1, 2))"]);
+    }
+
+    #[test]
+    fn rust_blocks_fold_up_to_their_closing_brackets() {
+        let text = "fn f() {\n    g(\n        1,\n    );\n}\n";
+        let mut syntax = Syntax::new(Some(Path::new("f.rs")));
+        assert_eq!(syntax.fold_ranges(text), vec![(0, 3), (1, 2)]);
+    }
+}
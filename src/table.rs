@@ -0,0 +1,319 @@
+//! Showing comma or tab separated values as a table: the fields line up in columns, which the
+//! view scrolls through a whole column at a time, with the first row kept on top as the header.
+//! A cell's new value is written back into the text, quoted as it needs to be. Shown as text,
+//! each column of the values is drawn in a color of its own.
+
+use crossterm::{*, event::KeyCode, event::KeyModifiers, style::Color};
+
+use std::io::Write;
+use std::path::Path;
+
+/// The widest a column is shown, however long its values are.
+static MAX_COLUMN_WIDTH: usize = 40;
+/// The colors the columns of delimited text take in turn.
+static COLUMN_COLORS: &[Color] = &[Color::Yellow, Color::Cyan, Color::Magenta, Color::Green, Color::White, Color::Red];
+/// The delimiters to pick from, with what to call them.
+pub static DELIMITERS: &[(char, &str)] = &[(',', "Comma"), (';', "Semicolon"), ('\t', "Tab"), ('|', "Pipe")];
+
+/// A field of a record, by where it is in the text, quotes and all, and its value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub start: usize,
+    pub end: usize,
+    pub value: String,
+}
+
+/// The records of some delimited text, each a line unless a quoted field goes on over more. A
+/// carriage return before the newline is not part of the last field.
+pub fn parse(text: &str, delimiter: char) -> Vec<Vec<Field>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    loop {
+        let start = chars.peek().map_or(text.len(), |&(i, _)| i);
+        let mut value = String::new();
+        let mut quoted = false;
+        if let Some(&(_, '"')) = chars.peek() {
+            chars.next();
+            quoted = true;
+        }
+        let mut end = text.len();
+        let mut ends_record = true;
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' if quoted => match chars.peek() {
+                    Some(&(_, '"')) => {
+                        chars.next();
+                        value.push('"');
+                    }
+                    _ => quoted = false,
+                },
+                c if quoted => value.push(c),
+                c if c == delimiter => {
+                    end = i;
+                    ends_record = false;
+                    break;
+                }
+                '\n' => {
+                    end = i;
+                    break;
+                }
+                c => value.push(c),
+            }
+        }
+        if ends_record && text[start..end].ends_with('\r') {
+            end -= 1;
+            value.pop();
+        }
+        record.push(Field { start, end, value });
+        if ends_record {
+            records.push(std::mem::take(&mut record));
+            // The newline at the end of the last record does not start another
+            if chars.peek().is_none() {
+                break;
+            }
+        }
+    }
+    records
+}
+
+/// A value as a field, in quotes if it has the delimiter, a quote or a line break in it, or
+/// spaces around it.
+pub fn quote(value: &str, delimiter: char) -> String {
+    let needs_quotes = value.contains([delimiter, '"', '\n', '\r']) || value.trim() != value;
+    match needs_quotes {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_owned(),
+    }
+}
+
+/// What separates the fields of a file: tabs in a `.tsv` file, or in others whose first line has
+/// them, and otherwise commas, unless the first line has more semicolons than commas.
+pub fn delimiter(path: Option<&Path>, text: &str) -> char {
+    let first_line = text.lines().next().unwrap_or("");
+    let extension = path.and_then(|p| p.extension()).map(|e| e.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("tsv") | Some("tab") => '\t',
+        Some("csv") => if first_line.matches(';').count() > first_line.matches(',').count() { ';' } else { ',' },
+        _ if first_line.contains('\t') => '\t',
+        _ => if first_line.matches(';').count() > first_line.matches(',').count() { ';' } else { ',' },
+    }
+}
+
+/// Whether a file holds delimited values, by its extension.
+pub fn is_delimited(path: Option<&Path>) -> bool {
+    let extension = path.and_then(|p| p.extension()).map(|e| e.to_string_lossy().to_lowercase());
+    matches!(extension.as_deref(), Some("csv" | "tsv" | "tab" | "psv"))
+}
+
+/// The color the values of a column are drawn in, shown as text.
+pub fn column_color(column: usize) -> Color {
+    COLUMN_COLORS[column % COLUMN_COLORS.len()]
+}
+
+/// The fields of the records, in the order they are in the text, each with the column it is in.
+pub fn fields_by_column(records: Vec<Vec<Field>>) -> Vec<(Field, usize)> {
+    records.into_iter().flat_map(|record| record.into_iter().enumerate().map(|(column, field)| (field, column))).collect()
+}
+
+/// Where a value goes in the text to become the value of a cell, as the bytes it replaces and
+/// what replaces them. A cell past the end of its record is added to it, along with the empty
+/// ones before it.
+pub fn edit(records: &[Vec<Field>], row: usize, column: usize, value: &str, delimiter: char) -> Option<(usize, usize, String)> {
+    let record = records.get(row)?;
+    match record.get(column) {
+        Some(field) => Some((field.start, field.end, quote(value, delimiter))),
+        None => {
+            let end = record.last()?.end;
+            let padding = delimiter.to_string().repeat(column + 1 - record.len());
+            Some((end, end, padding + &quote(value, delimiter)))
+        }
+    }
+}
+
+/// A viewport's text shown as a table, and the cell selected in it.
+pub struct Table {
+    pub delimiter: char,
+    /// Whether the first row stays on top as the others scroll under it.
+    pub pin_header: bool,
+    pub row: usize,
+    pub column: usize,
+    // The first row under the header and the first column in view, and how many rows fit, as of
+    // the last render.
+    first_row: usize,
+    first_column: usize,
+    page: usize,
+    // The cell the cursor was drawn in, and which column and record each part of the screen
+    // showed, as of the last render.
+    cursor: Option<(u16, u16)>,
+    shown_columns: Vec<(u16, u16, usize)>,
+    shown_rows: Vec<(u16, usize)>,
+}
+
+impl Table {
+    pub fn new(delimiter: char) -> Table {
+        Table {
+            delimiter,
+            pin_header: true,
+            row: 0,
+            column: 0,
+            first_row: 0,
+            first_column: 0,
+            page: 1,
+            cursor: None,
+            shown_columns: Vec::new(),
+            shown_rows: Vec::new(),
+        }
+    }
+
+    /// Select the cell a byte of the text is in.
+    pub fn select_at(&mut self, records: &[Vec<Field>], offset: usize) {
+        if let Some(row) = records.iter().position(|record| offset <= record[record.len() - 1].end) {
+            self.row = row;
+            self.column = records[row].iter().position(|field| offset <= field.end).unwrap_or(0);
+        }
+    }
+
+    /// The field of the selected cell, if its record has one there.
+    pub fn selected<'a>(&self, records: &'a [Vec<Field>]) -> Option<&'a Field> {
+        records.get(self.row)?.get(self.column)
+    }
+
+    /// The cell the cursor was drawn in, as of the last render.
+    pub fn cursor_cell(&self) -> Option<(u16, u16)> {
+        self.cursor
+    }
+
+    /// The cell shown on a cell of the screen, as of the last render.
+    pub fn cell_at(&self, x: u16, y: u16) -> Option<(usize, usize)> {
+        let &(_, row) = self.shown_rows.iter().find(|&&(row_y, _)| row_y == y)?;
+        let &(_, _, column) = self.shown_columns.iter().find(|&&(start, end, _)| x >= start && x < end)?;
+        Some((row, column))
+    }
+
+    /// Move the selection with a key. Returns whether the key was one that does.
+    pub fn handle_key(&mut self, key: KeyCode, modifiers: KeyModifiers, records: &[Vec<Field>]) -> bool {
+        let last_row = records.len().saturating_sub(1);
+        let last_column = columns(records).saturating_sub(1);
+        match key {
+            KeyCode::Up => self.row = self.row.saturating_sub(1),
+            KeyCode::Down => self.row = (self.row + 1).min(last_row),
+            KeyCode::Left => self.column = self.column.saturating_sub(1),
+            KeyCode::Right => self.column = (self.column + 1).min(last_column),
+            KeyCode::PageUp => self.row = self.row.saturating_sub(self.page),
+            KeyCode::PageDown => self.row = (self.row + self.page).min(last_row),
+            KeyCode::Home if modifiers.contains(KeyModifiers::CONTROL) => self.row = 0,
+            KeyCode::End if modifiers.contains(KeyModifiers::CONTROL) => self.row = last_row,
+            KeyCode::Home => self.column = 0,
+            KeyCode::End => self.column = records.get(self.row).map_or(0, |record| record.len() - 1),
+            // Tab goes on to the start of the next row from the end of one, and Shift+Tab back
+            KeyCode::Tab if self.column < last_column => self.column += 1,
+            KeyCode::Tab if self.row < last_row => {
+                self.row += 1;
+                self.column = 0;
+            }
+            KeyCode::BackTab if self.column > 0 => self.column -= 1,
+            KeyCode::BackTab if self.row > 0 => {
+                self.row -= 1;
+                self.column = last_column;
+            }
+            KeyCode::Tab | KeyCode::BackTab => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// Draw the records in columns as wide as their values, up to a limit, with the header on top.
+    /// Numbers are lined up on the right.
+    pub fn render<S: Write>(&mut self, s: &mut S, records: &[Vec<Field>], origin: (u16, u16), size: (usize, usize), focused: bool) {
+        let column_count = columns(records);
+        self.row = self.row.min(records.len().saturating_sub(1));
+        self.column = self.column.min(column_count.saturating_sub(1));
+        let widths: Vec<usize> = (0..column_count)
+            .map(|column| records.iter().filter_map(|record| record.get(column)).map(|field| crate::util::columns(&shown(&field.value))).max().unwrap_or(0).clamp(1, MAX_COLUMN_WIDTH))
+            .collect();
+
+        // Scroll so that the selected cell is in view, below the header if it is pinned
+        let pinned = self.pin_header && records.len() > 1;
+        let top = if pinned { 1 } else { 0 };
+        self.page = size.1.saturating_sub(top).max(1);
+        if self.row >= top {
+            self.first_row = self.first_row.max(top);
+            if self.row < self.first_row {
+                self.first_row = self.row;
+            } else if self.row >= self.first_row + self.page {
+                self.first_row = self.row + 1 - self.page;
+            }
+        }
+        self.first_column = self.first_column.min(self.column);
+        while self.first_column < self.column && widths[self.first_column..=self.column].iter().map(|w| w + 1).sum::<usize>() > size.0 {
+            self.first_column += 1;
+        }
+
+        // Where each column in view starts and ends, with the line after it
+        self.shown_columns.clear();
+        let mut x = origin.0 as usize;
+        for (column, &width) in widths.iter().enumerate().skip(self.first_column) {
+            if x >= origin.0 as usize + size.0 {
+                break;
+            }
+            self.shown_columns.push((x as u16, (x + width + 1).min(origin.0 as usize + size.0) as u16, column));
+            x += width + 1;
+        }
+
+        let rows: Vec<usize> = match pinned {
+            true => std::iter::once(0).chain(self.first_row..records.len()).take(size.1).collect(),
+            false => (self.first_row..records.len()).take(size.1).collect(),
+        };
+        let separator = if crate::config::get().screen_reader { ' ' } else { '│' };
+        let text_color = if focused { Color::White } else { Color::Grey };
+        self.cursor = None;
+        self.shown_rows.clear();
+        for (y, &row) in rows.iter().enumerate() {
+            let y = origin.1 + y as u16;
+            self.shown_rows.push((y, row));
+            queue!(s, cursor::MoveTo(origin.0, y));
+            for &(start, end, column) in &self.shown_columns {
+                let width = widths[column];
+                let value = records[row].get(column).map_or("", |field| &field.value);
+                let text = shown(value);
+                let text = match crate::util::columns(&text) > width {
+                    true => text.chars().take(width - 1).chain(std::iter::once('…')).collect(),
+                    false => text,
+                };
+                let cell = match value.trim().parse::<f64>() {
+                    Ok(_) => format!("{:>1$}", text, width),
+                    Err(_) => format!("{:<1$}", text, width),
+                };
+                let selected = (row, column) == (self.row, self.column);
+                let (bg, fg) = match (selected, focused) {
+                    (true, true) => (Color::Grey, Color::Black),
+                    (true, false) => (Color::DarkCyan, Color::White),
+                    _ if pinned && row == 0 => (Color::DarkBlue, Color::Yellow),
+                    _ => (Color::Blue, text_color),
+                };
+                if selected {
+                    self.cursor = Some((start, y));
+                }
+                // The last column in view may be cut off at the edge
+                let room = (end - start) as usize;
+                let cell: String = cell.chars().take(room).collect();
+                queue!(s, style::SetBackgroundColor(bg), style::SetForegroundColor(fg), style::Print(cell));
+                if room > width {
+                    queue!(s, style::SetBackgroundColor(Color::Blue), style::SetForegroundColor(Color::DarkGrey), style::Print(separator));
+                }
+            }
+        }
+        queue!(s, style::SetBackgroundColor(Color::Blue), style::SetForegroundColor(Color::Grey));
+    }
+}
+
+/// How many columns the table has: as many as its longest record has fields.
+pub fn columns(records: &[Vec<Field>]) -> usize {
+    records.iter().map(|record| record.len()).max().unwrap_or(0)
+}
+
+// A value as it is shown in its cell, on one line and with nothing the terminal would act on.
+fn shown(value: &str) -> String {
+    crate::util::sanitize(&value.replace("\r\n", "\n").replace('\n', "↵"))
+}
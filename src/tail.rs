@@ -0,0 +1,138 @@
+//! Following a file as lines are added to it, like `tail -f`, in a viewport of its own to read but
+//! not edit. The view keeps to the end of the file unless scrolled up, and lines matching a
+//! pattern can be highlighted, so that a log can be watched for errors.
+
+use crossterm::{*, event::KeyCode, style::Color};
+use regex::Regex;
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Only so much of the end of a file is read when it starts being followed.
+static MAX_START_BYTES: u64 = 1 << 20;
+/// Older lines are let go of once there are more than this many.
+static MAX_LINES: usize = 100_000;
+
+pub struct Tail {
+    pub path: PathBuf,
+    lines: Vec<String>,
+    // How much of the file has been read, and the last line read, which is not finished yet.
+    read: u64,
+    partial: Vec<u8>,
+    /// Whether the view keeps to the end of the file as lines are added. Scrolling up stops it,
+    /// and scrolling back down to the end starts it again.
+    pub follow: bool,
+    /// The lines matching this are highlighted.
+    pub highlight: Option<Regex>,
+    // The first line and column in view, and how many lines fit, as of the last render.
+    top: usize,
+    left: usize,
+    height: usize,
+}
+
+impl Tail {
+    /// Start following a file from the end of what it has now.
+    pub fn open(path: PathBuf, highlight: Option<Regex>) -> io::Result<Tail> {
+        let len = std::fs::metadata(&path)?.len();
+        let mut tail = Tail { path, lines: Vec::new(), read: len.saturating_sub(MAX_START_BYTES), partial: Vec::new(), follow: true, highlight, top: 0, left: 0, height: 1 };
+        let started_inside = tail.read > 0;
+        tail.read_more()?;
+        // Starting part of the way in, the first line is likely only the end of one
+        if started_inside && !tail.lines.is_empty() {
+            tail.lines.remove(0);
+        }
+        Ok(tail)
+    }
+
+    /// Read what was added to the file since the last time. Returns true if anything was. A file
+    /// that got shorter was emptied or replaced, as logs are when rotated, and is read afresh.
+    pub fn poll(&mut self) -> bool {
+        let Ok(len) = std::fs::metadata(&self.path).map(|m| m.len()) else { return false };
+        if len == self.read {
+            return false;
+        }
+        if len < self.read {
+            self.lines.clear();
+            self.partial.clear();
+            self.read = 0;
+        }
+        if let Err(e) = self.read_more() {
+            log::warn!("Could not read {:?}: {}", self.path, e);
+        }
+        true
+    }
+
+    fn read_more(&mut self) -> io::Result<()> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.read))?;
+        let mut bytes = Vec::new();
+        self.read += file.read_to_end(&mut bytes)? as u64;
+        self.partial.extend(bytes);
+        // The lines that are finished are taken out of what was read
+        if let Some(end) = self.partial.iter().rposition(|&b| b == b'\n') {
+            let rest = self.partial.split_off(end + 1);
+            let finished = std::mem::replace(&mut self.partial, rest);
+            self.lines.extend(String::from_utf8_lossy(&finished[..end]).split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l).to_owned()));
+        }
+        if self.lines.len() > MAX_LINES {
+            let dropped = self.lines.len() - MAX_LINES;
+            self.lines.drain(..dropped);
+            self.top = self.top.saturating_sub(dropped);
+        }
+        Ok(())
+    }
+
+    /// How many lines there are to show, with the last one still being written.
+    fn line_count(&self) -> usize {
+        self.lines.len() + !self.partial.is_empty() as usize
+    }
+
+    /// Scroll with a key. Returns whether the key was one that does.
+    pub fn handle_key(&mut self, key: KeyCode) -> bool {
+        let last = self.line_count().saturating_sub(self.height);
+        match key {
+            KeyCode::Up => self.top = self.top.saturating_sub(1),
+            KeyCode::Down | KeyCode::Enter => self.top = (self.top + 1).min(last),
+            KeyCode::PageUp => self.top = self.top.saturating_sub(self.height),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.top = (self.top + self.height).min(last),
+            KeyCode::Home => (self.top, self.left) = (0, 0),
+            KeyCode::End => self.top = last,
+            KeyCode::Left => self.left = self.left.saturating_sub(8),
+            KeyCode::Right => self.left += 8,
+            _ => return false,
+        }
+        self.follow = self.top >= last;
+        true
+    }
+
+    /// How far down the file is scrolled, from 0 to 1.
+    pub fn scroll_percent(&self) -> f32 {
+        match self.line_count() {
+            0 => 1.0,
+            lines => ((self.top + self.height) as f32 / lines as f32).min(1.0),
+        }
+    }
+
+    pub fn render<S: Write>(&mut self, s: &mut S, origin: (u16, u16), size: (usize, usize), focused: bool) {
+        self.height = size.1.max(1);
+        let last = self.line_count().saturating_sub(self.height);
+        self.top = if self.follow { last } else { self.top.min(last) };
+        let partial = String::from_utf8_lossy(&self.partial);
+        let lines = self.lines.iter().map(String::as_str).chain(Some(&*partial).filter(|l| !l.is_empty()));
+        for (row, line) in lines.skip(self.top).take(self.height).enumerate() {
+            // Matching lines stand out over the whole width of the view
+            let (bg, fg) = match &self.highlight {
+                Some(highlight) if highlight.is_match(line) => (Color::DarkBlue, Color::Yellow),
+                _ => (Color::Blue, if focused { Color::White } else { Color::Grey }),
+            };
+            let text: String = crate::util::sanitize(line).chars().skip(self.left).take(size.0).collect();
+            queue!(s,
+                cursor::MoveTo(origin.0, origin.1 + row as u16),
+                style::SetBackgroundColor(bg), style::SetForegroundColor(fg),
+                style::Print(format!("{:<1$}", text, size.0)),
+            );
+        }
+        queue!(s, style::SetBackgroundColor(Color::Blue), style::SetForegroundColor(Color::Grey));
+    }
+}
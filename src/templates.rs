@@ -0,0 +1,29 @@
+//! Text inserted from the Insert menu: the date and time, and templates from the config file with
+//! placeholders like `{filename}` filled in.
+
+use crate::clock::DateTime;
+use crate::config;
+
+/// The formats offered by Insert → Date in format, after the configured one.
+pub static DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y-%m-%d %H:%M:%S", "%d/%m/%Y", "%m/%d/%Y", "%B %e, %Y", "%a, %d %b %Y %H:%M:%S", "%H:%M"];
+
+/// Who is writing, for the `{author}` placeholder: the configured author, or the login name.
+pub fn author() -> String {
+    config::get().author.clone()
+        .or_else(|| std::env::var("USER").ok())
+        .or_else(|| std::env::var("USERNAME").ok())
+        .unwrap_or_default()
+}
+
+/// Fill in the placeholders of a template: `{filename}`, `{author}`, `{date}` (in the configured
+/// format), `{time}` and `{year}`.
+pub fn expand(template: &str, filename: &str) -> String {
+    let now = DateTime::now_local();
+    let date = now.format(&config::get().date_format);
+    template
+        .replace("{filename}", filename)
+        .replace("{author}", &author())
+        .replace("{date}", &date)
+        .replace("{time}", &now.format("%H:%M"))
+        .replace("{year}", &now.year.to_string())
+}
@@ -0,0 +1,209 @@
+//! A panel along the bottom of the screen for running shell commands, their output scrolling up
+//! above the line they are typed on.
+//!
+//! Commands run one at a time through the system's shell, with no input, and their output is
+//! read on threads of their own so that the editor carries on while they run. `cd` is not
+//! passed on: it changes the folder the next commands run in.
+
+use crossterm::{*, event::KeyCode, event::KeyEvent, event::KeyModifiers, style::Color};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+
+/// The most lines of output kept. The oldest are forgotten first.
+static MAX_LINES: usize = 1000;
+
+pub struct TerminalPanel {
+    /// The folder commands run in.
+    pub dir: PathBuf,
+    lines: Vec<String>,
+    input: String,
+    // The commands run before, the latest last, and which of them Up has brought back.
+    history: Vec<String>,
+    recalled: Option<usize>,
+    // The command running, and the lines of its output and errors as they come.
+    running: Option<(Child, Receiver<String>)>,
+    // How many lines back from the latest the output is scrolled, and how many fit, as of the
+    // last render.
+    scroll: usize,
+    height: usize,
+}
+
+impl TerminalPanel {
+    pub fn new(dir: PathBuf) -> TerminalPanel {
+        TerminalPanel { dir, lines: Vec::new(), input: String::new(), history: Vec::new(), recalled: None, running: None, scroll: 0, height: 1 }
+    }
+
+    /// Handle a key while the panel has focus.
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        match key {
+            KeyEvent { code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL, .. } => match &mut self.running {
+                Some((child, _)) => {
+                    let _ = child.kill();
+                    self.push("^C".to_owned());
+                }
+                None => self.input.clear(),
+            },
+            KeyEvent { code: KeyCode::Char('l'), modifiers: KeyModifiers::CONTROL, .. } => {
+                self.lines.clear();
+                self.scroll = 0;
+            }
+            KeyEvent { code: KeyCode::Char(c), modifiers, .. } if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => self.input.push(c),
+            KeyEvent { code: KeyCode::Backspace, .. } => { self.input.pop(); }
+            KeyEvent { code: KeyCode::Enter, .. } if self.running.is_none() => self.run(),
+            KeyEvent { code: KeyCode::Up, .. } => if let Some(i) = self.recalled.map_or(self.history.len(), |i| i).checked_sub(1) {
+                self.recalled = Some(i);
+                self.input = self.history[i].clone();
+            },
+            KeyEvent { code: KeyCode::Down, .. } => if let Some(i) = self.recalled {
+                self.recalled = Some(i + 1).filter(|&i| i < self.history.len());
+                self.input = self.recalled.map_or_else(String::new, |i| self.history[i].clone());
+            },
+            KeyEvent { code: KeyCode::PageUp, .. } => self.scroll = (self.scroll + self.height).min(self.lines.len().saturating_sub(self.height)),
+            KeyEvent { code: KeyCode::PageDown, .. } => self.scroll = self.scroll.saturating_sub(self.height),
+            _ => {}
+        }
+    }
+
+    /// Run the command typed in, or change folder if it is `cd`.
+    fn run(&mut self) {
+        let command = std::mem::take(&mut self.input);
+        self.recalled = None;
+        self.scroll = 0;
+        self.push(format!("$ {}", command));
+        let command = command.trim();
+        if command.is_empty() {
+            return;
+        }
+        if self.history.last().map(String::as_str) != Some(command) {
+            self.history.push(command.to_owned());
+        }
+
+        if command == "cd" || command.starts_with("cd ") {
+            let to = command[2..].trim();
+            let to = match to.is_empty() {
+                true => std::env::var_os("HOME").map(PathBuf::from).unwrap_or_else(|| self.dir.clone()),
+                false => self.dir.join(to),
+            };
+            match to.canonicalize() {
+                Ok(dir) if dir.is_dir() => self.dir = dir,
+                Ok(dir) => self.push(format!("cd: {} is not a folder", dir.display())),
+                Err(e) => self.push(format!("cd: {}: {}", to.display(), e)),
+            }
+            return;
+        }
+
+        let child = crate::ranges::shell(command)
+            .current_dir(&self.dir)
+            .stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped())
+            .spawn();
+        match child {
+            Ok(mut child) => {
+                let (sender, receiver) = mpsc::channel();
+                forward(child.stdout.take().unwrap(), sender.clone());
+                forward(child.stderr.take().unwrap(), sender);
+                self.running = Some((child, receiver));
+            }
+            Err(e) => self.push(format!("Could not run the shell: {}", e)),
+        }
+    }
+
+    fn push(&mut self, line: String) {
+        self.lines.push(line);
+        if self.lines.len() > MAX_LINES {
+            self.lines.remove(0);
+        }
+    }
+
+    /// Take in what the running command has written since. Returns true if there is anything new
+    /// to show.
+    pub fn poll(&mut self) -> bool {
+        let Some((_, output)) = &self.running else { return false };
+        let mut new = Vec::new();
+        let finished = loop {
+            match output.try_recv() {
+                Ok(line) => new.push(line),
+                Err(TryRecvError::Empty) => break false,
+                // Its output and errors are both closed, so it is done with
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+        let changed = finished || !new.is_empty();
+        for line in new {
+            self.push(line);
+        }
+        if finished {
+            let (mut child, _) = self.running.take().unwrap();
+            match child.wait() {
+                Ok(status) if !status.success() => self.push(format!("[{}]", status)),
+                Ok(_) => {}
+                Err(e) => self.push(format!("[{}]", e)),
+            }
+        }
+        changed
+    }
+
+    /// Draw the panel, with a rule and the folder on top and the command being typed at the
+    /// bottom. Returns the cell of the cursor, at the end of the command.
+    pub fn render<S: Write>(&mut self, s: &mut S, origin: (u16, u16), size: (usize, usize)) -> (u16, u16) {
+        crate::util::draw_rectangle(s, &Color::Black, origin, size);
+        self.height = size.1.saturating_sub(2).max(1);
+
+        let rule = if crate::config::get().screen_reader { " " } else { "─" };
+        let title = format!(" {} — {} ", crate::locale::tr("Terminal"), crate::util::sanitize(&self.dir.display().to_string()));
+        let title: String = title.chars().take(size.0.saturating_sub(2)).collect();
+        queue!(s,
+            cursor::MoveTo(origin.0, origin.1), style::SetForegroundColor(Color::Grey),
+            style::Print(rule.repeat(size.0)), cursor::MoveTo(origin.0 + 1, origin.1), style::Print(title),
+        );
+
+        let end = self.lines.len() - self.scroll.min(self.lines.len());
+        let start = end.saturating_sub(self.height);
+        for (i, line) in self.lines[start..end].iter().enumerate() {
+            let line: String = crate::util::sanitize(line).chars().take(size.0).collect();
+            queue!(s, cursor::MoveTo(origin.0, origin.1 + 1 + i as u16), style::Print(line));
+        }
+
+        // The end of a long command stays in view
+        let prompt_y = origin.1 + size.1 as u16 - 1;
+        match self.running {
+            Some(_) => {
+                let note: String = crate::locale::tr("Running — Ctrl+C stops it").chars().take(size.0).collect();
+                queue!(s, cursor::MoveTo(origin.0, prompt_y), style::SetForegroundColor(Color::DarkGrey), style::Print(&note));
+                (origin.0 + note.chars().count() as u16, prompt_y)
+            }
+            None => {
+                let input: Vec<char> = format!("$ {}", crate::util::sanitize(&self.input)).chars().collect();
+                let shown: String = input[input.len().saturating_sub(size.0.saturating_sub(1))..].iter().collect();
+                queue!(s, cursor::MoveTo(origin.0, prompt_y), style::SetForegroundColor(Color::White), style::Print(&shown));
+                (origin.0 + shown.chars().count() as u16, prompt_y)
+            }
+        }
+    }
+}
+
+impl Drop for TerminalPanel {
+    fn drop(&mut self) {
+        if let Some((child, _)) = &mut self.running {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Send each line read from a command's output over to the panel, on a thread of its own.
+fn forward<R: Read + Send + 'static>(from: R, to: Sender<String>) {
+    std::thread::spawn(move || {
+        let mut from = BufReader::new(from);
+        let mut line = Vec::new();
+        while from.read_until(b'\n', &mut line).is_ok_and(|n| n > 0) {
+            let text = String::from_utf8_lossy(&line);
+            if to.send(text.trim_end_matches(['\n', '\r']).to_owned()).is_err() {
+                break;
+            }
+            line.clear();
+        }
+    });
+}
@@ -0,0 +1,117 @@
+//! Whether to draw the text dark or light, to suit the terminal's own background. The editor is
+//! drawn in the colors it always was, and `colors::Degrade` repaints the blue of the text areas
+//! white for the light theme, and their text dark.
+//!
+//! Unless `theme = dark` or `theme = light` says which, the terminal is asked for its background
+//! color with OSC 11 as the editor starts. Many terminals do not answer that, so the question is
+//! followed by one for the device attributes, which they all answer, and the dark theme is kept
+//! when only that answer comes.
+
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The themes to choose between.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Theme {
+    /// Light text on blue, as the editor has always been.
+    Dark,
+    /// Dark text on white.
+    Light,
+}
+
+impl std::str::FromStr for Theme {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Theme, ()> {
+        match s {
+            "dark" => Ok(Theme::Dark),
+            "light" => Ok(Theme::Light),
+            _ => Err(()),
+        }
+    }
+}
+
+static LIGHT: AtomicBool = AtomicBool::new(false);
+
+/// The theme drawn with now.
+pub fn current() -> Theme {
+    match LIGHT.load(Ordering::Relaxed) {
+        true => Theme::Light,
+        false => Theme::Dark,
+    }
+}
+
+/// Draw with another theme from the next frame on.
+pub fn set(theme: Theme) {
+    LIGHT.store(theme == Theme::Light, Ordering::Relaxed);
+}
+
+/// Pick the theme as the editor starts: the configured one, or else the one suiting the
+/// terminal's background. The terminal has to be in raw mode already, so that its answer is
+/// neither echoed nor held back until a new line.
+pub fn init() {
+    let configured = crate::config::get().theme;
+    let theme = configured.unwrap_or_else(|| match query_background() {
+        Some(rgb) if crate::colors::is_light(rgb) => Theme::Light,
+        _ => Theme::Dark,
+    });
+    log::info!("Drawing with the {:?} theme", theme);
+    set(theme);
+}
+
+// How long to wait for the terminal to answer, which a local one does at once.
+static QUERY_TIMEOUT_MS: i32 = 200;
+
+/// Ask the terminal for its background color.
+#[cfg(unix)]
+fn query_background() -> Option<(u8, u8, u8)> {
+    if unsafe { libc::isatty(0) == 0 || libc::isatty(1) == 0 } {
+        return None;
+    }
+    let mut stdout = std::io::stdout();
+    stdout.write_all(b"\x1b]11;?\x1b\\\x1b[c").ok()?;
+    stdout.flush().ok()?;
+
+    let mut reply = Vec::new();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_millis(QUERY_TIMEOUT_MS as u64);
+    // Read until the device attributes, `ESC [ ? ... c`, which come after the color if it comes
+    while !answered(&reply) {
+        let left = deadline.saturating_duration_since(std::time::Instant::now()).as_millis() as i32;
+        let mut fd = libc::pollfd { fd: 0, events: libc::POLLIN, revents: 0 };
+        if left == 0 || unsafe { libc::poll(&mut fd, 1, left) } <= 0 {
+            break;
+        }
+        let mut buf = [0u8; 256];
+        let n = unsafe { libc::read(0, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n <= 0 {
+            break;
+        }
+        reply.extend_from_slice(&buf[..n as usize]);
+    }
+    parse_background(&String::from_utf8_lossy(&reply))
+}
+
+#[cfg(not(unix))]
+fn query_background() -> Option<(u8, u8, u8)> {
+    None
+}
+
+fn answered(reply: &[u8]) -> bool {
+    reply.windows(3).position(|w| w == b"\x1b[?").is_some_and(|start| reply[start..].contains(&b'c'))
+}
+
+/// The color in an answer to OSC 11, like `ESC ] 11 ; rgb:ffff/ffff/dddd ESC \`, where each
+/// channel has from one to four hexadecimal digits.
+fn parse_background(reply: &str) -> Option<(u8, u8, u8)> {
+    let start = reply.find("]11;rgb:")? + "]11;rgb:".len();
+    let end = reply[start..].find(['\x07', '\x1b']).map_or(reply.len(), |end| start + end);
+    let channel = |hex: &str| {
+        let value = u32::from_str_radix(hex, 16).ok().filter(|_| (1..=4).contains(&hex.len()))?;
+        Some((value * 255 / ((1 << (4 * hex.len())) - 1)) as u8)
+    };
+    let mut channels = reply[start..end].split('/').map(channel);
+    match (channels.next(), channels.next(), channels.next(), channels.next()) {
+        (Some(Some(r)), Some(Some(g)), Some(Some(b)), None) => Some((r, g, b)),
+        _ => None,
+    }
+}
@@ -0,0 +1,84 @@
+//! TODO, FIXME, HACK and NOTE markers in comments. They are picked out in the accent color when
+//! drawing a buffer, and can be gathered from every file in the project to go through in a list.
+
+use crate::jobs::Progress;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub static MARKERS: &[&str] = &["TODO", "FIXME", "HACK", "NOTE"];
+
+/// Where a comment can begin, in most languages. A line starting with `*` is taken to be in the
+/// middle of a block comment.
+static COMMENT_STARTS: &[&str] = &["//", "/*", "#", "--"];
+
+/// Files bigger than this are not searched for markers.
+static MAX_FILE_SIZE: u64 = 1 << 20;
+
+/// Directories that are not searched, besides hidden ones.
+static IGNORED_DIRECTORIES: &[&str] = &["target", "node_modules"];
+
+/// Where the markers are in a line, as the character offset and length of each one. Only whole
+/// words after the start of a comment count.
+pub fn in_line(line: &str) -> Vec<(usize, usize)> {
+    let comment = match COMMENT_STARTS.iter().filter_map(|start| line.find(start)).min() {
+        Some(i) => i,
+        None if line.trim_start().starts_with('*') => 0,
+        None => return Vec::new(),
+    };
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut found = Vec::new();
+    for marker in MARKERS {
+        for (i, _) in line[comment..].match_indices(marker) {
+            let i = comment + i;
+            let before = line[..i].chars().next_back();
+            let after = line[i + marker.len()..].chars().next();
+            if !before.is_some_and(is_word) && !after.is_some_and(is_word) {
+                found.push((crate::util::columns(&line[..i]), marker.len()));
+            }
+        }
+    }
+    found
+}
+
+/// A line with a marker on it, somewhere in the project.
+pub struct Marker {
+    pub path: PathBuf,
+    /// Zero-based.
+    pub line: usize,
+    pub text: String,
+}
+
+/// Find the markers in every file under a directory, skipping hidden directories, build output
+/// and anything that is not UTF-8 text. They come sorted by path and line.
+pub fn search(root: &Path, progress: &Progress) -> io::Result<Vec<Marker>> {
+    let mut markers = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(e) if directory == root => return Err(e),
+            Err(_) => continue, // Something we may not read, deeper in
+        };
+        progress.report(format!("Searching {} for markers", directory.display()));
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let path = entry.path();
+            match entry.metadata() {
+                Ok(m) if m.is_dir() && !name.starts_with('.') && !IGNORED_DIRECTORIES.contains(&name.as_ref()) => directories.push(path),
+                Ok(m) if m.is_file() && m.len() <= MAX_FILE_SIZE => if let Ok(text) = fs::read_to_string(&path) {
+                    for (line, l) in text.lines().enumerate() {
+                        if !in_line(l).is_empty() {
+                            markers.push(Marker { path: path.clone(), line, text: l.trim().to_owned() });
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+    markers.sort_by(|a, b| (&a.path, a.line).cmp(&(&b.path, b.line)));
+    Ok(markers)
+}
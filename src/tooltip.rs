@@ -0,0 +1,41 @@
+//! Small popups with the whole of something shown cut short on screen, like a line's diagnostic
+//! or a tab's title, for the cell the mouse pointer comes to rest on.
+//!
+//! With the mouse captured, the terminal reports the pointer each time it moves to another cell,
+//! so the tooltip comes up once it has rested there for `tooltip_delay`. It goes again on any key
+//! or mouse event.
+
+use crossterm::{*, style::Color};
+
+use std::io::Write;
+
+/// A tooltip wraps its text at this many columns, or at the width of the screen.
+static MAX_WIDTH: usize = 60;
+/// And shows at most this many of the lines it wraps to.
+static MAX_ROWS: usize = 6;
+
+/// Draw a tooltip with the text under the cell of the pointer, or over it if there is no room
+/// below, kept within the screen.
+pub fn render<S: Write>(s: &mut S, text: &str, pointer: (u16, u16)) {
+    let (w, h) = crate::backend::size();
+    let width = MAX_WIDTH.min((w as usize).saturating_sub(4)).max(1);
+    let mut lines: Vec<String> = textwrap::wrap(&crate::util::sanitize(text), width).into_iter().map(|line| line.into_owned()).collect();
+    if lines.len() > MAX_ROWS {
+        lines.truncate(MAX_ROWS);
+        lines[MAX_ROWS - 1].push('…');
+    }
+    let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) + 2;
+    let rows = lines.len() as u16;
+    let x = pointer.0.min((w as usize).saturating_sub(columns) as u16);
+    let y = match pointer.1 + 1 + rows <= h {
+        true => pointer.1 + 1,
+        false => pointer.1.saturating_sub(rows),
+    };
+    for (row, line) in lines.iter().enumerate() {
+        queue!(s,
+            cursor::MoveTo(x, y + row as u16),
+            style::SetBackgroundColor(Color::Grey), style::SetForegroundColor(Color::Black),
+            style::Print(format!(" {:<1$} ", line, columns - 2)),
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Encoding and decoding text for the places it ends up in: base64, URLs, JSON strings and HTML.
+//! Decoding fails on text that could not have been encoded that way, so that a selection is left
+//! as it was rather than mangled.
+
+/// A way to encode or decode text, as picked from the Edit menu.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Transform {
+    Base64Encode,
+    Base64Decode,
+    UrlEncode,
+    UrlDecode,
+    JsonEscape,
+    JsonUnescape,
+    HtmlEscape,
+    HtmlUnescape,
+}
+
+impl Transform {
+    /// The text transformed, or why it can not be.
+    pub fn apply(self, text: &str) -> Result<String, String> {
+        match self {
+            Transform::Base64Encode => Ok(crate::util::base64_encode(text.as_bytes())),
+            Transform::Base64Decode => base64_decode(text),
+            Transform::UrlEncode => Ok(url_encode(text)),
+            Transform::UrlDecode => url_decode(text),
+            Transform::JsonEscape => Ok(json_escape(text)),
+            Transform::JsonUnescape => json_unescape(text),
+            Transform::HtmlEscape => Ok(html_escape(text)),
+            Transform::HtmlUnescape => Ok(html_unescape(text)),
+        }
+    }
+}
+
+/// Decode base64 into the text it encodes. The URL-safe alphabet is taken as well, whitespace
+/// and line breaks are skipped, and the padding may be left off.
+pub fn base64_decode(text: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut n, mut bits) = (0u32, 0);
+    let mut padding = false;
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' | '-' => 62,
+            '/' | '_' => 63,
+            '=' => {
+                padding = true;
+                continue;
+            }
+            c => return Err(format!("'{}' is not a base64 character.", c.escape_default())),
+        };
+        if padding {
+            return Err("There is more base64 after its padding.".to_owned());
+        }
+        n = n << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+    // Every four characters make three bytes, and one left over makes not even one
+    if bits == 6 {
+        return Err("The base64 is cut short.".to_owned());
+    }
+    String::from_utf8(bytes).map_err(|_| "The base64 does not encode text.".to_owned())
+}
+
+/// Percent-encode every byte but letters, digits and `-._~`, which URLs leave as they are.
+pub fn url_encode(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for b in text.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(b as char),
+            b => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// Decode percent-encoded bytes, and `+` as a space, which is how forms send one.
+pub fn url_decode(text: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&b, after)) = rest.split_first() {
+        rest = after;
+        match b {
+            b'%' => {
+                let byte = rest.get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| "A '%' is not followed by two hexadecimal digits.".to_owned())?;
+                bytes.push(byte);
+                rest = &rest[2..];
+            }
+            b'+' => bytes.push(b' '),
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| "The decoded bytes are not UTF-8 text.".to_owned())
+}
+
+/// Escape text to go between the quotes of a JSON string.
+pub fn json_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\x08' => out.push_str("\\b"),
+            '\x0c' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Undo the escapes of a JSON string, including `\u` ones that pair up for characters outside
+/// the Basic Multilingual Plane.
+pub fn json_unescape(text: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('/') => out.push('/'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('b') => out.push('\x08'),
+            Some('f') => out.push('\x0c'),
+            Some('u') => {
+                let unit = hex_unit(&mut chars)?;
+                let code = match unit {
+                    0xd800..=0xdbff => {
+                        let low = match (chars.next(), chars.next()) {
+                            (Some('\\'), Some('u')) => hex_unit(&mut chars)?,
+                            _ => return Err(format!("\\u{:04x} is half of a character with no other half.", unit)),
+                        };
+                        if !(0xdc00..=0xdfff).contains(&low) {
+                            return Err(format!("\\u{:04x} is half of a character with no other half.", unit));
+                        }
+                        0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00)
+                    }
+                    0xdc00..=0xdfff => return Err(format!("\\u{:04x} is half of a character with no other half.", unit)),
+                    unit => unit,
+                };
+                out.push(std::char::from_u32(code).unwrap());
+            }
+            Some(c) => return Err(format!("\\{} is not a JSON escape.", c)),
+            None => return Err("The text ends in a lone backslash.".to_owned()),
+        }
+    }
+    Ok(out)
+}
+
+// The four hex digits after `\u`.
+fn hex_unit(chars: &mut std::str::Chars) -> Result<u32, String> {
+    let digits: String = chars.take(4).collect();
+    match digits.len() == 4 && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        true => Ok(u32::from_str_radix(&digits, 16).unwrap()),
+        false => Err(format!("\\u{} is not followed by four hexadecimal digits.", digits)),
+    }
+}
+
+/// Escape the characters HTML gives meaning to, in text and in quoted attributes alike.
+pub fn html_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Named entities that are decoded, besides the ones `html_escape` makes.
+static ENTITIES: &[(&str, char)] = &[
+    ("amp", '&'), ("lt", '<'), ("gt", '>'), ("quot", '"'), ("apos", '\''), ("nbsp", '\u{a0}'),
+    ("copy", '©'), ("reg", '®'), ("trade", '™'), ("hellip", '…'), ("mdash", '—'), ("ndash", '–'),
+    ("lsquo", '‘'), ("rsquo", '’'), ("ldquo", '“'), ("rdquo", '”'), ("laquo", '«'), ("raquo", '»'),
+    ("euro", '€'), ("pound", '£'), ("yen", '¥'), ("cent", '¢'), ("sect", '§'), ("deg", '°'),
+    ("plusmn", '±'), ("times", '×'), ("divide", '÷'), ("middot", '·'), ("bull", '•'),
+];
+
+/// Decode named and numeric character references. Like a browser, this leaves an `&` that
+/// starts no entity it knows as it is.
+pub fn html_unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest[1..].find(';').filter(|&end| end <= 32).and_then(|end| {
+            let name = &rest[1..1 + end];
+            let c = match name.strip_prefix('#') {
+                Some(number) => match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => number.parse().ok(),
+                }.and_then(std::char::from_u32),
+                None => ENTITIES.iter().find(|(entity, _)| *entity == name).map(|&(_, c)| c),
+            };
+            c.map(|c| (c, end + 2))
+        });
+        match decoded {
+            Some((c, length)) => {
+                out.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXTS: &[&str] = &["", "plain", "a b&c=d/e?f#g", "\"quoted\" <b>'it's'</b>\n\ttab\\", "Grüße, 世界! 🦀\u{0}\u{1f}"];
+
+    #[test]
+    fn decoding_undoes_encoding() {
+        let pairs = [
+            (Transform::Base64Encode, Transform::Base64Decode),
+            (Transform::UrlEncode, Transform::UrlDecode),
+            (Transform::JsonEscape, Transform::JsonUnescape),
+            (Transform::HtmlEscape, Transform::HtmlUnescape),
+        ];
+        for (encode, decode) in pairs {
+            for &text in TEXTS {
+                let encoded = encode.apply(text).unwrap();
+                assert_eq!(decode.apply(&encoded).as_deref(), Ok(text), "{:?} of {:?}", encode, text);
+            }
+        }
+    }
+
+    #[test]
+    fn encodings_are_the_usual_ones() {
+        assert_eq!(crate::util::base64_encode("Grüße".as_bytes()), "R3LDvMOfZQ==");
+        assert_eq!(url_encode("a b/ü"), "a%20b%2F%C3%BC");
+        assert_eq!(json_escape("\"\u{1}\""), "\\\"\\u0001\\\"");
+        assert_eq!(html_escape("<a href='x'>"), "&lt;a href=&#39;x&#39;&gt;");
+    }
+
+    #[test]
+    fn base64_padding_must_come_last() {
+        assert_eq!(base64_decode("R3LDvMOfZQ").as_deref(), Ok("Grüße"));
+        assert_eq!(base64_decode("R3LD\nvMOf ZQ==").as_deref(), Ok("Grüße"));
+        assert!(base64_decode("QQ==QQ==").is_err());
+        assert!(base64_decode("Q===").is_err());
+        assert!(base64_decode("QUJD*").is_err());
+        assert!(base64_decode("/w==").is_err()); // Not UTF-8
+    }
+
+    #[test]
+    fn percent_needs_two_hexadecimal_digits() {
+        assert_eq!(url_decode("100%25+sure").as_deref(), Ok("100% sure"));
+        assert!(url_decode("%zz").is_err());
+        assert!(url_decode("50%").is_err());
+        assert!(url_decode("%4").is_err());
+        assert!(url_decode("%C3").is_err());
+    }
+
+    #[test]
+    fn surrogates_must_pair_up() {
+        assert_eq!(json_unescape("\\ud83e\\udd80").as_deref(), Ok("🦀"));
+        assert!(json_unescape("\\ud83e").is_err());
+        assert!(json_unescape("\\ud83e and more").is_err());
+        assert!(json_unescape("\\ud83e\\u0041").is_err());
+        assert!(json_unescape("\\udd80").is_err());
+        assert!(json_unescape("\\u12").is_err());
+        assert!(json_unescape("ends in \\").is_err());
+    }
+
+    #[test]
+    fn an_unknown_or_unterminated_entity_is_left_alone() {
+        assert_eq!(html_unescape("&amp;&#169;&#xa9;&hellip;"), "&©©…");
+        assert_eq!(html_unescape("fish &amp chips"), "fish &amp chips");
+        assert_eq!(html_unescape("&amp"), "&amp");
+        assert_eq!(html_unescape("&"), "&");
+        assert_eq!(html_unescape("&nosuch; &#xd800; &#99999999;"), "&nosuch; &#xd800; &#99999999;");
+        assert_eq!(html_unescape("AT&T; Grüße &lt;"), "AT&T; Grüße <");
+    }
+}
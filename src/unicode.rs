@@ -0,0 +1,178 @@
+//! The characters offered by Insert → Unicode character, with their names and categories to
+//! search by. It is a hand-picked list of what is awkward to type, not the whole of Unicode.
+
+/// A character, its name and its category.
+pub static CHARACTERS: &[(char, &str, &str)] = &[
+    // Punctuation and typography
+    ('–', "En dash", "Punctuation"),
+    ('—', "Em dash", "Punctuation"),
+    ('…', "Horizontal ellipsis", "Punctuation"),
+    ('‘', "Left single quotation mark", "Punctuation"),
+    ('’', "Right single quotation mark", "Punctuation"),
+    ('“', "Left double quotation mark", "Punctuation"),
+    ('”', "Right double quotation mark", "Punctuation"),
+    ('«', "Left-pointing double angle quotation mark", "Punctuation"),
+    ('»', "Right-pointing double angle quotation mark", "Punctuation"),
+    ('•', "Bullet", "Punctuation"),
+    ('·', "Middle dot", "Punctuation"),
+    ('†', "Dagger", "Punctuation"),
+    ('‡', "Double dagger", "Punctuation"),
+    ('§', "Section sign", "Punctuation"),
+    ('¶', "Pilcrow sign", "Punctuation"),
+    ('¡', "Inverted exclamation mark", "Punctuation"),
+    ('¿', "Inverted question mark", "Punctuation"),
+    ('‽', "Interrobang", "Punctuation"),
+    ('\u{a0}', "No-break space", "Punctuation"),
+    ('\u{200b}', "Zero width space", "Punctuation"),
+    ('©', "Copyright sign", "Symbols"),
+    ('®', "Registered sign", "Symbols"),
+    ('™', "Trade mark sign", "Symbols"),
+    ('°', "Degree sign", "Symbols"),
+    ('№', "Numero sign", "Symbols"),
+    ('℃', "Degree Celsius", "Symbols"),
+    ('♠', "Black spade suit", "Symbols"),
+    ('♥', "Black heart suit", "Symbols"),
+    ('♦', "Black diamond suit", "Symbols"),
+    ('♣', "Black club suit", "Symbols"),
+    ('♪', "Eighth note", "Symbols"),
+    ('★', "Black star", "Symbols"),
+    ('☆', "White star", "Symbols"),
+    ('✓', "Check mark", "Symbols"),
+    ('✗', "Ballot X", "Symbols"),
+    ('☐', "Ballot box", "Symbols"),
+    ('☑', "Ballot box with check", "Symbols"),
+    ('⌘', "Place of interest sign (command key)", "Symbols"),
+    ('⌥', "Option key", "Symbols"),
+    ('⇧', "Upwards white arrow (shift key)", "Symbols"),
+    ('⏎', "Return symbol", "Symbols"),
+    ('⌫', "Erase to the left (backspace)", "Symbols"),
+    // Currency
+    ('€', "Euro sign", "Currency"),
+    ('£', "Pound sign", "Currency"),
+    ('¥', "Yen sign", "Currency"),
+    ('¢', "Cent sign", "Currency"),
+    ('₹', "Indian rupee sign", "Currency"),
+    ('₽', "Ruble sign", "Currency"),
+    ('₩', "Won sign", "Currency"),
+    ('₿', "Bitcoin sign", "Currency"),
+    // Arrows
+    ('←', "Leftwards arrow", "Arrows"),
+    ('→', "Rightwards arrow", "Arrows"),
+    ('↑', "Upwards arrow", "Arrows"),
+    ('↓', "Downwards arrow", "Arrows"),
+    ('↔', "Left right arrow", "Arrows"),
+    ('↕', "Up down arrow", "Arrows"),
+    ('⇐', "Leftwards double arrow", "Arrows"),
+    ('⇒', "Rightwards double arrow", "Arrows"),
+    ('⇔', "Left right double arrow", "Arrows"),
+    ('↩', "Leftwards arrow with hook", "Arrows"),
+    ('↪', "Rightwards arrow with hook", "Arrows"),
+    ('⟶', "Long rightwards arrow", "Arrows"),
+    ('↦', "Rightwards arrow from bar (maps to)", "Arrows"),
+    // Mathematics
+    ('±', "Plus-minus sign", "Math"),
+    ('×', "Multiplication sign", "Math"),
+    ('÷', "Division sign", "Math"),
+    ('−', "Minus sign", "Math"),
+    ('≠', "Not equal to", "Math"),
+    ('≈', "Almost equal to", "Math"),
+    ('≡', "Identical to", "Math"),
+    ('≤', "Less-than or equal to", "Math"),
+    ('≥', "Greater-than or equal to", "Math"),
+    ('∞', "Infinity", "Math"),
+    ('√', "Square root", "Math"),
+    ('∑', "N-ary summation", "Math"),
+    ('∏', "N-ary product", "Math"),
+    ('∫', "Integral", "Math"),
+    ('∂', "Partial differential", "Math"),
+    ('∇', "Nabla", "Math"),
+    ('∀', "For all", "Math"),
+    ('∃', "There exists", "Math"),
+    ('∈', "Element of", "Math"),
+    ('∉', "Not an element of", "Math"),
+    ('⊂', "Subset of", "Math"),
+    ('⊆', "Subset of or equal to", "Math"),
+    ('∪', "Union", "Math"),
+    ('∩', "Intersection", "Math"),
+    ('∅', "Empty set", "Math"),
+    ('¬', "Not sign", "Math"),
+    ('∧', "Logical and", "Math"),
+    ('∨', "Logical or", "Math"),
+    ('⊕', "Circled plus (xor)", "Math"),
+    ('∘', "Ring operator (compose)", "Math"),
+    ('⌊', "Left floor", "Math"),
+    ('⌋', "Right floor", "Math"),
+    ('⌈', "Left ceiling", "Math"),
+    ('⌉', "Right ceiling", "Math"),
+    ('½', "Vulgar fraction one half", "Math"),
+    ('¼', "Vulgar fraction one quarter", "Math"),
+    ('¾', "Vulgar fraction three quarters", "Math"),
+    ('²', "Superscript two", "Math"),
+    ('³', "Superscript three", "Math"),
+    ('ℕ', "Double-struck capital N (natural numbers)", "Math"),
+    ('ℤ', "Double-struck capital Z (integers)", "Math"),
+    ('ℝ', "Double-struck capital R (real numbers)", "Math"),
+    // Greek
+    ('α', "Greek small letter alpha", "Greek"),
+    ('β', "Greek small letter beta", "Greek"),
+    ('γ', "Greek small letter gamma", "Greek"),
+    ('δ', "Greek small letter delta", "Greek"),
+    ('ε', "Greek small letter epsilon", "Greek"),
+    ('θ', "Greek small letter theta", "Greek"),
+    ('λ', "Greek small letter lambda", "Greek"),
+    ('μ', "Greek small letter mu", "Greek"),
+    ('π', "Greek small letter pi", "Greek"),
+    ('σ', "Greek small letter sigma", "Greek"),
+    ('τ', "Greek small letter tau", "Greek"),
+    ('φ', "Greek small letter phi", "Greek"),
+    ('ω', "Greek small letter omega", "Greek"),
+    ('Δ', "Greek capital letter delta", "Greek"),
+    ('Σ', "Greek capital letter sigma", "Greek"),
+    ('Ω', "Greek capital letter omega", "Greek"),
+    // Box drawing
+    ('─', "Box drawings light horizontal", "Box drawing"),
+    ('│', "Box drawings light vertical", "Box drawing"),
+    ('┌', "Box drawings light down and right", "Box drawing"),
+    ('┐', "Box drawings light down and left", "Box drawing"),
+    ('└', "Box drawings light up and right", "Box drawing"),
+    ('┘', "Box drawings light up and left", "Box drawing"),
+    ('├', "Box drawings light vertical and right", "Box drawing"),
+    ('┤', "Box drawings light vertical and left", "Box drawing"),
+    ('┬', "Box drawings light down and horizontal", "Box drawing"),
+    ('┴', "Box drawings light up and horizontal", "Box drawing"),
+    ('┼', "Box drawings light vertical and horizontal", "Box drawing"),
+    ('═', "Box drawings double horizontal", "Box drawing"),
+    ('║', "Box drawings double vertical", "Box drawing"),
+    ('█', "Full block", "Box drawing"),
+    ('░', "Light shade", "Box drawing"),
+    ('▒', "Medium shade", "Box drawing"),
+    // Emoji
+    ('😀', "Grinning face", "Emoji"),
+    ('😂', "Face with tears of joy", "Emoji"),
+    ('🙂', "Slightly smiling face", "Emoji"),
+    ('😉', "Winking face", "Emoji"),
+    ('😍', "Smiling face with heart-eyes", "Emoji"),
+    ('🤔', "Thinking face", "Emoji"),
+    ('😢', "Crying face", "Emoji"),
+    ('😡', "Pouting face", "Emoji"),
+    ('👍', "Thumbs up", "Emoji"),
+    ('👎', "Thumbs down", "Emoji"),
+    ('👋', "Waving hand", "Emoji"),
+    ('🙏', "Folded hands", "Emoji"),
+    ('👀', "Eyes", "Emoji"),
+    ('❤', "Red heart", "Emoji"),
+    ('🔥', "Fire", "Emoji"),
+    ('✨', "Sparkles", "Emoji"),
+    ('🎉', "Party popper", "Emoji"),
+    ('🚀', "Rocket", "Emoji"),
+    ('🐛', "Bug", "Emoji"),
+    ('💡', "Light bulb", "Emoji"),
+    ('⚠', "Warning sign", "Emoji"),
+    ('✅', "Check mark button", "Emoji"),
+    ('❌', "Cross mark", "Emoji"),
+    ('📝', "Memo", "Emoji"),
+    ('📦', "Package", "Emoji"),
+    ('🔒', "Locked", "Emoji"),
+    ('⭐', "Star", "Emoji"),
+    ('☕', "Hot beverage", "Emoji"),
+];
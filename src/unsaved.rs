@@ -0,0 +1,104 @@
+//! The lines of a buffer that differ from its file as saved, marked in the gutter and tinted while
+//! shown, and worked out again as the text changes. A lighter way to see what saving would
+//! change than the differences in a viewport of their own.
+
+use crossterm::style::Color;
+
+use crate::diff::{self, Change};
+
+use std::collections::BTreeMap;
+
+/// How a line differs from the file as saved.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LineChange {
+    /// The line is new.
+    Added,
+    /// The line took the place of other lines.
+    Changed,
+    /// The line is as it was, but lines before it were removed, or after it for the last line.
+    Removed,
+}
+
+impl LineChange {
+    /// The mark in the gutter of a line changed so, and its color.
+    pub fn mark(self) -> (char, Color) {
+        match self {
+            LineChange::Added => ('+', Color::Green),
+            LineChange::Changed => ('~', Color::Yellow),
+            LineChange::Removed => ('-', Color::Red),
+        }
+    }
+
+    /// The color the line is tinted with, if any.
+    pub fn background(self) -> Option<Color> {
+        match self {
+            LineChange::Added => Some(Color::DarkGreen),
+            LineChange::Changed => Some(Color::DarkMagenta),
+            LineChange::Removed => None,
+        }
+    }
+}
+
+/// The lines of a text that differ from the file as saved.
+pub struct UnsavedChanges {
+    // The text of the file as last saved, and the text the lines were last worked out for.
+    saved: String,
+    text: String,
+    lines: BTreeMap<usize, LineChange>,
+}
+
+impl UnsavedChanges {
+    /// Mark the lines differing from the text of the file as saved.
+    pub fn new(saved: String) -> UnsavedChanges {
+        UnsavedChanges { text: saved.clone(), saved, lines: BTreeMap::new() }
+    }
+
+    /// Work out again which lines differ, if the text changed. A text that is not modified is the
+    /// file as saved, as it is just after saving it.
+    pub fn update(&mut self, text: &str, modified: bool) {
+        if !modified {
+            if text != self.saved {
+                self.saved = text.to_owned();
+            }
+            if self.text != self.saved {
+                self.text.clone_from(&self.saved);
+            }
+            self.lines.clear();
+            return;
+        }
+        if text == self.text {
+            return;
+        }
+        self.text = text.to_owned();
+        self.lines.clear();
+        // Lines removed and added in a row are changed lines, as far as there are as many of each
+        let (mut line, mut removed) = (0, 0usize);
+        for (change, _) in diff::lines(&self.saved, text) {
+            match change {
+                Change::Same => {
+                    if removed > 0 {
+                        self.lines.insert(line, LineChange::Removed);
+                    }
+                    removed = 0;
+                    line += 1;
+                }
+                Change::Removed => removed += 1,
+                Change::Added => {
+                    let change = if removed > 0 { LineChange::Changed } else { LineChange::Added };
+                    self.lines.insert(line, change);
+                    removed = removed.saturating_sub(1);
+                    line += 1;
+                }
+            }
+        }
+        // Lines removed from the end are marked on the last line left
+        if removed > 0 {
+            self.lines.entry(line.saturating_sub(1)).or_insert(LineChange::Removed);
+        }
+    }
+
+    /// How a line differs from the file as saved, if it does.
+    pub fn get(&self, line: usize) -> Option<LineChange> {
+        self.lines.get(&line).copied()
+    }
+}
@@ -0,0 +1,54 @@
+//! Keeping untitled buffers from one session to the next. Their text is written to the `untitled`
+//! folder of the data directory every so often and when the editor quits, and the next session
+//! offers to open them again. A session only ever replaces the files it wrote itself, so that two
+//! editors open at once cannot lose each other's buffers.
+
+use crate::config;
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn untitled_dir() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join("untitled"))
+}
+
+/// Write the texts to the untitled folder, in place of the files this session wrote before.
+/// Returns the files written.
+pub fn save(texts: &[String], previous: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    forget(previous);
+    if texts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let dir = untitled_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "There is no home directory"))?;
+    fs::create_dir_all(&dir)?;
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    texts.iter().enumerate().map(|(n, text)| {
+        let path = dir.join(format!("{}.{}.{:03}", time, std::process::id(), n));
+        fs::write(&path, text)?;
+        Ok(path)
+    }).collect()
+}
+
+/// The untitled buffers kept by earlier sessions, in the order they were written, with the files
+/// they are in.
+pub fn load() -> Vec<(PathBuf, String)> {
+    let Some(entries) = untitled_dir().and_then(|dir| fs::read_dir(dir).ok()) else { return Vec::new() };
+    let mut kept: Vec<(PathBuf, String)> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| fs::read_to_string(&path).ok().map(|text| (path, text)))
+        .collect();
+    kept.sort();
+    kept
+}
+
+/// Let go of kept files, once their buffers are open again or not wanted.
+pub fn forget(paths: &[PathBuf]) {
+    for path in paths {
+        if let Err(e) = fs::remove_file(path) {
+            log::warn!("Could not remove {}: {}", path.display(), e);
+        }
+    }
+}
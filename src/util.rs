@@ -3,41 +3,15 @@
 // use termion::input::TermRead;
 // use termion::*;
 
-use crossterm::{*, style::Color, event::Event, event::KeyEvent, event::KeyCode};
+use crossterm::{*, style::{Color, Stylize}, event::Event, event::KeyEvent, event::KeyCode, event::KeyModifiers};
+use scribe::buffer::Position;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::locale::tr;
 
 use std::io::Write;
 use std::path::PathBuf;
 
-pub fn lines(src: &str) -> Vec<&str> {
-    if src.is_empty() {
-        return vec!("");
-    }
-
-    let mut lines = Vec::new();
-    let mut current_start = 0usize; // Index of src that is the beginning of the current line
-    let mut starting_next_line = true; // Index of src that is the end of the current line
-
-    let mut chars = src.char_indices();
-    while let Some((i, c)) = chars.next() {
-        if starting_next_line {
-            starting_next_line = false;
-            current_start = i;
-        }
-        if c == '\n' {
-            lines.push(&src[current_start..i]); // Add the entire line (excluding the newline character)
-            starting_next_line = true;
-        }
-    }
-
-    if src.ends_with('\n') {
-        lines.push("");
-    } else if !src[current_start..].is_empty() {
-        lines.push(&src[current_start..]);
-    }
-
-    lines
-}
-
 pub fn draw_rectangle<S: Write>(s: &mut S, color: &Color, origin: (u16, u16), size: (usize, usize)) {
     queue!(s, style::SetBackgroundColor(*color));
     for l in 0..size.1 {
@@ -46,7 +20,9 @@ pub fn draw_rectangle<S: Write>(s: &mut S, color: &Color, origin: (u16, u16), si
 }
 
 pub fn draw_thin_unfilled_rectangle<S: Write>(s: &mut S, fg_color: &Color, bg_color: &Color, origin: (u16, u16), size: (usize, usize)) {
-    assert!(size.0 >= 2);
+    if size.0 < 2 || crate::config::get().screen_reader {
+        return; // No room for the corners, or box drawing that would be read out as noise
+    }
     queue!(s, style::SetForegroundColor(*fg_color), style::SetBackgroundColor(*bg_color));
     for l in 0..size.1 {
         if l == 0 {
@@ -62,6 +38,87 @@ pub fn draw_thin_unfilled_rectangle<S: Write>(s: &mut S, fg_color: &Color, bg_co
     }
 }
 
+/// In screen reader mode, write a line of plain text over the bottom row of the screen, where
+/// it is read out as it appears. Does nothing otherwise.
+pub fn announce<S: Write>(s: &mut S, message: &str) {
+    if !crate::config::get().screen_reader {
+        return;
+    }
+    let h = crate::backend::size().1;
+    queue!(s,
+        cursor::MoveTo(0, h - 1), style::SetForegroundColor(Color::White), style::SetBackgroundColor(Color::Black),
+        terminal::Clear(terminal::ClearType::UntilNewLine), style::Print(sanitize(message)),
+    );
+}
+
+/// How many columns of a position some text takes up. Like scribe, we count graphemes: what
+/// reads as one character, like a letter with combining accents, is one column.
+pub fn columns(text: &str) -> usize {
+    // ASCII is a column a byte, but for a CRLF line ending
+    match text.is_ascii() && !text.contains("\r\n") {
+        true => text.len(),
+        false => text.graphemes(true).count(),
+    }
+}
+
+/// The columns of byte offsets into some text, given in order, going through it only once.
+pub fn columns_at(text: &str, offsets: impl Iterator<Item = usize>) -> Vec<usize> {
+    let mut starts = text.grapheme_indices(true).map(|(i, _)| i).peekable();
+    let mut column = 0;
+    offsets.map(|offset| {
+        while starts.next_if(|&start| start < offset).is_some() {
+            column += 1;
+        }
+        column
+    }).collect()
+}
+
+/// The line and column of a byte offset into some text.
+pub fn position_at(text: &str, byte_offset: usize) -> Position {
+    let before = &text[..byte_offset];
+    match before.rfind('\n') {
+        Some(i) => Position { line: before.matches('\n').count(), offset: columns(&before[i + 1..]) },
+        None => Position { line: 0, offset: columns(before) },
+    }
+}
+
+/// The byte offset of a line and column in some text, the opposite of `position_at`. Positions
+/// past the end of a line or the text are clamped to it.
+pub fn offset_at(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    // Up to an ASCII character, each byte is a column
+    if position.offset <= line.len() && line.as_bytes()[..(position.offset + 1).min(line.len())].is_ascii() {
+        return line_start + position.offset;
+    }
+    line_start + line.grapheme_indices(true).nth(position.offset).map_or(line.len(), |(i, _)| i)
+}
+
+/// A visible stand-in for a control character, which printed as it is could move the cursor or
+/// change the state of the terminal. Returns `None` for other characters, and for tabs and newlines.
+pub fn control_picture(c: char) -> Option<char> {
+    match c {
+        '\t' | '\n' => None,
+        '\0'..='\x1f' => char::from_u32(0x2400 + c as u32), // The Control Pictures block, like ␛ and ␀
+        '\x7f' => Some('␡'),
+        c if c.is_control() => Some('\u{fffd}'),
+        _ => None,
+    }
+}
+
+/// Text made safe to print in a dialog: control characters are shown by their `control_picture`
+/// and tabs become spaces. Newlines are kept.
+pub fn sanitize(text: &str) -> String {
+    text.chars().map(|c| if c == '\t' { ' ' } else { control_picture(c).unwrap_or(c) }).collect()
+}
+
 static ALERT_MIN_WIDTH: u16 = 25;
 static ALERT_MIN_HEIGHT: u16 = 5;
 
@@ -69,28 +126,25 @@ static ALERT_MIN_HEIGHT: u16 = 5;
 /// for a message they receive in a dialog box in the middle of the
 /// screen.
 pub fn alert<S: Write>(s: &mut S, title: &str, body: &str) {
-    let (w, h) = terminal::size().unwrap();
-
-    // Adjusted dimensions to fit the text
-    let msg_lines: Vec<String> = {
-        let mut v = Vec::new();
-        let two_thirds = ((2./3.) * w as f32) as usize;
-        for mut l in body.lines().map(|s| s.to_owned()) {
-            if l.len() > two_thirds { // If the string is too long,
-                // Put newlines to split it, then turn into lines and append those.
-                l = textwrap::fill(&l, two_thirds);
-
-                for l in l.lines() {
-                    v.push(l.to_owned()); // Add those two or more sub-lines
-                }
-            } else {
-                v.push(l);
-            }
-        }
-        v
-    };
+    let (w, h) = crate::backend::size();
+    let (title, body) = (&sanitize(&tr(title)), &sanitize(&tr(body)));
+
+    // Adjusted dimensions to fit the text, wrapped narrower than the screen
+    let wrap_width = ((2. / 3.) * w as f32).max(1.) as usize;
+    let mut msg_lines: Vec<String> = body.lines()
+        .flat_map(|l| match l.chars().count() > wrap_width {
+            true => textwrap::fill(l, wrap_width).lines().map(|l| l.to_owned()).collect(), // Split the long line into two or more sub-lines
+            false => vec![l.to_owned()],
+        })
+        .collect();
+    // Lines that do not fit on a short screen are left out, saying so on the last one shown
+    let max_lines = (h as usize).saturating_sub(ALERT_MIN_HEIGHT as usize).max(1);
+    if msg_lines.len() > max_lines {
+        msg_lines.truncate(max_lines);
+        msg_lines[max_lines - 1] = "…".to_owned();
+    }
 
-    let body_max_len = msg_lines.iter().map(|l| l.len()).max().unwrap();
+    let body_max_len = msg_lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
 
     // Calculating the dimensions of the dialog based on maximum line lengths and title length.
     let mut alert_w: usize = ALERT_MIN_WIDTH as usize
@@ -98,12 +152,13 @@ pub fn alert<S: Write>(s: &mut S, title: &str, body: &str) {
             0 => 0,
             val => val + 4, // Add some left and right padding to the body text.
         }) as usize;
-    alert_w = std::cmp::max(title.len() + 2, alert_w); // At least fit to title length (+ 2 for padding)
+    alert_w = std::cmp::max(title.chars().count() + 2, alert_w).min(w as usize); // At least fit to title length (+ 2 for padding), but not past the screen
+    let title: String = title.chars().take(alert_w).collect();
 
     let alert_h: usize = ALERT_MIN_HEIGHT as usize + msg_lines.len();
 
     // 'o' as in 'origin'
-    let o = (w/2 - alert_w as u16/2, h/2 - alert_h as u16/2); // Character cell of top left of dialog
+    let o = ((w / 2).saturating_sub(alert_w as u16 / 2), (h / 2).saturating_sub(alert_h as u16 / 2)); // Character cell of top left of dialog
 
     // Render a white header square
     draw_rectangle(s, &Color::White, o, (alert_w, 1));
@@ -112,30 +167,30 @@ pub fn alert<S: Write>(s: &mut S, title: &str, body: &str) {
     draw_rectangle(s, &Color::Grey, (o.0, o.1 + 1), (alert_w, alert_h - 1));
 
     queue!(s,
-        cursor::MoveTo(w/2 - title.len() as u16/2, o.1), style::SetForegroundColor(Color::Black), style::SetBackgroundColor(Color::White),
-        style::Print(title), style::SetBackgroundColor(Color::Grey),
+        cursor::MoveTo((w / 2).saturating_sub(title.chars().count() as u16 / 2), o.1), style::SetForegroundColor(Color::Black), style::SetBackgroundColor(Color::White),
+        style::Print(&title), style::SetBackgroundColor(Color::Grey),
     );
 
     // Write the message text
     for (i, l) in msg_lines.iter().enumerate() {
-        queue!(s, cursor::MoveTo(w/2 - l.len() as u16/2, o.1 + 2 + i as u16), style::Print(l));
+        queue!(s, cursor::MoveTo((w / 2).saturating_sub(l.chars().count() as u16 / 2), o.1 + 2 + i as u16), style::Print(l));
     }
     let msg_lines = msg_lines.len() as u16; // Shadow the old variable with just the number of lines in the message
 
     // Draw the button
-    let button = " OK ";
+    let button = format!(" {} ", tr("OK"));
     queue!(s,
-        cursor::MoveTo(w/2 - (button.len() as u16 + 2) / 2, o.1 + 3 + msg_lines),
+        cursor::MoveTo((w / 2).saturating_sub((button.chars().count() as u16 + 2) / 2), o.1 + 3 + msg_lines),
         style::PrintStyledContent(style::style(button).on(Color::White)),
     );
+    announce(s, &format!("{}: {} Press Enter.", title, body.replace('\n', " ")));
 
     s.flush().unwrap();
 
     // Get input
     loop {
-        match event::read().unwrap() {
-            Event::Key(KeyEvent { code: KeyCode::Enter, .. }) => break,
-            _ => {},
+        if let Event::Key(KeyEvent { code: KeyCode::Enter, .. }) = crate::backend::read() {
+            break;
         }
     }
 }
@@ -158,12 +213,16 @@ static PATH_INPUT_HEIGHT: usize = 6;
 /// Will block the thread waiting for string input from the user.
 /// Will only accept valid input.
 pub fn input<S: Write>(s: &mut S, title: &str, initial_input: String, ty: InputType) -> Option<String> { // NOTE: need parent access to re-render (make render trait?)
-    let (w, h) = terminal::size().unwrap();
+    let (w, h) = crate::backend::size();
 
+    let title = &sanitize(&tr(title));
     let mut entered_text = initial_input;
 
-    let dialog_width = std::cmp::max(title.len() + 2, PATH_INPUT_MIN_WIDTH);
-    let o = (w/2 - dialog_width as u16/2, h/2 - PATH_INPUT_HEIGHT as u16/2); // Character cell of top left of dialog
+    let dialog_width = std::cmp::max(title.chars().count() + 2, PATH_INPUT_MIN_WIDTH).min(w as usize).max(4);
+    let title: String = title.chars().take(dialog_width).collect();
+    let o = ((w / 2).saturating_sub(dialog_width as u16 / 2), (h / 2).saturating_sub(PATH_INPUT_HEIGHT as u16 / 2)); // Character cell of top left of dialog
+    announce(s, &format!("{}: type, then Enter, or Esc to cancel.", title));
+    crate::cursorshape::apply(s, crate::cursorshape::Mode::Menu);
 
     'mainloop: loop {
         // Render a white header square
@@ -182,32 +241,34 @@ pub fn input<S: Write>(s: &mut S, title: &str, initial_input: String, ty: InputT
 
         // Render title
         queue!(s,
-            cursor::MoveTo(w/2 - title.len() as u16/2, o.1),
+            cursor::MoveTo((w / 2).saturating_sub(title.chars().count() as u16 / 2), o.1),
             style::SetForegroundColor(Color::Black), style::SetBackgroundColor(Color::White),
-            style::Print(title),
+            style::Print(&title),
         ); // line 1
 
-        // Render current entered_text in input box
+        // Render current entered_text in input box, only its end if it is longer than the box
+        let shown: Vec<char> = sanitize(&entered_text).chars().collect();
+        let shown: String = shown[shown.len().saturating_sub(dialog_width - 4)..].iter().collect();
         queue!(s,
-            cursor::MoveTo(o.0 + 2, o.1 + 2), style::Print(&entered_text)
+            cursor::MoveTo(o.0 + 2, o.1 + 2), style::Print(&shown)
         );
 
         // Render actions
         queue!(s,
-            cursor::MoveTo(o.0 + 1, o.1 + 4), style::SetBackgroundColor(Color::Grey), style::Print("Cancel=ESCAPE")
+            cursor::MoveTo(o.0 + 1, o.1 + 4), style::SetBackgroundColor(Color::Grey), style::Print(format!("{}=ESCAPE", tr("Cancel")))
         );
         if !button_disabled {
-            let ok_button = "OK=RETURN";
-            queue!(s, cursor::MoveTo(o.0 + dialog_width as u16 - 1 - ok_button.len() as u16, o.1 + 4), style::Print(ok_button));
+            let ok_button = format!("{}=RETURN", tr("OK"));
+            queue!(s, cursor::MoveTo((o.0 + dialog_width as u16 - 1).saturating_sub(ok_button.chars().count() as u16), o.1 + 4), style::Print(ok_button));
         }
 
         // Set cursor position
-        queue!(s, cursor::MoveTo(o.0 + 2 + entered_text.len() as u16, o.1 + 2), cursor::Show);
+        queue!(s, cursor::MoveTo(o.0 + 2 + shown.chars().count() as u16, o.1 + 2), cursor::Show);
 
         s.flush().unwrap();
 
         // Get input
-        match event::read().unwrap() {
+        match crate::backend::read() {
             Event::Key(KeyEvent { code: KeyCode::Enter, .. }) if !button_disabled => return Some(entered_text),
             Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => break 'mainloop,
 
@@ -219,3 +280,171 @@ pub fn input<S: Write>(s: &mut S, title: &str, initial_input: String, ty: InputT
 
     None
 }
+
+static CHOOSE_MIN_WIDTH: usize = 28;
+
+/// Will block the thread waiting for the user to pick one of the items from a list, returning
+/// its index, or `None` if they cancelled. Typing narrows the list down to the items containing
+/// what was typed.
+pub fn choose<S: Write>(s: &mut S, title: &str, items: &[String], initial_selection: usize) -> Option<usize> {
+    let (w, h) = crate::backend::size();
+    let title = &sanitize(&tr(title));
+
+    let visible_items = items.len().min((h as usize).saturating_sub(8)).max(1);
+    let dialog_width = std::cmp::max(title.chars().count(), items.iter().map(|i| i.chars().count()).max().unwrap_or(0)).max(CHOOSE_MIN_WIDTH).min((w as usize).saturating_sub(4)).max(2) + 2;
+    let dialog_height = visible_items + 4;
+    let title: String = title.chars().take(dialog_width).collect();
+    let o = ((w / 2).saturating_sub(dialog_width as u16 / 2), (h / 2).saturating_sub(dialog_height as u16 / 2)); // Character cell of top left of dialog
+
+    let mut filter = String::new();
+    let mut shown: Vec<usize> = (0..items.len()).collect(); // Indices of the items matching the filter
+    let mut selection = initial_selection.min(items.len().saturating_sub(1)); // Index into `shown`
+    let mut scroll = selection.saturating_sub(visible_items - 1);
+    announce(s, &format!("{}: {} items. Up and Down choose, typing filters, Enter picks.", title, items.len()));
+    crate::cursorshape::apply(s, crate::cursorshape::Mode::Menu);
+
+    loop {
+        // Render a white header square, and a grey body
+        draw_rectangle(s, &Color::White, o, (dialog_width, 1));
+        draw_rectangle(s, &Color::Grey, (o.0, o.1 + 1), (dialog_width, dialog_height - 1));
+
+        queue!(s,
+            cursor::MoveTo((w / 2).saturating_sub(title.chars().count() as u16 / 2), o.1),
+            style::SetForegroundColor(Color::Black), style::SetBackgroundColor(Color::White),
+            style::Print(&title), style::SetBackgroundColor(Color::Grey),
+        );
+        if !filter.is_empty() {
+            let text: String = sanitize(&format!("{}: {}", tr("Filter"), filter)).chars().take(dialog_width - 2).collect();
+            queue!(s, cursor::MoveTo(o.0 + 1, o.1 + 1), style::Print(text));
+        }
+
+        // Render the visible part of the list, highlighting the selection
+        for (i, &item) in shown.iter().enumerate().skip(scroll).take(visible_items) {
+            let text: String = sanitize(&items[item]).chars().take(dialog_width - 2).collect();
+            queue!(s,
+                cursor::MoveTo(o.0 + 1, o.1 + 2 + (i - scroll) as u16),
+                style::SetBackgroundColor(if i == selection { Color::White } else { Color::Grey }),
+                style::Print(format!("{:<width$}", text, width = dialog_width - 2)),
+            );
+        }
+
+        // Render actions
+        let ok_button = format!("{}=RETURN", tr("OK"));
+        queue!(s,
+            cursor::MoveTo(o.0 + 1, o.1 + dialog_height as u16 - 1), style::SetBackgroundColor(Color::Grey), style::Print(format!("{}=ESCAPE", tr("Cancel"))),
+            cursor::MoveTo((o.0 + dialog_width as u16 - 1).saturating_sub(ok_button.chars().count() as u16), o.1 + dialog_height as u16 - 1), style::Print(ok_button),
+        );
+        // A screen reader follows the cursor, so it is left on the selected item for one
+        match crate::config::get().screen_reader && !shown.is_empty() {
+            true => queue!(s, cursor::MoveTo(o.0 + 1, o.1 + 2 + (selection - scroll) as u16), cursor::Show),
+            false => queue!(s, cursor::Hide),
+        };
+
+        s.flush().unwrap();
+
+        // Get input
+        let filter_changed = match crate::backend::read() {
+            Event::Key(KeyEvent { code: KeyCode::Enter, .. }) if !shown.is_empty() => return Some(shown[selection]),
+            Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => return None,
+            Event::Key(KeyEvent { code: KeyCode::Up, .. }) => { selection = selection.saturating_sub(1); false }
+            Event::Key(KeyEvent { code: KeyCode::Down, .. }) if selection + 1 < shown.len() => { selection += 1; false }
+            Event::Key(KeyEvent { code: KeyCode::PageUp, .. }) => { selection = selection.saturating_sub(visible_items); false }
+            Event::Key(KeyEvent { code: KeyCode::PageDown, .. }) => { selection = (selection + visible_items).min(shown.len().saturating_sub(1)); false }
+            Event::Key(KeyEvent { code: KeyCode::Char(c), modifiers, .. }) if !modifiers.contains(KeyModifiers::CONTROL) => { filter.push(c); true }
+            Event::Key(KeyEvent { code: KeyCode::Backspace, .. }) => filter.pop().is_some(),
+            _ => continue,
+        };
+
+        if filter_changed {
+            let needle = filter.to_lowercase();
+            shown = (0..items.len()).filter(|&i| items[i].to_lowercase().contains(&needle)).collect();
+            selection = 0;
+            scroll = 0;
+        }
+
+        // Keep the selection in view
+        if selection < scroll {
+            scroll = selection;
+        } else if selection >= scroll + visible_items {
+            scroll = selection + 1 - visible_items;
+        }
+    }
+}
+
+static BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard, padded base64 encoding.
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// The files whose paths a terminal typed in when they were dropped onto it: absolute paths, each
+/// quoted or with its spaces escaped as a shell would take them, or `file://` URLs. Empty unless
+/// every one of them is a file that exists.
+pub fn dropped_files(text: &str) -> Vec<PathBuf> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = text.trim().chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            // Windows paths are full of backslashes, so there they escape nothing
+            (None, '\\') if !cfg!(windows) => if let Some(c) = chars.next() {
+                word.get_or_insert_with(String::new).push(c);
+            },
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Vec::new();
+    }
+    words.extend(word);
+
+    let paths: Option<Vec<PathBuf>> = words.iter().map(|word| match word.strip_prefix("file://") {
+        Some(url) => percent_decode(url.strip_prefix("localhost").unwrap_or(url)).map(PathBuf::from),
+        None => Some(PathBuf::from(word)),
+    }).collect();
+    match paths {
+        Some(paths) if !paths.is_empty() && paths.iter().all(|path| path.is_absolute() && path.is_file()) => paths,
+        _ => Vec::new(),
+    }
+}
+
+// Text with its `%XX` escapes, as in a URL, turned back into what they stand for.
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&b, after)) = rest.split_first() {
+        match b {
+            b'%' => {
+                let hex = std::str::from_utf8(after.get(..2)?).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                rest = &after[2..];
+            }
+            b => {
+                bytes.push(b);
+                rest = after;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
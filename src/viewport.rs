@@ -1,7 +1,34 @@
-use crossterm::{*, style::Color, event::KeyEvent, event::KeyCode};
+use crossterm::{*, style::Color, event::KeyEvent, event::KeyCode, event::KeyModifiers};
+use scribe::buffer::{Cursor, Position, Range};
 
+use crate::abbreviations;
+use crate::brackets;
+use crate::breadcrumbs::{self, Segment};
+use crate::clipboard::Clipboard;
+use crate::completion::{self, Completion};
+use crate::config::WrapIndent;
+use crate::diff::{self, Change};
+use crate::document::{BufferStore, Document};
+use crate::history::Edit;
+use crate::indent::{self, Indentation};
+use crate::indentcheck;
+use crate::jumplist::{Jump, JumpList};
+use crate::lines::Lines;
+use crate::minimap::{self, Highlight};
+use crate::registers::Registers;
+use crate::search::Search;
+use crate::table::{self, Table};
+use crate::unsaved::{LineChange, UnsavedChanges};
+
+use std::cell::{RefCell, RefMut};
+use std::collections::BTreeMap;
 use std::io::Write;
-use std::cmp;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark, is_nfc};
+use unicode_segmentation::UnicodeSegmentation;
 
 // Helper functions because float min and max is used in this source file.
 
@@ -15,13 +42,248 @@ fn flt_min(a: f32, b: f32) -> f32 {
     if a > b { b } else { a }
 }
 
+/// Where a line breaks when wrapped to a width, as the ranges of columns each row has
+/// room for, with the rows after the first indented by so many columns. Rows break after the
+/// last space or tab that fits, or wherever they must in a long word. The last row always has
+/// room left for the cursor at the end of the line.
+fn wrap(line: &str, width: usize, indent: usize) -> Vec<(usize, usize)> {
+    let graphemes: Vec<&str> = line.graphemes(true).collect();
+    let mut rows = Vec::new();
+    let mut start = 0;
+    let mut room = width;
+    while graphemes.len() - start >= room {
+        let end = (start + 1..=start + room).rev().find(|&end| graphemes[end - 1] == " " || graphemes[end - 1] == "\t").unwrap_or(start + room);
+        rows.push((start, end));
+        start = end;
+        room = width - indent;
+    }
+    rows.push((start, start + room));
+    rows
+}
+
+/// The part of a line from one column up to one past another, with the column and the byte it
+/// starts at. The graphemes of the line before the part are only counted when it is not all
+/// ASCII up to there, where each byte is a column.
+fn in_view(line: &str, (start, end): (usize, usize)) -> (usize, usize, &str) {
+    let before = start.min(line.len());
+    let (column, skipped) = match line.as_bytes()[..(before + 1).min(line.len())].is_ascii() {
+        true => (before, before),
+        false => line.graphemes(true).take(start).fold((0, 0), |(column, skipped), g| (column + 1, skipped + g.len())),
+    };
+    let rest = &line[skipped..];
+    let shown = rest.graphemes(true).take(end + 1 - start).map(str::len).sum();
+    (column, skipped, &rest[..shown])
+}
+
 /// The different types a Viewport can be, and their associated data.
 pub enum ViewportData {
-    Buffer(Box<scribe::Buffer>),
+    /// A document, which other viewports may be showing too, and this viewport's own cursor in it.
+    Buffer(Rc<RefCell<Document>>, Cursor),
+    #[allow(dead_code)]
     Terminal(String),
+    /// A file being read in the background, with how much of it has been read so far in percent,
+    /// once that is known. A document takes its place when it has been read.
+    Loading(PathBuf, Option<usize>),
+    /// A picture rather than text.
+    Image(Box<crate::image::Image>),
+    /// A manual page, or a command's help, to read but not edit.
+    Page(Box<crate::manpage::Page>),
+    /// A file followed as lines are added to it, to read but not edit.
+    Tail(Box<crate::tail::Tail>),
 }
 use ViewportData::*;
 
+impl ViewportData {
+    /// Show a document in a viewport.
+    pub fn show(document: &Rc<RefCell<Document>>) -> ViewportData {
+        let cursor = document.borrow().buffer.cursor.clone();
+        Buffer(document.clone(), cursor)
+    }
+
+    /// Another view of the same document, starting with the cursor where this one has it.
+    pub fn share(&self) -> Option<ViewportData> {
+        match self {
+            Buffer(document, cursor) => Some(Buffer(document.clone(), cursor.clone())),
+            Terminal(_) | Loading(..) | Image(_) | Page(_) | Tail(_) => None,
+        }
+    }
+
+    /// Whether this and another viewport show the same document.
+    pub fn same_document(&self, other: &ViewportData) -> bool {
+        match (self, other) {
+            (Buffer(a, _), Buffer(b, _)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// The title of a viewport showing this, unless it was renamed.
+    pub fn title(&self) -> String {
+        match self {
+            Buffer(document, _) => document.borrow().name(),
+            Terminal(_) => "Terminal".to_owned(),
+            Loading(path, _) => path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned()),
+            Image(image) => image.path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            Page(page) => page.title.clone(),
+            Tail(tail) => format!("tail {}", tail.path.file_name().unwrap_or_default().to_string_lossy()),
+        }
+    }
+
+    /// Borrow the document to work on it, with this viewport's cursor as its buffer's cursor.
+    pub fn document(&mut self) -> Option<DocumentMut<'_>> {
+        let Buffer(document, cursor) = self else { return None };
+        let mut document = document.borrow_mut();
+        // Another viewport may have taken away the text the cursor was in
+        let position = cursor.position;
+        let text = document.buffer.data();
+        let lines = document.line_index.lines(&text);
+        if !lines.contains(position) {
+            cursor.move_to(Position { line: position.line.min(lines.len() - 1), offset: 0 });
+            cursor.move_to_end_of_line();
+        }
+        std::mem::swap(&mut document.buffer.cursor, cursor);
+        Some(DocumentMut { document, cursor })
+    }
+}
+
+/// A viewport's document, borrowed with the viewport's cursor put in its buffer. The cursor goes
+/// back to the viewport when this is dropped.
+pub struct DocumentMut<'a> {
+    document: RefMut<'a, Document>,
+    cursor: &'a mut Cursor,
+}
+
+impl Deref for DocumentMut<'_> {
+    type Target = Document;
+
+    fn deref(&self) -> &Document {
+        &self.document
+    }
+}
+
+impl DerefMut for DocumentMut<'_> {
+    fn deref_mut(&mut self) -> &mut Document {
+        &mut self.document
+    }
+}
+
+impl Drop for DocumentMut<'_> {
+    fn drop(&mut self) {
+        std::mem::swap(&mut self.document.buffer.cursor, self.cursor);
+    }
+}
+
+/// Just the buffer of a borrowed document, for those who have no business with the rest of it.
+pub struct BufferMut<'a>(DocumentMut<'a>);
+
+impl Deref for BufferMut<'_> {
+    type Target = scribe::Buffer;
+
+    fn deref(&self) -> &scribe::Buffer {
+        &self.0.buffer
+    }
+}
+
+impl DerefMut for BufferMut<'_> {
+    fn deref_mut(&mut self) -> &mut scribe::Buffer {
+        &mut self.0.buffer
+    }
+}
+
+/// A selection besides the main one. The anchor and cursor are the same when nothing is selected.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Selection {
+    pub anchor: Position,
+    pub cursor: Position,
+}
+
+/// How much is selected, over all the selections of a viewport.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SelectionSize {
+    pub selections: usize,
+    /// The lines with selected text in them. A selection ending at the start of a line does not
+    /// count that line.
+    pub lines: usize,
+    pub chars: usize,
+    /// How many columns wide the selections are, if they make up a block: one on each of a run
+    /// of lines, all over the same columns.
+    pub block_columns: Option<usize>,
+}
+
+/// Where `Viewport::scroll_cursor_line` puts the cursor's line in view.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LineAt {
+    Top,
+    Center,
+    Bottom,
+}
+
+/// How the viewports show their text, as toggled in the View menu.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ViewOptions {
+    pub line_numbers: bool,
+    /// Whether long lines go on over more rows, rather than off the side of the view.
+    pub word_wrap: bool,
+    /// Whether spaces and tabs are drawn, as `·` and `→`.
+    pub whitespace: bool,
+    /// How the rows a wrapped line goes on over are indented.
+    pub wrap_indent: WrapIndent,
+    /// Whether the tiled viewports take up the whole screen, with no frame, tabs, menu bar or
+    /// status bar around them.
+    pub zen: bool,
+    /// Whether buffers have an overview of the whole of them down their right side.
+    pub minimap: bool,
+    /// Whether the first lines of the scopes the top of the view is in stay pinned over it.
+    pub sticky_scope: bool,
+    /// Whether tiled viewports have a bar over them showing where the cursor is.
+    pub breadcrumbs: bool,
+}
+
+impl Default for ViewOptions {
+    fn default() -> ViewOptions {
+        let config = crate::config::get();
+        ViewOptions {
+            line_numbers: config.line_numbers,
+            word_wrap: config.word_wrap,
+            whitespace: config.show_whitespace,
+            wrap_indent: config.wrap_indent,
+            zen: false,
+            minimap: config.minimap,
+            sticky_scope: config.sticky_scope,
+            breadcrumbs: config.breadcrumbs,
+        }
+    }
+}
+
+/// The smallest a floating viewport can be made, with its frame.
+static MIN_FLOATING_SIZE: (usize, usize) = (20, 5);
+/// How many closed files are kept to reopen. The ones closed longest ago are forgotten first.
+static MAX_CLOSED_FILES: usize = 50;
+/// How many untitled buffers closed without saving are kept to get back, for the session.
+static MAX_DISCARDED: usize = 20;
+/// How many lines starting scopes can be pinned at the top of a view, the outermost ones first.
+static MAX_STICKY_LINES: usize = 3;
+
+/// Lines longer than this many bytes, like those of minified code, are only gone through as far
+/// as they are scrolled into view when they are not wrapped, and are not colored by their syntax
+/// or brackets.
+static LONG_LINE: usize = 10_000;
+
+/// Two viewports scrolled together, for comparing them: scrolling either one scrolls the other
+/// by as many lines.
+pub struct ScrollLink {
+    ids: (usize, usize),
+    // The first visible line of each, as of the last render.
+    starts: (usize, usize),
+}
+
+/// Two viewports compared side by side, the lines that differ between them colored: removed in
+/// the first, added in the second.
+pub struct Comparison {
+    ids: (usize, usize),
+    // The texts the differences were last worked out for.
+    texts: (String, String),
+}
+
 /// Like the representation of a terminal within a terminal. Viewports are what
 /// make up the editor as the individual windows. They are handled much like a
 /// game uses an entity-component-system. The system is the entire editor, the
@@ -30,9 +292,12 @@ use ViewportData::*;
 /// A Viewport's origin and size should not be modified by the Viewport itself.
 pub struct Viewport {
     // Common Viewport properties
+    pub id: usize, // Unique for the session, unlike the Viewport's index
     pub origin: (u16, u16),
     pub size: (usize, usize),
     pub title: String,
+    // Whether the title was given to it by hand, which keeps it rather than following its file.
+    pub renamed: bool,
 
     // What does this Viewport represent?
     pub data: ViewportData,
@@ -40,127 +305,931 @@ pub struct Viewport {
     // Used for scrolling the text, zero-based.
     pub starting_visible_line: usize,
     pub starting_visible_column: usize,
+
+    // The other end of the selection, which spans from here to the cursor.
+    pub selection_anchor: Option<Position>,
+    // More selections made with "select next occurrence", which are edited along with the main one.
+    pub extra_selections: Vec<Selection>,
+
+    // What the Tab key inserts, guessed from the file when it was opened.
+    pub indentation: Indentation,
+    // Whether it floats over the others in a window of its own, rather than taking up the whole
+    // space as a tab.
+    pub floating: bool,
+    // How to show the text, as set for every viewport.
+    pub view: ViewOptions,
+    // Whether it goes without its line numbers and marks, and when tiled without a frame, tabs or
+    // scrollbar, for the most room for the text.
+    pub compact: bool,
+    // The closing brackets put in after opening ones as they were typed, innermost last, which
+    // typing the same bracket steps over. Each is kept as its line and how many graphemes from
+    // the end of it it is, which typing in between the brackets leaves as it is.
+    auto_closed: Vec<(usize, usize)>,
+    // The abbreviation expanded by the last key, as the byte offset it starts at, what it expanded
+    // to and the word typed, which Backspace as the next key puts back.
+    expansion: Option<(usize, String, String)>,
+    // The names offered to finish the path being typed before the cursor.
+    pub completion: Option<Completion>,
+    // The lines differing from those of the viewport it is compared with, removed in this one or
+    // added in it.
+    differences: BTreeMap<usize, Change>,
+    // The text shown as a table of its delimited values instead, with the cell selected in it.
+    pub table: Option<Table>,
+    // The lines differing from the file as saved, while they are marked.
+    pub unsaved_changes: Option<UnsavedChanges>,
+    // The lines starting the scopes the top of the view is in, pinned over it as of the last
+    // render because they are scrolled out of view, outermost first.
+    sticky_lines: Vec<usize>,
+    // The segments of the bar over the view as of the last render, with the columns each is on.
+    breadcrumbs: Vec<(std::ops::Range<u16>, Segment)>,
+    // The folded ranges of lines as of the last render, from the line each is folded under to the
+    // last one hidden.
+    folds: Vec<(usize, usize)>,
+    // The selections grown from, innermost last, each with the one it was grown to. Narrowing the
+    // selection goes back through them while it is still the one grown to.
+    grown_selections: Vec<(Selection, Selection)>,
 }
 
 impl Viewport {
-    /// Render the Viewport, ready or not.
-    pub fn render<S: Write>(&mut self, s: &mut S, focused: bool) {
+    /// Render the Viewport, ready or not, highlighting the matches of a search.
+    pub fn render<S: Write>(&mut self, s: &mut S, focused: bool, highlight: Option<&Search>) {
+        if let (Some(table), Buffer(document, _)) = (self.table.as_mut(), &self.data) {
+            let records = table::parse(&document.borrow().buffer.data(), table.delimiter);
+            table.render(s, &records, self.origin, (self.size.0.saturating_sub(1), self.size.1.saturating_sub(1)), focused);
+            match table.cursor_cell() {
+                Some((x, y)) if focused => queue!(s, cursor::MoveTo(x, y), cursor::Show),
+                None if focused => queue!(s, cursor::Hide),
+                _ => Ok(()),
+            };
+            return;
+        }
         match self.data {
-            Buffer(ref buffer) => {
+            Buffer(ref document, ref cursor) => {
+                let shared = Rc::strong_count(document) > 2; // The store's, ours and another viewport's
+                let mut document = document.borrow_mut();
+                let document = &mut *document;
+                let buffer = &document.buffer;
+                let cursor = cursor.position;
                 queue!(s, style::SetBackgroundColor(Color::Blue), style::SetForegroundColor(Color::Grey));
 
+                // Another viewport may have changed the text under the selections
+                let buf_data = buffer.data();
+                if shared {
+                    let clamp = |p: Position| crate::util::position_at(&buf_data, crate::util::offset_at(&buf_data, p));
+                    self.selection_anchor = self.selection_anchor.map(clamp);
+                    for sel in self.extra_selections.iter_mut() {
+                        *sel = Selection { anchor: clamp(sel.anchor), cursor: clamp(sel.cursor) };
+                    }
+                }
+
                 // Update cursor and scrolling (cursor rendering happens at the end)
+                let delimiter = document.delimiter();
+                let all_lines = document.line_index.lines(&buf_data);
+                let line_num_digits = self.line_number_digits(all_lines.len()); // Number of digits in the highest line number
+                let rows = self.size.1.saturating_sub(1);
+                // The cursor's line is never folded away
+                document.marks.unfold_around(cursor.line);
+                self.folds = document.marks.folds();
                 if focused {
-                    // Update the cursor: are we out of view and in need of vertical scrolling?
-                    if buffer.cursor.line >= self.starting_visible_line { // Only when it's safe to subtract...
-                        if buffer.cursor.line - self.starting_visible_line > self.size.1 - self.origin.1 as usize { // If buffer's cursor is beyond the visible lines
-                            self.starting_visible_line += buffer.cursor.line - (self.starting_visible_line + (self.size.1 - self.origin.1 as usize)); // Set visible lines to show at least that line
-                        }
-                    } else { // We need to scroll up, if the cursor is above the minimum visible line
-                        self.starting_visible_line = self.starting_visible_line - (self.starting_visible_line - buffer.cursor.line);
+                    // Update the cursor: are we out of view and in need of vertical scrolling? A margin of
+                    // lines is kept around the cursor, but never more than half the view, and not past
+                    // the end of the buffer.
+                    let margin = self.scroll_margin();
+                    let margin_below = margin.min(all_lines.len().saturating_sub(cursor.line + 1));
+                    let lines_above = self.shown_lines_between(self.starting_visible_line, cursor.line);
+                    if cursor.line < self.starting_visible_line || lines_above < margin {
+                        self.starting_visible_line = self.shown_line_above(cursor.line, margin);
+                    } else if lines_above + margin_below >= rows {
+                        self.starting_visible_line = self.shown_line_above(cursor.line, rows.saturating_sub(1 + margin_below));
                     }
 
-                    // Update the cursor: are we out of view horizontally and need to scroll?
-                    if buffer.cursor.offset >= self.starting_visible_column {
-                        if buffer.cursor.offset - self.starting_visible_column > self.size.0 - 5 - self.origin.0 as usize { // If buffer's cursor is beyond the visible columns
-                            self.starting_visible_column += buffer.cursor.offset - (self.starting_visible_column + (self.size.0 - 5 - self.origin.0 as usize)); // Set visible columns to show at least that column
+                    if self.view.word_wrap {
+                        // Wrapped lines take up more than a row, so the cursor can still be below the view
+                        self.starting_visible_column = 0;
+                        while self.starting_visible_line < cursor.line && self.cell_of(&all_lines, line_num_digits, cursor).is_some_and(|(_, row)| row >= rows) {
+                            self.starting_visible_line += 1;
+                        }
+                    } else if cursor.offset >= self.starting_visible_column { // Update the cursor: are we out of view horizontally and need to scroll?
+                        let visible_columns = self.wrap_width(line_num_digits) - 1;
+                        if cursor.offset - self.starting_visible_column > visible_columns { // If buffer's cursor is beyond the visible columns
+                            self.starting_visible_column += cursor.offset - (self.starting_visible_column + visible_columns); // Set visible columns to show at least that column
                         }
                     } else { // We need to scroll left, if the cursor is to the left of the minimum visible line
-                        self.starting_visible_column = self.starting_visible_column - (self.starting_visible_column - buffer.cursor.offset);
+                        self.starting_visible_column = self.starting_visible_column - (self.starting_visible_column - cursor.offset);
                     }
                 }
 
                 // Gather the line numbers for the visible portion of the screen.
-                let buf_data = buffer.data();
-                // let mut lines: Vec<(usize, &str)> = scribe::util::LineIterator::new(&buf_data).skip(self.starting_visible_line).take(self.size.1 - 1).collect();
-                let lines: Vec<&str> = crate::util::lines(&buf_data).into_iter().skip(self.starting_visible_line).take(self.size.1 - 1).collect();
-                // lines.push((lines.len(), ""));
-                // let line_num_digits = lines.iter().map(|(n, _)| n + 1).max().unwrap_or(1).to_string().len();
-                let line_num_digits = lines.len().to_string().len(); // Number of digits in the highest line number
-
-                // Render the lines from the text
-                for (i, l) in lines.iter().enumerate() {
-                    let mut l: String = l.to_string();
-
-                    if self.starting_visible_column > l.len().saturating_sub(1) {
-                        continue; // We don't want to render an empty line (nor index one!)
-                    } else {
-                        // The line fits within view, so we need to trim it down based on how far we've scrolled right
-                        let line_length = l.len() - self.starting_visible_column;
-                        let chars = l.chars().skip(self.starting_visible_column);
-                        l = if line_length >= self.size.0 - 5 {
-                            chars.take(cmp::min(l.len()-1, self.size.0 - 5 - 1)).collect() // Cut either the entire line, or whatever can fit within view
-                        } else {
-                            chars.collect()
-                        }
+                // Folded lines take up no rows, so the lines can go on to the end of the text
+                let lines = (self.starting_visible_line..all_lines.len()).map(|line| all_lines.line(line));
+                let (bracket_colors, syntax_colors, marker_color, color_swatches, rainbow_columns, screen_reader, inline_diagnostics, line_length) = {
+                    let config = crate::config::get();
+                    (config.bracket_colors, config.syntax_colors, config.marker_color, config.color_swatches, config.rainbow_columns, config.screen_reader, config.inline_diagnostics, config.line_length(document.buffer.path.as_deref()))
+                };
+                // Lines of Python and YAML indented against the buffer's indentation are marked
+                let indentation_problems = match indentcheck::tabs_allowed(document.buffer.path.as_deref()) {
+                    Some(tabs) if crate::config::get().indentation_warnings => indentcheck::problems(&all_lines, self.indentation, tabs),
+                    _ => Default::default(),
+                };
+                let show_whitespace = self.view.whitespace;
+                let selection = self.selection();
+                let extra_ranges: Vec<Range> = self.extra_selections.iter().filter(|sel| sel.anchor != sel.cursor).map(|sel| Range::new(sel.anchor, sel.cursor)).collect();
+                let extra_cursors: Vec<Position> = if focused { self.extra_selections.iter().map(|sel| sel.cursor).collect() } else { Vec::new() };
+                // The match that is selected is the current one, which stands out from the others
+                let matches = highlight.map(|search| search.find_all(&buf_data)).unwrap_or_default();
+                let current_match = selection.as_ref().map(|r| (crate::util::offset_at(&buf_data, r.start()), crate::util::offset_at(&buf_data, r.end())));
+                let mut line_start = all_lines.start(self.starting_visible_line);
+                // Delimited values are colored by the column they are in
+                let fields = match delimiter.filter(|_| rainbow_columns) {
+                    Some(delimiter) => table::fields_by_column(table::parse(&buf_data, delimiter)),
+                    None => Vec::new(),
+                };
+
+                // Render the lines from the text, each on as many rows as it is wrapped to
+                let mut row = 0;
+                let mut lines_shown = 0;
+                for (i, l) in lines.enumerate() {
+                    if row >= rows {
+                        break;
+                    }
+                    let line_index = self.starting_visible_line + i;
+                    if self.is_hidden(line_index) {
+                        line_start += l.len() + 1;
+                        continue;
                     }
+                    lines_shown = i + 1;
+                    let line_rows = self.line_rows(l, line_num_digits);
+                    // Of a long line, only the part in view is looked at: from the column it starts
+                    // at, and the byte of the text
+                    let long = !self.view.word_wrap && l.len() > LONG_LINE;
+                    let next_line_start = line_start + l.len() + 1;
+                    let (first, window_start, l) = match long {
+                        true => {
+                            let (column, skipped, shown) = in_view(l, line_rows[0]);
+                            (column, line_start + skipped, shown)
+                        }
+                        false => (0, line_start, l),
+                    };
+                    let graphemes: Vec<&str> = l.graphemes(true).collect();
+                    // How many columns the line has, or more than are in view for a long line going on past them
+                    let length = first + graphemes.len();
+                    let (bracket_colors, syntax_colors) = (bracket_colors && !long, syntax_colors && !long);
+                    let folded = self.folds.iter().any(|&(first, _)| first == line_index);
+                    let continuation_indent = self.continuation_indent(l, line_num_digits);
+
+                    // Lines differing from the viewport this one is compared with are colored through, as
+                    // are those changed since the file was saved while they are marked
+                    let unsaved = self.unsaved_changes.as_ref().and_then(|unsaved| unsaved.get(line_index));
+                    let background = match self.differences.get(&line_index) {
+                        Some(Change::Removed) => Color::DarkRed,
+                        Some(Change::Added) => Color::DarkGreen,
+                        _ => unsaved.and_then(LineChange::background).unwrap_or(Color::Blue),
+                    };
+                    let text_colors = (background, if focused { Color::White } else { Color::Grey });
+                    let mut colors = text_colors;
+                    let markers: Vec<(usize, usize)> = crate::todos::in_line(l).into_iter().map(|(start, len)| (first + start, len)).collect();
+                    let swatches: Vec<_> = match color_swatches {
+                        true => crate::swatches::in_line(l).into_iter().map(|(start, len, colors)| (first + start, len, colors)).collect(),
+                        false => Vec::new(),
+                    };
+                    let syntax = if syntax_colors { document.syntax.line_spans(&all_lines, line_index) } else { Vec::new() };
+                    // The matches on this line, as columns of it
+                    let line_matches: Vec<(usize, usize, bool)> = matches.iter()
+                        .filter(|&&(start, end)| start < window_start + l.len() && end > window_start)
+                        .map(|&(start, end)| (
+                            first + crate::util::columns(&l[..start.saturating_sub(window_start)]),
+                            first + crate::util::columns(&l[..(end - window_start).min(l.len())]),
+                            current_match == Some((start, end)),
+                        ))
+                        .collect();
+                    // The fields on this line, as columns of it, with the column of the table each is in
+                    let line_fields: Vec<(usize, usize, usize)> = fields[fields.partition_point(|(field, _)| field.end < window_start)..].iter()
+                        .take_while(|(field, _)| field.start <= window_start + l.len())
+                        .map(|(field, column)| (
+                            first + crate::util::columns(&l[..field.start.saturating_sub(window_start)]),
+                            first + crate::util::columns(&l[..(field.end - window_start).min(l.len())]),
+                            *column,
+                        ))
+                        .collect();
+                    line_start = next_line_start;
+                    let mut depth = match bracket_colors {
+                        true => graphemes[..line_rows[0].0.min(graphemes.len())].iter().flat_map(|g| g.chars()).fold(document.bracket_depths.at_line(&all_lines, line_index), brackets::step),
+                        false => 0,
+                    };
+
+                    let first_row = row;
+                    for (part, &(row_start, row_end)) in line_rows.iter().enumerate() {
+                        if row >= rows {
+                            break;
+                        }
+                        queue!(s, cursor::MoveTo(self.origin.0, self.origin.1 + row as u16));
+                        row += 1;
+                        if focused {
+                            queue!(s, style::SetForegroundColor(Color::White));
+                        }
+                        // The line number and the gutter after it, which shows bookmarks, named marks, folds,
+                        // indentation warnings and unsaved changes, are only on the first row of a line. The rows it wraps onto have a mark of
+                        // their own in the gutter instead, and start as far in as they are indented. In
+                        // compact mode there is no gutter.
+                        colors = text_colors;
+                        queue!(s, style::SetBackgroundColor(colors.0));
+                        if part > 0 {
+                            if !self.compact {
+                                queue!(s,
+                                    style::Print(" ".repeat(line_num_digits)), style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print(if screen_reader { ' ' } else { '↪' }),
+                                );
+                            }
+                            queue!(s, style::Print(" ".repeat(continuation_indent)));
+                        } else if !self.compact {
+                            if line_num_digits > 0 {
+                                queue!(s, style::Print(format!("{:>digits$}", line_index + 1, digits = line_num_digits))); // Print the line number
+                            }
+                            if document.marks.is_bookmarked(line_index) {
+                                queue!(s, style::SetForegroundColor(Color::Yellow), style::Print('►'));
+                            } else if let Some(name) = document.marks.mark_on_line(line_index) {
+                                queue!(s, style::SetForegroundColor(Color::Yellow), style::Print(name));
+                            } else if folded {
+                                queue!(s, style::SetForegroundColor(Color::DarkGrey), style::Print('▸'));
+                            } else if indentation_problems.contains_key(&line_index) {
+                                queue!(s, style::SetForegroundColor(Color::DarkYellow), style::Print('!'));
+                            } else if let Some((mark, color)) = unsaved.map(LineChange::mark) {
+                                queue!(s, style::SetForegroundColor(color), style::Print(mark));
+                            } else {
+                                queue!(s, style::Print(' '));
+                            }
+                        }
+                        queue!(s, style::SetForegroundColor(if focused { Color::White } else { Color::Grey }));
 
-                    let line_number_fmt = format!("{:>digits$}", i + 1, digits = line_num_digits);
-                    queue!(s, cursor::MoveTo(self.origin.0, self.origin.1 + (i - self.starting_visible_line) as u16));
-                    if focused {
-                        queue!(s, style::SetForegroundColor(Color::White));
+                        // Print whatever part of the line is on this row, each grapheme in a cell of its own
+                        for (offset, &grapheme) in (first..).zip(&graphemes).take(row_end - first).skip(row_start - first) {
+                            let c = grapheme.chars().next().unwrap();
+                            let position = Position { line: line_index, offset };
+                            let in_match = line_matches.iter().find(|&&(start, end, _)| offset >= start && offset < end).map(|m| m.2);
+                            let mut wanted = if extra_cursors.contains(&position) {
+                                (Color::White, Color::Blue)
+                            } else if in_match == Some(true) {
+                                (Color::Yellow, Color::Black)
+                            } else if selection.as_ref().is_some_and(|r| r.includes(&position)) || extra_ranges.iter().any(|r| r.includes(&position)) {
+                                (Color::Grey, Color::Blue)
+                            } else if in_match == Some(false) {
+                                (Color::DarkCyan, Color::Black)
+                            } else {
+                                text_colors
+                            };
+                            if let Some(&(_, _, swatch)) = swatches.iter().find(|&&(start, len, _)| offset >= start && offset < start + len) {
+                                if wanted == text_colors {
+                                    wanted = swatch;
+                                }
+                            }
+                            if wanted == text_colors && markers.iter().any(|&(start, len)| offset >= start && offset < start + len) {
+                                wanted = (marker_color, Color::Black);
+                            }
+                            // Characters past the line length limit are red over any syntax or column colors
+                            if wanted.0 == text_colors.0 && line_length.is_some_and(|limit| offset >= limit) {
+                                wanted.1 = Color::Red;
+                            } else if wanted == text_colors {
+                                if let Some(&(_, _, kind)) = syntax.iter().find(|&&(start, end, _)| offset >= start && offset < end) {
+                                    wanted.1 = kind.color();
+                                } else if let Some(&(_, _, column)) = line_fields.iter().find(|&&(start, end, _)| offset >= start && offset < end) {
+                                    wanted.1 = table::column_color(column);
+                                }
+                            }
+                            // Brackets are colored by depth, the closing one of a pair the same as the opening one
+                            if bracket_colors {
+                                let next_depth = brackets::step(depth, c);
+                                if next_depth != depth && wanted == text_colors {
+                                    wanted.1 = brackets::color(next_depth.min(depth));
+                                }
+                                depth = next_depth;
+                            }
+                            // Control characters would mess with the terminal, so they are shown by a picture of
+                            // them in another color instead. The carriage return of a CRLF line ending is not shown.
+                            let picture = crate::util::control_picture(c);
+                            let mut shown = String::new();
+                            match picture {
+                                Some(_) if c == '\r' && offset + 1 == length => shown.push(' '),
+                                Some(picture) => {
+                                    wanted.1 = Color::Red;
+                                    shown.push(picture);
+                                }
+                                None if show_whitespace && (c == ' ' || c == '\t') => {
+                                    if wanted == text_colors {
+                                        wanted.1 = Color::DarkGrey;
+                                    }
+                                    shown.push(if c == ' ' { '·' } else { '→' });
+                                }
+                                None => shown.push_str(grapheme),
+                            }
+                            if wanted != colors {
+                                colors = wanted;
+                                queue!(s, style::SetBackgroundColor(colors.0), style::SetForegroundColor(colors.1));
+                            }
+                            queue!(s, style::Print(shown));
+                        }
+                    }
+                    // An extra cursor at the end of the line has no character to show it on
+                    let end_of_line = Position { line: line_index, offset: length };
+                    let (last_start, last_end) = line_rows[line_rows.len() - 1];
+                    let cursor_at_end = row - first_row == line_rows.len() && end_of_line.offset >= last_start && end_of_line.offset < last_end && extra_cursors.contains(&end_of_line);
+                    if cursor_at_end {
+                        colors = (Color::White, Color::Blue);
+                        queue!(s, style::SetBackgroundColor(colors.0), style::SetForegroundColor(colors.1), style::Print(' '));
+                    }
+                    // What is drawn after the text of the line goes as far as the row has room for it
+                    let last_row_shown = row - first_row == line_rows.len();
+                    let indent = if line_rows.len() > 1 { continuation_indent } else { 0 };
+                    let mut used = indent + length.min(last_end).saturating_sub(last_start) + cursor_at_end as usize;
+                    // A line with others folded under it ends in an ellipsis standing for them
+                    if folded && last_row_shown && used + 2 <= self.wrap_width(line_num_digits) {
+                        colors = (Color::Blue, Color::DarkGrey);
+                        queue!(s, style::SetBackgroundColor(colors.0), style::SetForegroundColor(colors.1), style::Print(" ⋯"));
+                        used += 2;
+                    }
+                    // The first diagnostic on the line, or else what is wrong with its indentation, goes
+                    // dimmed after its text, as much of it as fits
+                    let message = document.marks.diagnostic(line_index).or_else(|| indentation_problems.get(&line_index).map(String::as_str));
+                    if let Some(message) = message.filter(|_| inline_diagnostics && last_row_shown) {
+                        let message: String = crate::util::sanitize(message).chars().take(self.wrap_width(line_num_digits).saturating_sub(used + 2)).collect();
+                        if !message.is_empty() {
+                            colors = (Color::Blue, Color::DarkGrey);
+                            queue!(s, style::SetBackgroundColor(colors.0), style::SetForegroundColor(colors.1), style::Print("  "), style::Print(&message));
+                            used += 2 + message.chars().count();
+                        }
+                    }
+                    // A line differing from the one compared with is colored to the end of the row
+                    if background != Color::Blue && last_row_shown {
+                        colors = text_colors;
+                        queue!(s, style::SetBackgroundColor(colors.0), style::Print(" ".repeat(self.wrap_width(line_num_digits).saturating_sub(used))));
                     }
-                    queue!(s, style::Print(format!("{} {}", line_number_fmt, l))); // Print the line number and line
+                    if colors != text_colors || background != Color::Blue {
+                        queue!(s, style::SetBackgroundColor(Color::Blue), style::SetForegroundColor(Color::Grey));
+                    }
+                }
+
+                // The first lines of the scopes the top of the view is in, pinned over it
+                let cursor_row = self.cell_of(&all_lines, line_num_digits, cursor).map(|(_, row)| row).filter(|_| focused);
+                self.sticky_lines = self.sticky_scope(&all_lines, cursor_row);
+                for (row, &line) in self.sticky_lines.iter().enumerate() {
+                    let text: String = crate::util::sanitize(&all_lines[line]).graphemes(true)
+                        .skip(if self.view.word_wrap { 0 } else { self.starting_visible_column })
+                        .take(self.wrap_width(line_num_digits))
+                        .collect();
+                    let gutter = match self.compact {
+                        true => String::new(),
+                        false => format!("{:>digits$} ", if line_num_digits > 0 { (line + 1).to_string() } else { String::new() }, digits = line_num_digits),
+                    };
+                    queue!(s,
+                        cursor::MoveTo(self.origin.0, self.origin.1 + row as u16), style::SetBackgroundColor(Color::DarkBlue),
+                        style::SetForegroundColor(Color::DarkGrey), style::Print(gutter),
+                        style::SetForegroundColor(if focused { Color::White } else { Color::Grey }),
+                        style::Print(format!("{:<1$}", text, self.wrap_width(line_num_digits))),
+                    );
+                }
+                if !self.sticky_lines.is_empty() {
+                    queue!(s, style::SetBackgroundColor(Color::Blue), style::SetForegroundColor(Color::Grey));
+                }
+
+                if self.minimap_columns() > 0 {
+                    let match_lines: Vec<usize> = matches.iter().map(|&(start, _)| buf_data[..start].matches('\n').count()).collect();
+                    let marks = &document.marks;
+                    let highlight = |line: usize| if match_lines.binary_search(&line).is_ok() {
+                        Some(Highlight::Match)
+                    } else if line == cursor.line {
+                        Some(Highlight::Cursor)
+                    } else if marks.is_bookmarked(line) || marks.mark_on_line(line).is_some() {
+                        Some(Highlight::Bookmark)
+                    } else if marks.is_changed(line) {
+                        Some(Highlight::Change)
+                    } else if !crate::todos::in_line(&all_lines[line]).is_empty() {
+                        Some(Highlight::Marker)
+                    } else {
+                        None
+                    };
+                    let shown = self.starting_visible_line..self.starting_visible_line + lines_shown;
+                    minimap::render(s, self.minimap_origin(), rows, &all_lines, shown, highlight);
+                    queue!(s, style::SetBackgroundColor(Color::Blue), style::SetForegroundColor(Color::Grey));
                 }
 
                 if focused {
                     // Render the cursor
-                    queue!(s, cursor::MoveTo(
-                            self.origin.0 + line_num_digits as u16 + (buffer.cursor.position.offset - self.starting_visible_column) as u16 + 1,
-                            self.origin.1 + (buffer.cursor.position.line - self.starting_visible_line) as u16,
-                        ),
-                        cursor::Show,
-                    );
-                    let v = format!("{}", buffer.cursor.position.line);
-                    execute!(s, terminal::SetTitle(&v));
+                    if let Some((x, y)) = self.cursor_cell_with(&all_lines, line_num_digits) {
+                        queue!(s, cursor::MoveTo(x, y), cursor::Show);
+                    }
                 }
             }
             Terminal(ref _lines) => unimplemented!(),
+            Loading(ref path, percent) => {
+                let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+                let text = match percent {
+                    Some(percent) => format!("{} {}… {}%", crate::locale::tr("Loading"), name, percent),
+                    None => format!("{} {}…", crate::locale::tr("Loading"), name),
+                };
+                let text: String = crate::util::sanitize(&text).chars().take(self.size.0).collect();
+                let x = self.origin.0 + (self.size.0.saturating_sub(text.chars().count()) / 2) as u16;
+                queue!(s,
+                    cursor::MoveTo(x, self.origin.1 + (self.size.1 / 2).saturating_sub(1) as u16),
+                    style::SetBackgroundColor(Color::Blue), style::SetForegroundColor(Color::Grey), style::Print(text),
+                );
+            }
+            Image(ref mut image) => image.render(s, self.origin, (self.size.0, self.size.1.saturating_sub(1))),
+            Page(ref mut page) => {
+                page.render(s, self.origin, (self.size.0.saturating_sub(1), self.size.1.saturating_sub(1)), focused);
+                if focused {
+                    queue!(s, cursor::Hide);
+                }
+            }
+            Tail(ref mut tail) => {
+                tail.render(s, self.origin, (self.size.0.saturating_sub(1), self.size.1.saturating_sub(1)), focused);
+                if focused {
+                    queue!(s, cursor::Hide);
+                }
+            }
         }
     }
 
     // TODO: get rid of this later
-    pub fn get_buffer(&mut self) -> Option<&mut scribe::Buffer> {
-        if let Buffer(buf) = &mut self.data {
-            Some(buf)
-        } else {
-            None
+    pub fn get_buffer(&mut self) -> Option<BufferMut<'_>> {
+        self.data.document().map(BufferMut)
+    }
+
+    /// The document the viewport shows, borrowed to work on.
+    pub fn document(&mut self) -> Option<DocumentMut<'_>> {
+        self.data.document()
+    }
+
+    /// Where the viewport's cursor is.
+    pub fn cursor_position(&self) -> Option<Position> {
+        match &self.data {
+            Buffer(_, cursor) => Some(cursor.position),
+            Terminal(_) | Loading(..) | Image(_) | Page(_) | Tail(_) => None,
+        }
+    }
+
+    /// The selected range of text, if anything is selected.
+    pub fn selection(&self) -> Option<Range> {
+        match (self.cursor_position(), self.selection_anchor) {
+            (Some(cursor), Some(anchor)) if anchor != cursor => Some(Range::new(anchor, cursor)),
+            _ => None,
+        }
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        match &self.data {
+            Buffer(document, _) => self.selection().and_then(|range| document.borrow().buffer.read(&range)),
+            Terminal(_) | Loading(..) | Image(_) | Page(_) | Tail(_) => None,
+        }
+    }
+
+    /// Whether the buffer has changes that are not saved to disk.
+    pub fn is_modified(&self) -> bool {
+        match &self.data {
+            Buffer(document, _) => document.borrow().is_modified(),
+            Terminal(_) | Loading(..) | Image(_) | Page(_) | Tail(_) => false,
+        }
+    }
+
+    /// Mark the lines that differ from the file as saved, or stop marking them. Fails if there
+    /// is no file, or it cannot be read.
+    pub fn toggle_unsaved_changes(&mut self) -> std::io::Result<()> {
+        if self.unsaved_changes.take().is_some() {
+            return Ok(());
+        }
+        let Buffer(document, _) = &self.data else { return Ok(()) };
+        let document = document.borrow();
+        let Some(path) = &document.buffer.path else {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "it has no file yet"));
+        };
+        let saved = match document.is_modified() {
+            true => std::fs::read_to_string(path)?,
+            false => document.buffer.data(),
+        };
+        self.unsaved_changes = Some(UnsavedChanges::new(saved));
+        Ok(())
+    }
+
+    /// Work out again which lines differ from the file as saved, if they are marked.
+    fn mark_unsaved_changes(&mut self) {
+        let (Some(unsaved), Buffer(document, _)) = (&mut self.unsaved_changes, &self.data) else { return };
+        let document = document.borrow();
+        unsaved.update(&document.buffer.data(), document.is_modified());
+    }
+
+    /// Remember that the buffer was just saved to disk.
+    pub fn mark_saved(&mut self) {
+        if let Some(mut document) = self.data.document() {
+            document.mark_saved();
+            if let Some(path) = document.buffer.path.clone() {
+                // Saving under another name can make it another language
+                document.syntax = crate::syntax::Syntax::new(Some(&path));
+            }
+        }
+    }
+
+    /// Record the buffer as it is now in the undo history. Call this once an edit is complete.
+    fn edited(&mut self) {
+        if let Some(mut document) = self.data.document() {
+            document.edited();
+        }
+    }
+
+    /// Delete the selected text, leaving the cursor where it began. Returns false if nothing was selected.
+    pub fn delete_selection(&mut self) -> bool {
+        let deleted = self.delete_selected_text();
+        self.edited();
+        deleted
+    }
+
+    fn delete_selected_text(&mut self) -> bool {
+        let selection = self.selection();
+        self.selection_anchor = None;
+        match (self.data.document(), selection) {
+            (Some(mut document), Some(range)) => {
+                document.buffer.delete_range(range.clone());
+                document.buffer.cursor.move_to(range.start());
+                document.marks.lines_changed(range.start().line, range.start().line as isize - range.end().line as isize);
+                document.marks.record_change(range.start());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Insert pasted text like `insert_str`, with its lines re-indented to the line it goes into
+    /// when `paste_reindent` is on. Text pasted into several selections goes in as it is.
+    pub fn paste(&mut self, text: &str) {
+        let (Some(data), Some(cursor)) = (self.text(), self.cursor_position()) else { return self.insert_str(text) };
+        if !text.contains('\n') || !self.extra_selections.is_empty() || self.table.is_some() || !crate::config::get().paste_reindent {
+            return self.insert_str(text);
+        }
+        let at = self.selection().map_or(cursor, |range| range.start());
+        let line = Lines::new(&data).line(at.line);
+        let line_start = crate::util::offset_at(&data, Position { line: at.line, offset: 0 });
+        let before = &data[line_start..crate::util::offset_at(&data, at)];
+        let indent = &line[..line.len() - line.trim_start().len()];
+        self.insert_str(&crate::indent::reindent(text, before, indent));
+    }
+
+    /// Insert a string at the current cursor position or selection, leaving the cursor after it.
+    /// In a table, it becomes the value of the selected cell.
+    pub fn insert_str(&mut self, text: &str) {
+        if self.table.is_some() {
+            return self.set_cell(text.trim_end_matches(['\n', '\r']));
+        }
+        let normalized;
+        let text = match crate::config::get().normalize_input {
+            true => {
+                normalized = self.normalize_input(text);
+                normalized.as_str()
+            }
+            false => text,
+        };
+        if !self.extra_selections.is_empty() {
+            return self.edit_every_selection(|_, start, end| (start, end, text.to_owned()));
+        }
+        self.delete_selected_text();
+        match self.data.document() {
+            Some(mut document) => {
+                // Where the text ends is counted in the text it is put into, as what is typed may join
+                // up with the graphemes either side of it
+                let start = crate::util::offset_at(&document.buffer.data(), *document.buffer.cursor);
+                document.buffer.insert(text);
+                let end = crate::util::position_at(&document.buffer.data(), start + text.len());
+                let line = document.buffer.cursor.line;
+                document.marks.lines_changed(line, (end.line - line) as isize);
+                document.buffer.cursor.move_to(end);
+                document.marks.record_change(end);
+            }
+            None => return, // Only a document can be edited
+        }
+        self.edited();
+    }
+
+    // Text about to be inserted in its composed form (NFC). A combining mark at its start may
+    // compose with the character before the cursor, which is then selected to be replaced by both.
+    fn normalize_input(&mut self, text: &str) -> String {
+        let mut text = text.to_owned();
+        let cursor = self.cursor_position().filter(|c| c.offset > 0 && self.selection_anchor.is_none() && self.extra_selections.is_empty());
+        if let (Some(cursor), true) = (cursor, text.starts_with(is_combining_mark)) {
+            let before = self.text().and_then(|data| Lines::new(&data).line(cursor.line).graphemes(true).nth(cursor.offset - 1).map(str::to_owned));
+            if let Some(before) = before.filter(|before| !is_nfc(&format!("{}{}", before, text))) {
+                self.selection_anchor = Some(Position { line: cursor.line, offset: cursor.offset - 1 });
+                text.insert_str(0, &before);
+            }
+        }
+        match is_nfc(&text) {
+            true => text,
+            false => text.nfc().collect(),
+        }
+    }
+
+    /// Put the whole text in its composed form (NFC), in one step of the undo history, keeping the
+    /// cursor on the same line. Returns how many characters fewer it has, or None if it was in
+    /// that form already.
+    pub fn normalize_text(&mut self) -> Option<usize> {
+        let text = self.text()?;
+        if is_nfc(&text) {
+            return None;
         }
+        let normalized: String = text.nfc().collect();
+        let cursor = self.cursor_position()?;
+        self.replace_bytes(0, text.len(), &normalized);
+        self.selection_anchor = None;
+        if let Some(mut document) = self.data.document() {
+            let length = crate::util::columns(Lines::new(&normalized).line(cursor.line));
+            document.buffer.cursor.move_to(Position { line: cursor.line, offset: cursor.offset.min(length) });
+        }
+        Some(text.chars().count() - normalized.chars().count())
+    }
+
+    /// Redo the indentation of a Python or YAML file in the buffer's style, keeping the cursor on
+    /// its line. Returns how many lines changed, or `None` for a file whose indentation is not
+    /// checked.
+    pub fn normalize_indentation(&mut self) -> Option<usize> {
+        let tabs = match &self.data {
+            Buffer(document, _) => indentcheck::tabs_allowed(document.borrow().buffer.path.as_deref())?,
+            _ => return None,
+        };
+        let (text, cursor) = (self.text()?, self.cursor_position()?);
+        let fixed = indentcheck::fix(&text, self.indentation, tabs);
+        let changed = text.split('\n').zip(fixed.split('\n')).filter(|(old, new)| old != new).count();
+        if changed > 0 {
+            self.replace_bytes(0, text.len(), &fixed);
+            self.selection_anchor = None;
+            if let Some(mut document) = self.data.document() {
+                let line = Lines::new(&fixed).line(cursor.line);
+                document.buffer.cursor.move_to(Position { line: cursor.line, offset: crate::util::columns(indent::leading(line)) });
+            }
+        }
+        Some(changed)
     }
 
     pub fn vertical_scroll_percent(&self) -> f32 {
         match &self.data {
-            Buffer(buffer) => {
-                let lines = buffer.line_count();
+            Buffer(document, _) => {
+                let lines = document.borrow().buffer.line_count();
                 // basically a min(1.0, the_expression)
-                flt_min(1.0, (self.starting_visible_line + self.size.1 - 1) as f32 / lines as f32)
+                flt_min(1.0, (self.starting_visible_line + self.size.1).saturating_sub(1) as f32 / lines as f32)
             }
             Terminal(_) => unimplemented!(),
+            Loading(..) | Image(_) => 0.0,
+            Page(ref page) => page.scroll_percent(),
+            Tail(ref tail) => tail.scroll_percent(),
         }
     }
 
-    /// Insert the given character at the current cursor position or selection.
+    /// Insert the given character at the current cursor position or selection. A combining mark
+    /// joins the character before it, and the cursor stays after the both of them.
     pub fn insert(&mut self, ch: char) {
-        match self.data {
-            Buffer(ref mut buffer) => {
-                // lines[self.cursor_pos.1].insert(self.cursor_pos.0, ch);
-                // self.cursor_pos.0 += 1;
-                buffer.insert(ch.to_string());
-                if ch == '\n' {
-                    buffer.cursor.move_down();
+        self.insert_str(ch.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Type a character at the cursor. With auto-closing on, an opening bracket brings its closing
+    /// one along after the cursor, unless it goes right before a word, and typing the closing one
+    /// then steps over it instead of typing another.
+    pub fn type_char(&mut self, c: char, auto_close: bool) {
+        let cursor = match self.cursor_position() {
+            Some(cursor) if auto_close && self.selection_anchor.is_none() && self.extra_selections.is_empty() => cursor,
+            _ => return self.insert(c),
+        };
+        let text = self.text().unwrap_or_default();
+        let graphemes: Vec<&str> = Lines::new(&text).line(cursor.line).graphemes(true).collect();
+        let from_end = graphemes.len() - cursor.offset.min(graphemes.len());
+        let next = graphemes.get(cursor.offset).and_then(|g| g.chars().next());
+
+        // Those the cursor has left behind, or left the line of, cannot be stepped over any more
+        self.auto_closed.retain(|&(line, end)| line == cursor.line && end <= from_end);
+        if self.auto_closed.last() == Some(&(cursor.line, from_end)) && next == Some(c) {
+            self.auto_closed.pop();
+            if let Some(mut document) = self.data.document() {
+                document.buffer.cursor.move_right();
+            }
+            return;
+        }
+
+        match brackets::closing(c) {
+            Some(close) if next.is_none_or(|next| next.is_whitespace() || ")]}".contains(next)) => {
+                self.insert_str(&format!("{}{}", c, close));
+                if let Some(mut document) = self.data.document() {
+                    document.buffer.cursor.move_left();
                 }
-                buffer.cursor.move_right();
+                self.auto_closed.push((cursor.line, from_end + 1));
             }
-            Terminal(ref _lines) => unimplemented!(),
+            _ => self.insert(c),
+        }
+    }
+
+    /// Start a new line at the cursor, indented like the line it is on with `auto_indent` on: a
+    /// level further after a line opening a block, and a level less if the text going onto it
+    /// closes one. Between the two, as in `{|}`, the closing text goes down a line of its own.
+    pub fn new_line(&mut self) {
+        let cursor = match self.cursor_position() {
+            Some(cursor) if crate::config::get().auto_indent && self.extra_selections.is_empty() => cursor,
+            _ => return self.insert('\n'),
+        };
+        let Buffer(document, _) = &self.data else { return self.insert('\n') };
+        let (text, path) = {
+            let document = document.borrow();
+            (document.buffer.data(), document.buffer.path.clone())
+        };
+        let rules = indent::Rules::for_path(path.as_deref());
+        let (start, end) = match self.selection() {
+            Some(range) => (crate::util::offset_at(&text, range.start()), crate::util::offset_at(&text, range.end())),
+            None => (crate::util::offset_at(&text, cursor), crate::util::offset_at(&text, cursor)),
+        };
+        let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+        let (before, after) = (&text[line_start..start], &text[end..line_end]);
+        let whitespace = indent::leading(before);
+        // The whitespace the rest of the line starts with gives way to the new indentation
+        let end = end + indent::leading(after).len();
+        let after = &after[indent::leading(after).len()..];
+
+        let further = || format!("{}{}", whitespace, self.indentation.text(whitespace.chars().count()));
+        // Text closing a block only goes back a level when it follows some of the line's own, as it was
+        // indented to sit where it is otherwise
+        let closes = !after.is_empty() && !before.trim().is_empty() && rules.decreases(after);
+        let (new, cursor_at) = match (rules.increases(before), closes) {
+            (true, true) => {
+                let further = further();
+                (format!("\n{}\n{}", further, whitespace), 1 + further.len())
+            }
+            (true, false) => {
+                let new = format!("\n{}", further());
+                let len = new.len();
+                (new, len)
+            }
+            (false, true) => {
+                let new = format!("\n{}", indent::outdent(whitespace, self.indentation));
+                let len = new.len();
+                (new, len)
+            }
+            (false, false) => (format!("\n{}", whitespace), 1 + whitespace.len()),
+        };
+        self.replace_bytes(start, end, &new);
+        self.select_bytes(start + cursor_at, start + cursor_at);
+        self.selection_anchor = None;
+    }
+
+    /// Take a level of indentation off the cursor's line once what is typed on it makes it a line
+    /// closing a block, like `}` or Python's `else:`, if it is still indented as the lines inside
+    /// the block are.
+    fn outdent_closing_line(&mut self) {
+        let cursor = match self.cursor_position() {
+            Some(cursor) if crate::config::get().auto_indent && self.selection_anchor.is_none() && self.extra_selections.is_empty() => cursor,
+            _ => return,
+        };
+        let Buffer(document, _) = &self.data else { return };
+        let (text, path) = {
+            let document = document.borrow();
+            (document.buffer.data(), document.buffer.path.clone())
+        };
+        let lines = Lines::new(&text);
+        let line = lines.line(cursor.line);
+        let rules = indent::Rules::for_path(path.as_deref());
+        let Some(above) = lines.iter().take(cursor.line).rev().find(|line| !line.trim().is_empty()) else { return };
+        if line.trim().is_empty() || !rules.decreases(line) {
+            return;
+        }
+        // Only a line indented as a line inside the block would be is taken back a level, leaving
+        // one already taken back, or indented by hand, as it is
+        let inside = match rules.increases(above) {
+            true => format!("{}{}", indent::leading(above), self.indentation.text(indent::leading(above).chars().count())),
+            false => indent::leading(above).to_owned(),
+        };
+        let whitespace = indent::leading(line);
+        if whitespace != inside {
+            return;
+        }
+        let outdented = indent::outdent(whitespace, self.indentation).len();
+        let line_start = crate::util::offset_at(&text, Position { line: cursor.line, offset: 0 });
+        let at = crate::util::offset_at(&text, cursor) - (whitespace.len() - outdented);
+        self.replace_bytes(line_start + outdented, line_start + whitespace.len(), "");
+        self.select_bytes(at, at);
+        self.selection_anchor = None;
+    }
+
+    /// Expand the word before the cursor, if it is an abbreviation in this file, for a character
+    /// that ends it. Nothing is expanded with a selection.
+    pub fn expand_abbreviation(&mut self) {
+        if self.selection_anchor.is_some() || !self.extra_selections.is_empty() {
+            return;
+        }
+        let (Buffer(document, _), Some(cursor)) = (&self.data, self.cursor_position()) else { return };
+        let (text, path) = {
+            let document = document.borrow();
+            (document.buffer.data(), document.buffer.path.clone())
+        };
+        let end = crate::util::offset_at(&text, cursor);
+        let Some(start) = abbreviations::word_before(&text, end) else { return };
+        let word = &text[start..end];
+        let Some(expansion) = abbreviations::expansion(&crate::config::get().abbreviations, word, path.as_deref()) else { return };
+        self.replace_bytes(start, end, &expansion);
+        self.selection_anchor = None;
+        self.expansion = Some((start, expansion, word.to_owned()));
+    }
+
+    /// Offer the names that finish the path being typed before the cursor, if there is one and
+    /// `complete_paths` is on.
+    fn complete_path(&mut self) {
+        self.completion = None;
+        if !crate::config::get().complete_paths || self.selection_anchor.is_some() || !self.extra_selections.is_empty() {
+            return;
+        }
+        let (Buffer(document, _), Some(cursor)) = (&self.data, self.cursor_position()) else { return };
+        let document = document.borrow();
+        let text = document.buffer.data();
+        let completion = completion::complete(&text, crate::util::offset_at(&text, cursor), document.buffer.path.as_deref());
+        drop(document);
+        self.completion = completion;
+    }
+
+    /// Finish the path being typed with the name selected from those offered, and offer what is
+    /// in it if it is a folder. Nothing happens if the cursor was moved away from the path since.
+    fn accept_completion(&mut self, completion: Completion) {
+        let (Some(text), Some(cursor)) = (self.text(), self.cursor_position()) else { return };
+        let end = completion.start + completion.typed.len();
+        if crate::util::offset_at(&text, cursor) != end || text.get(completion.start..end) != Some(&completion.typed) {
+            return;
+        }
+        let name = &completion.names[completion.selected];
+        self.replace_bytes(completion.start, end, name);
+        let after = completion.start + name.len();
+        self.select_bytes(after, after);
+        self.selection_anchor = None;
+        if name.ends_with('/') {
+            self.complete_path();
+        }
+    }
+
+    /// Put back the word expanded by the key before, keeping the character typed after it. Returns
+    /// false if there is none, or the text has changed since in some other way.
+    fn revert_expansion(&mut self, (start, expansion, word): (usize, String, String)) -> bool {
+        let (Some(text), Some(cursor)) = (self.text(), self.cursor_position()) else { return false };
+        let end = start + expansion.len();
+        let typed = match text.get(start..end) {
+            Some(expanded) if expanded == expansion && self.selection_anchor.is_none() => text[end..].chars().next(),
+            _ => None,
+        };
+        match typed {
+            Some(c) if crate::util::offset_at(&text, cursor) == end + c.len_utf8() => {
+                self.replace_bytes(start, end, &word);
+                let after = start + word.len() + c.len_utf8();
+                self.select_bytes(after, after);
+                self.selection_anchor = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Delete both brackets of an empty pair the cursor is between, for Backspace with
+    /// auto-closing on. Returns false if it is not between one.
+    pub fn delete_empty_pair(&mut self) -> bool {
+        let cursor = match self.cursor_position() {
+            Some(cursor) if cursor.offset > 0 && self.selection_anchor.is_none() && self.extra_selections.is_empty() => cursor,
+            _ => return false,
+        };
+        let text = self.text().unwrap_or_default();
+        let graphemes: Vec<&str> = Lines::new(&text).line(cursor.line).graphemes(true).collect();
+        let (before, after) = (graphemes.get(cursor.offset - 1), graphemes.get(cursor.offset));
+        let closes = before.and_then(|g| g.chars().next()).and_then(brackets::closing);
+        if closes.is_none() || after.and_then(|g| g.chars().next()) != closes {
+            return false;
+        }
+        let from_end = graphemes.len() - cursor.offset;
+        self.auto_closed.retain(|&closed| closed != (cursor.line, from_end));
+        self.selection_anchor = Some(Position { line: cursor.line, offset: cursor.offset - 1 });
+        if let Some(mut document) = self.data.document() {
+            document.buffer.cursor.move_to(Position { line: cursor.line, offset: cursor.offset + 1 });
+        }
+        self.delete_selection()
+    }
+
+    /// Insert one level of indentation at the cursor, or at every selection.
+    pub fn indent(&mut self) {
+        if let Some(cursor) = self.cursor_position() {
+            let text = self.indentation.text(cursor.offset);
+            self.insert_str(&text);
         }
     }
 
     /// Delete the character before the current cursor position or selection.
     pub fn backspace(&mut self) {
-        match self.data {
-            Buffer(ref mut buffer) => {
+        if !self.extra_selections.is_empty() {
+            return self.edit_every_selection(|text, start, end| match text[..start].chars().next_back() {
+                Some(c) if start == end => (start - c.len_utf8(), end, String::new()),
+                _ => (start, end, String::new()),
+            });
+        }
+        if self.delete_selection() {
+            return;
+        }
+        match self.data.document() {
+            Some(mut document) => {
                 // lines[self.cursor_pos.1].remove(self.cursor_pos.0);
                 // self.cursor_pos.0 -= 1;
+                let buffer = &mut document.buffer;
                 if buffer.cursor.position.offset > 0 {
                     buffer.cursor.move_to({
                         let mut p = buffer.cursor.position;
@@ -172,116 +1241,1528 @@ impl Viewport {
                     if buffer.cursor.position.line > 0 { // Lines begin counting at zero
                         buffer.cursor.move_up();
                         buffer.cursor.move_to_end_of_line();
+                        let line = buffer.cursor.line;
+                        document.marks.lines_changed(line, -1);
                     }
                 }
 
-                buffer.delete();
+                document.buffer.delete();
+                let position = document.buffer.cursor.position;
+                document.marks.record_change(position);
             }
-            Terminal(ref _lines) => unimplemented!(),
+            None => return, // Only a document can be edited
         }
+        self.edited();
     }
 
     /// Delete the character at the current cursor position or selection.
     pub fn delete(&mut self) {
-        match self.data {
-            Buffer(ref mut buffer) => {
+        if !self.extra_selections.is_empty() {
+            return self.edit_every_selection(|text, start, end| match text[end..].chars().next() {
+                Some(c) if start == end => (start, end + c.len_utf8(), String::new()),
+                _ => (start, end, String::new()),
+            });
+        }
+        if self.delete_selection() {
+            return;
+        }
+        match self.data.document() {
+            Some(mut document) => {
                 // lines[self.cursor_pos.1].remove(self.cursor_pos.0);
                 // self.cursor_pos.0 -= 1;
-                buffer.delete();
+                let line_count = document.buffer.line_count();
+                document.buffer.delete();
+                let position = document.buffer.cursor.position;
+                if document.buffer.line_count() < line_count { // We deleted the line break, joining the next line onto this one
+                    document.marks.lines_changed(position.line, -1);
+                }
+                document.marks.record_change(position);
             }
-            Terminal(ref _lines) => unimplemented!(),
+            None => return, // Only a document can be edited
         }
+        self.edited();
     }
-}
 
-/// Manages and renders zero or more viewports at any given time. The Viewport Manager
-/// tiles viewports, relocating and resizing them so they can fit better within the
-/// margins of the screen. This also dispatches events and commands to those viewports,
-/// and renders their bounding boxes and titles.
-pub struct ViewportManager {
-    pub origin: (u16, u16),
-    pub size: (usize, usize),
-    pub viewports: Vec<Viewport>,
-    pub focus_index: usize, // Current index for focused viewport
-}
+    /// Every selection, the main one first, as byte ranges into the given text.
+    fn selection_ranges(&self, text: &str) -> Vec<(usize, usize)> {
+        let main = match self.cursor_position() {
+            Some(cursor) => Selection { anchor: self.selection_anchor.unwrap_or(cursor), cursor },
+            None => return Vec::new(),
+        };
+        std::iter::once(main).chain(self.extra_selections.iter().copied()).map(|sel| {
+            let (a, b) = (crate::util::offset_at(text, sel.anchor), crate::util::offset_at(text, sel.cursor));
+            (a.min(b), a.max(b))
+        }).collect()
+    }
 
-impl ViewportManager {
-    pub fn render<S: Write>(&mut self, s: &mut S, has_focus: bool) {
-        if self.viewports.is_empty() {
-            return; // No need to render nothing.
+    /// How much is selected, if anything is.
+    pub fn selection_size(&self) -> Option<SelectionSize> {
+        if self.selection().is_none() && self.extra_selections.iter().all(|sel| sel.anchor == sel.cursor) {
+            return None;
         }
+        let text = self.text()?;
+        let ranges: Vec<(usize, usize)> = self.selection_ranges(&text).into_iter().filter(|(start, end)| start < end).collect();
+        if ranges.is_empty() {
+            return None;
+        }
+        let lines = ranges.iter().map(|&(start, end)| text[start..end].trim_end_matches('\n').matches('\n').count() + 1).sum();
+        let chars = ranges.iter().map(|&(start, end)| crate::util::columns(&text[start..end])).sum();
 
-        // Update proportions of the viewport
-        let (v_origin, v_size) = {
-            let v = &self.viewports[self.focus_index];
-            (v.origin, v.size)
+        let mut spans: Vec<(usize, usize, usize)> = ranges.iter().map(|&(start, end)| {
+            let (a, b) = (crate::util::position_at(&text, start), crate::util::position_at(&text, end));
+            (a.line, a.offset, if a.line == b.line { b.offset } else { usize::MAX })
+        }).collect();
+        spans.sort();
+        let block_columns = match spans.first() {
+            Some(&(first, left, right)) if spans.len() > 1 && right != usize::MAX
+                && spans.iter().enumerate().all(|(i, &(line, l, r))| line == first + i && l == left && r == right) => Some(right - left),
+            _ => None,
         };
+        Some(SelectionSize { selections: ranges.len(), lines, chars, block_columns })
+    }
 
-        // Draw the inside of the bounding box
-        crate::util::draw_rectangle(s, &Color::Blue, (v_origin.0-1, v_origin.1-1), (v_size.0+1, v_size.1+1));
-        // Draw the Viewport's 'beam' bounding box
-        crate::util::draw_thin_unfilled_rectangle(s, &Color::Grey, &Color::Blue, (v_origin.0-1, v_origin.1-1), (v_size.0+1, v_size.1+1));
-
-        {
-            let titles: Vec<String> = self.viewports.iter_mut().map(|v| {
-            	let mut title = v.title.clone();
-                if let Some(buf) = v.get_buffer() {
-                	if buf.modified() {
-                    	title.insert(0, '*');
-                	}
-                }
-                title
-            }).collect();
-            let total_len: usize = titles.len() * 3 + titles.iter().map(|t| t.len()).sum::<usize>(); // The number characters all of the titles will take up
-
-            let starting_x: u16 = v_origin.0 + (v_size.0/2 - total_len/2) as u16;
-            for (i, t) in titles.iter().enumerate() {
-                if i == self.focus_index {
-                    queue!(s,
-                        cursor::MoveTo(starting_x + (i * (t.len() + 3)) as u16, v_origin.1 - 1), style::SetForegroundColor(Color::Blue), style::SetBackgroundColor(Color::Grey),
-                        style::Print(format!(" {} ", t)),
-                    );
-                } else {
-                    queue!(s,
-                        cursor::MoveTo(starting_x + (i * (t.len() + 3)) as u16, v_origin.1 - 1),
-                        style::Print(format!("┤{}├", t)), // NOTE: skip a char each time
-                    );
-                }
-            }
+    /// The whole text of the buffer.
+    pub fn text(&self) -> Option<String> {
+        match &self.data {
+            Buffer(document, _) => Some(document.borrow().buffer.data()),
+            Terminal(_) | Loading(..) | Image(_) | Page(_) | Tail(_) => None,
         }
+    }
+
+    /// Make the same kind of edit at every selection, as one step in the undo history. The edit is
+    /// given the text and a selection's byte range, and returns the byte range to replace and what
+    /// to replace it with. Afterwards there is a cursor after each replacement, and nothing is selected.
+    fn edit_every_selection<F: Fn(&str, usize, usize) -> (usize, usize, String)>(&mut self, edit: F) {
+        let text = match self.text() {
+            Some(text) => text,
+            None => return,
+        };
+        let mut changes: Vec<(usize, usize, String, bool)> = self.selection_ranges(&text).into_iter().enumerate()
+            .map(|(i, (start, end))| {
+                let (start, end, inserted) = edit(&text, start, end);
+                (start, end, inserted, i == 0)
+            })
+            .collect();
+        changes.sort_by_key(|change| (change.0, change.1));
+        // Selections that ran into each other become one
+        let mut merged: Vec<(usize, usize, String, bool)> = Vec::with_capacity(changes.len());
+        for change in changes {
+            match merged.last_mut() {
+                Some(last) if change.0 < last.1 || (change.0 == last.0 && change.1 == last.1) => last.3 |= change.3,
+                _ => merged.push(change),
+            }
+        }
+
+        if let Some(mut document) = self.data.document() {
+            // Working back from the end keeps the positions of the changes still to come the same
+            for (start, end, inserted, _) in merged.iter().rev() {
+                let (start_position, end_position) = (crate::util::position_at(&text, *start), crate::util::position_at(&text, *end));
+                if start_position != end_position {
+                    document.buffer.delete_range(Range::new(start_position, end_position));
+                }
+                document.buffer.cursor.move_to(start_position);
+                document.buffer.insert(inserted.as_str());
+                document.marks.lines_changed(start_position.line, inserted.matches('\n').count() as isize - text[*start..*end].matches('\n').count() as isize);
+            }
+
+            let new_text = document.buffer.data();
+            let mut shift = 0isize;
+            let mut main = None;
+            self.extra_selections.clear();
+            for (start, end, inserted, is_main) in &merged {
+                let cursor = crate::util::position_at(&new_text, (*start as isize + shift) as usize + inserted.len());
+                shift += inserted.len() as isize - (end - start) as isize;
+                if *is_main && main.is_none() {
+                    main = Some(cursor);
+                } else {
+                    self.extra_selections.push(Selection { anchor: cursor, cursor });
+                }
+            }
+            if let Some(cursor) = main {
+                document.buffer.cursor.move_to(cursor);
+                document.marks.record_change(cursor);
+            }
+            self.selection_anchor = None;
+        }
+        self.edited();
+    }
+
+    /// Add to the number at (or after) every cursor, as one step in the undo history. Making a
+    /// sequence adds nothing to the first number, `by` to the second, twice that to the third
+    /// and so on, in the order they come in the buffer. Returns false if there was no number.
+    pub fn add_to_numbers(&mut self, by: i64, sequence: bool) -> bool {
+        let text = match self.text() {
+            Some(text) => text,
+            None => return false,
+        };
+        let number_at = |text: &str, at: usize| {
+            let line_start = text[..at].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = text[at..].find('\n').map_or(text.len(), |i| at + i);
+            crate::numbers::find(&text[line_start..line_end], at - line_start)
+                .map(|(start, end)| (line_start + start, line_start + end))
+        };
+        let mut starts: Vec<usize> = self.selection_ranges(&text).iter().map(|&(start, _)| start).collect();
+        if !starts.iter().any(|&start| number_at(&text, start).is_some()) {
+            return false;
+        }
+        starts.sort_unstable();
+        self.edit_every_selection(|text, start, _| match number_at(text, start) {
+            Some((number_start, number_end)) => {
+                let delta = match sequence {
+                    true => by.saturating_mul(starts.binary_search(&start).unwrap_or(0) as i64),
+                    false => by,
+                };
+                (number_start, number_end, crate::numbers::add(&text[number_start..number_end], delta))
+            }
+            None => (start, start, String::new()),
+        });
+        true
+    }
+
+    /// Select the word under the cursor if nothing is selected, otherwise add a selection of the
+    /// next place the selected text appears, wrapping around the buffer. With `skip`, the current
+    /// selection is dropped rather than kept, moving on past an occurrence that should stay as it is.
+    pub fn select_next_occurrence(&mut self, skip: bool) {
+        let selection = self.selection();
+        let text = match self.text() {
+            Some(text) => text,
+            None => return,
+        };
+        let range = match selection {
+            Some(range) => range,
+            None => return self.select_word(),
+        };
+        let selected = self.selection_ranges(&text);
+        let Some(mut document) = self.data.document() else { return };
+
+        let (start, end) = (crate::util::offset_at(&text, range.start()), crate::util::offset_at(&text, range.end()));
+        let needle = &text[start..end];
+
+        // Look after the newest selection, skipping over anything that is already selected
+        let mut from = end;
+        let mut wrapped = false;
+        let found = loop {
+            match text[from..].find(needle).map(|i| from + i) {
+                Some(found) if selected.iter().any(|&(s, e)| found < e && s < found + needle.len()) => {
+                    from = found + needle.chars().next().map_or(1, char::len_utf8);
+                }
+                Some(found) => break Some(found),
+                None if !wrapped => {
+                    wrapped = true;
+                    from = 0;
+                }
+                None => break None,
+            }
+        };
+
+        if let Some(found) = found {
+            if !skip {
+                self.extra_selections.push(Selection { anchor: range.start(), cursor: range.end() });
+            }
+            self.selection_anchor = Some(crate::util::position_at(&text, found));
+            document.buffer.cursor.move_to(crate::util::position_at(&text, found + needle.len()));
+        }
+    }
+
+    /// The first and last lines the selection touches, if anything is selected. A selection ending
+    /// at the very start of a line does not take in that line.
+    pub fn selected_lines(&self) -> Option<(usize, usize)> {
+        self.selection().map(|range| match range.end() {
+            end if end.offset == 0 && end.line > range.start().line => (range.start().line, end.line - 1),
+            end => (range.start().line, end.line),
+        })
+    }
+
+    /// Replace the lines from `first` to `last` with what a function makes of their text, as one
+    /// step in the undo history. The new lines are selected afterwards.
+    pub fn transform_lines<F: FnOnce(&str) -> String>(&mut self, first: usize, last: usize, transform: F) {
+        if let Some(mut document) = self.data.document() {
+            let text = document.buffer.data();
+            let lines = Lines::new(&text);
+            let last = last.min(lines.len() - 1);
+            let old = lines.between(first, last);
+            let new = transform(old);
+            if new == old {
+                return;
+            }
+
+            let start = Position { line: first, offset: 0 };
+            let end = Position { line: last, offset: crate::util::columns(&lines[last]) };
+            if start != end {
+                document.buffer.delete_range(Range::new(start, end));
+            }
+            document.buffer.cursor.move_to(start);
+            document.buffer.insert(new.as_str());
+            let new_last = first + new.matches('\n').count();
+            self.extra_selections.clear();
+            self.selection_anchor = Some(start);
+            document.buffer.cursor.move_to(Position { line: new_last, offset: crate::util::columns(new.rsplit('\n').next().unwrap()) });
+            document.marks.lines_changed(first, new_last as isize - last as isize);
+            document.marks.record_change(start);
+        }
+        self.edited();
+    }
+
+    /// The cell of the screen the cursor is drawn on, as of the last render.
+    pub fn cursor_cell(&self) -> Option<(u16, u16)> {
+        if let Some(table) = &self.table {
+            return table.cursor_cell();
+        }
+        let Buffer(ref document, _) = self.data else { return None };
+        let mut document = document.borrow_mut();
+        let document = &mut *document;
+        let text = document.buffer.data();
+        let lines = document.line_index.lines(&text);
+        self.cursor_cell_with(&lines, self.line_number_digits(lines.len()))
+    }
+
+    // The cell of the cursor, given the lines of the text and how wide their numbers are.
+    fn cursor_cell_with(&self, lines: &Lines, line_num_digits: usize) -> Option<(u16, u16)> {
+        let (column, row) = self.cell_of(lines, line_num_digits, self.cursor_position()?)?;
+        Some((self.origin.0 + (self.gutter_width(line_num_digits) + column) as u16, self.origin.1 + row as u16))
+    }
+
+    // The column and row a position is drawn at, counting from the start of the text and the top of
+    // the view. None if it is above the view or scrolled off to the left.
+    fn cell_of(&self, lines: &Lines, line_num_digits: usize, position: Position) -> Option<(usize, usize)> {
+        let above: usize = (self.starting_visible_line..position.line).map(|line| self.rows_of(lines, line, line_num_digits).len()).sum();
+        let line = lines.get(position.line)?;
+        let rows = self.rows_of(lines, position.line, line_num_digits);
+        let part = rows.iter().rposition(|&(start, _)| start <= position.offset)?;
+        let indent = if part > 0 { self.continuation_indent(line, line_num_digits) } else { 0 };
+        Some((indent + position.offset - rows[part].0, above + part))
+    }
+
+    /// How many columns the line numbers take up, in a buffer of so many lines: none if they are
+    /// hidden.
+    fn line_number_digits(&self, line_count: usize) -> usize {
+        match self.view.line_numbers && !self.compact {
+            true => line_count.to_string().len(),
+            false => 0,
+        }
+    }
+
+    /// How many columns the line numbers and the marks after them take up: none in compact mode.
+    fn gutter_width(&self, line_num_digits: usize) -> usize {
+        if self.compact { 0 } else { line_num_digits + 1 }
+    }
+
+    /// The columns of a line drawn on each row, as ranges of character offsets: just the ones
+    /// scrolled into view, or all of them over as many rows as it takes when lines wrap.
+    fn line_rows(&self, line: &str, line_num_digits: usize) -> Vec<(usize, usize)> {
+        match self.view.word_wrap {
+            true => wrap(line, self.wrap_width(line_num_digits), self.continuation_indent(line, line_num_digits)),
+            false => vec![(self.starting_visible_column, self.starting_visible_column + self.size.0.saturating_sub(5 + self.minimap_columns()))],
+        }
+    }
+
+    // The rows of a line, as `line_rows` gives them, or none if it is folded away.
+    fn rows_of(&self, lines: &Lines, line: usize, line_num_digits: usize) -> Vec<(usize, usize)> {
+        match self.is_hidden(line) {
+            true => Vec::new(),
+            false => lines.get(line).map_or_else(Vec::new, |l| self.line_rows(l, line_num_digits)),
+        }
+    }
+
+    // Whether a line is folded away, as of the last render.
+    fn is_hidden(&self, line: usize) -> bool {
+        self.folds.iter().any(|&(first, last)| line > first && line <= last)
+    }
+
+    // How many of the lines from one up to another are not folded away.
+    fn shown_lines_between(&self, from: usize, to: usize) -> usize {
+        match self.folds.is_empty() {
+            true => to.saturating_sub(from),
+            false => (from..to).filter(|&line| !self.is_hidden(line)).count(),
+        }
+    }
+
+    // The line so many lines above another that are not folded away, or the first line.
+    fn shown_line_above(&self, mut line: usize, count: usize) -> usize {
+        if self.folds.is_empty() {
+            return line.saturating_sub(count);
+        }
+        let mut counted = 0;
+        while counted < count && line > 0 {
+            line -= 1;
+            if !self.is_hidden(line) {
+                counted += 1;
+            }
+        }
+        line
+    }
+
+    // How many columns of text fit on a row when lines wrap.
+    fn wrap_width(&self, line_num_digits: usize) -> usize {
+        self.size.0.saturating_sub(self.gutter_width(line_num_digits) + 1 + self.minimap_columns()).max(1)
+    }
+
+    /// How many columns the minimap takes from the text, with the one keeping them apart: none if
+    /// it is hidden, or the viewport is too narrow for it.
+    fn minimap_columns(&self) -> usize {
+        let shown = self.view.minimap && self.table.is_none() && matches!(self.data, Buffer(..))
+            && self.size.0 >= minimap::MIN_VIEW_WIDTH && !crate::config::get().screen_reader;
+        if shown { minimap::WIDTH + 1 } else { 0 }
+    }
+
+    // The top left cell of the minimap.
+    fn minimap_origin(&self) -> (u16, u16) {
+        (self.origin.0 + self.size.0.saturating_sub(1 + minimap::WIDTH) as u16, self.origin.1)
+    }
+
+    /// The line at the start of the part of the minimap drawn at a cell of the screen, if there
+    /// is one there.
+    pub fn minimap_line_at(&self, x: u16, y: u16) -> Option<usize> {
+        let origin = self.minimap_origin();
+        if self.minimap_columns() == 0 || x < origin.0 || x >= origin.0 + minimap::WIDTH as u16 || y < origin.1 || y >= origin.1 + self.size.1 as u16 - 1 {
+            return None;
+        }
+        let line_count = match &self.data {
+            Buffer(document, _) => document.borrow_mut().line_count(),
+            _ => return None,
+        };
+        minimap::line_at_row((y - origin.1) as usize, line_count, self.size.1.saturating_sub(1))
+    }
+
+    /// How far the rows a line wraps onto are indented, leaving at least half of each for the
+    /// text. None when lines do not wrap.
+    fn continuation_indent(&self, line: &str, line_num_digits: usize) -> usize {
+        if !self.view.word_wrap {
+            return 0;
+        }
+        let indent = match self.view.wrap_indent {
+            WrapIndent::None => 0,
+            WrapIndent::Same => line.graphemes(true).take_while(|&g| g == " " || g == "\t").count(),
+            WrapIndent::Hanging(columns) => columns,
+        };
+        indent.min(self.wrap_width(line_num_digits) / 2)
+    }
+
+    /// Whether a cell of the screen is within the viewport, or its frame.
+    pub fn covers(&self, x: u16, y: u16) -> bool {
+        x + 1 >= self.origin.0 && y + 1 >= self.origin.1 && x <= self.origin.0 + self.size.0 as u16 && y <= self.origin.1 + self.size.1 as u16
+    }
+
+    /// How many lines are kept in view around the cursor: the configured number, but at most half
+    /// the view.
+    fn scroll_margin(&self) -> usize {
+        crate::config::get().scrolloff.min(self.size.1.saturating_sub(2) / 2)
+    }
+
+    /// Scroll so the cursor's line is at the top, middle or bottom of the view, without moving the
+    /// cursor. The scroll margin still applies.
+    pub fn scroll_cursor_line(&mut self, at: LineAt) {
+        let Some(cursor) = self.cursor_position() else { return };
+        let rows = self.size.1.saturating_sub(1);
+        let line = cursor.line;
+        self.starting_visible_line = match at {
+            LineAt::Top => line,
+            LineAt::Center => self.shown_line_above(line, rows / 2),
+            LineAt::Bottom => self.shown_line_above(line, rows.saturating_sub(1)),
+        };
+    }
+
+    /// Select the word the cursor is in or next to, if there is one.
+    pub fn select_word(&mut self) {
+        let Some(mut document) = self.data.document() else { return };
+        let cursor = document.buffer.cursor.position;
+        let text = document.buffer.data();
+        let line: Vec<&str> = Lines::new(&text).line(cursor.line).graphemes(true).collect();
+        // A letter with accents on it is as much a part of the word as one without
+        let is_word = |g: &&&str| g.chars().next().is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let start = cursor.offset - line[..cursor.offset].iter().rev().take_while(is_word).count();
+        let end = cursor.offset + line[cursor.offset..].iter().take_while(is_word).count();
+        if start != end {
+            self.extra_selections.clear();
+            self.selection_anchor = Some(Position { line: cursor.line, offset: start });
+            document.buffer.cursor.move_to(Position { line: cursor.line, offset: end });
+        }
+    }
+
+    /// Select the whole of the cursor's line, with its line break.
+    pub fn select_line(&mut self) {
+        if let Some(cursor) = self.cursor_position() {
+            self.select_lines(cursor.line, cursor.line);
+        }
+    }
+
+    /// Select whole lines, with the line break after the last. Lines past the end of the buffer
+    /// select up to its end.
+    pub fn select_lines(&mut self, first: usize, last: usize) {
+        let Some(mut document) = self.data.document() else { return };
+        let text = document.buffer.data();
+        let lines = Lines::new(&text);
+        let last = last.min(lines.len() - 1);
+        let first = first.min(last);
+        let end = match lines.get(last + 1) {
+            Some(_) => Position { line: last + 1, offset: 0 },
+            None => Position { line: last, offset: lines.get(last).map_or(0, crate::util::columns) },
+        };
+        self.extra_selections.clear();
+        self.selection_anchor = Some(Position { line: first, offset: 0 });
+        document.buffer.cursor.move_to(end);
+    }
+
+    /// Delete whole lines, in one undo step.
+    pub fn delete_lines(&mut self, first: usize, last: usize) {
+        let text = match self.text() {
+            Some(text) => text,
+            None => return,
+        };
+        let lines = Lines::new(&text);
+        let mut start = crate::util::offset_at(&text, Position { line: first, offset: 0 });
+        let end = match lines.get(last + 1) {
+            Some(_) => crate::util::offset_at(&text, Position { line: last + 1, offset: 0 }),
+            None => {
+                // The last line has no line break after it to take, so the one before it goes
+                start = start.saturating_sub(1);
+                text.len()
+            }
+        };
+        self.replace_bytes(start, end, "");
+        self.selection_anchor = None;
+    }
+
+    /// The position of the text drawn at a cell of the screen, as of the last render. Cells past
+    /// the end of a line (or below the last one) give its end, and the line numbers its start.
+    pub fn position_at_cell(&self, x: u16, y: u16) -> Option<Position> {
+        let text = self.text()?;
+        if x < self.origin.0 || y < self.origin.1 || x >= self.origin.0 + self.size.0 as u16 || y >= self.origin.1 + self.size.1 as u16 - 1 {
+            return None;
+        }
+        let lines = Lines::new(&text);
+        let digits = self.line_number_digits(lines.len());
+        let column = (x as usize).saturating_sub(self.origin.0 as usize + self.gutter_width(digits));
+        // Count down the rows to the line drawn on this one, or the last line if it is below them all
+        let mut row = (y - self.origin.1) as usize;
+        let mut line = self.starting_visible_line.min(lines.len() - 1);
+        let mut rows = self.rows_of(&lines, line, digits);
+        while row >= rows.len() && line + 1 < lines.len() {
+            row -= rows.len();
+            line += 1;
+            rows = self.rows_of(&lines, line, digits);
+        }
+        // Below the last line, a line folded away there gives the one it is folded under
+        while self.is_hidden(line) && line > 0 {
+            line -= 1;
+        }
+        let length = crate::util::columns(&lines[line]);
+        let column = match row {
+            0 => column,
+            _ => column.saturating_sub(self.continuation_indent(&lines[line], digits)),
+        };
+        let offset = match rows.get(row) {
+            // Past the end of a row that wraps onto the next is the last character of it
+            Some(&(start, end)) if row + 1 < rows.len() => (start + column).min(end - 1),
+            Some(&(start, _)) => start + column,
+            None => length,
+        };
+        Some(Position { line, offset: offset.min(length) })
+    }
+
+    /// The lines starting the scopes the top of the view is in, which are pinned over it while
+    /// they are scrolled out of view. At most `MAX_STICKY_LINES` are, and none over the cursor's
+    /// row.
+    fn sticky_scope(&self, lines: &Lines, cursor_row: Option<usize>) -> Vec<usize> {
+        let rows = self.size.1.saturating_sub(1);
+        if !self.view.sticky_scope || rows < 2 * MAX_STICKY_LINES + 2 || crate::config::get().screen_reader {
+            return Vec::new();
+        }
+        // The lines pinned hide as many under them, whose scopes count too
+        let top = self.starting_visible_line;
+        let scopes = |hidden: usize| {
+            let mut scopes = crate::scopes::enclosing(lines, top + hidden);
+            scopes.retain(|&line| line < top + hidden);
+            scopes.truncate(MAX_STICKY_LINES);
+            scopes
+        };
+        let mut pinned = scopes(scopes(0).len());
+        pinned.truncate(cursor_row.unwrap_or(usize::MAX));
+        pinned
+    }
+
+    /// The line pinned at the top of the view at a cell of the screen, as of the last render.
+    pub fn sticky_line_at(&self, x: u16, y: u16) -> Option<usize> {
+        if x < self.origin.0 || x >= self.origin.0 + self.size.0 as u16 - 1 || y < self.origin.1 {
+            return None;
+        }
+        self.sticky_lines.get((y - self.origin.1) as usize).copied()
+    }
+
+    /// Whether the viewport has the bar showing where the cursor is over it, in the row under its
+    /// frame's top. Only tiled buffers do.
+    pub fn has_breadcrumbs(&self) -> bool {
+        self.view.breadcrumbs && !self.view.zen && !self.compact && !self.floating && matches!(self.data, Buffer(..))
+    }
+
+    /// Where the cursor is, from the folders of the file down to the scopes around it.
+    pub fn breadcrumbs(&self) -> Vec<Segment> {
+        let Buffer(ref document, ref cursor) = self.data else { return Vec::new() };
+        let document = document.borrow();
+        let text = document.buffer.data();
+        breadcrumbs::segments(document.buffer.path.as_deref(), &self.title, &Lines::new(&text), cursor.position.line)
+    }
+
+    /// Draw the bar showing where the cursor is, in the row over the view.
+    pub fn render_breadcrumbs<S: Write>(&mut self, s: &mut S, focused: bool) {
+        let segments = self.breadcrumbs();
+        let drawn = breadcrumbs::render(s, (self.origin.0, self.origin.1 - 1), self.size.0.saturating_sub(1), &segments, focused);
+        self.breadcrumbs = drawn.into_iter().zip(segments).collect();
+    }
+
+    /// The segment of the bar over the view at a cell of the screen, as of the last render.
+    pub fn breadcrumb_at(&self, x: u16, y: u16) -> Option<&Segment> {
+        if !self.has_breadcrumbs() || y + 1 != self.origin.1 {
+            return None;
+        }
+        self.breadcrumbs.iter().find(|(columns, _)| columns.contains(&x)).map(|(_, segment)| segment)
+    }
+
+    /// The line whose gutter is drawn at a cell of the screen, as of the last render, and whether
+    /// the cell is the marks column after its number rather than the number itself. The rows a
+    /// line wraps onto count as its own.
+    pub fn gutter_at_cell(&self, x: u16, y: u16) -> Option<(usize, bool)> {
+        let text = self.text()?;
+        let lines = Lines::new(&text);
+        let digits = self.line_number_digits(lines.len());
+        if self.compact || x < self.origin.0 || x as usize > self.origin.0 as usize + digits {
+            return None;
+        }
+        let line = self.position_at_cell(x, y)?.line;
+        // Below the last line there is no gutter
+        let (_, row) = self.cell_of(&lines, digits, Position { line, offset: 0 })?;
+        match ((y - self.origin.1) as usize) < row + self.rows_of(&lines, line, digits).len() {
+            true => Some((line, x as usize == self.origin.0 as usize + digits)),
+            false => None,
+        }
+    }
+
+    /// What a tooltip over a cell of the view says, if anything: what the mark in the gutter
+    /// there stands for, or the whole of the diagnostic or indentation problem of the line.
+    pub fn tooltip_at(&self, x: u16, y: u16) -> Option<String> {
+        let Buffer(document, _) = &self.data else { return None };
+        let document = document.borrow();
+        let text = document.buffer.data();
+        let lines = Lines::new(&text);
+        let digits = self.line_number_digits(lines.len());
+        let line = self.position_at_cell(x, y)?.line;
+        // Below the last line there is nothing to tell
+        let (_, row) = self.cell_of(&lines, digits, Position { line, offset: 0 })?;
+        if (y.checked_sub(self.origin.1)? as usize) >= row + self.rows_of(&lines, line, digits).len() {
+            return None;
+        }
+        if !self.compact && x as usize == self.origin.0 as usize + digits {
+            if document.marks.is_bookmarked(line) {
+                return Some(crate::locale::tr("Bookmark"));
+            } else if let Some(name) = document.marks.mark_on_line(line) {
+                return Some(format!("{} {}", crate::locale::tr("Mark"), name));
+            } else if let Some(&(first, last)) = self.folds.iter().find(|&&(first, _)| first == line) {
+                return Some(format!("{} {}", last - first, crate::locale::tr("lines folded")));
+            }
+        }
+        if let Some(message) = document.marks.diagnostic(line) {
+            return Some(message.to_owned());
+        }
+        let tabs = indentcheck::tabs_allowed(document.buffer.path.as_deref()).filter(|_| crate::config::get().indentation_warnings)?;
+        indentcheck::problems(&lines, self.indentation, tabs).remove(&line)
+    }
+
+    /// Put the cursor back where it was left in the file last time, as far as the text still has
+    /// room for it.
+    pub fn restore_position(&mut self, remembered: crate::positions::Remembered) {
+        let Some(mut document) = self.data.document() else { return };
+        let text = document.buffer.data();
+        let cursor = crate::util::position_at(&text, crate::util::offset_at(&text, remembered.cursor));
+        document.buffer.cursor.move_to(cursor);
+        self.starting_visible_line = remembered.first_line.min(cursor.line);
+    }
+
+    /// Remember where the cursor is in the file, if the viewport shows one.
+    fn remember_position(&self) {
+        let (Buffer(document, _), Some(cursor)) = (&self.data, self.cursor_position()) else { return };
+        if let Some(path) = &document.borrow().buffer.path {
+            crate::positions::remember(path, crate::positions::Remembered { cursor, first_line: self.starting_visible_line });
+        }
+    }
+
+    /// Put the cursor at a position, selecting from where it was if `extend` is set, or clearing
+    /// the selection otherwise.
+    pub fn move_cursor_to(&mut self, position: Position, extend: bool) {
+        let Some(mut document) = self.data.document() else { return };
+        self.extra_selections.clear();
+        if !extend {
+            self.selection_anchor = None;
+        } else if self.selection_anchor.is_none() {
+            self.selection_anchor = Some(document.buffer.cursor.position);
+        }
+        document.buffer.cursor.move_to(position);
+    }
+
+    /// Replace the text between two byte offsets in one undo step, selecting what replaced it.
+    pub fn replace_bytes(&mut self, start: usize, end: usize, new: &str) {
+        if let Some(mut document) = self.data.document() {
+            let text = document.buffer.data();
+            let (start_position, end_position) = (crate::util::position_at(&text, start), crate::util::position_at(&text, end));
+            if start_position != end_position {
+                document.buffer.delete_range(Range::new(start_position, end_position));
+            }
+            document.buffer.cursor.move_to(start_position);
+            document.buffer.insert(new);
+            let new_text = document.buffer.data();
+            self.extra_selections.clear();
+            self.selection_anchor = Some(start_position);
+            let end_position = crate::util::position_at(&new_text, start + new.len());
+            document.buffer.cursor.move_to(end_position);
+            document.marks.lines_changed(start_position.line, new.matches('\n').count() as isize - text[start..end].matches('\n').count() as isize);
+            document.marks.record_change(start_position);
+        }
+        self.edited();
+    }
+
+    /// Replace the selected text in one undo step, selecting what replaced it. Other selections
+    /// are dropped.
+    pub fn replace_selection(&mut self, new: &str) {
+        let text = match self.get_buffer() {
+            Some(buffer) => buffer.data(),
+            None => return,
+        };
+        if let Some(range) = self.selection() {
+            self.replace_bytes(crate::util::offset_at(&text, range.start()), crate::util::offset_at(&text, range.end()), new);
+        }
+    }
+
+    /// Select the text between two byte offsets, leaving the cursor at the end.
+    pub fn select_bytes(&mut self, start: usize, end: usize) {
+        if let Some(mut document) = self.data.document() {
+            let text = document.buffer.data();
+            let lines = document.line_index.lines(&text);
+            let (start, end) = (lines.position_at(start), lines.position_at(end));
+            self.extra_selections.clear();
+            self.selection_anchor = Some(start);
+            document.buffer.cursor.move_to(end);
+        }
+    }
+
+    /// Select the smallest part of the text's structure that takes in more than the selection
+    /// does: the word the cursor is in, then the string or comment, then what is inside the brackets
+    /// around it, then those brackets too, and so on outwards. Languages with a grammar go by the
+    /// nodes it parses the text into instead.
+    pub fn grow_selection(&mut self) {
+        let Some(cursor) = self.cursor_position() else { return };
+        let Some(mut document) = self.data.document() else { return };
+        let text = document.buffer.data();
+        let current = Selection { anchor: self.selection_anchor.unwrap_or(cursor), cursor };
+        if self.grown_selections.last().is_some_and(|&(_, grown)| grown != current) {
+            self.grown_selections.clear();
+        }
+        let (start, end) = {
+            let (a, b) = (crate::util::offset_at(&text, current.anchor), crate::util::offset_at(&text, current.cursor));
+            (a.min(b), a.max(b))
+        };
+        let Some((start, end)) = document.syntax.enclosing_node(&text, start, end) else { return };
+        let grown = Selection { anchor: crate::util::position_at(&text, start), cursor: crate::util::position_at(&text, end) };
+        self.extra_selections.clear();
+        self.selection_anchor = Some(grown.anchor);
+        document.buffer.cursor.move_to(grown.cursor);
+        self.grown_selections.push((current, grown));
+    }
+
+    /// Go back to the selection the last one was grown from, if it has not changed since.
+    pub fn narrow_selection(&mut self) {
+        let Some(cursor) = self.cursor_position() else { return };
+        let current = Selection { anchor: self.selection_anchor.unwrap_or(cursor), cursor };
+        match self.grown_selections.pop() {
+            Some((before, grown)) if grown == current => {
+                self.selection_anchor = Some(before.anchor).filter(|&anchor| anchor != before.cursor);
+                if let Some(mut buffer) = self.get_buffer() {
+                    buffer.cursor.move_to(before.cursor);
+                }
+            }
+            _ => self.grown_selections.clear(),
+        }
+    }
+
+    /// Fold away the lines under the cursor's line, as far as the comment, brackets or scope it
+    /// starts go, or unfold them if they are folded. A line starting none folds the innermost one
+    /// it is in instead, leaving the cursor on its first line.
+    pub fn toggle_fold(&mut self) {
+        let Some(mut document) = self.data.document() else { return };
+        let line = document.buffer.cursor.line;
+        if document.marks.unfold(line) {
+            return;
+        }
+        let text = document.buffer.data();
+        let ranges = document.syntax.fold_ranges(&text);
+        let fold = ranges.iter().find(|&&(first, _)| first == line)
+            .or_else(|| ranges.iter().filter(|&&(first, last)| first < line && line <= last).max_by_key(|&&(first, _)| first));
+        if let Some(&(first, last)) = fold {
+            document.marks.fold(first, last);
+            if first != line {
+                self.selection_anchor = None;
+                document.buffer.cursor.move_to(Position { line: first, offset: 0 });
+            }
+        }
+    }
 
-        // Draw the scrollbars
+    pub fn unfold_all(&mut self) {
+        if let Some(mut document) = self.data.document() {
+            document.marks.unfold_all();
+        }
+    }
+
+    /// Move the cursor to the start of the next or previous line starting a function, type or the
+    /// like, as told by indentation.
+    pub fn goto_definition(&mut self, forward: bool) {
+        let Some(mut document) = self.data.document() else { return };
+        let text = document.buffer.data();
+        let lines = Lines::new(&text);
+        if let Some(line) = crate::scopes::next_definition(&lines, document.buffer.cursor.line, forward) {
+            let offset = lines[line].graphemes(true).take_while(|&g| g == " " || g == "\t").count();
+            self.selection_anchor = None;
+            document.buffer.cursor.move_to(Position { line, offset });
+        }
+    }
+
+    /// Move the cursor to the opening or closing bracket of the innermost pair around it, or onto
+    /// the one around that if it is on that bracket already. Text without brackets around the
+    /// cursor goes by indentation instead, to the first or last line of the scope it is in.
+    pub fn goto_block_edge(&mut self, end: bool) {
+        let Some(mut document) = self.data.document() else { return };
+        let text = document.buffer.data();
+        let cursor = document.buffer.cursor.position;
+        let offset = crate::util::offset_at(&text, cursor);
+        let pair = crate::syntax::bracket_pairs(&text, document.syntax.language()).into_iter()
+            .filter(|&(open, close)| if end { open <= offset && offset < close } else { open < offset && offset <= close })
+            .min_by_key(|&(open, close)| close - open);
+        let target = match pair {
+            Some((open, close)) => crate::util::position_at(&text, if end { close } else { open }),
+            None => {
+                let lines = Lines::new(&text);
+                let scopes = crate::scopes::enclosing(&lines, cursor.line);
+                let line = match end {
+                    true => scopes.iter().rev().filter_map(|&scope| crate::scopes::last_line(&lines, scope)).find(|&last| last > cursor.line),
+                    false => scopes.last().copied(),
+                };
+                let Some(line) = line else { return };
+                Position { line, offset: lines[line].graphemes(true).take_while(|&g| g == " " || g == "\t").count() }
+            }
+        };
+        self.selection_anchor = None;
+        document.buffer.cursor.move_to(target);
+    }
+
+    /// Move the cursor up or down a line, past the lines folded away.
+    pub fn move_line(&mut self, down: bool) {
+        let Some(mut document) = self.data.document() else { return };
+        loop {
+            let line = document.buffer.cursor.line;
+            if down {
+                document.buffer.cursor.move_down();
+            } else {
+                document.buffer.cursor.move_up();
+            }
+            if document.buffer.cursor.line == line || !document.marks.is_hidden(document.buffer.cursor.line) {
+                break;
+            }
+        }
+    }
+
+    /// Go back to the main selection alone.
+    pub fn clear_extra_selections(&mut self) {
+        self.extra_selections.clear();
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(edit) = self.data.document().and_then(|mut document| document.history.undo()) {
+            self.apply_edit(&edit);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(edit) = self.data.document().and_then(|mut document| document.history.redo()) {
+            self.apply_edit(&edit);
+        }
+    }
+
+    /// Return the buffer to any state in its undo history.
+    pub fn restore(&mut self, id: usize) {
+        let edits = match self.data.document() {
+            Some(mut document) => document.history.restore(id),
+            None => return,
+        };
+        for edit in edits {
+            self.apply_edit(&edit);
+        }
+    }
+
+    /// Make an edit from the undo history to the buffer, leaving the cursor after it.
+    fn apply_edit(&mut self, edit: &Edit) {
+        if let Some(mut document) = self.data.document() {
+            self.selection_anchor = None;
+            self.extra_selections.clear();
+            let text = document.buffer.data();
+            let start = crate::util::position_at(&text, edit.start);
+            let end = crate::util::position_at(&text, edit.start + edit.removed.len());
+            if start != end {
+                document.buffer.delete_range(Range::new(start, end));
+            }
+            document.buffer.cursor.move_to(start);
+            document.buffer.insert(edit.inserted.as_str());
+            let cursor = crate::util::position_at(&document.buffer.data(), edit.start + edit.inserted.len());
+            document.buffer.cursor.move_to(cursor);
+            document.marks.lines_changed(start.line, edit.inserted.matches('\n').count() as isize - edit.removed.matches('\n').count() as isize);
+            document.marks.record_change(cursor);
+            document.line_index.edit(edit);
+            document.bracket_depths.invalidate(start.line);
+            document.syntax.edit(&text, start.line, edit);
+        }
+        // A table selects the cell that was changed
+        let records = self.table_records();
+        if let Some(table) = self.table.as_mut() {
+            table.select_at(&records, edit.start + edit.inserted.len());
+        }
+    }
+
+    /// Move the cursor to the start of the given line, or the last line if there are not that many.
+    pub fn goto_line(&mut self, line: usize) {
+        if let Some(mut document) = self.data.document() {
+            self.selection_anchor = None;
+            self.extra_selections.clear();
+            let line = line.min(document.line_count() - 1);
+            document.buffer.cursor.move_to(Position { line, offset: 0 });
+        }
+    }
+
+    pub fn toggle_bookmark(&mut self) {
+        if let Some(cursor) = self.cursor_position() {
+            self.toggle_bookmark_on(cursor.line);
+        }
+    }
+
+    /// Bookmark a line, or take its bookmark away.
+    pub fn toggle_bookmark_on(&mut self, line: usize) {
+        if let Some(mut document) = self.data.document() {
+            document.marks.toggle_bookmark(line);
+        }
+    }
+
+    /// Move the cursor to the next (or previous) bookmarked line, wrapping around the buffer.
+    pub fn goto_bookmark(&mut self, forward: bool) {
+        if let Some(mut document) = self.data.document() {
+            let line = document.buffer.cursor.line;
+            let target = if forward { document.marks.next_bookmark(line) } else { document.marks.previous_bookmark(line) };
+            if let Some(target) = target {
+                self.selection_anchor = None;
+                self.extra_selections.clear();
+                document.buffer.cursor.move_to(Position { line: target, offset: 0 });
+            }
+        }
+    }
+
+    /// Move the cursor back to an earlier edit location, cycling through the recent ones.
+    pub fn goto_previous_change(&mut self) {
+        if let Some(mut document) = self.data.document() {
+            let line = document.buffer.cursor.line;
+            if let Some(position) = document.marks.previous_change(line) {
+                self.selection_anchor = None;
+                self.extra_selections.clear();
+                let buffer = &mut document.buffer;
+                if !buffer.cursor.move_to(position) { // The line may have been shortened since
+                    buffer.cursor.move_to(Position { line: position.line, offset: 0 });
+                    buffer.cursor.move_to_end_of_line();
+                }
+            }
+        }
+    }
+
+    pub fn set_mark(&mut self, name: char) {
+        if let Some(mut document) = self.data.document() {
+            let position = document.buffer.cursor.position;
+            document.marks.set_mark(name, position);
+        }
+    }
+
+    /// Move the cursor to the named mark. Returns false if there is no such mark.
+    pub fn goto_mark(&mut self, name: char) -> bool {
+        let Some(mut document) = self.data.document() else { return false };
+        match document.marks.mark(name) {
+            Some(position) => {
+                self.selection_anchor = None;
+                self.extra_selections.clear();
+                let buffer = &mut document.buffer;
+                if !buffer.cursor.move_to(position) { // The line may have been shortened since
+                    buffer.cursor.move_to(Position { line: position.line, offset: 0 });
+                    buffer.cursor.move_to_end_of_line();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Show the text as a table of its delimited values, with the cell the cursor is in
+    /// selected, or go back to showing it as text with the cursor at the start of the cell.
+    pub fn toggle_table(&mut self) {
+        if self.table.take().is_some() {
+            return;
+        }
+        let Some(text) = self.text() else { return };
+        let path = self.get_buffer().and_then(|buf| buf.path.clone());
+        let delimiter = self.data.document().and_then(|document| document.delimiter());
+        let mut table = Table::new(delimiter.unwrap_or_else(|| table::delimiter(path.as_deref(), &text)));
+        let cursor = self.cursor_position().map_or(0, |position| crate::util::offset_at(&text, position));
+        table.select_at(&table::parse(&text, table.delimiter), cursor);
+        self.selection_anchor = None;
+        self.extra_selections.clear();
+        self.table = Some(table);
+        self.follow_table();
+    }
+
+    /// Take the text for values separated by a delimiter, or by the one told from the file if
+    /// there is none, for coloring its columns and showing it as a table.
+    pub fn set_delimiter(&mut self, delimiter: Option<char>) {
+        if let Some(mut document) = self.data.document() {
+            document.delimiter = delimiter;
+        }
+        // A table shown splits its cells again, keeping the cursor in the cell it is in
+        if let Some(pin_header) = self.table.as_ref().map(|table| table.pin_header) {
+            self.follow_table();
+            self.table = None;
+            self.toggle_table();
+            if let Some(table) = self.table.as_mut() {
+                table.pin_header = pin_header;
+            }
+        }
+    }
+
+    /// The records of the text, split the way the table shows them.
+    pub fn table_records(&self) -> Vec<Vec<table::Field>> {
+        match (&self.table, self.text()) {
+            (Some(table), Some(text)) => table::parse(&text, table.delimiter),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Put the cursor at the start of the selected cell, so that the text is where the table is
+    /// when it is shown as text again.
+    pub fn follow_table(&mut self) {
+        let records = self.table_records();
+        let Some(field) = self.table.as_ref().and_then(|table| table.selected(&records)) else { return };
+        let start = field.start;
+        if let (Some(text), Some(mut buf)) = (self.text(), self.get_buffer()) {
+            buf.cursor.move_to(crate::util::position_at(&text, start));
+        }
+    }
+
+    /// Give the selected cell of the table a new value, quoting it in the text as it needs.
+    pub fn set_cell(&mut self, value: &str) {
+        let records = self.table_records();
+        let Some(table) = &self.table else { return };
+        if let Some((start, end, new)) = table::edit(&records, table.row, table.column, value, table.delimiter) {
+            self.replace_bytes(start, end, &new);
+            self.selection_anchor = None;
+            self.follow_table();
+        }
+    }
+}
+
+/// A named group of viewports, like `frontend` and `backend`. Only the active workspace's
+/// viewports are shown; the others wait here as they were left until it is switched to.
+pub struct Workspace {
+    pub name: String,
+    viewports: Vec<Viewport>,
+    focus_index: usize,
+    z_order: Vec<usize>,
+    tiled_id: usize,
+}
+
+impl Workspace {
+    fn new(name: String) -> Workspace {
+        Workspace { name, viewports: Vec::new(), focus_index: 0, z_order: Vec::new(), tiled_id: 0 }
+    }
+}
+
+/// What the first workspace is called, before the user makes any others.
+static DEFAULT_WORKSPACE: &str = "main";
+
+/// Manages and renders zero or more viewports at any given time. The Viewport Manager
+/// tiles viewports, relocating and resizing them so they can fit better within the
+/// margins of the screen. This also dispatches events and commands to those viewports,
+/// and renders their bounding boxes and titles.
+pub struct ViewportManager {
+    pub origin: (u16, u16),
+    pub size: (usize, usize),
+    pub viewports: Vec<Viewport>,
+    pub focus_index: usize, // Current index for focused viewport
+    pub clipboard: Clipboard,
+    pub registers: Registers,
+    /// Every open document, including those no viewport shows.
+    pub buffers: BufferStore,
+    pub jump_list: JumpList,
+    /// The search whose matches are highlighted in the viewport, until they are cleared.
+    pub highlight: Option<Search>,
+    /// The viewports whose scrolling is linked, if any are.
+    pub scroll_link: Option<ScrollLink>,
+    /// The viewports compared side by side, if any are.
+    pub comparison: Option<Comparison>,
+    /// The files of closed viewports and where their cursors were left, the most recently closed
+    /// last.
+    pub closed_files: Vec<(PathBuf, crate::positions::Remembered)>,
+    /// The titles and text of untitled buffers closed without saving, the most recently closed
+    /// last, to get back one discarded by mistake.
+    pub discarded: Vec<(String, String)>,
+    /// How every viewport shows its text.
+    pub view: ViewOptions,
+    /// Every workspace, in the order they were made. The active one's viewports are the ones
+    /// above, and its own list is empty until another is switched to.
+    pub workspaces: Vec<Workspace>,
+    /// The index of the active workspace.
+    pub workspace: usize,
+    /// Whether typing an opening bracket puts in the closing one too.
+    pub auto_close: bool,
+    /// Whether keys go in as they are, without indenting new lines, closing brackets, expanding
+    /// abbreviations or offering paths, for terminals that paste by typing the text out.
+    pub paste_mode: bool,
+    // The line whose number the mouse was pressed on, while lines are selected by dragging over
+    // their numbers.
+    line_drag: Option<usize>,
+    // The ids of the floating viewports, from the bottom one to the one on top.
+    z_order: Vec<usize>,
+    // The id of the tiled viewport last focused, which stays in view under the floating ones.
+    tiled_id: usize,
+    next_viewport_id: usize,
+}
+
+impl ViewportManager {
+    pub fn new(origin: (u16, u16), size: (usize, usize)) -> ViewportManager {
+        ViewportManager {
+            origin,
+            size,
+            viewports: Vec::new(),
+            focus_index: 0,
+            clipboard: Clipboard::new(),
+            registers: Registers::default(),
+            buffers: BufferStore::default(),
+            jump_list: JumpList::default(),
+            highlight: None,
+            scroll_link: None,
+            comparison: None,
+            closed_files: Vec::new(),
+            discarded: Vec::new(),
+            view: ViewOptions::default(),
+            workspaces: vec![Workspace::new(DEFAULT_WORKSPACE.to_owned())],
+            workspace: 0,
+            auto_close: crate::config::get().auto_close_brackets,
+            paste_mode: false,
+            line_drag: None,
+            z_order: Vec::new(),
+            tiled_id: 0,
+            next_viewport_id: 0,
+        }
+    }
+
+    pub fn render<S: Write>(&mut self, s: &mut S, has_focus: bool) {
+        // Images shown last time are drawn again if they are still in view
+        crate::image::clear(s);
+        if self.viewports.is_empty() {
+            return; // No need to render nothing.
+        }
+
+        // Fit every tiled viewport to the space we have, which changes as the terminal is resized,
+        // and keep the floating ones within it. In zen mode the tiled ones go without a frame, as do
+        // those in compact mode.
+        for v in self.viewports.iter_mut() {
+            v.view = self.view;
+            if v.floating {
+                v.size = (v.size.0.max(MIN_FLOATING_SIZE.0).min(self.size.0.saturating_sub(1)), v.size.1.max(MIN_FLOATING_SIZE.1).min(self.size.1.saturating_sub(2)));
+                v.origin = (
+                    v.origin.0.clamp(self.origin.0 + 1, self.origin.0 + (self.size.0 - v.size.0).max(1) as u16),
+                    v.origin.1.clamp(self.origin.1 + 1, self.origin.1 + self.size.1.saturating_sub(1 + v.size.1).max(1) as u16),
+                );
+            } else if self.view.zen || v.compact {
+                v.origin = self.origin;
+                v.size = self.size;
+            } else {
+                // The bar showing where the cursor is takes the row under the frame's top
+                let bar = v.has_breadcrumbs() as usize;
+                v.origin = (self.origin.0 + 1, self.origin.1 + 1 + bar as u16);
+                v.size = (self.size.0.saturating_sub(1), self.size.1.saturating_sub(2 + bar));
+            }
+        }
+
+        self.mark_differences();
+        for v in self.viewports.iter_mut() {
+            v.mark_unsaved_changes();
+        }
+
+        // The focused viewport, or the tiled one under it if it floats
+        if !self.viewports[self.focus_index].floating {
+            self.tiled_id = self.viewports[self.focus_index].id;
+        }
+        match self.tiled_index() {
+            Some(tiled) => self.render_tiled(s, tiled, has_focus),
+            None => crate::util::draw_rectangle(s, &Color::Blue, self.origin, (self.size.0 + 1, self.size.1)),
+        }
+
+        // Then the floating ones over it, the top one last
+        for id in self.z_order.clone() {
+            if let Some(i) = self.viewports.iter().position(|v| v.id == id) {
+                self.render_floating(s, i, has_focus);
+            }
+        }
+        // The focused viewport's cursor is put back, unless a floating one is over it
+        let focused = &self.viewports[self.focus_index];
+        if has_focus && !focused.floating && !self.z_order.is_empty() {
+            match focused.cursor_cell() {
+                Some((x, y)) if !self.viewports.iter().any(|v| v.floating && v.covers(x, y)) => queue!(s, cursor::MoveTo(x, y), cursor::Show),
+                _ => queue!(s, cursor::Hide),
+            };
+        }
+        // The paths offered for what is being typed go over everything, by the cursor
+        if let (true, Some(completion), Some((x, y))) = (has_focus, &focused.completion, focused.cursor_cell()) {
+            completion.render(s, (x, y));
+            queue!(s, cursor::MoveTo(x, y));
+        }
+        self.follow_scroll_link();
+    }
+
+    /// Draw a tiled viewport with its frame, the tabs of all the tiled viewports and a scrollbar,
+    /// or on its own in zen mode or compact mode.
+    fn render_tiled<S: Write>(&mut self, s: &mut S, index: usize, has_focus: bool) {
+        let (v_origin, v_size) = {
+            let v = &self.viewports[index];
+            (v.origin, v.size)
+        };
+        if self.view.zen || self.viewports[index].compact {
+            crate::util::draw_rectangle(s, &Color::Blue, v_origin, (v_size.0, v_size.1.saturating_sub(1)));
+            return self.viewports[index].render(s, has_focus && index == self.focus_index, self.highlight.as_ref());
+        }
+
+        // The frame goes around the bar over the view too, if it has one
+        let bar = self.viewports[index].has_breadcrumbs() as u16;
+        let (frame_origin, frame_size) = ((v_origin.0-1, v_origin.1-1-bar), (v_size.0+1, v_size.1+1+bar as usize));
+        // Draw the inside of the bounding box
+        crate::util::draw_rectangle(s, &Color::Blue, frame_origin, frame_size);
+        // Draw the Viewport's 'beam' bounding box
+        crate::util::draw_thin_unfilled_rectangle(s, &Color::Grey, &Color::Blue, frame_origin, frame_size);
+
+        for (index_of_viewport, x, t) in self.tabs(v_origin, v_size) {
+            if index_of_viewport == index {
+                queue!(s,
+                    cursor::MoveTo(x, frame_origin.1), style::SetForegroundColor(Color::Blue), style::SetBackgroundColor(Color::Grey),
+                    style::Print(format!(" {} ", t)),
+                );
+            } else {
+                let tab = match crate::config::get().screen_reader {
+                    true => format!(" {} ", t),
+                    false => format!("┤{}├", t), // NOTE: skip a char each time
+                };
+                queue!(s, cursor::MoveTo(x, frame_origin.1), style::Print(tab));
+            }
+        }
+
+        // Draw the scrollbars, for a viewport with text to scroll through
         // Scrollbar height must be between 1 and v_size.1 (height of viewport).
-        let scrollbar_height: usize = flt_min((v_size.1 - 1) as f32, flt_max(1.0, v_size.1 as f32 * (v_size.1 as f32 / self.viewports[self.focus_index].get_buffer().unwrap().line_count() as f32))) as usize;
-        let scrollbar_v_origin: u16 = v_origin.1 + (f32::from(v_size.1 as u16) * self.viewports[self.focus_index].vertical_scroll_percent()) as u16 - scrollbar_height as u16;
-        for i in 0..scrollbar_height {
-            queue!(s, cursor::MoveTo(v_origin.0 + v_size.0 as u16 - 1, i as u16 + scrollbar_v_origin - 1), style::Print("X"));
+        let line_count = self.viewports[index].data.document().map(|mut document| document.line_count());
+        if let (Some(line_count), false, true) = (line_count, crate::config::get().screen_reader, v_size.0 > 0 && v_size.1 > 1) {
+            let scrollbar_height: usize = flt_min((v_size.1 - 1) as f32, flt_max(1.0, v_size.1 as f32 * (v_size.1 as f32 / line_count as f32))) as usize;
+            let scrollbar_v_origin: u16 = v_origin.1 + (f32::from(v_size.1 as u16) * self.viewports[index].vertical_scroll_percent()) as u16 - scrollbar_height as u16;
+            for i in 0..scrollbar_height {
+                queue!(s, cursor::MoveTo(v_origin.0 + v_size.0 as u16 - 1, i as u16 + scrollbar_v_origin - 1), style::Print("X"));
+            }
+        }
+
+        if bar > 0 {
+            self.viewports[index].render_breadcrumbs(s, has_focus && index == self.focus_index);
+        }
+        self.viewports[index].render(s, has_focus && index == self.focus_index, self.highlight.as_ref());
+    }
+
+    /// The tabs of the tiled viewports over a view, as the index of each viewport, the column its
+    /// tab starts at and its title.
+    fn tabs(&self, v_origin: (u16, u16), v_size: (usize, usize)) -> Vec<(usize, u16, String)> {
+        let titles: Vec<(usize, String)> = self.viewports.iter().enumerate().filter(|(_, v)| !v.floating).map(|(i, v)| {
+            let mut title = crate::util::sanitize(&v.title);
+            if v.is_modified() {
+                title.insert(0, '*');
+            }
+            (i, title)
+        }).collect();
+        let total_len: usize = titles.len() * 3 + titles.iter().map(|(_, t)| t.len()).sum::<usize>(); // The number characters all of the titles will take up
+
+        let starting_x: u16 = v_origin.0 + (v_size.0/2).saturating_sub(total_len/2) as u16;
+        titles.into_iter().enumerate().map(|(i, (index, t))| (index, starting_x + (i * (t.len() + 3)) as u16, t)).collect()
+    }
+
+    /// What a tooltip over a cell says, if anything: the whole title of a tab cut short at the
+    /// edge of the frame, or of a floating viewport too narrow for it, or what the viewport there
+    /// tells of the cell.
+    pub fn tooltip_at(&self, x: u16, y: u16) -> Option<String> {
+        let index = self.viewport_at_cell(x, y)?;
+        let v = &self.viewports[index];
+        let framed = !(self.view.zen || v.compact);
+        let frame_top = v.origin.1.checked_sub(1 + (!v.floating && v.has_breadcrumbs()) as u16);
+        if framed && Some(y) == frame_top {
+            let title = match v.floating {
+                true => crate::util::sanitize(&v.title),
+                false => {
+                    let frame_end = v.origin.0 as usize + v.size.0;
+                    let (tab, start, title) = self.tabs(v.origin, v.size).into_iter().find(|&(_, start, ref t)| x >= start && (x as usize) < start as usize + t.len() + 2)?;
+                    return (start as usize + title.chars().count() + 2 > frame_end).then(|| self.viewports[tab].title.clone());
+                }
+            };
+            return (title.chars().count() > (v.size.0 + 1).saturating_sub(4)).then(|| v.title.clone());
+        }
+        v.tooltip_at(x, y)
+    }
+
+    /// Draw a floating viewport in a frame of its own, with its title on top.
+    fn render_floating<S: Write>(&mut self, s: &mut S, index: usize, has_focus: bool) {
+        let focused = index == self.focus_index;
+        let v = &mut self.viewports[index];
+        let (frame_origin, frame_size) = ((v.origin.0 - 1, v.origin.1 - 1), (v.size.0 + 1, v.size.1 + 1));
+        crate::util::draw_rectangle(s, &Color::Blue, frame_origin, frame_size);
+        crate::util::draw_thin_unfilled_rectangle(s, &if focused { Color::White } else { Color::Grey }, &Color::Blue, frame_origin, frame_size);
+
+        let mut title = crate::util::sanitize(&v.title);
+        if v.is_modified() {
+            title.insert(0, '*');
         }
-        queue!(s, cursor::MoveTo(v_origin.0, v_origin.1 + v_size.1 as u16 - 1),
-            style::Print(format!("scroll% = {}", self.viewports[self.focus_index].vertical_scroll_percent() * 100.0))
-        );
+        let title: String = title.chars().take(frame_size.0.saturating_sub(4)).collect();
+        let x = frame_origin.0 + (frame_size.0 / 2).saturating_sub(title.chars().count() / 2 + 1) as u16;
+        match focused {
+            true => queue!(s, cursor::MoveTo(x, frame_origin.1), style::SetForegroundColor(Color::Blue), style::SetBackgroundColor(Color::Grey), style::Print(format!(" {} ", title))),
+            false => queue!(s, cursor::MoveTo(x, frame_origin.1), style::SetForegroundColor(Color::Grey), style::SetBackgroundColor(Color::Blue), style::Print(format!(" {} ", title))),
+        };
+        v.render(s, has_focus && focused, self.highlight.as_ref());
+    }
 
-        self.viewports[self.focus_index].render(s, has_focus);
+    /// Pop the focused viewport out into a floating window over the others, or put a floating one
+    /// back among the tabs.
+    pub fn toggle_floating(&mut self) {
+        let (origin, size) = (self.origin, self.size);
+        let v = match self.viewports.get_mut(self.focus_index) {
+            Some(v) => v,
+            None => return,
+        };
+        v.floating = !v.floating;
+        if v.floating {
+            // Two thirds of the space, in the middle
+            v.size = ((size.0 * 2 / 3).max(MIN_FLOATING_SIZE.0), (size.1 * 2 / 3).max(MIN_FLOATING_SIZE.1));
+            v.origin = (origin.0 + (size.0.saturating_sub(v.size.0) / 2) as u16, origin.1 + (size.1.saturating_sub(v.size.1) / 2) as u16);
+            self.z_order.push(v.id);
+        } else {
+            let id = v.id;
+            self.z_order.retain(|&z| z != id);
+        }
+    }
+
+    /// Show the focused viewport's document in another viewport as well, floating over it. The
+    /// new one has a cursor and scrolling of its own, while edits made in either show in both.
+    pub fn split_view(&mut self) {
+        let (data, title, renamed, indentation, start) = match self.viewports.get(self.focus_index) {
+            Some(v) => match v.data.share() {
+                Some(data) => (data, v.title.clone(), v.renamed, v.indentation, v.starting_visible_line),
+                None => return,
+            },
+            None => return,
+        };
+        let index = self.new_viewport(data);
+        let v = &mut self.viewports[index];
+        v.title = title;
+        v.renamed = renamed;
+        v.indentation = indentation;
+        v.starting_visible_line = start;
+        self.focus_viewport(index);
+        self.toggle_floating();
     }
 
-    pub fn handle_key_event(&mut self, key: KeyEvent) {
-        if self.viewports.is_empty() {
-            return; // We cannot handle input without viewports
+    /// Move and resize the focused viewport, if it floats. It is kept within the editor's space
+    /// when it is next rendered.
+    pub fn move_floating(&mut self, by: (isize, isize), grow: (isize, isize)) {
+        if let Some(v) = self.viewports.get_mut(self.focus_index).filter(|v| v.floating) {
+            v.origin = ((v.origin.0 as isize + by.0).max(0) as u16, (v.origin.1 as isize + by.1).max(0) as u16);
+            v.size = ((v.size.0 as isize + grow.0).max(0) as usize, (v.size.1 as isize + grow.1).max(0) as usize);
+        }
+    }
+
+    /// The viewport drawn at a cell of the screen: the top floating one there, or the tiled one.
+    fn viewport_at_cell(&self, x: u16, y: u16) -> Option<usize> {
+        self.z_order.iter().rev()
+            .filter_map(|&id| self.viewports.iter().position(|v| v.id == id))
+            .find(|&i| self.viewports[i].covers(x, y))
+            .or_else(|| self.tiled_index())
+    }
+
+    /// The tiled viewport in view: the focused one, or the last focused if a floating one is.
+    fn tiled_index(&self) -> Option<usize> {
+        self.viewports.iter().position(|v| v.id == self.tiled_id && !v.floating)
+            .or_else(|| self.viewports.iter().position(|v| !v.floating))
+    }
+
+    pub fn handle_key_event<S: Write>(&mut self, s: &mut S, key: KeyEvent) {
+        if self.viewports.is_empty() {
+            return; // We cannot handle input without viewports
+        }
+        // A viewport still waiting for its file, or showing an image, can only be closed, and a
+        // manual page or a followed file scrolled as well. `/` picks the lines a followed file
+        // highlights.
+        if let Loading(..) | Image(_) | Page(_) | Tail(_) = self.viewports[self.focus_index].data {
+            if matches!(key, KeyEvent { code: KeyCode::Char('q'), modifiers: KeyModifiers::CONTROL, .. }) {
+                self.close_focused_viewport();
+            } else if let Page(page) = &mut self.viewports[self.focus_index].data {
+                page.handle_key(key.code);
+            } else if let Tail(tail) = &mut self.viewports[self.focus_index].data {
+                if key.code == KeyCode::Char('/') {
+                    if let Some(pattern) = crate::util::input(s, "Highlight lines matching", tail.highlight.as_ref().map(|h| h.as_str().to_owned()).unwrap_or_default(), crate::util::InputType::Any) {
+                        match pattern.is_empty() {
+                            true => tail.highlight = None,
+                            false => match regex::Regex::new(&pattern) {
+                                Ok(regex) => tail.highlight = Some(regex),
+                                Err(e) => crate::util::alert(s, "Highlight lines matching", &format!("{} is not a pattern: {}", pattern, e)),
+                            },
+                        }
+                    }
+                } else {
+                    tail.handle_key(key.code);
+                }
+            }
+            return;
+        }
+        if self.viewports[self.focus_index].table.is_some() {
+            return self.handle_table_key(s, key);
+        }
+
+        let (auto_close, paste_mode) = (self.auto_close && !self.paste_mode, self.paste_mode);
+        let focused_viewport = &mut self.viewports[self.focus_index];
+        let expansion = focused_viewport.expansion.take();
+
+        // While paths are offered, Up and Down pick one and Tab or Enter puts it in
+        let completing = match focused_viewport.completion.take() {
+            Some(mut completion) => match key.code {
+                KeyCode::Up | KeyCode::Down if key.modifiers.is_empty() => {
+                    completion.select(key.code == KeyCode::Down);
+                    focused_viewport.completion = Some(completion);
+                    return;
+                }
+                KeyCode::Tab | KeyCode::Enter if key.modifiers.is_empty() => return focused_viewport.accept_completion(completion),
+                _ => true,
+            },
+            None => false,
+        };
+
+        // Holding shift while moving the cursor extends the selection, moving without it drops the selection
+        // and any extra selections.
+        if let KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right = key.code {
+            if key.modifiers == KeyModifiers::CONTROL && matches!(key.code, KeyCode::Up | KeyCode::Down) {
+                // Adding to numbers, which keeps every cursor where it is
+            } else if !key.modifiers.contains(KeyModifiers::SHIFT) {
+                focused_viewport.selection_anchor = None;
+                focused_viewport.clear_extra_selections();
+            } else if focused_viewport.selection_anchor.is_none() {
+                focused_viewport.selection_anchor = focused_viewport.cursor_position();
+            }
         }
 
-        let focused_viewport = &mut self.viewports[self.focus_index];
         match key {
-            KeyEvent { code: KeyCode::Char('q'), modifiers: event::KeyModifiers::CONTROL } => self.close_focused_viewport(),
-            KeyEvent { code: KeyCode::Char(c), .. } => focused_viewport.insert(c),
-            KeyEvent { code: KeyCode::Enter, .. } => focused_viewport.insert('\n'),
-            KeyEvent { code: KeyCode::Tab, .. } => focused_viewport.insert('\t'),
-            KeyEvent { code: KeyCode::Backspace, .. } => focused_viewport.backspace(),
+            KeyEvent { code: KeyCode::Char('q'), modifiers: KeyModifiers::CONTROL, .. } => self.close_focused_viewport(),
+            KeyEvent { code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL, .. } => self.copy(s),
+            KeyEvent { code: KeyCode::Char('x'), modifiers: KeyModifiers::CONTROL, .. } => self.cut(s),
+            KeyEvent { code: KeyCode::Char('v'), modifiers: KeyModifiers::CONTROL, .. } => self.paste(),
+            KeyEvent { code: KeyCode::Char('b'), modifiers: KeyModifiers::CONTROL, .. } => focused_viewport.toggle_bookmark(),
+            KeyEvent { code: KeyCode::Char('z'), modifiers: KeyModifiers::CONTROL, .. } => focused_viewport.undo(),
+            KeyEvent { code: KeyCode::Char('y'), modifiers: KeyModifiers::CONTROL, .. } => focused_viewport.redo(),
+            KeyEvent { code: KeyCode::Char('d'), modifiers: KeyModifiers::CONTROL, .. } => focused_viewport.select_next_occurrence(false),
+            KeyEvent { code: KeyCode::Char('d'), modifiers: KeyModifiers::ALT, .. } => focused_viewport.select_next_occurrence(true),
+            KeyEvent { code: KeyCode::Char('g'), modifiers: KeyModifiers::CONTROL, .. } => self.prompt_goto_line(s),
+            KeyEvent { code: KeyCode::Char('l'), modifiers: KeyModifiers::CONTROL, .. } => focused_viewport.scroll_cursor_line(LineAt::Center),
+            KeyEvent { code: KeyCode::Char('e'), modifiers: KeyModifiers::CONTROL, .. } => {
+                self.record_jump();
+                self.viewports[self.focus_index].goto_previous_change();
+            }
+            KeyEvent { code: KeyCode::F(2), modifiers, .. } => {
+                self.record_jump();
+                self.viewports[self.focus_index].goto_bookmark(!modifiers.contains(KeyModifiers::SHIFT));
+            }
+            KeyEvent { code: KeyCode::Up, modifiers: KeyModifiers::CONTROL, .. } => { focused_viewport.add_to_numbers(1, false); }
+            KeyEvent { code: KeyCode::Down, modifiers: KeyModifiers::CONTROL, .. } => { focused_viewport.add_to_numbers(-1, false); }
+            KeyEvent { code: KeyCode::Left, modifiers: KeyModifiers::ALT, .. } => self.jump_back(),
+            KeyEvent { code: KeyCode::Right, modifiers: KeyModifiers::ALT, .. } => self.jump_forward(),
+            // In paste mode the keys of the pasted text go in as they are
+            KeyEvent { code: KeyCode::Char(c), modifiers, .. } if paste_mode && !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => focused_viewport.insert(c),
+            KeyEvent { code: KeyCode::Enter, .. } if paste_mode => focused_viewport.insert('\n'),
+            KeyEvent { code: KeyCode::Tab, .. } if paste_mode => focused_viewport.insert('\t'),
+            KeyEvent { code: KeyCode::Char(c), modifiers, .. } if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                if !abbreviations::is_word_char(c) {
+                    focused_viewport.expand_abbreviation();
+                }
+                focused_viewport.type_char(c, auto_close);
+                focused_viewport.outdent_closing_line();
+                focused_viewport.complete_path();
+            }
+            // With Alt, the character after an abbreviation leaves it as it is
+            KeyEvent { code: KeyCode::Char(c), modifiers: KeyModifiers::ALT, .. } if !abbreviations::is_word_char(c) => focused_viewport.type_char(c, auto_close),
+            KeyEvent { code: KeyCode::Enter, modifiers, .. } => {
+                if !modifiers.contains(KeyModifiers::ALT) {
+                    focused_viewport.expand_abbreviation();
+                }
+                focused_viewport.new_line();
+            }
+            KeyEvent { code: KeyCode::Tab, .. } => focused_viewport.indent(),
+            KeyEvent { code: KeyCode::Backspace, .. } => {
+                if expansion.is_some_and(|expansion| focused_viewport.revert_expansion(expansion)) {
+                    return;
+                }
+                if !(auto_close && focused_viewport.delete_empty_pair()) {
+                    focused_viewport.backspace();
+                }
+                if completing {
+                    focused_viewport.complete_path();
+                }
+            }
             KeyEvent { code: KeyCode::Delete, .. } => focused_viewport.delete(),
-            KeyEvent { code: KeyCode::Up, .. } => focused_viewport.get_buffer().unwrap().cursor.move_up(),
-            KeyEvent { code: KeyCode::Down, .. } => focused_viewport.get_buffer().unwrap().cursor.move_down(),
+            KeyEvent { code: KeyCode::Up, .. } => focused_viewport.move_line(false),
+            KeyEvent { code: KeyCode::Down, .. } => focused_viewport.move_line(true),
             KeyEvent { code: KeyCode::Right, .. } => focused_viewport.get_buffer().unwrap().cursor.move_right(),
             KeyEvent { code: KeyCode::Left, .. } => focused_viewport.get_buffer().unwrap().cursor.move_left(),
-            _ => crate::util::alert(&mut std::io::stdout(), "Unhandled key event", &format!("{:?}", key)),
+            _ => crate::util::alert(s, "Unhandled key event", &format!("{:?}", key)),
+        }
+    }
+
+    /// Keys for a viewport showing its text as a table: they move between the cells, and typing,
+    /// Enter or pasting gives the selected one a new value.
+    fn handle_table_key<S: Write>(&mut self, s: &mut S, key: KeyEvent) {
+        let viewport = &mut self.viewports[self.focus_index];
+        let records = viewport.table_records();
+        let Some(table) = viewport.table.as_mut() else { return };
+        let value = table.selected(&records).map(|field| field.value.clone()).unwrap_or_default();
+        let initial = match key {
+            KeyEvent { code: KeyCode::Char('q'), modifiers: KeyModifiers::CONTROL, .. } => return self.close_focused_viewport(),
+            KeyEvent { code: KeyCode::Char('z'), modifiers: KeyModifiers::CONTROL, .. } => return viewport.undo(),
+            KeyEvent { code: KeyCode::Char('y'), modifiers: KeyModifiers::CONTROL, .. } => return viewport.redo(),
+            KeyEvent { code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL, .. } => {
+                if let Err(message) = self.clipboard.copy(s, value) {
+                    log::warn!("Copy: {}", message);
+                    crate::util::alert(s, "Copy", &message);
+                }
+                return;
+            }
+            KeyEvent { code: KeyCode::Char('v'), modifiers: KeyModifiers::CONTROL, .. } => {
+                let pasted = self.clipboard.contents().trim_end_matches(['\n', '\r']).to_owned();
+                return self.viewports[self.focus_index].set_cell(&pasted);
+            }
+            KeyEvent { code: KeyCode::Delete, .. } | KeyEvent { code: KeyCode::Backspace, .. } => return viewport.set_cell(""),
+            KeyEvent { code: KeyCode::Enter, .. } => value,
+            KeyEvent { code: KeyCode::Char(c), modifiers, .. } if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => c.to_string(),
+            KeyEvent { code, modifiers, .. } => {
+                if table.handle_key(code, modifiers, &records) {
+                    viewport.follow_table();
+                }
+                return;
+            }
+        };
+        if let Some(value) = crate::util::input(s, "Edit cell", initial, crate::util::InputType::Any) {
+            self.viewports[self.focus_index].set_cell(&value);
         }
     }
 
@@ -289,33 +2770,899 @@ impl ViewportManager {
         self.viewports.get_mut(self.focus_index)
     }
 
+    /// Where the cursor is right now, as far as the jump list is concerned.
+    fn current_location(&self) -> Option<Jump> {
+        let viewport = self.viewports.get(self.focus_index)?;
+        Some(Jump { viewport_id: viewport.id, position: viewport.cursor_position()? })
+    }
+
+    /// Remember the current location in the jump list. Call this before any significant cursor jump.
+    pub fn record_jump(&mut self) {
+        if let Some(here) = self.current_location() {
+            self.jump_list.record(here);
+        }
+    }
+
+    /// Travel back through the jump list.
+    pub fn jump_back(&mut self) {
+        if let Some(here) = self.current_location() {
+            if let Some(jump) = self.jump_list.back(here) {
+                self.goto_jump(jump);
+            }
+        }
+    }
+
+    /// Travel forward through the jump list.
+    pub fn jump_forward(&mut self) {
+        if let Some(jump) = self.jump_list.forward() {
+            self.goto_jump(jump);
+        }
+    }
+
+    fn goto_jump(&mut self, jump: Jump) {
+        // A jump into another workspace goes there
+        if let Some(workspace) = self.workspaces.iter().position(|w| w.viewports.iter().any(|v| v.id == jump.viewport_id)) {
+            self.switch_workspace(workspace);
+        }
+        if let Some(index) = self.viewports.iter().position(|v| v.id == jump.viewport_id) {
+            self.focus_index = index;
+            let viewport = &mut self.viewports[index];
+            viewport.selection_anchor = None;
+            viewport.extra_selections.clear();
+            if let Some(mut buf) = viewport.get_buffer() {
+                buf.cursor.move_to(jump.position);
+            }
+        }
+    }
+
+    /// Focus the viewport at the given index, remembering where we came from.
+    pub fn focus_viewport(&mut self, index: usize) {
+        if index != self.focus_index {
+            self.record_jump();
+            self.focus_index = index;
+        }
+        // A floating viewport comes to the top when it is focused
+        let id = self.viewports[index].id;
+        if !self.viewports[index].floating {
+            self.tiled_id = id;
+        } else if self.z_order.last() != Some(&id) {
+            self.z_order.retain(|&z| z != id);
+            self.z_order.push(id);
+        }
+    }
+
+    /// Ask the user for a line number, and move the focused viewport's cursor there.
+    pub fn prompt_goto_line<S: Write>(&mut self, s: &mut S) {
+        if self.viewports.is_empty() {
+            return;
+        }
+        if let Some(input) = crate::util::input(s, "Go to line", String::new(), crate::util::InputType::Any) {
+            match input.trim().parse::<usize>() {
+                Ok(line) if line > 0 => {
+                    self.record_jump();
+                    self.viewports[self.focus_index].goto_line(line - 1);
+                }
+                _ => crate::util::alert(s, "Go to line", &format!("'{}' is not a line number.", input)),
+            }
+        }
+    }
+
+    /// The focused viewport's selection, or the line under the cursor if nothing is selected.
+    fn text_to_copy(&mut self) -> Option<String> {
+        let viewport = self.viewports.get_mut(self.focus_index)?;
+        viewport.selected_text().or_else(|| {
+            let buf = viewport.get_buffer()?;
+            Some(format!("{}\n", Lines::new(&buf.data()).line(buf.cursor.line)))
+        })
+    }
+
+    /// Copy the focused viewport's selection, or the line under the cursor if nothing is selected.
+    pub fn copy<S: Write>(&mut self, s: &mut S) {
+        let Some(text) = self.text_to_copy() else { return };
+        if let Err(message) = self.clipboard.copy(s, text) {
+            log::warn!("Copy: {}", message);
+            crate::util::alert(s, "Copy", &message);
+        }
+    }
+
+    /// Copy the focused viewport's selection, then delete it.
+    pub fn cut<S: Write>(&mut self, s: &mut S) {
+        if self.viewports.get(self.focus_index).and_then(|v| v.selection()).is_some() {
+            self.copy(s);
+            self.viewports[self.focus_index].delete_selection();
+        }
+    }
+
+    pub fn paste(&mut self) {
+        if let Some(viewport) = self.viewports.get_mut(self.focus_index) {
+            viewport.paste(self.clipboard.contents());
+        }
+    }
+
+    /// Let the user pick one of the recently copied snippets, and paste it. It goes back on the
+    /// clipboard too, so pasting again repeats it.
+    pub fn paste_from_history<S: Write>(&mut self, s: &mut S) {
+        let history = self.clipboard.history();
+        if history.is_empty() {
+            crate::util::alert(s, "Paste from history", "Nothing has been copied yet.");
+            return;
+        }
+        let items: Vec<String> = history.iter().map(|text| describe_snippet(text)).collect();
+        if let Some(choice) = crate::util::choose(s, "Paste from history", &items, 0) {
+            let text = history[choice].to_owned();
+            if let Err(message) = self.clipboard.copy(s, text) {
+                log::warn!("Paste from history: {}", message);
+            }
+            self.paste();
+        }
+    }
+
+    /// Copy the focused viewport's selection, or the line under the cursor if nothing is selected,
+    /// into a register, deleting the selection when cutting.
+    pub fn copy_to_register<S: Write>(&mut self, s: &mut S, name: char, cut: bool) {
+        let title = if cut { "Cut to register" } else { "Copy to register" };
+        let Some(text) = self.text_to_copy() else { return };
+        if !self.registers.set(name, text) {
+            return crate::util::alert(s, title, "Only the registers a to z can be copied into.");
+        }
+        if cut && self.viewports[self.focus_index].selection().is_some() {
+            self.viewports[self.focus_index].delete_selection();
+        }
+    }
+
+    /// Paste the text in a register into the focused viewport.
+    pub fn paste_from_register<S: Write>(&mut self, s: &mut S, name: char) {
+        let Some(viewport) = self.viewports.get_mut(self.focus_index) else { return };
+        match self.registers.get(name, &self.clipboard) {
+            Some(text) => viewport.paste(text),
+            None => crate::util::alert(s, "Paste from register", &format!("Register {} is empty.", name)),
+        }
+    }
+
+    /// Let the user pick one of the registers with text in them, and paste it.
+    pub fn choose_register<S: Write>(&mut self, s: &mut S) {
+        let filled = self.registers.filled(&self.clipboard);
+        if filled.is_empty() {
+            return crate::util::alert(s, "Registers", "Nothing has been copied yet.");
+        }
+        let items: Vec<String> = filled.iter().map(|(name, text)| format!("{}  {}", name, describe_snippet(text))).collect();
+        if let Some(choice) = crate::util::choose(s, "Registers", &items, 0) {
+            let name = filled[choice].0;
+            self.paste_from_register(s, name);
+        }
+    }
+
+    /// The viewport with a segment of the bar showing where its cursor is at a cell of the screen,
+    /// and the segment.
+    pub fn breadcrumb_at(&self, x: u16, y: u16) -> Option<(usize, Segment)> {
+        let index = self.viewport_at_cell(x, y)?;
+        self.viewports[index].breadcrumb_at(x, y).map(|segment| (index, segment.clone()))
+    }
+
+    /// Go to one of the scopes beside the one a line of the focused viewport starts, picked from
+    /// a list of them.
+    pub fn pick_scope<S: Write>(&mut self, s: &mut S, line: usize) {
+        let Some(text) = self.viewports.get(self.focus_index).and_then(|v| v.text()) else { return };
+        let lines = Lines::new(&text);
+        let siblings = crate::scopes::siblings(&lines, line);
+        let items: Vec<String> = siblings.iter().map(|&l| crate::scopes::name(&lines[l])).collect();
+        let current = siblings.iter().position(|&l| l == line).unwrap_or(0);
+        if let Some(choice) = crate::util::choose(s, "Scopes", &items, current) {
+            self.record_jump();
+            let viewport = &mut self.viewports[self.focus_index];
+            viewport.move_cursor_to(Position { line: siblings[choice], offset: 0 }, false);
+            viewport.scroll_cursor_line(LineAt::Center);
+        }
+    }
+
+    /// A click of the left mouse button on a cell: the first puts the cursor there, the second
+    /// selects the word and the third the line. Shift+click selects up to the cell. In the gutter,
+    /// a click on a line number selects the line, and one just after it bookmarks the line. On the
+    /// minimap, it goes to the lines there, and on a line pinned at the top to that line.
+    pub fn click(&mut self, x: u16, y: u16, count: usize, extend: bool) {
+        if let Some(index) = self.viewport_at_cell(x, y) {
+            self.focus_viewport(index);
+        }
+        self.line_drag = None;
+        // A click on the minimap goes to the lines there, centering them in the view, and one on a
+        // line pinned at the top of the view scrolls up to it
+        if let Some(line) = self.viewports.get(self.focus_index).and_then(|v| v.minimap_line_at(x, y)) {
+            self.record_jump();
+            let viewport = &mut self.viewports[self.focus_index];
+            viewport.move_cursor_to(Position { line, offset: 0 }, false);
+            viewport.scroll_cursor_line(LineAt::Center);
+            return;
+        }
+        if let Some(line) = self.viewports.get(self.focus_index).and_then(|v| v.sticky_line_at(x, y)) {
+            self.record_jump();
+            let viewport = &mut self.viewports[self.focus_index];
+            viewport.move_cursor_to(Position { line, offset: 0 }, false);
+            viewport.scroll_cursor_line(LineAt::Top);
+            return;
+        }
+        if let Some(viewport) = self.viewports.get_mut(self.focus_index) {
+            // A click on a table selects the cell
+            if let Some(table) = viewport.table.as_mut() {
+                if let Some((row, column)) = table.cell_at(x, y) {
+                    (table.row, table.column) = (row, column);
+                    viewport.follow_table();
+                }
+                return;
+            }
+            // A line's number selects the line, from the one the selection starts on with Shift, and
+            // the marks column after it bookmarks the line
+            match viewport.gutter_at_cell(x, y) {
+                Some((line, true)) => return viewport.toggle_bookmark_on(line),
+                Some((line, false)) => {
+                    let from = match extend {
+                        true => viewport.selection_anchor.or(viewport.cursor_position()).map_or(line, |p| p.line),
+                        false => line,
+                    };
+                    viewport.select_lines(from.min(line), from.max(line));
+                    self.line_drag = Some(from);
+                    return;
+                }
+                None => {}
+            }
+            if let Some(position) = viewport.position_at_cell(x, y) {
+                viewport.move_cursor_to(position, extend && count == 1);
+                match count {
+                    1 => {}
+                    2 => viewport.select_word(),
+                    _ => viewport.select_line(),
+                }
+            }
+        }
+    }
+
+    /// Dragging with the left mouse button held down selects from where it was pressed.
+    pub fn drag(&mut self, x: u16, y: u16) {
+        if let Some(viewport) = self.viewports.get_mut(self.focus_index).filter(|v| v.table.is_none()) {
+            if let Some(position) = viewport.position_at_cell(x, y) {
+                match self.line_drag {
+                    // Started on the line numbers, whole lines are selected
+                    Some(from) => viewport.select_lines(from.min(position.line), from.max(position.line)),
+                    None => viewport.move_cursor_to(position, true),
+                }
+            }
+        }
+    }
+
+    /// Link the scrolling of the focused viewport with another one.
+    pub fn link_scrolling(&mut self, other: usize) {
+        let (focused, other) = (&self.viewports[self.focus_index], &self.viewports[other]);
+        self.scroll_link = Some(ScrollLink {
+            ids: (focused.id, other.id),
+            starts: (focused.starting_visible_line, other.starting_visible_line),
+        });
+    }
+
+    /// Compare the focused viewport with another side by side: both float, the focused one over
+    /// the left half of the space and the other over the right, scrolling together, with the
+    /// lines that differ between them colored.
+    pub fn compare_with(&mut self, other: usize) {
+        let first = self.focus_index;
+        let (origin, size) = (self.origin, self.size);
+        let half = size.0 / 2;
+        for (index, x, width) in [(first, 0, half), (other, half, size.0 - half)] {
+            self.focus_viewport(index);
+            if !self.viewports[index].floating {
+                self.toggle_floating();
+            }
+            let v = &mut self.viewports[index];
+            v.origin = (origin.0 + x as u16 + 1, origin.1 + 1);
+            v.size = (width.saturating_sub(1), size.1.saturating_sub(2));
+        }
+        self.focus_viewport(first);
+        self.link_scrolling(other);
+        self.comparison = Some(Comparison { ids: (self.viewports[first].id, self.viewports[other].id), texts: Default::default() });
+    }
+
+    /// Work out again which lines of the compared viewports differ, if their texts changed.
+    fn mark_differences(&mut self) {
+        let Some(comparison) = &mut self.comparison else { return };
+        let viewports = &mut self.viewports;
+        let index = |id: usize| viewports.iter().position(|v| v.id == id);
+        let (Some(first), Some(second)) = (index(comparison.ids.0), index(comparison.ids.1)) else { return };
+        let (Some(old), Some(new)) = (viewports[first].text(), viewports[second].text()) else { return };
+        if (&old, &new) == (&comparison.texts.0, &comparison.texts.1) {
+            return;
+        }
+        let (mut removed, mut added) = (BTreeMap::new(), BTreeMap::new());
+        let (mut old_line, mut new_line) = (0, 0);
+        for (change, _) in diff::lines(&old, &new) {
+            match change {
+                Change::Same => {
+                    old_line += 1;
+                    new_line += 1;
+                }
+                Change::Removed => {
+                    removed.insert(old_line, change);
+                    old_line += 1;
+                }
+                Change::Added => {
+                    added.insert(new_line, change);
+                    new_line += 1;
+                }
+            }
+        }
+        viewports[first].differences = removed;
+        viewports[second].differences = added;
+        comparison.texts = (old, new);
+    }
+
+    /// Scroll the viewport linked to the focused one as far as the focused one scrolled since the
+    /// last render. Its cursor moves along, so it stays scrolled there once it is focused.
+    fn follow_scroll_link(&mut self) {
+        let link = match &mut self.scroll_link {
+            Some(link) => link,
+            None => return,
+        };
+        let focused = &self.viewports[self.focus_index];
+        let (last_start, other_id) = match focused.id {
+            id if id == link.ids.0 => (link.starts.0, link.ids.1),
+            id if id == link.ids.1 => (link.starts.1, link.ids.0),
+            _ => return,
+        };
+        let start = focused.starting_visible_line;
+        let delta = start as isize - last_start as isize;
+        let other = match self.viewports.iter_mut().find(|v| v.id == other_id) {
+            Some(other) => other,
+            None => return,
+        };
+        if delta != 0 {
+            let margin = other.scroll_margin();
+            let rows = other.size.1.saturating_sub(1);
+            if let Some(mut buffer) = other.data.document().map(BufferMut) {
+                let last_line = buffer.line_count().saturating_sub(1);
+                other.starting_visible_line = ((other.starting_visible_line as isize + delta).max(0) as usize).min(last_line);
+                // Within the scroll margin, or the view would jump back to the cursor
+                let line = ((buffer.cursor.line as isize + delta).max(0) as usize)
+                    .clamp(other.starting_visible_line + margin, (other.starting_visible_line + rows).saturating_sub(margin + 1).max(other.starting_visible_line + margin))
+                    .min(last_line);
+                let offset = buffer.cursor.offset.min(crate::util::columns(Lines::new(&buffer.data()).line(line)));
+                buffer.cursor.move_to(Position { line, offset });
+            }
+        }
+        link.starts = if link.ids.0 == other_id { (other.starting_visible_line, start) } else { (start, other.starting_visible_line) };
+    }
+
+    /// Open a buffer as a new document, in a viewport of its own. Returns the index of the viewport.
+    pub fn open_buffer(&mut self, buffer: scribe::Buffer) -> usize {
+        let remembered = buffer.path.as_deref().and_then(crate::positions::recall);
+        let document = self.buffers.add(buffer);
+        let index = self.new_viewport(ViewportData::show(&document));
+        if let Some(remembered) = remembered {
+            self.viewports[index].restore_position(remembered);
+        }
+        index
+    }
+
+    /// Show a file in a viewport of its own while it is read in the background. A viewport that
+    /// is already waiting for it is focused instead. Returns false if there was one.
+    pub fn open_loading(&mut self, path: PathBuf) -> bool {
+        let (index, new) = match self.viewports.iter().position(|v| matches!(&v.data, Loading(p, _) if *p == path)) {
+            Some(index) => (index, false),
+            None => (self.new_viewport(Loading(path, None)), true),
+        };
+        self.focus_viewport(index);
+        new
+    }
+
+    /// Show how much of a file being read has been.
+    pub fn loading_progress(&mut self, path: &Path, percent: usize) {
+        for viewport in self.all_viewports_mut() {
+            if let Loading(p, progress) = &mut viewport.data {
+                if p == path {
+                    *progress = Some(percent);
+                }
+            }
+        }
+    }
+
+    /// Put the buffer read from a file in the viewports waiting for it, in whichever workspace.
+    /// Returns false if there are none, because they were closed while the file was read.
+    pub fn finish_loading(&mut self, path: &Path, buffer: scribe::Buffer) -> bool {
+        let waiting = |v: &Viewport| matches!(&v.data, Loading(p, _) if p == path);
+        if !self.all_viewports().any(waiting) {
+            return false;
+        }
+        let remembered = buffer.path.as_deref().and_then(crate::positions::recall);
+        let document = self.buffers.add(buffer);
+        let config = crate::config::get();
+        let indentation = match config.detect_indentation {
+            true => Some(Indentation::detect(&document.borrow().buffer.data()).unwrap_or(config.indentation)),
+            false => None,
+        };
+        drop(config);
+        for viewport in self.all_viewports_mut().filter(|v| waiting(v)) {
+            viewport.data = ViewportData::show(&document);
+            if !viewport.renamed {
+                viewport.title = document.borrow().name();
+            }
+            if let Some(indentation) = indentation {
+                viewport.indentation = indentation;
+            }
+            if let Some(remembered) = remembered {
+                viewport.restore_position(remembered);
+            }
+        }
+        true
+    }
+
+    /// Show the image read from a file in the viewport waiting for it. Returns false if there is
+    /// none, because it was closed while the file was read.
+    pub fn finish_loading_image(&mut self, path: &Path, image: crate::image::Image) -> bool {
+        let Some(viewport) = self.all_viewports_mut().find(|v| matches!(&v.data, Loading(p, _) if p == path)) else { return false };
+        viewport.data = Image(Box::new(image));
+        true
+    }
+
+    /// Show a manual page in a viewport of its own, and focus it.
+    pub fn open_page(&mut self, page: crate::manpage::Page) {
+        let index = self.new_viewport(ViewportData::Page(Box::new(page)));
+        self.focus_viewport(index);
+    }
+
+    /// Follow a file in a viewport of its own, and focus it.
+    pub fn open_tail(&mut self, tail: crate::tail::Tail) {
+        let index = self.new_viewport(ViewportData::Tail(Box::new(tail)));
+        self.focus_viewport(index);
+    }
+
+    /// Read what was added to the files being followed. Returns true if anything was.
+    pub fn poll_tails(&mut self) -> bool {
+        let mut changed = false;
+        for viewport in self.all_viewports_mut() {
+            if let Tail(tail) = &mut viewport.data {
+                changed |= tail.poll();
+            }
+        }
+        changed
+    }
+
+    /// Close the viewports waiting for a file that could not be read.
+    pub fn cancel_loading(&mut self, path: &Path) {
+        let waiting = |v: &Viewport| matches!(&v.data, Loading(p, _) if p == path);
+        while let Some(index) = self.viewports.iter().position(waiting) {
+            self.close_viewport(index);
+        }
+        for workspace in &mut self.workspaces {
+            workspace.viewports.retain(|v| !waiting(v));
+            workspace.focus_index = workspace.focus_index.min(workspace.viewports.len().saturating_sub(1));
+        }
+    }
+
+    /// Remember where the cursor is in the file of every viewport, for when they are opened again.
+    pub fn remember_positions(&self) {
+        for viewport in self.all_viewports() {
+            viewport.remember_position();
+        }
+    }
+
+    /// Focus a viewport showing a document, or a new one if none does.
+    pub fn show_document(&mut self, id: usize) {
+        let document = match self.buffers.get(id) {
+            Some(document) => document.clone(),
+            None => return,
+        };
+        let index = match self.viewports.iter().position(|v| matches!(&v.data, Buffer(d, _) if Rc::ptr_eq(d, &document))) {
+            Some(index) => index,
+            None => self.new_viewport(ViewportData::show(&document)),
+        };
+        self.focus_viewport(index);
+    }
+
+    /// How many viewports show a document, in every workspace.
+    pub fn viewers(&self, id: usize) -> usize {
+        self.all_viewports().filter(|v| matches!(&v.data, Buffer(d, _) if d.borrow().id == id)).count()
+    }
+
+    /// Close a document along with every viewport showing it, in any workspace, whether or not it has unsaved changes.
+    /// The text of an untitled one is kept in `discarded`, should that have been a mistake.
+    pub fn close_document(&mut self, id: usize) {
+        if let Some(document) = self.buffers.remove(id) {
+            let text = document.borrow().buffer.data();
+            if document.borrow().buffer.path.is_none() && !text.is_empty() {
+                let title = self.all_viewports().find(|v| matches!(&v.data, Buffer(d, _) if Rc::ptr_eq(d, &document)))
+                    .map_or_else(|| document.borrow().name(), |v| v.title.clone());
+                self.discarded.push((title, text));
+                if self.discarded.len() > MAX_DISCARDED {
+                    self.discarded.remove(0);
+                }
+            }
+            for i in (0..self.viewports.len()).rev() {
+                if matches!(&self.viewports[i].data, Buffer(d, _) if Rc::ptr_eq(d, &document)) {
+                    self.close_viewport(i);
+                }
+            }
+            for workspace in &mut self.workspaces {
+                workspace.viewports.retain(|v| !matches!(&v.data, Buffer(d, _) if Rc::ptr_eq(d, &document)));
+                workspace.focus_index = workspace.focus_index.min(workspace.viewports.len().saturating_sub(1));
+            }
+        }
+    }
+
     /// Create a new viewport with the given data. Returns the index of the new viewport.
     pub fn new_viewport(&mut self, data: ViewportData) -> usize {
+        self.next_viewport_id += 1;
+        let indentation = match &data {
+            ViewportData::Buffer(document, _) => {
+                let text = document.borrow().buffer.data();
+                let config = crate::config::get();
+                match config.detect_indentation {
+                    true => Indentation::detect(&text).unwrap_or(config.indentation),
+                    false => config.indentation,
+                }
+            }
+            ViewportData::Terminal(_) => Indentation::Tabs,
+            ViewportData::Loading(..) | ViewportData::Image(_) | ViewportData::Page(_) | ViewportData::Tail(_) => crate::config::get().indentation,
+        };
         self.viewports.push(Viewport {
+            id: self.next_viewport_id,
             origin: (self.origin.0 + 1, self.origin.1 + 1),
-            size: (self.size.0 - 1, self.size.1 - 2),
-            title: match &data {
-                ViewportData::Buffer(buf) => buf.file_name().unwrap_or_else(|| "Untitled".to_owned()),
-                ViewportData::Terminal(_) => "Terminal".to_owned(),
-            },
+            size: (self.size.0.saturating_sub(1), self.size.1.saturating_sub(2)),
+            title: data.title(),
+            renamed: false,
             data,
             starting_visible_line: 0,
             starting_visible_column: 0,
+            selection_anchor: None,
+            extra_selections: Vec::new(),
+            indentation,
+            floating: false,
+            view: self.view,
+            auto_closed: Vec::new(),
+            expansion: None,
+            completion: None,
+            differences: BTreeMap::new(),
+            table: None,
+            unsaved_changes: None,
+            sticky_lines: Vec::new(),
+            breadcrumbs: Vec::new(),
+            folds: Vec::new(),
+            grown_selections: Vec::new(),
+            compact: false,
         });
         self.viewports.len()-1 // Return the index of the created viewport
     }
 
     pub fn close_focused_viewport(&mut self) {
         if !self.viewports.is_empty() {
-            //self.viewports[self.focus_index].save().unwrap(); // TODO: prompt if user wants to save first
-            self.viewports.remove(self.focus_index);
-            if self.focus_index > 0 { // Only if focus_index is not already zero
-                self.focus_index -= 1;
+            self.close_viewport(self.focus_index);
+        }
+    }
+
+    pub fn close_viewport(&mut self, index: usize) {
+        //self.viewports[index].save().unwrap(); // TODO: prompt if user wants to save first
+        let closed = self.viewports.remove(index);
+        closed.remember_position();
+        self.jump_list.forget_viewport(closed.id);
+        self.z_order.retain(|&z| z != closed.id);
+        if self.scroll_link.as_ref().is_some_and(|link| link.ids.0 == closed.id || link.ids.1 == closed.id) {
+            self.scroll_link = None;
+        }
+        if self.comparison.as_ref().is_some_and(|comparison| comparison.ids.0 == closed.id || comparison.ids.1 == closed.id) {
+            self.comparison = None;
+            self.viewports.iter_mut().for_each(|v| v.differences.clear());
+        }
+        // The document is closed with the last viewport showing it, unless that would lose changes,
+        // when it stays open out of sight
+        if let Buffer(document, _) = &closed.data {
+            let document = document.borrow();
+            if document.history.is_saved() && !self.all_viewports().any(|v| v.data.same_document(&closed.data)) {
+                self.buffers.remove(document.id);
+                if let (Some(path), Some(cursor)) = (&document.buffer.path, closed.cursor_position()) {
+                    self.closed_files.retain(|(p, _)| p != path);
+                    self.closed_files.push((path.clone(), crate::positions::Remembered { cursor, first_line: closed.starting_visible_line }));
+                    if self.closed_files.len() > MAX_CLOSED_FILES {
+                        self.closed_files.remove(0);
+                    }
+                }
+            }
+        }
+        if self.focus_index > index || (self.focus_index == index && self.focus_index > 0) { // Only if focus_index is not already zero
+            self.focus_index -= 1;
+        }
+    }
+
+    /// Close every viewport but the focused one, keeping those with unsaved changes. Returns how
+    /// many were kept.
+    pub fn close_others(&mut self) -> usize {
+        let focused_id = match self.viewports.get(self.focus_index) {
+            Some(v) => v.id,
+            None => return 0,
+        };
+        self.close_where(|v| v.id != focused_id)
+    }
+
+    /// Close every viewport without unsaved changes.
+    pub fn close_unmodified(&mut self) {
+        self.close_where(|v| !v.is_modified());
+    }
+
+    /// Close the viewports matching a condition, except for those with unsaved changes. Returns
+    /// how many of those there were.
+    fn close_where<F: Fn(&Viewport) -> bool>(&mut self, condition: F) -> usize {
+        let mut kept = 0;
+        for i in (0..self.viewports.len()).rev() {
+            if condition(&self.viewports[i]) {
+                if self.viewports[i].is_modified() {
+                    kept += 1;
+                } else {
+                    self.close_viewport(i);
+                }
             }
         }
+        kept
     }
 
     pub fn next_tab(&mut self) {
-        if self.focus_index >= self.viewports.len() - 1 { self.focus_index = 0 } else { self.focus_index += 1 };
+        let next = if self.focus_index >= self.viewports.len() - 1 { 0 } else { self.focus_index + 1 };
+        self.focus_viewport(next);
+    }
+
+    /// The viewports of every workspace, the active one's first.
+    pub fn all_viewports(&self) -> impl Iterator<Item = &Viewport> {
+        self.viewports.iter().chain(self.workspaces.iter().flat_map(|w| w.viewports.iter()))
+    }
+
+    pub fn all_viewports_mut(&mut self) -> impl Iterator<Item = &mut Viewport> {
+        self.viewports.iter_mut().chain(self.workspaces.iter_mut().flat_map(|w| w.viewports.iter_mut()))
+    }
+
+    /// Title the viewports showing files after them, in every workspace. Where files of the same
+    /// name from different folders are open, each title also has as many of the folders it is in
+    /// as tell it apart, as in `mod.rs — parser/`. Renamed viewports keep the names they were given.
+    pub fn retitle(&mut self) {
+        let path = |v: &Viewport| match &v.data {
+            Buffer(document, _) if !v.renamed => document.borrow().buffer.path.clone(),
+            _ => None,
+        };
+        let mut paths: Vec<PathBuf> = self.all_viewports().filter_map(path).collect();
+        paths.sort();
+        paths.dedup();
+        for viewport in self.all_viewports_mut() {
+            if let Some(path) = path(viewport) {
+                viewport.title = title_among(&path, &paths);
+            }
+        }
+    }
+
+    /// Whether a viewport in any workspace shows a file, or is reading it.
+    pub fn shows(&self, path: &Path) -> bool {
+        self.all_viewports().any(|v| match &v.data {
+            Buffer(document, _) => document.borrow().buffer.path.as_deref() == Some(path),
+            Loading(loading, _) => loading == path,
+            _ => false,
+        })
+    }
+
+    /// The name of every workspace, and the files its viewports show, for the session to keep.
+    pub fn workspace_files(&self) -> Vec<(String, Vec<(PathBuf, bool)>)> {
+        (0..self.workspaces.len()).map(|i| {
+            let viewports = if i == self.workspace { &self.viewports } else { &self.workspaces[i].viewports };
+            let mut files: Vec<(PathBuf, bool)> = Vec::new();
+            for viewport in viewports {
+                let path = match &viewport.data {
+                    Buffer(document, _) => document.borrow().buffer.path.clone(),
+                    Loading(path, _) => Some(path.clone()),
+                    _ => None,
+                };
+                if let Some(path) = path.filter(|path| !files.iter().any(|(p, _)| p == path)) {
+                    files.push((path, viewport.compact));
+                }
+            }
+            (self.workspaces[i].name.clone(), files)
+        }).collect()
+    }
+
+    /// The name of the active workspace.
+    pub fn workspace_name(&self) -> &str {
+        &self.workspaces[self.workspace].name
+    }
+
+    /// How many viewports a workspace has.
+    pub fn workspace_len(&self, index: usize) -> usize {
+        match index == self.workspace {
+            true => self.viewports.len(),
+            false => self.workspaces[index].viewports.len(),
+        }
+    }
+
+    /// Put the active workspace's viewports away and show another's, as they were left. A
+    /// workspace left with no viewports is dropped, unless it is the only one.
+    pub fn switch_workspace(&mut self, index: usize) {
+        if index == self.workspace || index >= self.workspaces.len() {
+            return;
+        }
+        let mut index = index;
+        if self.viewports.is_empty() {
+            self.workspaces.remove(self.workspace);
+            if index > self.workspace {
+                index -= 1;
+            }
+        } else {
+            let active = &mut self.workspaces[self.workspace];
+            active.viewports = std::mem::take(&mut self.viewports);
+            active.focus_index = self.focus_index;
+            active.z_order = std::mem::take(&mut self.z_order);
+            active.tiled_id = self.tiled_id;
+        }
+        let next = &mut self.workspaces[index];
+        self.viewports = std::mem::take(&mut next.viewports);
+        self.focus_index = next.focus_index.min(self.viewports.len().saturating_sub(1));
+        self.z_order = std::mem::take(&mut next.z_order);
+        self.tiled_id = next.tiled_id;
+        self.workspace = index;
+    }
+
+    /// Make a new, empty workspace, to switch to or put viewports in. Returns its index.
+    pub fn add_workspace(&mut self, name: String) -> usize {
+        self.workspaces.push(Workspace::new(name));
+        self.workspaces.len() - 1
+    }
+
+    /// Move the focused viewport into another workspace, among its tabs, and stay in this one.
+    pub fn move_to_workspace(&mut self, index: usize) {
+        if index == self.workspace || index >= self.workspaces.len() || self.viewports.is_empty() {
+            return;
+        }
+        let mut viewport = self.viewports.remove(self.focus_index);
+        self.z_order.retain(|&z| z != viewport.id);
+        viewport.floating = false;
+        if self.focus_index > 0 && self.focus_index >= self.viewports.len() {
+            self.focus_index -= 1;
+        }
+        let workspace = &mut self.workspaces[index];
+        workspace.tiled_id = viewport.id;
+        workspace.focus_index = workspace.viewports.len();
+        workspace.viewports.push(viewport);
+    }
+}
+
+/// A snippet of copied text as a list shows it: by its first line, and how many more it has.
+fn describe_snippet(text: &str) -> String {
+    let first_line = text.lines().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+    match text.trim_end_matches('\n').matches('\n').count() {
+        0 => first_line.to_owned(),
+        more => format!("{}  (+{} line{})", first_line, more, if more == 1 { "" } else { "s" }),
+    }
+}
+
+/// The title of a file among the others open: its name, and if another of them has the same name,
+/// the fewest of the folders it is in that the other's are not.
+fn title_among(path: &Path, paths: &[PathBuf]) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let parent = path.parent().unwrap_or_else(|| Path::new(""));
+    let others: Vec<&Path> = paths.iter().filter(|p| p.as_path() != path && p.file_name() == path.file_name())
+        .map(|p| p.parent().unwrap_or_else(|| Path::new(""))).collect();
+    if others.is_empty() {
+        return name;
+    }
+    let folders: Vec<_> = parent.components().collect();
+    let folders = (1..folders.len()).map(|n| folders[folders.len() - n..].iter().collect::<PathBuf>())
+        .find(|tail| !others.iter().any(|other| other.ends_with(tail)))
+        .unwrap_or_else(|| parent.to_owned());
+    format!("{} — {}/", name, folders.display().to_string().trim_end_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::Editor;
+    use crate::menu::Action;
+
+    #[test]
+    fn viewports_without_a_document_are_not_edited() {
+        crate::backend::start_headless((80, 24));
+        let mut grid = crate::backend::Grid::new((80, 24));
+        let mut editor = Editor::new();
+        editor.viewport_manager.open_page(crate::manpage::Page::new("page".to_owned(), "Some help"));
+        editor.viewport_manager.open_loading(PathBuf::from("/nonexistent/file"));
+        for index in 0..2 {
+            editor.viewport_manager.focus_viewport(index);
+            for action in [Action::Cut, Action::Paste, Action::Undo, Action::Redo] {
+                editor.handle_action(&mut grid, action);
+            }
+            let viewport = &mut editor.viewport_manager.viewports[index];
+            viewport.insert_str("text");
+            viewport.insert('x');
+            viewport.backspace();
+            viewport.delete();
+            assert!(viewport.text().is_none());
+        }
+        editor.render(&mut grid);
+    }
+
+    #[test]
+    fn undoing_an_accent_typed_on_a_letter_takes_only_the_accent_away() {
+        crate::backend::start_headless((80, 24));
+        let mut editor = Editor::new();
+        let mut buffer = scribe::Buffer::new();
+        buffer.insert("ex");
+        editor.open_buffer(buffer);
+        let viewport = &mut editor.viewport_manager.viewports[0];
+        viewport.get_buffer().unwrap().cursor.move_to(Position { line: 0, offset: 2 });
+        viewport.insert('\u{301}');
+        assert_eq!(viewport.text().unwrap(), "ex\u{301}");
+        viewport.undo();
+        assert_eq!(viewport.text().unwrap(), "ex");
+        viewport.redo();
+        assert_eq!(viewport.text().unwrap(), "ex\u{301}");
+        viewport.undo();
+        viewport.undo();
+        assert_eq!(viewport.text().unwrap(), "ex");
+    }
+
+    #[test]
+    fn new_lines_keep_accents_on_the_spaces_after_them() {
+        crate::backend::start_headless((80, 24));
+        let mut editor = Editor::new();
+        for (text, expected) in [("a \u{301}b", "a\n \u{301}b"), ("a  \u{301}b", "a\n \u{301}b"), ("\t{ \u{301}}", "\t{\n\t \u{301}}")] {
+            let mut buffer = scribe::Buffer::new();
+            buffer.insert(text);
+            editor.open_buffer(buffer);
+            let viewport = editor.viewport_manager.get_focused_viewport_mut().unwrap();
+            viewport.get_buffer().unwrap().cursor.move_to(Position { line: 0, offset: text.find(' ').unwrap() });
+            viewport.new_line();
+            assert_eq!(viewport.text().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn numbers_next_to_accents_are_added_to_whole() {
+        crate::backend::start_headless((80, 24));
+        let mut editor = Editor::new();
+        let mut buffer = scribe::Buffer::new();
+        buffer.insert("1\u{301}0 é 5\u{301}\nü 9");
+        editor.open_buffer(buffer);
+        let viewport = &mut editor.viewport_manager.viewports[0];
+        assert!(viewport.add_to_numbers(1, false));
+        assert_eq!(viewport.text().unwrap(), "1\u{301}1 é 5\u{301}\nü 9");
+        viewport.get_buffer().unwrap().cursor.move_to(Position { line: 0, offset: 4 });
+        assert!(!viewport.add_to_numbers(1, false));
+        viewport.get_buffer().unwrap().cursor.move_to(Position { line: 1, offset: 0 });
+        assert!(viewport.add_to_numbers(-10, false));
+        assert_eq!(viewport.text().unwrap(), "1\u{301}1 é 5\u{301}\nü -1");
+    }
+
+    #[test]
+    fn selecting_lines_past_the_end_selects_up_to_it() {
+        crate::backend::start_headless((80, 24));
+        let mut editor = Editor::new();
+        let mut buffer = scribe::Buffer::new();
+        buffer.insert("one\ntwo\nthree\n");
+        editor.open_buffer(buffer);
+        let viewport = &mut editor.viewport_manager.viewports[0];
+        viewport.select_lines(1, 9);
+        assert_eq!(viewport.selected_text().as_deref(), Some("two\nthree\n"));
+        viewport.select_lines(7, 9);
+        assert_eq!(viewport.selected_text(), None);
+        viewport.select_lines(0, 0);
+        assert_eq!(viewport.selected_text().as_deref(), Some("one\n"));
+    }
+
+    #[test]
+    fn tiny_terminals_and_splits_still_render() {
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::empty()));
+        let sizes = (1..=4).flat_map(|w| (1..=4).map(move |h| (w, h))).chain((1..=4).flat_map(|n| [(80, n), (n, 24)]));
+        for size in sizes {
+            crate::backend::start_headless(size);
+            let mut grid = crate::backend::Grid::new(size);
+            let mut editor = Editor::new();
+            let mut buffer = scribe::Buffer::new();
+            buffer.insert("fn main() {\n    println!(\"hello\");\n}\n".repeat(20));
+            editor.open_buffer(buffer);
+            editor.in_menu_mode = false;
+            editor.render(&mut grid);
+            for action in [Action::SplitView, Action::SplitView, Action::ToggleMinimap, Action::ToggleLineNumbers, Action::ToggleBreadcrumbs, Action::ToggleWordWrap, Action::ToggleZenMode] {
+                editor.handle_action(&mut grid, action);
+                editor.render(&mut grid);
+            }
+            let keys = [KeyCode::Down, KeyCode::Down, KeyCode::Right, KeyCode::Char('x'), KeyCode::Enter, KeyCode::Backspace];
+            editor.feed(&mut grid, keys.iter().map(|&code| key(code)));
+        }
     }
 }
@@ -0,0 +1,34 @@
+//! The start screen of a folder opened as a project, shown instead of an empty buffer: which
+//! folder it is, and how to get going in it.
+
+use crate::config;
+use crate::locale::tr;
+use crate::manpage::Page;
+
+use std::path::Path;
+
+/// The start screen of a project folder, its text wrapped to fit so many columns.
+pub fn page(root: &Path, has_config: bool, width: usize) -> Page {
+    let name = root.file_name().map_or_else(|| root.display().to_string(), |name| name.to_string_lossy().into_owned());
+    let mut paragraphs = vec![
+        tr("The files of the folder are listed on the left. Up and Down go through them, Enter opens a file or a folder, and typing finds an entry by its name. F6 goes between the list and the open files."),
+        tr("Go → Project markers lists the TODO, FIXME, HACK and NOTE markers in the files of the folder."),
+    ];
+    if let Some(command) = &config::get().check_command {
+        paragraphs.push(format!("{} {}", tr("Go → Check project runs"), command));
+    }
+    paragraphs.push(match has_config {
+        true => format!("{} {}.", tr("Settings for this project are read from"), config::PROJECT_FILE),
+        false => format!("{} {}.", tr("Settings for this project alone can go in"), config::PROJECT_FILE),
+    });
+    paragraphs.push(tr("Ctrl+Q closes this page."));
+
+    let width = width.clamp(20, 80);
+    let mut text = format!("\x1b[1m{}\x1b[0m\n{}\n", name, root.display());
+    for paragraph in paragraphs {
+        text.push('\n');
+        text.push_str(&textwrap::fill(&paragraph, width));
+        text.push('\n');
+    }
+    Page::new(name, &text)
+}
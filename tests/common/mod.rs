@@ -0,0 +1,26 @@
+//! What the integration tests share.
+
+#![allow(dead_code)] // Each test file uses only some of it
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+use std::path::PathBuf;
+
+pub fn key(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::new(code, KeyModifiers::empty()))
+}
+
+pub fn typed(text: &str) -> Vec<Event> {
+    text.chars().map(|c| key(if c == '\n' { KeyCode::Enter } else { KeyCode::Char(c) })).collect()
+}
+
+/// A folder of its own for a test, with the editor's config and data kept in it too. The
+/// editor reads its config once, so a test that needs settings of its own needs a file of its own.
+pub fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("diesel-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::env::set_var("XDG_CONFIG_HOME", dir.join("config"));
+    std::env::set_var("XDG_DATA_HOME", dir.join("data"));
+    dir
+}
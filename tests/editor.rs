@@ -0,0 +1,57 @@
+//! Driving the editor headlessly, the way a user would, and checking what reaches the disk and
+//! the screen.
+
+mod common;
+
+use common::{key, scratch, typed};
+use crossterm::event::KeyCode;
+use diesel::{backend, Editor};
+
+#[test]
+fn typed_text_is_saved_and_shown_again_when_reopened() {
+    let dir = scratch("save");
+    let path = dir.join("notes.txt");
+    std::fs::write(&path, "").unwrap();
+    let size = (60, 12);
+    backend::start_headless(size);
+
+    let mut screen = backend::Grid::new(size);
+    let mut editor = Editor::new();
+    editor.open_file(path.clone());
+    assert!(editor.feed(&mut screen, vec![])); // Until it is read
+    assert!(editor.feed(&mut screen, typed("Hello, world!\nA second line")));
+    // File → Save, from menu mode. Save as starts with an S too, so Enter picks the first
+    assert!(editor.feed(&mut screen, vec![key(KeyCode::Esc), key(KeyCode::Char('f')), key(KeyCode::Char('s')), key(KeyCode::Enter)]));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "Hello, world!\nA second line");
+    assert!(!editor.unsaved_changes());
+
+    let mut screen = backend::Grid::new(size);
+    let mut editor = Editor::new();
+    editor.open_file(path.clone());
+    assert!(editor.feed(&mut screen, vec![]));
+    let rows: Vec<String> = (0..size.1).map(|y| screen.row(y)).collect();
+    let first = rows.iter().position(|row| row.contains("Hello, world!")).expect("The saved text is not shown");
+    assert!(rows[first + 1].contains("A second line"), "{}", screen.text());
+    assert!(screen.text().contains("notes.txt"), "{}", screen.text());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn keys_typed_all_at_once_each_type_as_they_would_alone() {
+    let dir = scratch("burst");
+    let path = dir.join("main.rs");
+    std::fs::write(&path, "").unwrap();
+    let size = (60, 12);
+    backend::start_headless(size);
+
+    let mut screen = backend::Grid::new(size);
+    let mut editor = Editor::new();
+    editor.open_file(path.clone());
+    assert!(editor.feed(&mut screen, vec![]));
+    // The bracket still brings its closing one along, though the keys after it were waiting
+    assert!(editor.feed(&mut screen, typed("call(x")));
+    assert!(screen.text().contains("call(x)"), "{}", screen.text());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,57 @@
+//! Saving when the terminal loses focus, and reading files changed on disk when it gets it back.
+
+mod common;
+
+use common::{scratch, typed};
+use crossterm::event::Event;
+use diesel::{backend, Editor};
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Change a file the way another program would, with a modification time of its own.
+fn change_on_disk(path: &Path, text: &str, seconds_later: u64) {
+    std::fs::write(path, text).unwrap();
+    let file = std::fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(seconds_later)).unwrap();
+}
+
+#[test]
+fn focus_loss_saves_and_focus_gained_reads_changed_files() {
+    let dir = scratch("focus");
+    std::fs::create_dir_all(dir.join("config").join("qedit")).unwrap();
+    std::fs::write(dir.join("config").join("qedit").join("config"), "autosave_on_focus_loss = true\n").unwrap();
+    let path = dir.join("notes.txt");
+    std::fs::write(&path, "").unwrap();
+    let size = (100, 12);
+    backend::start_headless(size);
+
+    let mut screen = backend::Grid::new(size);
+    let mut editor = Editor::new();
+    editor.open_file(path.clone());
+    assert!(editor.feed(&mut screen, vec![])); // Until it is read
+
+    // Typed text is saved as focus goes elsewhere
+    assert!(editor.feed(&mut screen, typed("Typed here")));
+    assert!(editor.unsaved_changes());
+    assert!(editor.feed(&mut screen, vec![Event::FocusLost]));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "Typed here");
+    assert!(!editor.unsaved_changes());
+
+    // A file changed elsewhere is read again when focus comes back
+    change_on_disk(&path, "Changed elsewhere\non two lines", 10);
+    assert!(editor.feed(&mut screen, vec![Event::FocusGained]));
+    assert!(screen.text().contains("Changed elsewhere"), "{}", screen.text());
+    assert!(!screen.text().contains("Typed here"), "{}", screen.text());
+    assert!(!editor.unsaved_changes());
+
+    // But neither overwrites changes made both here and elsewhere
+    assert!(editor.feed(&mut screen, typed("!")));
+    change_on_disk(&path, "Changed elsewhere again", 20);
+    assert!(editor.feed(&mut screen, vec![Event::FocusLost, Event::FocusGained]));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "Changed elsewhere again");
+    assert!(editor.unsaved_changes());
+    assert!(screen.text().contains("notes.txt was changed on disk"), "{}", screen.text());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,37 @@
+//! Text pasted into the terminal, and the paths of files dropped onto it.
+
+mod common;
+
+use common::{key, scratch, typed};
+use crossterm::event::{Event, KeyCode};
+use diesel::{backend, Editor};
+
+#[test]
+fn pasted_text_is_inserted_and_dropped_files_are_offered_to_open() {
+    let dir = scratch("paste");
+    let path = dir.join("notes.txt");
+    let dropped = dir.join("dropped file.txt");
+    std::fs::write(&path, "").unwrap();
+    std::fs::write(&dropped, "Dropped in").unwrap();
+    let size = (80, 12);
+    backend::start_headless(size);
+
+    let mut screen = backend::Grid::new(size);
+    let mut editor = Editor::new();
+    editor.open_file(path.clone());
+    assert!(editor.feed(&mut screen, vec![]));
+    // A pasted bracket is taken as it is, with nothing closing it
+    assert!(editor.feed(&mut screen, vec![Event::Paste("call(x".to_owned())]));
+    assert!(screen.text().contains("call(x"), "{}", screen.text());
+    assert!(!screen.text().contains("call(x)"), "{}", screen.text());
+    assert!(editor.feed(&mut screen, typed(")")));
+    assert!(screen.text().contains("call(x)"), "{}", screen.text());
+
+    // The path a terminal pastes in as a file is dropped onto it opens that file
+    let pasted = format!("'{}'", dropped.display());
+    assert!(editor.feed(&mut screen, vec![Event::Paste(pasted), key(KeyCode::Enter)]));
+    assert!(editor.feed(&mut screen, vec![])); // Until it is read
+    assert!(screen.text().contains("Dropped in"), "{}", screen.text());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
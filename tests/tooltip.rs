@@ -0,0 +1,38 @@
+//! Tooltips for the mouse pointer resting on a cell, without any button held.
+
+mod common;
+
+use common::scratch;
+use crossterm::event::{Event, KeyModifiers, MouseEvent, MouseEventKind};
+use diesel::{backend, Editor};
+
+use std::time::Duration;
+
+#[test]
+fn resting_the_pointer_on_a_line_with_a_problem_tells_it() {
+    let dir = scratch("tooltip");
+    std::fs::create_dir_all(dir.join("config").join("qedit")).unwrap();
+    std::fs::write(dir.join("config").join("qedit").join("config"), "tooltip_delay = 1\n").unwrap();
+    let path = dir.join("config.yaml");
+    std::fs::write(&path, "a:\n\tb: 1\n").unwrap();
+    let size = (80, 12);
+    backend::start_headless(size);
+
+    let mut screen = backend::Grid::new(size);
+    let mut editor = Editor::new();
+    editor.open_file(path.clone());
+    assert!(editor.feed(&mut screen, vec![]));
+    let row = (0..size.1).find(|&y| screen.row(y).contains("b: 1")).expect("The line is not shown");
+    let column = screen.row(row).chars().position(|c| c == 'b').unwrap() as u16;
+    // The problem is told after the line as well, and the tooltip tells it again below
+    let told = |screen: &backend::Grid| screen.text().matches("Tabs are not allowed").count();
+    assert_eq!(told(&screen), 1, "{}", screen.text());
+
+    let moved = Event::Mouse(MouseEvent { kind: MouseEventKind::Moved, column, row, modifiers: KeyModifiers::NONE });
+    assert!(editor.feed(&mut screen, vec![moved]));
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(editor.feed(&mut screen, vec![]));
+    assert_eq!(told(&screen), 2, "{}", screen.text());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}